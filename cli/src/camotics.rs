@@ -0,0 +1,79 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use log::info;
+
+use crate::is_stdio;
+
+/// Length of the cutting part of the tool, which only needs to reach past the deepest cut
+const TOOL_LENGTH: f64 = 20.;
+/// Material left around the toolpath when CAMotics sizes the workpiece
+const WORKPIECE_MARGIN: f64 = 5.;
+
+/// End of the tool that cuts the material
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ToolShape {
+    /// Flat end mill
+    Cylindrical,
+    /// Ball end mill, i.e. for 3D carving
+    Ballnose,
+}
+
+/// Write a CAMotics project that simulates the G-Code at `gcode_path` with a single tool, for checking how material is
+/// removed before cutting it
+///
+/// The tool is number 1, which CAMotics uses until a program changes tools, and the workpiece is sized to fit the
+/// toolpath.
+pub fn write(
+    project_path: &Path,
+    gcode_path: &Path,
+    shape: ToolShape,
+    diameter: f64,
+) -> io::Result<()> {
+    if is_stdio(gcode_path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CAMotics needs the G-Code in a file, please give --out a file path",
+        ));
+    }
+    let project = serde_json::json!({
+        "units": "metric",
+        "tools": {
+            "1": {
+                "number": 1,
+                "units": "metric",
+                "shape": match shape {
+                    ToolShape::Cylindrical => "cylindrical",
+                    ToolShape::Ballnose => "ballnose",
+                },
+                "length": TOOL_LENGTH,
+                "diameter": diameter,
+            },
+        },
+        "workpiece": {
+            "automatic": true,
+            "margin": WORKPIECE_MARGIN,
+        },
+        "files": [relative_path(gcode_path, project_path)?],
+    });
+    fs::write(project_path, serde_json::to_vec_pretty(&project)?)?;
+    info!("Wrote a CAMotics project to {}", project_path.display());
+    Ok(())
+}
+
+/// Where the G-Code is from the project, which CAMotics resolves file paths against
+fn relative_path(gcode_path: &Path, project_path: &Path) -> io::Result<PathBuf> {
+    let gcode_path = gcode_path.canonicalize()?;
+    let project_dir = project_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+    Ok(gcode_path
+        .strip_prefix(&project_dir)
+        .map(Path::to_path_buf)
+        .unwrap_or(gcode_path))
+}
@@ -0,0 +1,269 @@
+use std::{
+    io::{self, Write},
+    mem,
+};
+
+use g_code::parse::ParseError;
+use log::{error, info};
+use svg2gcode::MachineConfig;
+
+use crate::simulate::{Motion, Move, Simulation};
+
+/// Leeway for the rounding of coordinates and feedrates when they are formatted
+const EPSILON: f64 = 1e-3;
+/// Violations that are logged before the rest are only counted
+const MAX_REPORTED: usize = 10;
+
+/// Writer that checks a program against the work area and feedrate limits of the machine as it passes through
+///
+/// Each line is checked as soon as it is complete, so that the program never has to be in memory all at once. What
+/// was found is reported by [Self::finish].
+pub struct Checker<'a, W> {
+    inner: W,
+    machine: &'a MachineConfig,
+    simulation: Simulation,
+    /// What has been written but is not yet a complete line
+    pending: Vec<u8>,
+    /// The first [MAX_REPORTED] violations, each with its line number
+    violations: Vec<String>,
+    /// Number of violations, including those that are only counted
+    count: usize,
+    /// Set once a line can't be parsed, after which nothing more is checked
+    error: Option<ParseError>,
+}
+
+impl<'a, W: Write> Checker<'a, W> {
+    pub fn new(inner: W, machine: &'a MachineConfig) -> Self {
+        if machine.work_area.is_none() && machine.kinematics.max_feedrate.is_none() {
+            info!("There is no --work-area or --max-feedrate to check the G-Code against");
+        }
+        Self {
+            inner,
+            machine,
+            simulation: Simulation::default(),
+            pending: vec![],
+            violations: vec![],
+            count: 0,
+            error: None,
+        }
+    }
+
+    /// Check the last line, even without a newline, and report what was found
+    ///
+    /// Returns the writer, with whether the program is within the machine's limits.
+    pub fn finish(mut self) -> (W, bool) {
+        self.end();
+        if let Some(err) = &self.error {
+            error!("The G-Code could not be parsed to check it: {err}");
+            return (self.inner, false);
+        }
+        if self.count == 0 {
+            info!("The G-Code is within the machine's limits");
+            return (self.inner, true);
+        }
+        for violation in &self.violations {
+            error!("{violation}");
+        }
+        if self.count > MAX_REPORTED {
+            error!("...and {} more", self.count - MAX_REPORTED);
+        }
+        error!(
+            "The G-Code exceeds the machine's limits in {} place{}",
+            self.count,
+            if self.count == 1 { "" } else { "s" }
+        );
+        (self.inner, false)
+    }
+
+    /// Check what is left after the last newline
+    fn end(&mut self) {
+        if !self.pending.is_empty() {
+            let line = mem::take(&mut self.pending);
+            self.check_line(&line);
+        }
+    }
+
+    fn check_line(&mut self, line: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+        match self.simulation.step(&line) {
+            Ok(Some(step)) => {
+                for violation in violations(&step, self.machine) {
+                    if self.violations.len() < MAX_REPORTED {
+                        self.violations.push(violation);
+                    }
+                    self.count += 1;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl<W: Write> Write for Checker<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let mut rest = &buf[..written];
+        while let Some(newline) = rest.iter().position(|&byte| byte == b'\n') {
+            let mut line = mem::take(&mut self.pending);
+            line.extend_from_slice(&rest[..newline]);
+            self.check_line(&line);
+            line.clear();
+            self.pending = line;
+            rest = &rest[newline + 1..];
+        }
+        self.pending.extend_from_slice(rest);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where a move goes beyond the work area or faster than the maximum feedrate, with its line number
+fn violations(
+    Move {
+        line,
+        motion,
+        to,
+        feedrate,
+        ..
+    }: &Move,
+    machine: &MachineConfig,
+) -> Vec<String> {
+    let mut violations = vec![];
+    if !matches!(motion, Motion::Rapid) {
+        match (feedrate, machine.kinematics.max_feedrate) {
+            (None, _) => violations.push(format!("Line {line}: cuts without a feedrate")),
+            (Some(feedrate), Some(max_feedrate)) if *feedrate > max_feedrate + EPSILON => {
+                violations.push(format!(
+                    "Line {line}: feedrate {feedrate:.3} mm/min exceeds the maximum of {max_feedrate} mm/min"
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let Some([width, height]) = machine.work_area else {
+        return violations;
+    };
+    let (Some(x), Some(y)) = (to[0], to[1]) else {
+        return violations;
+    };
+    let mut reached = vec![[x, y]];
+    if let Motion::Arc(arc) = motion {
+        reached.extend(arc.extremes());
+    }
+    if let Some([x, y]) = reached.into_iter().find(|[x, y]| {
+        *x < -EPSILON || *y < -EPSILON || *x > width + EPSILON || *y > height + EPSILON
+    }) {
+        violations.push(format!(
+            "Line {line}: reaches ({x:.3}, {y:.3}), outside the {width} × {height} mm work area"
+        ));
+    }
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use svg2gcode::Kinematics;
+
+    use super::*;
+
+    /// Everything wrong with a whole program
+    fn violations(gcode: &str, machine: &MachineConfig) -> Result<Vec<String>, ParseError> {
+        let mut checker = Checker::new(io::sink(), machine);
+        checker.write_all(gcode.as_bytes()).unwrap();
+        checker.end();
+        match checker.error {
+            Some(err) => Err(err),
+            None => Ok(checker.violations),
+        }
+    }
+
+    fn machine() -> MachineConfig {
+        MachineConfig {
+            work_area: Some([100., 50.]),
+            kinematics: Kinematics {
+                max_feedrate: Some(1000.),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn program_within_the_limits_passes() {
+        let gcode = "G21\nG90\nG0 X10 Y10\nG1 X90 Y40 F1000\nG3 X90 Y10 I0 J-15\nM2\n";
+        assert_eq!(violations(gcode, &machine()).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn moves_outside_the_work_area_are_reported() {
+        let gcode = "G21\nG90\nF500\nG1 X10 Y10\nG1 X120 Y10\nG91\nG0 Y-20\n";
+        assert_eq!(
+            violations(gcode, &machine()).unwrap(),
+            [
+                "Line 5: reaches (120.000, 10.000), outside the 100 × 50 mm work area",
+                "Line 7: reaches (120.000, -10.000), outside the 100 × 50 mm work area",
+            ]
+        );
+    }
+
+    #[test]
+    fn arcs_bulging_past_the_work_area_are_reported() {
+        // Both ends are inside, but the top of the circle is not
+        let gcode = "G21\nG90\nF500\nG0 X30 Y40\nG2 X70 Y40 I20 J0\n";
+        assert_eq!(
+            violations(gcode, &machine()).unwrap(),
+            ["Line 5: reaches (50.000, 60.000), outside the 100 × 50 mm work area"]
+        );
+    }
+
+    #[test]
+    fn feedrates_are_checked_in_millimeters() {
+        // 50 in/min is 1270 mm/min
+        let gcode = "G20\nG90\nG0 X1 Y1\nG1 X2 F50\nG21\nG1 X3\n";
+        assert_eq!(
+            violations(gcode, &machine()).unwrap(),
+            [
+                "Line 4: feedrate 1270.000 mm/min exceeds the maximum of 1000 mm/min",
+                "Line 6: feedrate 1270.000 mm/min exceeds the maximum of 1000 mm/min",
+            ]
+        );
+    }
+
+    #[test]
+    fn cutting_without_a_feedrate_is_reported() {
+        let gcode = "G21\nG0 X1 Y1\nG1 X2 Y2\n";
+        assert_eq!(
+            violations(gcode, &MachineConfig::default()).unwrap(),
+            ["Line 3: cuts without a feedrate"]
+        );
+    }
+
+    #[test]
+    fn lines_split_across_writes_are_checked_whole() {
+        let machine = machine();
+        let mut checker = Checker::new(vec![], &machine);
+        for piece in ["G21\nG0 X1", "0 Y10\nG1 X1", "20 F5", "00"] {
+            checker.write_all(piece.as_bytes()).unwrap();
+        }
+        checker.end();
+        assert_eq!(
+            checker.violations,
+            ["Line 3: reaches (120.000, 10.000), outside the 100 × 50 mm work area"]
+        );
+        assert_eq!(checker.inner, b"G21\nG0 X10 Y10\nG1 X120 F500");
+    }
+
+    #[test]
+    fn parse_errors_are_located_in_the_whole_program() {
+        let err = violations("G21\nG0 X1\nG1 X(\n", &machine()).unwrap_err();
+        assert_eq!(err.location.line, 3);
+    }
+}
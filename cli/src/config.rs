@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde_json::Value;
+use svg2gcode::Settings;
+
+use crate::is_stdio;
+
+/// Read settings from a JSON file, or a TOML file when the path ends in `.toml`
+///
+/// Settings left out of the file keep their defaults, so a config file only needs what differs for a machine.
+pub fn load(path: &Path) -> io::Result<Settings> {
+    parse(&fs::read_to_string(path)?, is_toml(path))
+}
+
+/// Read settings from the text of a JSON or TOML settings file, see [load]
+pub fn parse(text: &str, toml: bool) -> io::Result<Settings> {
+    let file: Value = if toml {
+        toml::from_str(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+    } else {
+        serde_json::from_str(text)?
+    };
+    let mut settings = serde_json::to_value(Settings::default())?;
+    merge(&mut settings, file);
+    Ok(serde_json::from_value(settings)?)
+}
+
+/// Write settings as JSON, or as TOML when the path ends in `.toml`
+///
+/// Settings are written to standard out as JSON for `-`.
+pub fn export(settings: &Settings, path: &Path) -> io::Result<()> {
+    let bytes = if is_toml(path) {
+        let mut settings = serde_json::to_value(settings)?;
+        // TOML has no null, so unset settings are left out
+        remove_nulls(&mut settings);
+        toml::to_string_pretty(&settings)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            .into_bytes()
+    } else {
+        serde_json::to_vec_pretty(settings)?
+    };
+    if is_stdio(path) {
+        io::stdout().write_all(&bytes)
+    } else {
+        fs::write(path, bytes)
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// Overwrite what `from` sets in `into`, going into objects so the rest of them is kept
+fn merge(into: &mut Value, from: Value) {
+    match (into, from) {
+        (Value::Object(into), Value::Object(from)) => {
+            for (key, value) in from {
+                match into.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        into.insert(key, value);
+                    }
+                }
+            }
+        }
+        (into, from) => *into = from,
+    }
+}
+
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            object.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(array) => array.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use svg2gcode::Polyline;
+
+use crate::is_stdio;
+
+/// Write the toolpath as CSV, or as JSON when the path ends in `.json`, for controllers that don't take G-Code
+///
+/// Coordinates are in millimeters as they would be cut. In CSV, each row is a point that the tool moves to with the
+/// pen up (the start of a path), down, or to drill a hole. In JSON, each path is a list of points where the tool moves
+/// to the first with the pen up and to the rest with it down. Either is written to standard out for `-`.
+pub fn write(polylines: &[Polyline], path: &Path) -> io::Result<()> {
+    let bytes = if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        serde_json::to_vec_pretty(&json(polylines))?
+    } else {
+        csv(polylines).into_bytes()
+    };
+    if is_stdio(path) {
+        io::stdout().write_all(&bytes)
+    } else {
+        fs::write(path, bytes)
+    }
+}
+
+fn csv(polylines: &[Polyline]) -> String {
+    let mut csv = String::from("path,pen,x,y,id,layer,stroke\n");
+    for (i, polyline) in polylines.iter().enumerate() {
+        for (j, point) in polyline.points.iter().enumerate() {
+            let pen = match (polyline.drill, j) {
+                (true, _) => "drill",
+                (false, 0) => "up",
+                (false, _) => "down",
+            };
+            let source = &polyline.source;
+            csv += &format!(
+                "{},{pen},{},{},{},{},{}\n",
+                i + 1,
+                point.x,
+                point.y,
+                csv_field(source.id.as_deref()),
+                csv_field(source.layer.as_deref()),
+                csv_field(source.stroke.as_deref()),
+            );
+        }
+    }
+    csv
+}
+
+/// Text quoted if it holds a separator, quote or line break, with its quotes doubled
+fn csv_field(text: Option<&str>) -> String {
+    let text = text.unwrap_or_default();
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json(polylines: &[Polyline]) -> serde_json::Value {
+    let paths = polylines
+        .iter()
+        .map(|polyline| {
+            serde_json::json!({
+                "source": polyline.source,
+                "drill": polyline.drill,
+                "points": polyline
+                    .points
+                    .iter()
+                    .map(|point| [point.x, point.y])
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({ "paths": paths })
+}
@@ -0,0 +1,27 @@
+use svg2gcode::ConversionStatistics;
+
+/// Print how long a job takes and how much it cuts, travels and covers, one per line
+pub fn print(statistics: &ConversionStatistics) {
+    let seconds = statistics.estimated_duration.as_secs();
+    println!(
+        "Estimated duration: {}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    println!("Cut length: {:.3} mm", statistics.cut_length);
+    println!("Travel length: {:.3} mm", statistics.travel_length);
+    // The material needed is taken to be the bounding box of the cuts
+    match statistics.bounding_box {
+        Some(bounding_box) => {
+            let size = bounding_box.size();
+            println!(
+                "Material: {:.3} × {:.3} mm ({:.3} cm²)",
+                size.width,
+                size.height,
+                size.area() / 100.
+            );
+        }
+        None => println!("Material: none"),
+    }
+}
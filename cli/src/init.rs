@@ -0,0 +1,268 @@
+use std::io::{self, BufRead, Write};
+
+use log::{error, info};
+use svg2gcode::{ArcFormat, PenServo, ProgramEnd, Settings};
+
+use crate::profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MachineType {
+    Laser,
+    Pen,
+    Spindle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Firmware {
+    Grbl,
+    Marlin,
+    Other,
+}
+
+/// How a pen plotter lifts its pen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PenLift {
+    Servo,
+    ZAxis,
+}
+
+/// Ask about the machine on the terminal and save a profile with the settings it needs
+///
+/// Errors are logged, exiting with an error.
+pub fn run(name: Option<String>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut prompt = Prompt {
+        input: stdin.lock(),
+        output: io::stderr(),
+    };
+    if let Err(err) = try_run(name, &mut prompt) {
+        error!("{err}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn try_run(name: Option<String>, prompt: &mut Prompt<impl BufRead, impl Write>) -> io::Result<()> {
+    let machine_type = prompt.choose(
+        "What kind of machine is it?",
+        &[
+            ("laser", MachineType::Laser),
+            ("pen", MachineType::Pen),
+            ("spindle", MachineType::Spindle),
+        ],
+    )?;
+    let name = match name {
+        Some(name) => name,
+        None => prompt.text(
+            "Name of the profile",
+            match machine_type {
+                MachineType::Laser => "laser",
+                MachineType::Pen => "pen",
+                MachineType::Spindle => "spindle",
+            },
+        )?,
+    };
+    if profile::exists(&name)?
+        && !prompt.confirm(&format!(
+            "There is already a profile named {name}, replace it?"
+        ))?
+    {
+        info!("Kept the profile {name} as it was");
+        return Ok(());
+    }
+
+    let mut settings = Settings::default();
+    settings.machine.work_area = prompt.size("Size of the bed in millimeters, as width,height")?;
+    let firmware = prompt.choose(
+        "Which firmware does the machine run?",
+        &[
+            ("grbl", Firmware::Grbl),
+            ("marlin", Firmware::Marlin),
+            ("other", Firmware::Other),
+        ],
+    )?;
+    let functionality = &mut settings.machine.supported_functionality;
+    match firmware {
+        Firmware::Grbl => functionality.circular_interpolation = true,
+        Firmware::Marlin => {
+            functionality.circular_interpolation = true;
+            functionality.arc_format = ArcFormat::Center;
+        }
+        Firmware::Other => {}
+    }
+    settings.conversion.feedrate = prompt.number(
+        "Feedrate for cutting in mm/min",
+        settings.conversion.feedrate,
+    )?;
+
+    let machine = &mut settings.machine;
+    let conversion = &mut settings.conversion;
+    machine.begin_sequence = Some("G90 G21".to_string());
+    match machine_type {
+        MachineType::Laser => {
+            // GRBL's dynamic mode scales power with speed so corners don't overburn, Marlin's inline mode keeps
+            // power in step with the moves
+            let (tool_on, max_power) = match firmware {
+                Firmware::Grbl => ("M4", 1000.),
+                Firmware::Marlin => ("M3 I", 255.),
+                Firmware::Other => ("M3", 1000.),
+            };
+            let max_power = prompt.number(
+                "Largest power (S) the laser accepts, i.e. GRBL's $30",
+                max_power,
+            )?;
+            conversion.power = Some(prompt.number("Power (S) to cut with", max_power)?);
+            conversion.min_power = prompt.optional_number(
+                "Lowest power (S) at which the laser fires, or blank if it fires at any",
+            )?;
+            machine.tool_on_sequence = Some(tool_on.to_string());
+            machine.tool_off_sequence = Some("M5".to_string());
+            machine.end_sequence = Some("M5".to_string());
+        }
+        MachineType::Pen => {
+            match prompt.choose(
+                "How is the pen lifted?",
+                &[("servo", PenLift::Servo), ("z-axis", PenLift::ZAxis)],
+            )? {
+                PenLift::Servo => {
+                    machine.pen_servo = Some(PenServo {
+                        up: prompt.number("Servo angle with the pen up", 90.)?,
+                        down: prompt.number("Servo angle with the pen down", 30.)?,
+                        dwell: prompt.number("Milliseconds the servo takes to move", 150.)?,
+                        rate: None,
+                    });
+                }
+                PenLift::ZAxis => {
+                    conversion.safe_z = Some(prompt.number("Z with the pen up in mm", 5.)?);
+                    conversion.cut_z = Some(prompt.number("Z with the pen down in mm", 0.)?);
+                }
+            }
+        }
+        MachineType::Spindle => {
+            conversion.power = Some(prompt.number("Spindle speed (S) in RPM", 10000.)?);
+            conversion.safe_z = Some(prompt.number("Z to move above the material in mm", 5.)?);
+            conversion.cut_z = Some(prompt.number("Z to cut at in mm", -1.)?);
+            machine.tool_on_sequence = Some("M3".to_string());
+            machine.tool_off_sequence = Some("M5".to_string());
+            machine.end_sequence = Some("M5".to_string());
+        }
+    }
+    if firmware == Firmware::Grbl {
+        machine.program_end = Some(ProgramEnd::M2);
+    }
+
+    let path = profile::save(&name, &settings)?;
+    info!(
+        "Saved profile {name} to {}, use it with `svg2gcode --profile {name}`",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Questions asked on `output`, with the answers read from `input` a line at a time
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Answer to a question, trimmed, or an error if input ends before one is given
+    fn ask(&mut self, question: &str, hint: &str) -> io::Result<String> {
+        write!(self.output, "{question} [{hint}]: ")?;
+        self.output.flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            writeln!(self.output)?;
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "setup was cancelled, no profile was saved",
+            ));
+        }
+        Ok(answer.trim().to_string())
+    }
+
+    /// One of the options, with the first as the default
+    fn choose<T: Copy>(&mut self, question: &str, options: &[(&str, T)]) -> io::Result<T> {
+        let hint = options
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join("/");
+        loop {
+            let answer = self.ask(question, &hint)?.to_lowercase();
+            if answer.is_empty() {
+                return Ok(options[0].1);
+            }
+            if let Some((_, value)) = options.iter().find(|(name, _)| name.starts_with(&answer)) {
+                return Ok(*value);
+            }
+            writeln!(self.output, "Please answer one of {hint}")?;
+        }
+    }
+
+    fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        self.choose(question, &[("no", false), ("yes", true)])
+    }
+
+    fn text(&mut self, question: &str, default: &str) -> io::Result<String> {
+        let answer = self.ask(question, default)?;
+        Ok(if answer.is_empty() {
+            default.to_string()
+        } else {
+            answer
+        })
+    }
+
+    fn number(&mut self, question: &str, default: f64) -> io::Result<f64> {
+        loop {
+            let answer = self.ask(question, &default.to_string())?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match answer.parse::<f64>() {
+                Ok(number) if number.is_finite() => return Ok(number),
+                _ => writeln!(self.output, "Please answer with a number")?,
+            }
+        }
+    }
+
+    /// A number, or [None] if left blank
+    fn optional_number(&mut self, question: &str) -> io::Result<Option<f64>> {
+        loop {
+            let answer = self.ask(question, "none")?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match answer.parse::<f64>() {
+                Ok(number) if number.is_finite() => return Ok(Some(number)),
+                _ => writeln!(
+                    self.output,
+                    "Please answer with a number, or leave it blank"
+                )?,
+            }
+        }
+    }
+
+    /// Width and height, or [None] if left blank
+    fn size(&mut self, question: &str) -> io::Result<Option<[f64; 2]>> {
+        loop {
+            let answer = self.ask(question, "blank to skip")?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            let numbers = answer
+                .split([',', 'x', 'X'])
+                .map(|number| number.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>();
+            match numbers.as_deref() {
+                Ok(&[width, height]) if width > 0. && height > 0. => {
+                    return Ok(Some([width, height]));
+                }
+                _ => writeln!(
+                    self.output,
+                    "Please answer with a width and height, i.e. 400,300"
+                )?,
+            }
+        }
+    }
+}
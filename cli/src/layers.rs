@@ -0,0 +1,63 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use log::error;
+use roxmltree::{Document, ParsingOptions};
+use svg2gcode::svg2layers;
+
+use crate::{EXIT_PARSE_ERROR, is_stdio};
+
+/// Print the layers and top-level groups of an SVG, read from standard in if there's no file or it is `-`
+///
+/// Each is printed on its own line, indented by how deeply it is nested, i.e.
+/// `cut (#layer1, layer): 2 shapes, colors #ff0000 #0000ff`
+pub fn list(file: Option<&Path>) -> io::Result<()> {
+    let input = match file.filter(|file| !is_stdio(file)) {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let document = match Document::parse_with_options(
+        &input,
+        ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    ) {
+        Ok(document) => document,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_PARSE_ERROR);
+        }
+    };
+
+    for layer in svg2layers(&document) {
+        let name = layer
+            .label
+            .as_deref()
+            .or(layer.id.as_deref())
+            .unwrap_or("?");
+        let kind = if layer.is_layer { "layer" } else { "group" };
+        let details = match (&layer.label, &layer.id) {
+            (Some(_), Some(id)) => format!("#{id}, {kind}"),
+            _ => kind.to_string(),
+        };
+        print!(
+            "{}{name} ({details}): {} shape{}",
+            "  ".repeat(layer.depth),
+            layer.shapes,
+            if layer.shapes == 1 { "" } else { "s" }
+        );
+        if !layer.colors.is_empty() {
+            print!(", colors {}", layer.colors.join(" "));
+        }
+        println!();
+    }
+    Ok(())
+}
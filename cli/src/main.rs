@@ -1,31 +1,180 @@
 use std::{
     env,
-    fs::File,
-    io::{self, Read, Write},
-    path::PathBuf,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
 };
 
-use clap::Parser;
-use g_code::{
-    emit::{FormatOptions, format_gcode_io},
-    parse::snippet_parser,
-};
-use log::{error, info};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use g_code::parse::snippet_parser;
+use log::{error, info, warn};
+use rayon::prelude::*;
 use roxmltree::ParsingOptions;
 use svg2gcode::{
-    ConversionOptions, Machine, Settings, SupportedFunctionality, Version, svg2program,
+    ArcFormat, ChunkLimit, CommentStyle, CommentVerbosity, ConversionError, ConversionOptions,
+    ConversionStatistics, CutterDialect, DrillCycle, Drilling, FeedratePolicy, Flip, Homing, Hooks,
+    Klipper, Machine, MachineConfig, ParkPosition, PathOrder, PenServo, ProgramEnd,
+    ProgressMarkers, RefinementBudget, Settings, Sphere, SplitBy, SupportedFunctionality, Version,
+    format_program_io, polylines2cutter, polylines2dxf, svg2chunks, svg2lightburn, svg2polylines,
+    svg2program, svg2program_io,
 };
 use svgtypes::LengthListParser;
 
+use crate::{camotics::ToolShape, profile::ProfileCommand};
+
+/// Writing projects for simulating material removal in CAMotics
+mod camotics;
+/// Checking programs against the machine's limits
+mod check;
+/// Reading and writing settings files
+mod config;
+/// Writing the toolpath as plain coordinates
+mod coordinates;
+/// Printing the time and material a job needs
+mod estimate;
+/// Setting up a profile for a new machine
+mod init;
+/// Listing the layers of an SVG
+mod layers;
+/// Reading Gerber and Excellon files for milling PCBs
+mod pcb;
+/// Drawing the toolpath to check it before cutting
+mod preview;
+/// Saving and loading settings by name
+mod profile;
+/// Drawing programs as SVGs
+mod render;
+/// Reporting statistics and warnings as JSON
+mod report;
+/// Streaming G-Code to a machine
+mod send;
+/// Following the moves of a program
+mod simulate;
+/// Writing a program for each layer or color
+mod split;
+/// Uploading G-Code to a print server
+mod upload;
+/// Comparing programs with the geometry they were generated from
+mod verify;
+/// Converting again as SVGs change
+mod watch;
+
+/// Exit code when an SVG, Gerber or Excellon file, or G-Code given to render, could not be parsed
+const EXIT_PARSE_ERROR: i32 = 3;
+/// Exit code when an SVG could not be converted
+const EXIT_CONVERSION_ERROR: i32 = 4;
+/// Exit code when warnings were logged with --strict
+const EXIT_WARNINGS: i32 = 5;
+/// Exit code when --check finds the program exceeds the machine's limits, or verify finds it strays from the SVG
+const EXIT_CHECK_FAILED: i32 = 6;
+
 #[derive(Debug, Parser)]
 #[command(name = "svg2gcode", version, author, about)]
 struct Opt {
     /// Curve interpolation tolerance (mm)
     #[arg(long)]
     tolerance: Option<f64>,
+    /// Snap every coordinate to a grid this many millimeters apart (i.e. 0.005), like the resolution of the machine
+    ///
+    /// Z heights are snapped too, i.e. --safe-z, --cut-z and the drill depth.
+    #[arg(long)]
+    grid: Option<f64>,
     /// Machine feed rate (mm/min)
     #[arg(long)]
     feedrate: Option<f64>,
+    /// Which cutting moves carry a feedrate (F) word
+    #[arg(long, value_enum)]
+    feedrate_policy: Option<FeedratePolicyArg>,
+    /// Slow down for sharp corners: feed is divided by 1 + factor × angle / 180°
+    #[arg(long)]
+    corner_slowdown: Option<f64>,
+    /// Height for rapid moves (mm)
+    #[arg(long, allow_hyphen_values = true)]
+    safe_z: Option<f64>,
+    /// Depth to plunge to at the start of each path (mm)
+    #[arg(long, allow_hyphen_values = true)]
+    cut_z: Option<f64>,
+    /// Retract to the safe Z height before every rapid move, not just at the start and end
+    #[arg(long)]
+    retract_between_paths: Option<bool>,
+    /// Keep the tool on between paths that start where the last one ended, and only repeat the power once it changes
+    #[arg(long)]
+    skip_redundant_commands: Option<bool>,
+    /// Order that paths are drawn in
+    #[arg(long, value_enum)]
+    path_order: Option<PathOrderArg>,
+    /// Improve on the path order with up to this many 2-opt/Or-opt candidate moves
+    #[arg(long, conflicts_with = "refine_ms")]
+    refine_iterations: Option<usize>,
+    /// Improve on the path order with 2-opt/Or-opt moves for up to this long (ms)
+    ///
+    /// How far it gets depends on the speed of the computer, so use --refine-iterations to get the same program on
+    /// every run.
+    #[arg(long)]
+    refine_ms: Option<u64>,
+    /// Number of times to cut each path
+    ///
+    /// Feedrate, power, passes and depth can be set per layer or color with `overrides` in a settings file.
+    #[arg(long)]
+    passes: Option<usize>,
+    /// Drill circles up to this diameter (mm) at their center instead of tracing them
+    #[arg(long, requires_all = ["drill_depth", "drill_retract"])]
+    drill_diameter: Option<f64>,
+    /// Depth of drilled holes (mm)
+    #[arg(long, allow_hyphen_values = true)]
+    drill_depth: Option<f64>,
+    /// Height that drilling starts from and returns to (mm)
+    #[arg(long, allow_hyphen_values = true)]
+    drill_retract: Option<f64>,
+    /// How holes are drilled
+    #[arg(long, value_enum, default_value = "simple")]
+    drill_cycle: DrillCycleArg,
+    /// Depth of each peck for --drill-cycle peck (mm)
+    #[arg(long, default_value_t = 1.)]
+    drill_peck: f64,
+    /// Width of the tool that routes around the copper of a Gerber file (mm), i.e. a V-bit at its cutting depth
+    #[arg(long, default_value_t = 0.2)]
+    isolation_width: f64,
+    /// Number of outlines routed around the copper of a Gerber file, each half a tool width further out
+    ///
+    /// More passes leave wider gaps between the copper, for boards that are easier to solder.
+    #[arg(long, default_value_t = 1)]
+    isolation_passes: usize,
+    /// Where to move once the job is done: "behind-job" (X0 and the far edge of the job) or "X,Y" (mm)
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_park)]
+    park: Option<ParkPosition>,
+    /// Trace the job with the tool off to check placement
+    #[arg(long)]
+    dry_run: Option<bool>,
+    /// Print the bounding box, path count, cut/travel lengths and estimated duration of the job instead of its G-Code
+    ///
+    /// Unlike --dry-run, no program is written.
+    #[arg(long, conflicts_with_all = ["out", "out_dir", "watch", "max_lines", "max_bytes"])]
+    stats_only: bool,
+    /// Write the job statistics and any warnings as JSON to this file, for checking jobs in scripts
+    ///
+    /// Use `-` to write to standard out, which needs --out or --stats-only so it isn't mixed with the G-Code.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch", "max_lines", "max_bytes", "split_by_color", "split_by_layer"])]
+    stats_json: Option<PathBuf>,
+    /// Instead of the job, trace the perimeter of its bounding box to check material alignment
+    #[arg(long)]
+    framing: Option<bool>,
+    /// Power while framing, leave unset to keep the tool off
+    #[arg(long)]
+    framing_power: Option<f64>,
+    /// Laser power or spindle speed (S word) for cutting moves
+    #[arg(long)]
+    power: Option<f64>,
+    /// Reduce power where acceleration slows the machine down, i.e. corners
+    ///
+    /// Has no effect without --acceleration, which models how fast the machine goes
+    #[arg(long)]
+    power_ramping: Option<bool>,
+    /// Lowest power at which the tool fires, lower nonzero power is raised to it
+    #[arg(long)]
+    min_power: Option<f64>,
     /// Dots per Inch (DPI)
     /// Used for scaling visual units (pixels, points, picas, etc.)
     #[arg(long)]
@@ -42,15 +191,140 @@ struct Opt {
     /// G-Code for stopping/idling the machine at the end of the program
     #[arg(alias = "end_sequence", long = "end")]
     end_sequence: Option<String>,
-    /// A file path to an SVG, else reads from stdin
-    file: Option<PathBuf>,
+    /// G-Code that terminates the program: "M2", "M30", or any other G-Code
+    #[arg(long)]
+    program_end: Option<String>,
+    /// File paths or glob patterns of SVGs, else reads from stdin
+    ///
+    /// Use `-` to read from standard in. Converting more than one SVG requires --out-dir. A Gerber copper layer
+    /// (.gbr, .gtl, .gbl) is milled around for isolation routing, and the holes of an Excellon drill file (.drl, .xln)
+    /// are drilled with --drill-diameter, at the coordinates in the file unless --origin is given.
+    files: Vec<PathBuf>,
     /// Output file path (overwrites old files), else writes to stdout
+    ///
+    /// Use `-` to write to standard out.
     #[arg(short, long)]
     out: Option<PathBuf>,
-    /// Provide settings from a JSON file. Overrides command-line arguments.
+    /// Directory to write the G-Code for each SVG to, named after the SVG (overwrites old files)
+    ///
+    /// SVGs are converted in parallel with the same settings.
+    #[arg(long, conflicts_with = "out")]
+    out_dir: Option<PathBuf>,
+    /// Simulate the G-Code as it is written, failing if it leaves --work-area or exceeds --max-feedrate
+    ///
+    /// A file is only replaced once the G-Code passes, while standard out gets the G-Code as it is checked.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch", "stats_only", "max_lines", "max_bytes", "split_by_color", "split_by_layer"])]
+    check: bool,
+    /// Exit with an error if any warnings were logged, i.e. for unsupported elements or clamped feedrates
+    ///
+    /// Failures exit with 3 when an SVG, Gerber or Excellon file could not be parsed, 4 when it could not be converted,
+    /// 5 for warnings with --strict, 6 when --check or verify fails and 1 otherwise.
     #[arg(long)]
+    strict: bool,
+    /// Convert again whenever an SVG changes, until stopped with Ctrl+C
+    ///
+    /// Requires --out or --out-dir.
+    #[arg(long, conflicts_with_all = ["max_lines", "max_bytes", "split_by_color", "split_by_layer"])]
+    watch: bool,
+    /// Also write a preview of the toolpath to this SVG file, with travel moves and the direction of each cut
+    ///
+    /// Writes a PNG instead if the path ends in `.png`, when built with the raster feature.
+    #[arg(long, conflicts_with = "out_dir")]
+    preview: Option<PathBuf>,
+    /// Also plot the toolpath in the terminal, i.e. to check its orientation and size over SSH
+    #[arg(long, conflicts_with_all = ["out_dir", "watch"])]
+    preview_term: bool,
+    /// Also write the toolpath to this DXF file, as a polyline for each path on the layer it came from
+    ///
+    /// Curves are flattened into lines and the paths are transformed and ordered as they are cut, for checking or
+    /// reworking them in CAD and CAM programs.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch"])]
+    dxf: Option<PathBuf>,
+    /// Also write the coordinates of the toolpath to this CSV file, or JSON file if the path ends in `.json`
+    ///
+    /// Points are in millimeters as they would be cut, with whether the pen is up or down on the way to them and the
+    /// id, layer and stroke of the element they came from, for robots and controllers that don't take G-Code.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch"])]
+    coordinates: Option<PathBuf>,
+    /// Also write the toolpath to this file in the command set of a vinyl cutter that doesn't take G-Code
+    ///
+    /// The file can be sent to the cutter as is, i.e. by copying it to its port.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch"])]
+    cutter: Option<PathBuf>,
+    /// Command set of the vinyl cutter for --cutter
+    #[arg(long, value_enum, default_value = "gp-gl", requires = "cutter")]
+    cutter_dialect: CutterDialectArg,
+    /// Also write the toolpath to this LightBurn project (.lbrn2), with a layer for each override or Inkscape layer
+    ///
+    /// Each layer gets the feedrate, power and passes its paths would be cut with, for finishing the job in LightBurn.
+    #[arg(long, conflicts_with_all = ["out_dir", "watch"])]
+    lightburn: Option<PathBuf>,
+    /// Power (S) at which LightBurn's power is 100%, i.e. GRBL's $30
+    #[arg(long, default_value_t = 1000., requires = "lightburn")]
+    lightburn_max_power: f64,
+    /// Also write a CAMotics project to this file, for simulating how the G-Code written to --out removes material
+    #[arg(long, requires = "out", conflicts_with_all = ["watch", "max_lines", "max_bytes", "split_by_color", "split_by_layer"])]
+    camotics: Option<PathBuf>,
+    /// Shape of the tool in the CAMotics project
+    #[arg(long, value_enum, default_value = "cylindrical", requires = "camotics")]
+    tool_shape: ToolShape,
+    /// Diameter of the tool in the CAMotics project (mm)
+    #[arg(long, default_value_t = 3.175, requires = "camotics")]
+    tool_diameter: f64,
+    /// Upload the G-Code to OctoPrint or Moonraker once it is written to --out
+    ///
+    /// The server is read from `upload.toml` in the user's config directory (i.e. `~/.config/svg2gcode` on Linux), with
+    /// `server` set to "octoprint" or "moonraker", its `host` URL and an `api_key` if it needs one. Requires --out to be a
+    /// file path.
+    #[arg(long, requires = "out", conflicts_with_all = ["watch", "max_lines", "max_bytes", "split_by_color", "split_by_layer"])]
+    upload: bool,
+    /// Only convert the layers or top-level groups with these Inkscape labels or ids, i.e. cut,score
+    ///
+    /// See `svg2gcode list-layers` for the names in an SVG.
+    #[arg(long, value_delimiter = ',')]
+    layers: Vec<String>,
+    /// Leave out the layers or top-level groups with these Inkscape labels or ids
+    #[arg(long, value_delimiter = ',')]
+    exclude_layers: Vec<String>,
+    /// Only convert the elements with these ids and everything in them, i.e. to cut one part of a larger drawing
+    #[arg(long, value_delimiter = ',')]
+    select_ids: Vec<String>,
+    /// Leave out the elements with these ids and everything in them, i.e. registration marks or construction lines
+    #[arg(long, value_delimiter = ',')]
+    exclude_ids: Vec<String>,
+    /// Skip the paths before this one (counting from zero) to restart an interrupted job
+    ///
+    /// The path index can be found in the last checkpoint comment the machine ran, see --checkpoint-interval
+    #[arg(long)]
+    resume_from: Option<usize>,
+    /// Split the output into files of at most this many lines, numbered after the output file
+    #[arg(long, requires = "out", conflicts_with = "max_bytes")]
+    max_lines: Option<usize>,
+    /// Split the output into files of at most this many bytes, numbered after the output file
+    #[arg(long, requires = "out")]
+    max_bytes: Option<usize>,
+    /// Write a program for each stroke color, named after the output file and the color, i.e. job-ff0000.gcode
+    ///
+    /// A manifest of what each file contains is written alongside them, i.e. job-manifest.json.
+    #[arg(long, requires = "out", conflicts_with_all = ["split_by_layer", "max_lines", "max_bytes"])]
+    split_by_color: bool,
+    /// Write a program for each Inkscape layer, named after the output file and the layer, i.e. job-cut.gcode
+    ///
+    /// A manifest of what each file contains is written alongside them, i.e. job-manifest.json.
+    #[arg(long, requires = "out", conflicts_with_all = ["max_lines", "max_bytes"])]
+    split_by_layer: bool,
+    /// Provide settings from a JSON or TOML file, which command-line arguments override
+    ///
+    /// Settings left out of the file keep their defaults.
+    #[arg(long, alias = "config")]
     settings: Option<PathBuf>,
-    /// Export current settings to a JSON file instead of converting.
+    /// Use the settings of a saved profile, which command-line arguments override
+    ///
+    /// Built-in profiles for common machines are also available, i.e. `axidraw` and `klipper`.
+    /// See `svg2gcode profile --help` for managing profiles.
+    #[arg(long, conflicts_with = "settings")]
+    profile: Option<String>,
+    /// Export current settings to a JSON file, or TOML if the path ends in `.toml`, instead of converting.
     ///
     /// Use `-` to export to standard out.
     #[arg(long)]
@@ -58,6 +332,28 @@ struct Opt {
     /// Coordinates for the bottom left corner of the machine
     #[arg(long, allow_hyphen_values = true)]
     origin: Option<String>,
+    /// Mirror the drawing before it is placed at the origin
+    #[arg(long, value_enum)]
+    flip: Option<FlipArg>,
+    /// Rotate the drawing counterclockwise by this many degrees before it is placed at the origin
+    #[arg(long, allow_hyphen_values = true)]
+    rotate: Option<f64>,
+    /// Scale the drawing by this factor before it is placed at the origin
+    #[arg(long)]
+    scale: Option<f64>,
+    /// Scale the drawing to fit within this width and height in millimeters (i.e. 300,200), keeping its aspect ratio
+    #[arg(long, value_parser = parse_size)]
+    fit_to: Option<[f64; 2]>,
+    /// Draw on an egg or sphere of this diameter (mm), i.e. with an EggBot or Sphere-O-Bot
+    ///
+    /// X and Y become the rotation of the egg and the angle of the pen arm in degrees, with Y = 0 at the equator, so
+    /// place the drawing with --origin (i.e. 0,-20 for a drawing 40 mm tall) and set the machine's steps per degree.
+    #[arg(long)]
+    sphere_diameter: Option<f64>,
+    /// Turn the egg further away from the equator, where its surface is narrower, so that the drawing keeps its
+    /// proportions
+    #[arg(long, requires = "sphere_diameter")]
+    compensate_latitude: bool,
     /// Override the width and height of the SVG (i.e. 210mm,297mm)
     ///
     /// Useful when the SVG does not specify these (see https://github.com/sameer/svg2gcode/pull/16)
@@ -65,11 +361,48 @@ struct Opt {
     /// Passing "210mm," or ",297mm" calculates the missing dimension to conform to the viewBox aspect ratio.
     #[arg(long)]
     dimensions: Option<String>,
+    /// Machine acceleration (mm/s²), used to estimate job duration
+    #[arg(long)]
+    acceleration: Option<f64>,
+    /// Machine junction deviation (mm), used to estimate cornering speed
+    #[arg(long)]
+    junction_deviation: Option<f64>,
+    /// Feed rate of rapid moves (mm/min), used to estimate job duration
+    #[arg(long)]
+    rapid_feedrate: Option<f64>,
+    /// Highest feed rate the machine supports (mm/min), faster feeds are clamped to it
+    #[arg(long)]
+    max_feedrate: Option<f64>,
+    /// Width and height in millimeters that the machine can reach from its origin (i.e. 300,200), for --check
+    #[arg(long, value_parser = parse_size)]
+    work_area: Option<[f64; 2]>,
+    /// Servo angle for raising the pen on plotters where `M3 S<angle>` moves a servo
+    ///
+    /// Replaces turning the tool on and off when given with --pen-down
+    #[arg(long)]
+    pen_up: Option<f64>,
+    /// Servo angle for lowering the pen
+    #[arg(long)]
+    pen_down: Option<f64>,
+    /// Time to wait for the pen servo to move (ms)
+    #[arg(long)]
+    pen_dwell: Option<f64>,
+    /// Speed of the pen servo (degrees/s), to lower and raise the pen gently in small steps
+    #[arg(long)]
+    pen_rate: Option<f64>,
     /// Whether to use circular arcs when generating g-code
     ///
     /// Please check if your machine supports G2/G3 commands before enabling this.
     #[arg(long)]
     circular_interpolation: Option<bool>,
+    /// How G2/G3 arcs are encoded, for controllers that only accept one format
+    #[arg(long, value_enum)]
+    arc_format: Option<ArcFormatArg>,
+    /// Arc tolerance of the firmware (mm), i.e. GRBL's `$12`
+    ///
+    /// Arcs are fitted to this tolerance, and shallower ones are cut as lines.
+    #[arg(long)]
+    arc_tolerance: Option<f64>,
 
     #[arg(long)]
     /// Include line numbers at the beginning of each line
@@ -77,20 +410,301 @@ struct Opt {
     /// Useful for debugging/streaming g-code
     line_numbers: Option<bool>,
     #[arg(long)]
+    /// Number of the first line when line numbers are enabled
+    line_number_start: Option<usize>,
+    #[arg(long)]
+    /// Amount the line number increases by on each line
+    line_number_increment: Option<usize>,
+    #[arg(long)]
     /// Include checksums at the end of each line
     ///
     /// Useful for streaming g-code
     checksums: Option<bool>,
     #[arg(long)]
+    /// Number and checksum every line for Marlin, stripping comments
+    ///
+    /// Useful for streaming g-code directly to Marlin over serial
+    marlin_checksums: Option<bool>,
+    /// Home the machine before the job
+    #[arg(long, value_enum)]
+    homing: Option<HomingArg>,
+    /// Rewrite the program for a laser conversion running Klipper, which turns the laser on and off with the
+    /// LASER_ON POWER=<S> and LASER_OFF macros
+    ///
+    /// --acceleration is set with SET_VELOCITY_LIMIT and --rapid-feedrate is given on each G0, since Klipper moves at
+    /// the last F word for those. The built-in `klipper` profile sets this up along with other settings Klipper needs.
+    #[arg(long)]
+    klipper: bool,
+    #[arg(long)]
     /// Add a newline character before each comment
     ///
     /// Workaround for parsers that don't accept comments on the same line
     newline_before_comment: Option<bool>,
+    /// Syntax used for comments
+    #[arg(long, value_enum)]
+    comment_style: Option<CommentStyleArg>,
+    /// How much of the SVG structure is annotated with comments
+    #[arg(long, value_enum)]
+    comment_verbosity: Option<CommentVerbosityArg>,
+    #[arg(long)]
+    /// Start the program with a comment block describing the job
+    ///
+    /// Includes the source filename, settings, bounding box, cut/travel lengths and an estimated duration
+    metadata_header: Option<bool>,
+    /// Periodically report job progress, i.e. for a progress bar on the machine's display
+    #[arg(long, value_enum)]
+    progress_markers: Option<ProgressMarkersArg>,
+    /// Minimum advance in percent between progress markers
+    #[arg(long)]
+    progress_interval: Option<usize>,
+    /// Comment the path index and machine state every this many paths, for use with --resume-from
+    #[arg(long)]
+    checkpoint_interval: Option<usize>,
+    /// Emit shapes repeated with <use> once as an M98/M99 subprogram (LinuxCNC, Mach3, Fanuc)
+    #[arg(long)]
+    subprograms: Option<bool>,
     #[arg(long)]
     /// When printing a node name , print a extra attribute
     ///
     /// Useful to print the label of layer on SVG generated by Inkscape
     extra_attribute_name: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage named settings profiles stored in the user's config directory
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Set up a profile for a machine by answering questions about it, i.e. its bed size, firmware and tool
+    ///
+    /// Use it afterwards with `svg2gcode --profile <NAME>`.
+    Init {
+        /// Name to save the profile as, else asked for
+        name: Option<String>,
+    },
+    /// List the layers and top-level groups of an SVG with their shape counts and stroke colors
+    ListLayers {
+        /// SVG file path, else reads from stdin
+        file: Option<PathBuf>,
+    },
+    /// Print a script that completes arguments in the given shell
+    Completions { shell: Shell },
+    /// Print the estimated duration, cut and travel lengths and material size of an SVG's job, without writing it
+    ///
+    /// Settings are given by the other arguments, i.e. `svg2gcode --profile laser estimate drawing.svg`.
+    Estimate {
+        /// SVG file path, else reads from stdin
+        file: Option<PathBuf>,
+    },
+    /// Draw a G-Code file as an SVG of its cuts, travel moves and power, i.e. to inspect programs from other tools
+    ///
+    /// The SVG is written to --out, else to standard out.
+    Render {
+        /// G-Code file path, else reads from stdin
+        file: Option<PathBuf>,
+    },
+    /// Stream the G-Code for an SVG to a machine running GRBL over a serial port, instead of writing it
+    ///
+    /// Ctrl+C stops the machine with a feed hold and discards the rest of the job.
+    Send {
+        /// Serial port of the machine, i.e. /dev/ttyUSB0 or COM3, or the ws:// URL of a WebSocket controller like FluidNC
+        port: String,
+        /// Baud rate of the serial port
+        #[arg(long, default_value_t = 115200)]
+        baud: u32,
+    },
+    /// Convert an SVG, then parse the G-Code again and check that it cuts the planned paths, without writing it
+    ///
+    /// Fails if the G-Code strays further than --max-deviation from the paths or misses part of them. Subprograms and
+    /// framing can't be followed, so they must be turned off.
+    Verify {
+        /// SVG file path, else reads from stdin
+        file: Option<PathBuf>,
+        /// How far the G-Code may stray from the paths (mm), defaults to twice the larger of --tolerance and
+        /// --arc-tolerance
+        #[arg(long)]
+        max_deviation: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum FeedratePolicyArg {
+    /// On every cutting move
+    EveryMove,
+    /// On the first cutting move of each path
+    PerPath,
+    /// Only when the feedrate changes
+    OnChange,
+}
+
+impl From<FeedratePolicyArg> for FeedratePolicy {
+    fn from(arg: FeedratePolicyArg) -> Self {
+        match arg {
+            FeedratePolicyArg::EveryMove => Self::EveryMove,
+            FeedratePolicyArg::PerPath => Self::PerPath,
+            FeedratePolicyArg::OnChange => Self::OnChange,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CommentStyleArg {
+    /// `;comment`
+    Semicolon,
+    /// `(comment)`
+    Parentheses,
+}
+
+impl From<CommentStyleArg> for CommentStyle {
+    fn from(arg: CommentStyleArg) -> Self {
+        match arg {
+            CommentStyleArg::Semicolon => Self::Semicolon,
+            CommentStyleArg::Parentheses => Self::Parentheses,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CommentVerbosityArg {
+    /// No comments
+    None,
+    /// A comment at the start of each Inkscape layer
+    PerLayer,
+    /// A comment for each drawn element
+    PerElement,
+    /// A comment for each element and path segment
+    PerSegment,
+}
+
+impl From<CommentVerbosityArg> for CommentVerbosity {
+    fn from(arg: CommentVerbosityArg) -> Self {
+        match arg {
+            CommentVerbosityArg::None => Self::None,
+            CommentVerbosityArg::PerLayer => Self::PerLayer,
+            CommentVerbosityArg::PerElement => Self::PerElement,
+            CommentVerbosityArg::PerSegment => Self::PerSegment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ProgressMarkersArg {
+    /// No progress markers
+    None,
+    /// `M73 P<percent>`
+    M73,
+    /// A `Progress: <percent>%` comment
+    Comment,
+}
+
+impl From<ProgressMarkersArg> for ProgressMarkers {
+    fn from(arg: ProgressMarkersArg) -> Self {
+        match arg {
+            ProgressMarkersArg::None => Self::None,
+            ProgressMarkersArg::M73 => Self::M73,
+            ProgressMarkersArg::Comment => Self::Comment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ArcFormatArg {
+    /// `R` word, splitting arcs over 180°
+    Radius,
+    /// `I` and `J` center offsets
+    Center,
+}
+
+impl From<ArcFormatArg> for ArcFormat {
+    fn from(arg: ArcFormatArg) -> Self {
+        match arg {
+            ArcFormatArg::Radius => Self::Radius,
+            ArcFormatArg::Center => Self::Center,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PathOrderArg {
+    /// As they appear in the SVG
+    Document,
+    /// Closest path first, to shorten travel
+    NearestNeighbor,
+}
+
+impl From<PathOrderArg> for PathOrder {
+    fn from(arg: PathOrderArg) -> Self {
+        match arg {
+            PathOrderArg::Document => Self::Document,
+            PathOrderArg::NearestNeighbor => Self::NearestNeighbor,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum FlipArg {
+    /// Left to right
+    Horizontal,
+    /// Top to bottom
+    Vertical,
+    /// Both ways
+    Both,
+}
+
+impl From<FlipArg> for Flip {
+    fn from(arg: FlipArg) -> Self {
+        match arg {
+            FlipArg::Horizontal => Self::Horizontal,
+            FlipArg::Vertical => Self::Vertical,
+            FlipArg::Both => Self::Both,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DrillCycleArg {
+    /// `G81` canned cycle
+    Simple,
+    /// `G83` peck drilling canned cycle
+    Peck,
+    /// Plain `G1`/`G0` moves, for controllers without canned cycles
+    Plunge,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum HomingArg {
+    /// GRBL's `$H`
+    Grbl,
+    /// `G28`
+    G28,
+}
+
+impl From<HomingArg> for Homing {
+    fn from(arg: HomingArg) -> Self {
+        match arg {
+            HomingArg::Grbl => Self::Grbl,
+            HomingArg::G28 => Self::G28,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CutterDialectArg {
+    /// Graphtec GP-GL, with the cutter's step size set to 0.1 mm
+    GpGl,
+    /// Houston Instrument DM/PL, i.e. for Summa cutters
+    DmPl,
+}
+
+impl From<CutterDialectArg> for CutterDialect {
+    fn from(arg: CutterDialectArg) -> Self {
+        match arg {
+            CutterDialectArg::GpGl => Self::GpGl,
+            CutterDialectArg::DmPl => Self::DmPl,
+        }
+    }
 }
 
 fn main() -> io::Result<()> {
@@ -98,189 +712,651 @@ fn main() -> io::Result<()> {
         // SAFETY: calling in a single-threaded context
         unsafe { env::set_var("RUST_LOG", "svg2gcode=info") }
     }
-    env_logger::init();
+    report::init_logger();
 
     let opt = Opt::parse();
+    let strict = opt.strict;
+    run(opt)?;
 
-    let settings = {
-        let mut settings = if let Some(path) = opt.settings {
-            serde_json::from_reader(File::open(path)?)?
-        } else {
-            Settings::default()
-        };
+    let warnings = report::warning_count();
+    if strict && warnings > 0 {
+        error!(
+            "{warnings} warning{} logged, failing because of --strict",
+            if warnings == 1 { " was" } else { "s were" }
+        );
+        std::process::exit(EXIT_WARNINGS);
+    }
+    Ok(())
+}
 
-        {
-            let conversion = &mut settings.conversion;
-            conversion.dpi = opt.dpi.unwrap_or(conversion.dpi);
-            conversion.feedrate = opt.feedrate.unwrap_or(conversion.feedrate);
-            conversion.tolerance = opt.tolerance.unwrap_or(conversion.tolerance);
+/// Carry out the command line, exiting with an error code as soon as something fails
+fn run(opt: Opt) -> io::Result<()> {
+    // Refused rather than ignored, since they only apply to G-Code that is written or sent
+    if opt.upload && opt.out.as_deref().is_some_and(is_stdio) {
+        error!("--upload needs --out to be a file path");
+        std::process::exit(1);
+    }
+    if opt.upload && opt.command.is_some() {
+        error!("--upload only uploads converted G-Code and can't be used with a command");
+        std::process::exit(1);
+    }
+    if opt.check
+        && opt
+            .command
+            .as_ref()
+            .is_some_and(|command| !matches!(command, Command::Send { .. }))
+    {
+        error!(
+            "--check only checks G-Code that is written or sent and can't be used with this command"
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(Command::Init { name }) = opt.command {
+        return init::run(name);
+    }
+
+    if let Some(Command::ListLayers { file }) = &opt.command {
+        return layers::list(file.as_deref());
+    }
+
+    if let Some(Command::Render { file }) = &opt.command {
+        return render::write(file.as_deref(), opt.out.as_deref());
+    }
+
+    if let Some(Command::Completions { shell }) = opt.command {
+        clap_complete::generate(shell, &mut Opt::command(), "svg2gcode", &mut io::stdout());
+        return Ok(());
+    }
+
+    if let Some(Command::Profile(command @ (ProfileCommand::List | ProfileCommand::Show { .. }))) =
+        opt.command
+    {
+        return profile::run(command, &Settings::default());
+    }
+
+    let settings = resolve_settings(&opt)?;
+
+    if let Some(Command::Profile(command)) = opt.command {
+        return profile::run(command, &settings);
+    }
+
+    if let Some(export_path) = opt.export {
+        return config::export(&settings, &export_path);
+    }
+
+    let options = conversion_options(&opt);
+    if let Err(err) = settings
+        .conversion
+        .validate()
+        .and_then(|()| settings.machine.validate())
+        .and_then(|()| options.validate())
+    {
+        error!("{err}");
+        std::process::exit(1);
+    }
+
+    let files = input_files(&opt);
+    let machine = machine(&settings.machine);
+
+    if opt.watch {
+        let jobs = files
+            .iter()
+            .map(|file| {
+                let out_path = match (&opt.out_dir, &opt.out) {
+                    (Some(out_dir), _) => out_path(out_dir, file),
+                    (None, out) => out.clone().expect("checked above"),
+                };
+                (file.clone(), out_path)
+            })
+            .collect::<Vec<_>>();
+        if let Some(out_dir) = &opt.out_dir {
+            fs::create_dir_all(out_dir)?;
         }
-        {
-            let machine = &mut settings.machine;
-            machine.supported_functionality = SupportedFunctionality {
-                circular_interpolation: opt
-                    .circular_interpolation
-                    .unwrap_or(machine.supported_functionality.circular_interpolation),
-            };
-            if let seq @ Some(_) = opt.tool_on_sequence {
-                machine.tool_on_sequence = seq;
-            }
-            if let seq @ Some(_) = opt.tool_off_sequence {
-                machine.tool_off_sequence = seq;
-            }
-            if let seq @ Some(_) = opt.begin_sequence {
-                machine.begin_sequence = seq;
-            }
-            if let seq @ Some(_) = opt.end_sequence {
-                machine.end_sequence = seq;
-            }
+        return watch::watch(&jobs, opt.preview.as_deref(), &settings, &options);
+    }
+
+    if let Some(out_dir) = opt.out_dir {
+        return convert_batch(&files, &out_dir, &settings, &options);
+    }
+
+    let options = ConversionOptions {
+        source_name: files
+            .first()
+            .filter(|file| !is_stdio(file))
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned()),
+        ..options
+    };
+    let input = read_input(&opt, files.first(), &settings)?;
+
+    let document = match roxmltree::Document::parse_with_options(
+        &input,
+        ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    ) {
+        Ok(document) => document,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_PARSE_ERROR);
         }
-        {
-            if let Some(origin) = opt.origin {
-                for (i, dimension_origin) in origin
-                    .split(',')
-                    .map(|point| {
-                        if point.is_empty() {
-                            Default::default()
-                        } else {
-                            point.parse::<f64>().expect("could not parse coordinate")
-                        }
-                    })
-                    .take(2)
-                    .enumerate()
-                {
-                    settings.conversion.origin[i] = Some(dimension_origin);
-                }
+    };
+
+    write_toolpaths(&opt, &document, &settings, &options)?;
+
+    if opt.stats_only {
+        match svg2program(&document, &settings.conversion, options, machine) {
+            Ok(program) => match &opt.stats_json {
+                Some(stats_json) => report::write(&program.statistics, stats_json)?,
+                None => println!("{}", program.statistics),
+            },
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
             }
         }
+        return Ok(());
+    }
 
-        if let Some(line_numbers) = opt.line_numbers {
-            settings.postprocess.line_numbers = line_numbers;
+    if let Some(Command::Estimate { .. }) = &opt.command {
+        match svg2program(&document, &settings.conversion, options, machine) {
+            Ok(program) => estimate::print(&program.statistics),
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
+            }
         }
+        return Ok(());
+    }
 
-        if let Some(checksums) = opt.checksums {
-            settings.postprocess.checksums = checksums;
-        }
+    if let Some(Command::Verify { max_deviation, .. }) = &opt.command {
+        verify::verify(&document, &settings, options, machine, *max_deviation);
+        return Ok(());
+    }
 
-        if let Some(newline_before_comment) = opt.newline_before_comment {
-            settings.postprocess.newline_before_comment = newline_before_comment;
+    if let Some(Command::Send { port, baud }) = &opt.command {
+        // Held in memory, since the number of lines is shown while they are sent
+        let mut gcode = vec![];
+        let (statistics, passed) =
+            match convert(&opt, &document, &settings, options, machine, &mut gcode) {
+                Ok(converted) => converted,
+                Err(ConversionError::Io(err)) => return Err(err),
+                Err(err) => {
+                    error!("{err}");
+                    std::process::exit(EXIT_CONVERSION_ERROR);
+                }
+            };
+        if let Some(stats_json) = &opt.stats_json {
+            report::write(&statistics, stats_json)?;
         }
+        if !passed {
+            std::process::exit(EXIT_CHECK_FAILED);
+        }
+        let gcode = String::from_utf8(gcode)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Err(err) = send::send(port, *baud, &gcode) {
+            error!("{err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-        settings.conversion.extra_attribute_name = opt.extra_attribute_name;
-
-        if let Version::Unknown(ref unknown) = settings.version {
-            error!(
-                "Your settings use an unknown version. Your version: {unknown}, latest: {}. See {} to download the latest CLI version.",
-                Version::latest(),
-                env!("CARGO_PKG_REPOSITORY"),
-            );
+    let split_by = if opt.split_by_color {
+        Some(SplitBy::Color)
+    } else if opt.split_by_layer {
+        Some(SplitBy::Layer)
+    } else {
+        None
+    };
+    if let (Some(split_by), Some(out_path)) = (split_by, &opt.out) {
+        if is_stdio(out_path) {
+            error!("Split output can't be written to standard out, please give a file path");
             std::process::exit(1);
         }
+        return split::write(&document, &settings, options, machine, split_by, out_path);
+    }
 
-        let old_version = settings.version.clone();
-        if let Err(msg) = settings.try_upgrade() {
-            error!(
-                "Your settings are out of date and require manual intervention: {msg}. Your version: {old_version}, latest: {}. See {} for instructions.",
-                Version::latest(),
-                env!("CARGO_PKG_REPOSITORY"),
-            );
+    let limit = opt
+        .max_lines
+        .map(ChunkLimit::Lines)
+        .or(opt.max_bytes.map(ChunkLimit::Bytes));
+    if let (Some(limit), Some(out_path)) = (limit, &opt.out) {
+        if is_stdio(out_path) {
+            error!("Split output can't be written to standard out, please give a file path");
             std::process::exit(1);
         }
+        return write_chunks(&document, &settings, options, machine, limit, out_path);
+    }
 
-        settings
+    write_program(&opt, &document, &settings, options, machine)
+}
+
+/// Settings from --settings or --profile, else the defaults, with the arguments given on the command line applied over
+/// them
+fn resolve_settings(opt: &Opt) -> io::Result<Settings> {
+    let mut settings = if let Some(path) = &opt.settings {
+        config::load(path)?
+    } else if let Some(name) = &opt.profile {
+        match profile::load(name) {
+            Ok(settings) => settings,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Settings::default()
     };
 
-    if let Some(export_path) = opt.export {
-        let config_json_bytes = serde_json::to_vec_pretty(&settings)?;
-        if export_path.to_string_lossy() == "-" {
-            return io::stdout().write_all(&config_json_bytes);
-        } else {
-            return File::create(export_path)?.write_all(&config_json_bytes);
+    {
+        let conversion = &mut settings.conversion;
+        conversion.dpi = opt.dpi.unwrap_or(conversion.dpi);
+        conversion.feedrate = opt.feedrate.unwrap_or(conversion.feedrate);
+        conversion.tolerance = opt.tolerance.unwrap_or(conversion.tolerance);
+        if let grid @ Some(_) = opt.grid {
+            conversion.grid = grid;
+        }
+        conversion.corner_slowdown = opt.corner_slowdown.unwrap_or(conversion.corner_slowdown);
+        if let safe_z @ Some(_) = opt.safe_z {
+            conversion.safe_z = safe_z;
+        }
+        if let cut_z @ Some(_) = opt.cut_z {
+            conversion.cut_z = cut_z;
+        }
+        conversion.retract_between_paths = opt
+            .retract_between_paths
+            .unwrap_or(conversion.retract_between_paths);
+        conversion.skip_redundant_commands = opt
+            .skip_redundant_commands
+            .unwrap_or(conversion.skip_redundant_commands);
+        if let (Some(max_diameter), Some(depth), Some(retract)) =
+            (opt.drill_diameter, opt.drill_depth, opt.drill_retract)
+        {
+            conversion.drilling = Some(Drilling {
+                max_diameter,
+                depth,
+                retract,
+                cycle: match opt.drill_cycle {
+                    DrillCycleArg::Simple => DrillCycle::Simple,
+                    DrillCycleArg::Peck => DrillCycle::Peck {
+                        peck: opt.drill_peck,
+                    },
+                    DrillCycleArg::Plunge => DrillCycle::Plunge,
+                },
+            });
+        }
+        conversion.dry_run = opt.dry_run.unwrap_or(conversion.dry_run);
+        conversion.framing = opt.framing.unwrap_or(conversion.framing);
+        if let framing_power @ Some(_) = opt.framing_power {
+            conversion.framing_power = framing_power;
+        }
+        if let power @ Some(_) = opt.power {
+            conversion.power = power;
+        }
+        conversion.power_ramping = opt.power_ramping.unwrap_or(conversion.power_ramping);
+        if let min_power @ Some(_) = opt.min_power {
+            conversion.min_power = min_power;
+        }
+        if let Some(feedrate_policy) = opt.feedrate_policy {
+            conversion.feedrate_policy = feedrate_policy.into();
+        }
+        if let Some(comment_style) = opt.comment_style {
+            conversion.comment_style = comment_style.into();
+        }
+        if let Some(comment_verbosity) = opt.comment_verbosity {
+            conversion.comment_verbosity = comment_verbosity.into();
+        }
+        conversion.metadata_header = opt.metadata_header.unwrap_or(conversion.metadata_header);
+        if let Some(progress_markers) = opt.progress_markers {
+            conversion.progress_markers = progress_markers.into();
+        }
+        conversion.progress_interval = opt
+            .progress_interval
+            .unwrap_or(conversion.progress_interval);
+        if let checkpoint_interval @ Some(_) = opt.checkpoint_interval {
+            conversion.checkpoint_interval = checkpoint_interval;
+        }
+        conversion.subprograms = opt.subprograms.unwrap_or(conversion.subprograms);
+        conversion.passes = opt.passes.unwrap_or(conversion.passes);
+        if let Some(path_order) = opt.path_order {
+            conversion.path_order = path_order.into();
+        }
+        if let Some(iterations) = opt.refine_iterations {
+            conversion.path_refinement = Some(RefinementBudget::Iterations(iterations));
+        } else if let Some(milliseconds) = opt.refine_ms {
+            conversion.path_refinement = Some(RefinementBudget::Milliseconds(milliseconds));
         }
     }
-
-    let options = {
-        let mut dimensions = [None, None];
-
-        if let Some(dimensions_str) = opt.dimensions {
-            dimensions_str
+    {
+        let machine = &mut settings.machine;
+        machine.supported_functionality = SupportedFunctionality {
+            circular_interpolation: opt
+                .circular_interpolation
+                .unwrap_or(machine.supported_functionality.circular_interpolation),
+            arc_format: opt
+                .arc_format
+                .map(ArcFormat::from)
+                .unwrap_or(machine.supported_functionality.arc_format),
+            arc_tolerance: opt
+                .arc_tolerance
+                .or(machine.supported_functionality.arc_tolerance),
+        };
+        if let acceleration @ Some(_) = opt.acceleration {
+            machine.kinematics.acceleration = acceleration;
+        }
+        machine.kinematics.junction_deviation = opt
+            .junction_deviation
+            .unwrap_or(machine.kinematics.junction_deviation);
+        if let rapid_feedrate @ Some(_) = opt.rapid_feedrate {
+            machine.kinematics.rapid_feedrate = rapid_feedrate;
+        }
+        if let max_feedrate @ Some(_) = opt.max_feedrate {
+            machine.kinematics.max_feedrate = max_feedrate;
+        }
+        if let work_area @ Some(_) = opt.work_area {
+            machine.work_area = work_area;
+        }
+        if let (Some(up), Some(down)) = (opt.pen_up, opt.pen_down) {
+            machine.pen_servo = Some(PenServo {
+                up,
+                down,
+                dwell: opt.pen_dwell.unwrap_or_default(),
+                rate: opt.pen_rate,
+            });
+        } else if let Some(pen_servo) = &mut machine.pen_servo {
+            if let Some(dwell) = opt.pen_dwell {
+                pen_servo.dwell = dwell;
+            }
+            if let rate @ Some(_) = opt.pen_rate {
+                pen_servo.rate = rate;
+            }
+        }
+        if let seq @ Some(_) = opt.tool_on_sequence.clone() {
+            machine.tool_on_sequence = seq;
+        }
+        if let seq @ Some(_) = opt.tool_off_sequence.clone() {
+            machine.tool_off_sequence = seq;
+        }
+        if let seq @ Some(_) = opt.begin_sequence.clone() {
+            machine.begin_sequence = seq;
+        }
+        if let seq @ Some(_) = opt.end_sequence.clone() {
+            machine.end_sequence = seq;
+        }
+        if let Some(program_end) = &opt.program_end {
+            machine.program_end = Some(match program_end.to_uppercase().as_str() {
+                "M2" => ProgramEnd::M2,
+                "M30" => ProgramEnd::M30,
+                _ => ProgramEnd::Custom(program_end.clone()),
+            });
+        }
+    }
+    if let park @ Some(_) = opt.park {
+        settings.conversion.park = park;
+    }
+    {
+        if let Some(origin) = &opt.origin {
+            for (i, dimension_origin) in origin
                 .split(',')
-                .map(|dimension_str| {
-                    if dimension_str.is_empty() {
-                        None
+                .map(|point| {
+                    if point.is_empty() {
+                        Default::default()
                     } else {
-                        LengthListParser::from(dimension_str)
-                            .next()
-                            .transpose()
-                            .expect("could not parse dimension")
+                        point.parse::<f64>().expect("could not parse coordinate")
                     }
                 })
                 .take(2)
                 .enumerate()
-                .for_each(|(i, dimension_origin)| {
-                    dimensions[i] = dimension_origin;
-                });
+            {
+                settings.conversion.origin[i] = Some(dimension_origin);
+            }
+        } else if opt
+            .files
+            .first()
+            .is_some_and(|file| pcb::Format::of(file).is_some())
+        {
+            // Copper and drill files of a board only line up if neither is moved
+            settings.conversion.origin = [None, None];
         }
-        ConversionOptions { dimensions }
-    };
+        if let Some(flip) = opt.flip {
+            settings.conversion.flip = flip.into();
+        }
+        if let Some(rotate) = opt.rotate {
+            settings.conversion.rotation = rotate;
+        }
+        if let Some(scale) = opt.scale {
+            settings.conversion.scale = scale;
+        }
+        if let fit_to @ Some(_) = opt.fit_to {
+            settings.conversion.fit_to = fit_to;
+        }
+        if let Some(diameter) = opt.sphere_diameter {
+            settings.conversion.sphere = Some(Sphere {
+                diameter,
+                compensate_latitude: opt.compensate_latitude,
+            });
+        }
+    }
 
-    let input = match opt.file {
-        Some(filename) => {
-            let mut f = File::open(filename)?;
-            let len = f.metadata()?.len();
-            let mut input = String::with_capacity(len as usize + 1);
-            f.read_to_string(&mut input)?;
-            input
+    if let Some(homing) = opt.homing {
+        settings.postprocess.homing = Some(homing.into());
+    }
+
+    if opt.klipper {
+        let kinematics = settings.machine.kinematics;
+        let klipper = settings
+            .postprocess
+            .klipper
+            .get_or_insert_with(Klipper::default);
+        klipper.acceleration = kinematics.acceleration.or(klipper.acceleration);
+        klipper.rapid_feedrate = kinematics.rapid_feedrate.or(klipper.rapid_feedrate);
+    }
+
+    if let Some(line_numbers) = opt.line_numbers {
+        settings.postprocess.line_numbers = line_numbers;
+    }
+
+    if let Some(line_number_start) = opt.line_number_start {
+        settings.postprocess.line_number_start = line_number_start;
+    }
+
+    if let Some(line_number_increment) = opt.line_number_increment {
+        settings.postprocess.line_number_increment = line_number_increment;
+    }
+
+    if let Some(checksums) = opt.checksums {
+        settings.postprocess.checksums = checksums;
+    }
+
+    if let Some(marlin_checksums) = opt.marlin_checksums {
+        settings.postprocess.marlin_checksums = marlin_checksums;
+    }
+
+    if let Some(newline_before_comment) = opt.newline_before_comment {
+        settings.postprocess.newline_before_comment = newline_before_comment;
+    }
+
+    if let extra_attribute_name @ Some(_) = opt.extra_attribute_name.clone() {
+        settings.conversion.extra_attribute_name = extra_attribute_name;
+    }
+
+    if let Version::Unknown(ref unknown) = settings.version {
+        error!(
+            "Your settings use an unknown version. Your version: {unknown}, latest: {}. See {} to download the latest CLI version.",
+            Version::latest(),
+            env!("CARGO_PKG_REPOSITORY"),
+        );
+        std::process::exit(1);
+    }
+
+    let old_version = settings.version.clone();
+    if let Err(msg) = settings.try_upgrade() {
+        error!(
+            "Your settings are out of date and require manual intervention: {msg}. Your version: {old_version}, latest: {}. See {} for instructions.",
+            Version::latest(),
+            env!("CARGO_PKG_REPOSITORY"),
+        );
+        std::process::exit(1);
+    }
+
+    Ok(settings)
+}
+
+/// Options for converting the SVGs, from the dimensions, layers, ids and path to resume from given on the command line
+fn conversion_options(opt: &Opt) -> ConversionOptions {
+    let mut dimensions = [None, None];
+
+    if let Some(dimensions_str) = &opt.dimensions {
+        dimensions_str
+            .split(',')
+            .map(|dimension_str| {
+                if dimension_str.is_empty() {
+                    None
+                } else {
+                    LengthListParser::from(dimension_str)
+                        .next()
+                        .transpose()
+                        .expect("could not parse dimension")
+                }
+            })
+            .take(2)
+            .enumerate()
+            .for_each(|(i, dimension_origin)| {
+                dimensions[i] = dimension_origin;
+            });
+    }
+    let mut hooks = Hooks::default();
+    if !opt.layers.is_empty() {
+        hooks = hooks.only_layers(opt.layers.clone());
+    }
+    if !opt.exclude_layers.is_empty() {
+        hooks = hooks.skip_layers(opt.exclude_layers.clone());
+    }
+    if !opt.select_ids.is_empty() {
+        hooks = hooks.only_ids(opt.select_ids.clone());
+    }
+    if !opt.exclude_ids.is_empty() {
+        hooks = hooks.skip_ids(opt.exclude_ids.clone());
+    }
+    ConversionOptions {
+        dimensions,
+        source_name: None,
+        group: None,
+        path_range: opt.resume_from.map(|path| path..usize::MAX),
+        cancellation: None,
+        hooks,
+    }
+}
+
+/// Files to convert, after checking that they go with where the G-Code is written to
+fn input_files(opt: &Opt) -> Vec<PathBuf> {
+    let files = match &opt.command {
+        Some(Command::Estimate { file } | Command::Verify { file, .. }) => {
+            Ok(file.iter().cloned().collect())
         }
-        None => {
-            info!("Reading from standard input");
-            let mut input = String::new();
-            io::stdin().read_to_string(&mut input)?;
-            input
+        _ => expand_globs(&opt.files),
+    };
+    let files = match files {
+        Ok(files) => files,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(1);
         }
     };
+    if opt.out_dir.is_some() {
+        if files.is_empty() || files.iter().any(|file| is_stdio(file)) {
+            error!("--out-dir needs file paths to convert, not standard in");
+            std::process::exit(1);
+        }
+    } else if files.len() > 1 {
+        error!("Converting more than one SVG requires --out-dir");
+        std::process::exit(1);
+    }
+    if (opt.out_dir.is_some() || opt.watch)
+        && files.iter().any(|file| pcb::Format::of(file).is_some())
+    {
+        error!(
+            "Gerber and Excellon files are converted one at a time, since they are milled and drilled with different settings"
+        );
+        std::process::exit(1);
+    }
+    if opt.watch
+        && (files.is_empty()
+            || files.iter().any(|file| is_stdio(file))
+            || (opt.out_dir.is_none() && opt.out.as_deref().is_none_or(is_stdio)))
+    {
+        error!("--watch needs file paths to convert and --out or --out-dir to write to");
+        std::process::exit(1);
+    }
+    if matches!(opt.command, Some(Command::Send { .. }))
+        && (opt.out_dir.is_some() || opt.watch || opt.stats_only)
+    {
+        error!("send streams one SVG and can't be used with --out-dir, --watch or --stats-only");
+        std::process::exit(1);
+    }
+    if opt.stats_json.as_deref().is_some_and(is_stdio)
+        && !opt.stats_only
+        && !matches!(opt.command, Some(Command::Send { .. }))
+        && opt.out.as_deref().is_none_or(is_stdio)
+    {
+        error!(
+            "--stats-json can't write to standard out along with the G-Code, please give --out a file path"
+        );
+        std::process::exit(1);
+    }
+    files
+}
 
+/// Machine for the tool sequences of the settings, exiting with a diagnostic for any of them that can't be parsed
+fn machine(machine: &MachineConfig) -> Machine<'_> {
     let snippets = [
-        settings
-            .machine
+        machine
             .tool_on_sequence
             .as_deref()
             .map(snippet_parser)
             .transpose(),
-        settings
-            .machine
+        machine
             .tool_off_sequence
             .as_deref()
             .map(snippet_parser)
             .transpose(),
-        settings
-            .machine
+        machine
             .begin_sequence
             .as_deref()
             .map(snippet_parser)
             .transpose(),
-        settings
-            .machine
+        machine
             .end_sequence
             .as_deref()
             .map(snippet_parser)
             .transpose(),
+        machine
+            .program_end
+            .as_ref()
+            .map(|program_end| snippet_parser(program_end.gcode()))
+            .transpose(),
     ];
 
-    let machine = if let [
+    if let [
         Ok(tool_on_action),
         Ok(tool_off_action),
         Ok(program_begin_sequence),
         Ok(program_end_sequence),
+        Ok(program_end),
     ] = snippets
     {
         Machine::new(
-            settings.machine.supported_functionality,
+            machine.supported_functionality.clone(),
+            machine.kinematics,
             tool_on_action,
             tool_off_action,
             program_begin_sequence,
             program_end_sequence,
         )
+        .with_pen_servo(machine.pen_servo)
+        .with_program_end(program_end)
     } else {
         use codespan_reporting::term::{
             emit,
@@ -290,10 +1366,14 @@ fn main() -> io::Result<()> {
         let config = codespan_reporting::term::Config::default();
 
         for (i, (filename, gcode)) in [
-            ("tool_on_sequence", &settings.machine.tool_on_sequence),
-            ("tool_off_sequence", &settings.machine.tool_off_sequence),
-            ("begin_sequence", &settings.machine.begin_sequence),
-            ("end_sequence", &settings.machine.end_sequence),
+            ("tool_on_sequence", machine.tool_on_sequence.as_deref()),
+            ("tool_off_sequence", machine.tool_off_sequence.as_deref()),
+            ("begin_sequence", machine.begin_sequence.as_deref()),
+            ("end_sequence", machine.end_sequence.as_deref()),
+            (
+                "program_end",
+                machine.program_end.as_ref().map(ProgramEnd::gcode),
+            ),
         ]
         .iter()
         .enumerate()
@@ -302,46 +1382,398 @@ fn main() -> io::Result<()> {
                 emit(
                     &mut writer,
                     &config,
-                    &codespan_reporting::files::SimpleFile::new(filename, gcode.as_ref().unwrap()),
+                    &codespan_reporting::files::SimpleFile::new(filename, gcode.unwrap()),
                     &g_code::parse::into_diagnostic(err),
                 )
                 .unwrap();
             }
         }
         std::process::exit(1)
+    }
+}
+
+/// Contents of the file to convert, else of standard in, with Gerber and Excellon files turned into an SVG
+fn read_input(opt: &Opt, file: Option<&PathBuf>, settings: &Settings) -> io::Result<String> {
+    let input = match file.filter(|file| !is_stdio(file)) {
+        Some(filename) => {
+            let mut f = File::open(filename)?;
+            let len = f.metadata()?.len();
+            let mut input = String::with_capacity(len as usize + 1);
+            f.read_to_string(&mut input)?;
+            input
+        }
+        None => {
+            info!("Reading from standard input");
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
     };
+    match file.and_then(|file| pcb::Format::of(file)) {
+        Some(format) => {
+            if format == pcb::Format::Excellon && settings.conversion.drilling.is_none() {
+                warn!(
+                    "Without --drill-diameter, the holes of the drill file are traced instead of drilled"
+                );
+            }
+            let isolation = pcb::Isolation {
+                width: opt.isolation_width,
+                passes: opt.isolation_passes,
+            };
+            match pcb::to_svg(format, &input, isolation) {
+                Ok(svg) => Ok(svg),
+                Err(err) => {
+                    error!("{err}");
+                    std::process::exit(EXIT_PARSE_ERROR);
+                }
+            }
+        }
+        None => Ok(input),
+    }
+}
 
-    let document = roxmltree::Document::parse_with_options(
-        &input,
-        ParsingOptions {
-            allow_dtd: true,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-
-    let program = svg2program(&document, &settings.conversion, options, machine);
-
-    if let Some(out_path) = opt.out {
-        format_gcode_io(
-            &program,
-            FormatOptions {
-                line_numbers: settings.postprocess.line_numbers,
-                checksums: settings.postprocess.checksums,
-                ..Default::default()
-            },
-            File::create(out_path)?,
-        )
+/// Write the toolpath to the other formats that were asked for, i.e. a preview or a DXF
+fn write_toolpaths(
+    opt: &Opt,
+    document: &roxmltree::Document,
+    settings: &Settings,
+    options: &ConversionOptions,
+) -> io::Result<()> {
+    if let Some(preview_path) = &opt.preview
+        && let Err(err) = preview::write(document, settings, options.clone(), preview_path)
+    {
+        error!("{err}");
+        std::process::exit(1);
+    }
+
+    if opt.preview_term
+        && let Err(err) = preview::print_terminal(document, settings, options.clone())
+    {
+        error!("{err}");
+        std::process::exit(1);
+    }
+
+    if let Some(dxf_path) = &opt.dxf {
+        match svg2polylines(document, &settings.conversion, options.clone()) {
+            Ok(polylines) => fs::write(dxf_path, polylines2dxf(&polylines))?,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
+            }
+        }
+    }
+
+    if let Some(coordinates_path) = &opt.coordinates {
+        match svg2polylines(document, &settings.conversion, options.clone()) {
+            Ok(polylines) => coordinates::write(&polylines, coordinates_path)?,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
+            }
+        }
+    }
+
+    if let Some(cutter_path) = &opt.cutter {
+        match svg2polylines(document, &settings.conversion, options.clone()) {
+            Ok(polylines) => fs::write(
+                cutter_path,
+                polylines2cutter(&polylines, opt.cutter_dialect.into()),
+            )?,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
+            }
+        }
+    }
+
+    if let Some(lightburn_path) = &opt.lightburn {
+        match svg2lightburn(
+            document,
+            &settings.conversion,
+            options.clone(),
+            opt.lightburn_max_power,
+        ) {
+            Ok(project) => fs::write(lightburn_path, project)?,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(EXIT_CONVERSION_ERROR);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert an SVG, then check, write and upload its G-Code as the command line asks
+fn write_program(
+    opt: &Opt,
+    document: &roxmltree::Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    machine: Machine,
+) -> io::Result<()> {
+    let out_path = opt.out.as_deref().filter(|out_path| !is_stdio(out_path));
+    // A checked program is written next to the file first, so that one that fails does not replace it
+    let written = out_path.map(|out_path| {
+        if opt.check {
+            let mut file_name = OsString::from(".");
+            file_name.push(out_path.file_name().unwrap_or_default());
+            file_name.push(".tmp");
+            out_path.with_file_name(file_name)
+        } else {
+            out_path.to_path_buf()
+        }
+    });
+    let out: Box<dyn Write> = match &written {
+        Some(written) => Box::new(BufWriter::new(File::create(written)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let converted = convert(opt, document, settings, options, machine, out);
+    if let (Some(out_path), Some(written)) = (out_path, &written)
+        && written != out_path
+    {
+        match &converted {
+            Ok((_, true)) => fs::rename(written, out_path)?,
+            _ => fs::remove_file(written)?,
+        }
+    }
+    let statistics = match converted {
+        Ok((statistics, true)) => statistics,
+        Ok((_, false)) => std::process::exit(EXIT_CHECK_FAILED),
+        Err(ConversionError::Io(err)) => return Err(err),
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_CONVERSION_ERROR);
+        }
+    };
+    if let Some(bounding_box) = statistics
+        .bounding_box
+        .filter(|_| settings.conversion.scale != 1. || settings.conversion.fit_to.is_some())
+    {
+        let size = bounding_box.size();
+        info!("Resized to {:.3} × {:.3} mm", size.width, size.height);
+    }
+    if let Some(out_path) = opt.out.as_deref().filter(|_| opt.upload)
+        && let Err(err) = upload::upload(out_path)
+    {
+        error!("{err}");
+        std::process::exit(1);
+    }
+    if let (Some(camotics_path), Some(out_path)) = (&opt.camotics, &opt.out)
+        && let Err(err) =
+            camotics::write(camotics_path, out_path, opt.tool_shape, opt.tool_diameter)
+    {
+        error!("{err}");
+        std::process::exit(1);
+    }
+    match &opt.stats_json {
+        Some(stats_json) => report::write(&statistics, stats_json),
+        None => Ok(()),
+    }
+}
+
+/// Convert an SVG to G-Code that is streamed to `out`, checking it on the way with --check
+///
+/// Returns whether the G-Code passed the check, which it always does when it is not checked.
+fn convert(
+    opt: &Opt,
+    document: &roxmltree::Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    machine: Machine,
+    mut out: impl Write,
+) -> Result<(ConversionStatistics, bool), ConversionError> {
+    let (statistics, passed) = if opt.check {
+        let mut checker = check::Checker::new(&mut out, &settings.machine);
+        let statistics = svg2program_io(
+            document,
+            &settings.conversion,
+            options,
+            machine,
+            &settings.postprocess,
+            &mut checker,
+        )?;
+        (statistics, checker.finish().1)
     } else {
-        format_gcode_io(
-            &program,
-            FormatOptions {
-                line_numbers: settings.postprocess.line_numbers,
-                checksums: settings.postprocess.checksums,
-                newline_before_comment: settings.postprocess.newline_before_comment,
-                ..Default::default()
-            },
-            std::io::stdout(),
-        )
+        let statistics = svg2program_io(
+            document,
+            &settings.conversion,
+            options,
+            machine,
+            &settings.postprocess,
+            &mut out,
+        )?;
+        (statistics, true)
+    };
+    out.flush().map_err(ConversionError::Io)?;
+    Ok((statistics, passed))
+}
+
+/// Convert an SVG to programs of at most `limit` lines or bytes, numbered after `out_path`
+fn write_chunks(
+    document: &roxmltree::Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    machine: Machine,
+    limit: ChunkLimit,
+    out_path: &Path,
+) -> io::Result<()> {
+    let chunks = match svg2chunks(document, &settings.conversion, options, machine, limit) {
+        Ok(chunks) => chunks,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_CONVERSION_ERROR);
+        }
+    };
+    let stem = out_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = out_path.extension().unwrap_or_default().to_string_lossy();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut file_name = format!("{stem}-{}", i + 1);
+        if !extension.is_empty() {
+            file_name += ".";
+            file_name += &extension;
+        }
+        info!("Writing {file_name}");
+        format_program_io(
+            &chunk.tokens,
+            &settings.postprocess,
+            BufWriter::new(File::create(out_path.with_file_name(file_name))?),
+        )?;
+    }
+    Ok(())
+}
+
+/// Paths of the SVGs to convert, with glob patterns replaced by the files that match them
+///
+/// Paths that exist are kept as they are, even if they look like a pattern.
+fn expand_globs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = vec![];
+    for path in paths {
+        if is_stdio(path) || path.exists() {
+            files.push(path.clone());
+            continue;
+        }
+        let pattern = path.to_string_lossy();
+        let matches = glob::glob(&pattern)
+            .map_err(|err| format!("{pattern} is not a valid glob pattern: {err}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?;
+        if matches.is_empty() {
+            return Err(format!("No files match {pattern}"));
+        }
+        files.extend(matches);
+    }
+    Ok(files)
+}
+
+/// Convert each SVG to G-Code in `out_dir`, in parallel
+///
+/// A failure is logged and the rest are still converted, exiting with an error at the end.
+fn convert_batch(
+    files: &[PathBuf],
+    out_dir: &Path,
+    settings: &Settings,
+    options: &ConversionOptions,
+) -> io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let failures = files
+        .par_iter()
+        .filter(|file| !convert_file(file, &out_path(out_dir, file), None, settings, options))
+        .count();
+    if failures > 0 {
+        error!("{failures} of {} SVGs could not be converted", files.len());
+        std::process::exit(EXIT_CONVERSION_ERROR);
+    }
+    Ok(())
+}
+
+/// Convert an SVG file to a G-Code file and optionally a preview, logging the outcome and returning whether it succeeded
+fn convert_file(
+    file: &Path,
+    out_path: &Path,
+    preview_path: Option<&Path>,
+    settings: &Settings,
+    options: &ConversionOptions,
+) -> bool {
+    let converted = fs::read_to_string(file)
+        .map_err(|err| err.to_string())
+        .and_then(|svg| {
+            let options = ConversionOptions {
+                source_name: file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned()),
+                ..options.clone()
+            };
+            let (gcode, _) = svg2gcode::convert(&svg, settings, options.clone())
+                .map_err(|err| err.to_string())?;
+            if let Some(preview_path) = preview_path {
+                let document = roxmltree::Document::parse_with_options(
+                    &svg,
+                    ParsingOptions {
+                        allow_dtd: true,
+                        ..Default::default()
+                    },
+                )
+                .map_err(|err| err.to_string())?;
+                preview::write(&document, settings, options, preview_path)?;
+            }
+            Ok(gcode)
+        })
+        .and_then(|gcode| fs::write(out_path, gcode).map_err(|err| err.to_string()));
+    match converted {
+        Ok(()) => {
+            info!("Converted {} to {}", file.display(), out_path.display());
+            true
+        }
+        Err(err) => {
+            error!("{}: {err}", file.display());
+            false
+        }
+    }
+}
+
+/// Where the G-Code for an SVG is written in `out_dir`
+fn out_path(out_dir: &Path, file: &Path) -> PathBuf {
+    out_dir.join(Path::new(file.file_name().unwrap_or_default()).with_extension("gcode"))
+}
+
+/// Whether a path is `-`, which stands for standard in or out
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Width and height in millimeters, i.e. `300,200`
+fn parse_size(size: &str) -> Result<[f64; 2], String> {
+    match size.split(',').collect::<Vec<_>>().as_slice() {
+        [width, height] => {
+            let parse = |dimension: &str| {
+                dimension
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|dimension| dimension.is_finite() && *dimension > 0.)
+                    .ok_or_else(|| format!("{dimension:?} is not a positive number of millimeters"))
+            };
+            Ok([parse(width)?, parse(height)?])
+        }
+        _ => Err("expected a width and height separated by a comma, i.e. 300,200".to_string()),
+    }
+}
+
+/// `behind-job` or the X and Y coordinates in millimeters to park at, i.e. `0,-10`
+fn parse_park(park: &str) -> Result<ParkPosition, String> {
+    if park == "behind-job" {
+        return Ok(ParkPosition::BehindJob);
+    }
+    match park.split(',').collect::<Vec<_>>().as_slice() {
+        [x, y] => {
+            let parse = |coordinate: &str| {
+                coordinate
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("{coordinate:?} is not a coordinate in millimeters"))
+            };
+            Ok(ParkPosition::Custom([parse(x)?, parse(y)?]))
+        }
+        _ => Err("expected \"behind-job\" or X,Y coordinates separated by a comma".to_string()),
     }
 }
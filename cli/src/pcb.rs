@@ -0,0 +1,1385 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f64::consts::TAU,
+    fmt::Write,
+    path::Path,
+};
+
+use log::warn;
+
+/// Millimeters in an inch, for files in inch units
+const MM_PER_INCH: f64 = 25.4;
+/// Cells of the grid that isolation outlines are traced on, across the width of the tool
+const CELLS_PER_TOOL: f64 = 4.;
+/// Most cells the grid may have, to keep memory in check for large boards
+const MAX_CELLS: usize = 64_000_000;
+/// Segments that arcs are flattened into for a full turn
+const ARC_SEGMENTS: f64 = 72.;
+
+/// Kind of PCB fabrication file, told apart by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Copper layer in RS-274X, i.e. `board-F_Cu.gtl`
+    Gerber,
+    /// Drill file, i.e. `board.drl`
+    Excellon,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "gbr" | "ger" | "gtl" | "gbl" | "cmp" | "sol" => Some(Self::Gerber),
+            "drl" | "xln" | "drd" => Some(Self::Excellon),
+            _ => None,
+        }
+    }
+}
+
+/// How copper is isolated from the rest of the board
+#[derive(Debug, Clone, Copy)]
+pub struct Isolation {
+    /// Diameter of the tool in millimeters, i.e. the width of a V-bit at its cutting depth
+    pub width: f64,
+    /// Outlines around the copper, each half a tool width further out, to widen the gaps that are cut
+    pub passes: usize,
+}
+
+/// Translate a PCB file into an SVG in millimeters that converts like any other, keeping the coordinates of the file
+///
+/// For Gerber copper layers, it has the outlines that the center of the tool follows to route around the copper,
+/// in a layer for each pass. For Excellon drill files, it has a circle for each hole in a layer for each tool, to
+/// drill with [svg2gcode::Drilling].
+pub fn to_svg(format: Format, text: &str, isolation: Isolation) -> Result<String, String> {
+    match format {
+        Format::Gerber => {
+            let shapes = parse_gerber(text)?;
+            let layers = (0..isolation.passes.max(1))
+                .map(|pass| {
+                    let offset = isolation.width / 2. * (1. + pass as f64);
+                    (format!("isolation {}", pass + 1), offset)
+                })
+                .collect::<Vec<_>>();
+            let spacing = isolation.width / CELLS_PER_TOOL;
+            let reach = layers.last().map_or(0., |(_, offset)| *offset) + 2. * spacing;
+            let field = Field::new(&shapes, spacing, reach)?;
+            let layers = layers
+                .into_iter()
+                .map(|(name, offset)| {
+                    let paths = field
+                        .contours(offset)
+                        .iter()
+                        .map(|contour| path_data(&simplify(contour, spacing / 100.)))
+                        .collect::<Vec<_>>();
+                    (name, paths)
+                })
+                .collect::<Vec<_>>();
+            let points = layers
+                .iter()
+                .flat_map(|(_, paths)| paths)
+                .flat_map(|(_, points)| points.iter().copied())
+                .collect::<Vec<_>>();
+            Ok(svg(&points, |svg| {
+                for (name, paths) in &layers {
+                    let _ = writeln!(svg, "{}", layer_start(name));
+                    for (data, _) in paths {
+                        let _ = writeln!(svg, r#"<path d="{data}"/>"#);
+                    }
+                    let _ = writeln!(svg, "</g>");
+                }
+            }))
+        }
+        Format::Excellon => {
+            let tools = parse_excellon(text)?;
+            let points = tools
+                .iter()
+                .flat_map(|tool| {
+                    let radius = tool.diameter / 2.;
+                    tool.holes.iter().flat_map(move |[x, y]| {
+                        [[x - radius, y - radius], [x + radius, y + radius]]
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(svg(&points, |svg| {
+                for tool in &tools {
+                    let label = format!("{} {} mm", tool.name, tool.diameter);
+                    let _ = writeln!(svg, "{}", layer_start(&label));
+                    for [x, y] in &tool.holes {
+                        let _ = writeln!(
+                            svg,
+                            r#"<circle cx="{x}" cy="{}" r="{}"/>"#,
+                            -y,
+                            tool.diameter / 2.
+                        );
+                    }
+                    let _ = writeln!(svg, "</g>");
+                }
+            }))
+        }
+    }
+}
+
+/// SVG document sized to fit `points`, with its contents written by `body`
+///
+/// Y is negated, which the conversion flips back, so that the coordinates come out as they are in the file.
+fn svg(points: &[[f64; 2]], body: impl FnOnce(&mut String)) -> String {
+    let (min, max) = points.iter().fold(
+        ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+        |(min, max), [x, y]| {
+            (
+                [min[0].min(*x), min[1].min(*y)],
+                [max[0].max(*x), max[1].max(*y)],
+            )
+        },
+    );
+    let (min, max) = if points.is_empty() {
+        ([0.; 2], [1.; 2])
+    } else {
+        (min, max)
+    };
+    let [width, height] = [(max[0] - min[0]).max(1e-3), (max[1] - min[1]).max(1e-3)];
+
+    let mut svg = String::new();
+    // Writing to a string can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{width}mm" height="{height}mm" viewBox="{} {} {width} {height}" fill="none" stroke="black">"#,
+        min[0], -max[1]
+    );
+    body(&mut svg);
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+fn layer_start(name: &str) -> String {
+    format!(r#"<g inkscape:groupmode="layer" inkscape:label="{name}">"#)
+}
+
+/// SVG path data for a closed outline, along with its points
+fn path_data(points: &[[f64; 2]]) -> (String, Vec<[f64; 2]>) {
+    let mut data = String::new();
+    for (i, [x, y]) in points.iter().enumerate() {
+        let _ = write!(data, "{}{x:.4},{:.4}", if i == 0 { "M" } else { " L" }, -y);
+    }
+    data += " Z";
+    (data, points.to_vec())
+}
+
+/// Copper that a Gerber file puts down, in millimeters
+#[derive(Debug, Clone)]
+enum Shape {
+    /// Line drawn with a round aperture
+    Stroke {
+        from: [f64; 2],
+        to: [f64; 2],
+        radius: f64,
+    },
+    Circle {
+        center: [f64; 2],
+        radius: f64,
+    },
+    Rectangle {
+        center: [f64; 2],
+        half_size: [f64; 2],
+    },
+    /// Filled region, which is closed
+    Polygon(Vec<[f64; 2]>),
+}
+
+impl Shape {
+    /// Distance from the edge of the copper, negative inside of it
+    fn distance(&self, [x, y]: [f64; 2]) -> f64 {
+        match self {
+            Self::Stroke { from, to, radius } => segment_distance([x, y], *from, *to) - radius,
+            Self::Circle { center, radius } => (x - center[0]).hypot(y - center[1]) - radius,
+            Self::Rectangle { center, half_size } => {
+                let dx = (x - center[0]).abs() - half_size[0];
+                let dy = (y - center[1]).abs() - half_size[1];
+                dx.max(0.).hypot(dy.max(0.)) + dx.max(dy).min(0.)
+            }
+            Self::Polygon(points) => {
+                let mut distance = f64::INFINITY;
+                let mut inside = false;
+                for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+                    distance = distance.min(segment_distance([x, y], *a, *b));
+                    if (a[1] > y) != (b[1] > y)
+                        && x < a[0] + (y - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+                    {
+                        inside = !inside;
+                    }
+                }
+                if inside { -distance } else { distance }
+            }
+        }
+    }
+
+    /// The same copper moved by `offset`, i.e. to where an aperture is flashed
+    fn translated(&self, [dx, dy]: [f64; 2]) -> Self {
+        let shift = |[x, y]: [f64; 2]| [x + dx, y + dy];
+        match self {
+            Self::Stroke { from, to, radius } => Self::Stroke {
+                from: shift(*from),
+                to: shift(*to),
+                radius: *radius,
+            },
+            Self::Circle { center, radius } => Self::Circle {
+                center: shift(*center),
+                radius: *radius,
+            },
+            Self::Rectangle { center, half_size } => Self::Rectangle {
+                center: shift(*center),
+                half_size: *half_size,
+            },
+            Self::Polygon(points) => Self::Polygon(points.iter().copied().map(shift).collect()),
+        }
+    }
+
+    /// Smallest and largest corner of the copper
+    fn bounds(&self) -> ([f64; 2], [f64; 2]) {
+        let around = |[x, y]: [f64; 2], [rx, ry]: [f64; 2]| ([x - rx, y - ry], [x + rx, y + ry]);
+        match self {
+            Self::Stroke { from, to, radius } => (
+                [from[0].min(to[0]) - radius, from[1].min(to[1]) - radius],
+                [from[0].max(to[0]) + radius, from[1].max(to[1]) + radius],
+            ),
+            Self::Circle { center, radius } => around(*center, [*radius; 2]),
+            Self::Rectangle { center, half_size } => around(*center, *half_size),
+            Self::Polygon(points) => points.iter().fold(
+                ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+                |(min, max), [x, y]| {
+                    (
+                        [min[0].min(*x), min[1].min(*y)],
+                        [max[0].max(*x), max[1].max(*y)],
+                    )
+                },
+            ),
+        }
+    }
+}
+
+fn segment_distance([x, y]: [f64; 2], [x0, y0]: [f64; 2], [x1, y1]: [f64; 2]) -> f64 {
+    let [dx, dy] = [x1 - x0, y1 - y0];
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0. {
+        (((x - x0) * dx + (y - y0) * dy) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (x - (x0 + t * dx)).hypot(y - (y0 + t * dy))
+}
+
+/// Distance from the copper sampled on a grid, which isolation outlines are traced on
+struct Field {
+    origin: [f64; 2],
+    spacing: f64,
+    columns: usize,
+    rows: usize,
+    /// Row by row, capped at the reach of the outlines since nothing further is traced
+    values: Vec<f64>,
+}
+
+impl Field {
+    fn new(shapes: &[Shape], spacing: f64, reach: f64) -> Result<Self, String> {
+        if shapes.is_empty() {
+            return Err("There is no copper in the Gerber file".to_string());
+        }
+        let (min, max) = shapes.iter().map(Shape::bounds).fold(
+            ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+            |(min, max), (shape_min, shape_max)| {
+                (
+                    [min[0].min(shape_min[0]), min[1].min(shape_min[1])],
+                    [max[0].max(shape_max[0]), max[1].max(shape_max[1])],
+                )
+            },
+        );
+        // A cell of margin keeps the outlines off the edges of the grid, so that they all close
+        let origin = [min[0] - reach - spacing, min[1] - reach - spacing];
+        let columns = ((max[0] + reach - origin[0]) / spacing).ceil() as usize + 2;
+        let rows = ((max[1] + reach - origin[1]) / spacing).ceil() as usize + 2;
+        if columns.saturating_mul(rows) > MAX_CELLS {
+            return Err(format!(
+                "The board is too large to isolate with a {} mm tool, please use a wider --isolation-width",
+                spacing * CELLS_PER_TOOL
+            ));
+        }
+
+        let mut values = vec![reach; columns * rows];
+        for shape in shapes {
+            let (shape_min, shape_max) = shape.bounds();
+            let cell = |value: f64, origin: f64| ((value - origin) / spacing).max(0.);
+            let first = [
+                cell(shape_min[0] - reach, origin[0]).floor() as usize,
+                cell(shape_min[1] - reach, origin[1]).floor() as usize,
+            ];
+            let last = [
+                (cell(shape_max[0] + reach, origin[0]).ceil() as usize).min(columns - 1),
+                (cell(shape_max[1] + reach, origin[1]).ceil() as usize).min(rows - 1),
+            ];
+            for row in first[1]..=last[1] {
+                for column in first[0]..=last[0] {
+                    let point = [
+                        origin[0] + column as f64 * spacing,
+                        origin[1] + row as f64 * spacing,
+                    ];
+                    let value = &mut values[row * columns + column];
+                    *value = value.min(shape.distance(point));
+                }
+            }
+        }
+        Ok(Self {
+            origin,
+            spacing,
+            columns,
+            rows,
+            values,
+        })
+    }
+
+    fn point(&self, [column, row]: [usize; 2]) -> [f64; 2] {
+        [
+            self.origin[0] + column as f64 * self.spacing,
+            self.origin[1] + row as f64 * self.spacing,
+        ]
+    }
+
+    fn value(&self, [column, row]: [usize; 2]) -> f64 {
+        self.values[row * self.columns + column]
+    }
+
+    /// Closed outlines where the copper is `level` away, by marching squares
+    fn contours(&self, level: f64) -> Vec<Vec<[f64; 2]>> {
+        // Edges of a cell are keyed by their lower left grid point and whether they are vertical
+        type Edge = ([usize; 2], bool);
+        let mut segments: Vec<[Edge; 2]> = vec![];
+        for row in 0..self.rows - 1 {
+            for column in 0..self.columns - 1 {
+                let corners = [
+                    [column, row],
+                    [column + 1, row],
+                    [column + 1, row + 1],
+                    [column, row + 1],
+                ];
+                let inside = corners.map(|corner| self.value(corner) < level);
+                // Bottom, right, top and left, each between the corners of the same and next index
+                let edges: [Edge; 4] = [
+                    ([column, row], false),
+                    ([column + 1, row], true),
+                    ([column, row + 1], false),
+                    ([column, row], true),
+                ];
+                let crossed = (0..4)
+                    .filter(|&i| inside[i] != inside[(i + 1) % 4])
+                    .map(|i| edges[i])
+                    .collect::<Vec<_>>();
+                match crossed.as_slice() {
+                    [a, b] => segments.push([*a, *b]),
+                    [bottom, right, top, left] => {
+                        let center = corners
+                            .iter()
+                            .map(|corner| self.value(*corner))
+                            .sum::<f64>()
+                            / 4.;
+                        // A saddle, where the center tells which pair of opposite corners is joined
+                        if (center < level) == inside[0] {
+                            segments.push([*bottom, *right]);
+                            segments.push([*top, *left]);
+                        } else {
+                            segments.push([*bottom, *left]);
+                            segments.push([*right, *top]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+        for (i, segment) in segments.iter().enumerate() {
+            for edge in segment {
+                by_edge.entry(*edge).or_default().push(i);
+            }
+        }
+        let crossing = |(start, vertical): Edge| {
+            let end = if vertical {
+                [start[0], start[1] + 1]
+            } else {
+                [start[0] + 1, start[1]]
+            };
+            let (a, b) = (self.value(start), self.value(end));
+            let t = (level - a) / (b - a);
+            let (start, end) = (self.point(start), self.point(end));
+            [
+                start[0] + t * (end[0] - start[0]),
+                start[1] + t * (end[1] - start[1]),
+            ]
+        };
+
+        let mut visited = HashSet::new();
+        let mut contours = vec![];
+        for first in 0..segments.len() {
+            if !visited.insert(first) {
+                continue;
+            }
+            let [start, mut edge] = segments[first];
+            let mut contour = vec![crossing(start)];
+            while edge != start {
+                contour.push(crossing(edge));
+                let Some(&next) = by_edge[&edge].iter().find(|i| !visited.contains(*i)) else {
+                    break;
+                };
+                visited.insert(next);
+                let [a, b] = segments[next];
+                edge = if a == edge { b } else { a };
+            }
+            if contour.len() > 2 {
+                contours.push(contour);
+            }
+        }
+        contours
+    }
+}
+
+/// Leave out points of a closed outline that are within `tolerance` of the line between their neighbors
+fn simplify(points: &[[f64; 2]], tolerance: f64) -> Vec<[f64; 2]> {
+    let mut kept: Vec<[f64; 2]> = vec![];
+    for (i, point) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        match kept.last() {
+            Some(last) if segment_distance(*point, *last, next) < tolerance => {}
+            _ => kept.push(*point),
+        }
+    }
+    kept
+}
+
+/// Aperture of a Gerber file, in millimeters
+#[derive(Debug, Clone)]
+enum Aperture {
+    Circle(f64),
+    Rectangle([f64; 2]),
+    Obround([f64; 2]),
+    /// Regular polygon (`P`) or aperture macro, as the copper it puts down around the point it is flashed at
+    Shapes(Vec<Shape>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interpolation {
+    Linear,
+    Clockwise,
+    Counterclockwise,
+}
+
+/// How coordinates without a decimal point are read
+#[derive(Debug, Clone, Copy)]
+struct NumberFormat {
+    integer_digits: usize,
+    decimal_digits: usize,
+    /// Leading zeros are left out, else trailing zeros are
+    omits_leading: bool,
+}
+
+impl NumberFormat {
+    fn parse(self, text: &str) -> Result<f64, String> {
+        let invalid = || format!("{text} is not a valid coordinate");
+        if text.contains('.') {
+            return text.parse().map_err(|_| invalid());
+        }
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => (-1., digits),
+            None => (1., text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let digits = if self.omits_leading {
+            digits.to_string()
+        } else {
+            format!(
+                "{digits:0<width$}",
+                width = self.integer_digits + self.decimal_digits
+            )
+        };
+        let value = digits.parse::<f64>().map_err(|_| invalid())?;
+        Ok(sign * value / 10f64.powi(self.decimal_digits as i32))
+    }
+}
+
+/// Copper of an aperture macro's primitives, with `parameters` from the aperture definition in the file's units
+///
+/// Circles, vector and center lines, outlines and polygons are supported, as long as they add copper rather than
+/// clear it.
+fn expand_macro(body: &[String], parameters: &[f64], unit: f64) -> Result<Vec<Shape>, String> {
+    let mut variables = (1..)
+        .zip(parameters.iter().copied())
+        .collect::<HashMap<usize, f64>>();
+    let mut shapes = vec![];
+    for statement in body {
+        if let Some(definition) = statement.strip_prefix('$') {
+            let (variable, expression) = definition
+                .split_once('=')
+                .and_then(|(variable, expression)| Some((variable.parse().ok()?, expression)))
+                .ok_or_else(|| format!("{statement} is not a valid variable definition"))?;
+            let value = evaluate(expression, &variables)?;
+            variables.insert(variable, value);
+            continue;
+        }
+        // Comment
+        if statement.starts_with('0') {
+            continue;
+        }
+        let mut fields = statement.split(',');
+        let code = fields.next().unwrap_or_default();
+        let values = fields
+            .map(|field| evaluate(field, &variables))
+            .collect::<Result<Vec<_>, _>>()?;
+        match values.first() {
+            Some(exposure) if *exposure == 1. => {}
+            Some(_) => {
+                return Err(format!(
+                    "{statement} clears copper, which aperture macros are not supported for"
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "{statement} is not a valid aperture macro primitive"
+                ));
+            }
+        }
+        let point = |x: f64, y: f64| [x * unit, y * unit];
+        let rotated = |points: Vec<[f64; 2]>, rotation: Option<&f64>| {
+            Shape::Polygon(
+                points
+                    .into_iter()
+                    .map(|point| rotate(point, rotation.copied().unwrap_or_default()))
+                    .collect(),
+            )
+        };
+        let shape = match (code, &values[1..]) {
+            ("1", [diameter, x, y, rotation @ ..]) => Shape::Circle {
+                center: rotate(point(*x, *y), rotation.first().copied().unwrap_or_default()),
+                radius: diameter * unit / 2.,
+            },
+            ("20", [width, x0, y0, x1, y1, rotation @ ..]) => {
+                let (start, end) = (point(*x0, *y0), point(*x1, *y1));
+                let length = (end[0] - start[0]).hypot(end[1] - start[1]);
+                if length == 0. {
+                    continue;
+                }
+                // Half the width, across the line
+                let [nx, ny] = [
+                    -(end[1] - start[1]) / length * width * unit / 2.,
+                    (end[0] - start[0]) / length * width * unit / 2.,
+                ];
+                rotated(
+                    vec![
+                        [start[0] + nx, start[1] + ny],
+                        [end[0] + nx, end[1] + ny],
+                        [end[0] - nx, end[1] - ny],
+                        [start[0] - nx, start[1] - ny],
+                    ],
+                    rotation.first(),
+                )
+            }
+            ("21", [width, height, x, y, rotation @ ..]) => {
+                let [x, y] = point(*x, *y);
+                let [dx, dy] = [width * unit / 2., height * unit / 2.];
+                rotated(
+                    vec![
+                        [x - dx, y - dy],
+                        [x + dx, y - dy],
+                        [x + dx, y + dy],
+                        [x - dx, y + dy],
+                    ],
+                    rotation.first(),
+                )
+            }
+            ("4", [count, rest @ ..]) if rest.len() >= 2 * (*count as usize + 1) => {
+                // The last point repeats the first
+                let (coordinates, rotation) = rest.split_at(2 * *count as usize);
+                rotated(
+                    coordinates
+                        .chunks(2)
+                        .map(|pair| point(pair[0], pair[1]))
+                        .collect(),
+                    rotation.get(2),
+                )
+            }
+            ("5", [vertices, x, y, diameter, rotation @ ..]) => rotated(
+                regular_polygon(point(*x, *y), diameter * unit, *vertices),
+                rotation.first(),
+            ),
+            ("6" | "7", _) => {
+                return Err(format!(
+                    "{statement} is a moiré or thermal, which aperture macros are not supported for"
+                ));
+            }
+            _ => {
+                return Err(format!(
+                    "{statement} is not a supported aperture macro primitive"
+                ));
+            }
+        };
+        shapes.push(shape);
+    }
+    Ok(shapes)
+}
+
+/// Value of an arithmetic expression in an aperture macro, i.e. `$1+$1` or `($2-0.5)x2`
+///
+/// Variables that were not given are zero.
+fn evaluate(expression: &str, variables: &HashMap<usize, f64>) -> Result<f64, String> {
+    fn sum(rest: &mut &str, variables: &HashMap<usize, f64>) -> Option<f64> {
+        let mut value = product(rest, variables)?;
+        loop {
+            match rest.chars().next() {
+                Some('+') => {
+                    *rest = &rest[1..];
+                    value += product(rest, variables)?;
+                }
+                Some('-') => {
+                    *rest = &rest[1..];
+                    value -= product(rest, variables)?;
+                }
+                _ => return Some(value),
+            }
+        }
+    }
+    fn product(rest: &mut &str, variables: &HashMap<usize, f64>) -> Option<f64> {
+        let mut value = factor(rest, variables)?;
+        loop {
+            match rest.chars().next() {
+                Some('x' | 'X') => {
+                    *rest = &rest[1..];
+                    value *= factor(rest, variables)?;
+                }
+                Some('/') => {
+                    *rest = &rest[1..];
+                    value /= factor(rest, variables)?;
+                }
+                _ => return Some(value),
+            }
+        }
+    }
+    fn factor(rest: &mut &str, variables: &HashMap<usize, f64>) -> Option<f64> {
+        let first = rest.chars().next()?;
+        *rest = &rest[1..];
+        match first {
+            '-' => factor(rest, variables).map(|value| -value),
+            '+' => factor(rest, variables),
+            '(' => {
+                let value = sum(rest, variables)?;
+                *rest = rest.strip_prefix(')')?;
+                Some(value)
+            }
+            '$' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let variable = rest[..len].parse().ok()?;
+                *rest = &rest[len..];
+                Some(variables.get(&variable).copied().unwrap_or_default())
+            }
+            _ => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(rest.len());
+                let number = format!("{first}{}", &rest[..len]);
+                *rest = &rest[len..];
+                number.parse().ok()
+            }
+        }
+    }
+
+    let mut rest = expression;
+    sum(&mut rest, variables)
+        .filter(|_| rest.is_empty())
+        .ok_or_else(|| format!("{expression} is not a valid aperture macro expression"))
+}
+
+/// Point turned counterclockwise around the origin by `degrees`
+fn rotate([x, y]: [f64; 2], degrees: f64) -> [f64; 2] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [x * cos - y * sin, x * sin + y * cos]
+}
+
+/// Corners of a polygon with `vertices` equal sides inside a circle, with the first on the right of the center
+fn regular_polygon(center: [f64; 2], diameter: f64, vertices: f64) -> Vec<[f64; 2]> {
+    let vertices = (vertices as usize).max(3);
+    (0..vertices)
+        .map(|i| {
+            let angle = TAU * i as f64 / vertices as f64;
+            [
+                center[0] + diameter / 2. * angle.cos(),
+                center[1] + diameter / 2. * angle.sin(),
+            ]
+        })
+        .collect()
+}
+
+/// Smallest convex polygon around the points, counterclockwise
+fn convex_hull(mut points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    };
+    // Lower half from left to right, then the upper half back
+    let mut hull: Vec<[f64; 2]> = vec![];
+    for half in [
+        points.iter().collect::<Vec<_>>(),
+        points.iter().rev().collect(),
+    ] {
+        let start = hull.len();
+        for point in half {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], *point) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        // Each half ends where the other starts
+        hull.pop();
+    }
+    hull
+}
+
+/// Split a Gerber file into its commands, with extended commands (`%...%`) keeping their `%`
+fn gerber_commands(text: &str) -> Vec<String> {
+    let mut commands = vec![];
+    let mut command = String::new();
+    let mut extended = false;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            '%' => {
+                extended = !extended;
+                command.clear();
+            }
+            '*' => {
+                if !command.is_empty() {
+                    commands.push(if extended {
+                        format!("%{command}")
+                    } else {
+                        command.clone()
+                    });
+                }
+                command.clear();
+            }
+            c => command.push(c),
+        }
+    }
+    commands
+}
+
+/// Words of a command as letters and the text of their values, i.e. `X100Y-20D01` as X, Y and D
+fn words(command: &str) -> Vec<(char, &str)> {
+    let mut words = vec![];
+    let mut rest = command;
+    while let Some(letter) = rest.chars().next() {
+        let value_len = rest[1..]
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(rest.len() - 1);
+        words.push((letter, &rest[1..1 + value_len]));
+        rest = &rest[1 + value_len..];
+    }
+    words
+}
+
+fn parse_gerber(text: &str) -> Result<Vec<Shape>, String> {
+    let mut format = NumberFormat {
+        integer_digits: 3,
+        decimal_digits: 6,
+        omits_leading: true,
+    };
+    let mut unit = 1.;
+    let mut macros = HashMap::<String, Vec<String>>::new();
+    // Macro whose primitives are being read
+    let mut defining: Option<String> = None;
+    let mut apertures = HashMap::new();
+    let mut aperture = None;
+    let mut interpolation = Interpolation::Linear;
+    let mut position = [0., 0.];
+    let mut operation = None;
+    let mut region: Option<Vec<[f64; 2]>> = None;
+    let mut unsupported = HashSet::new();
+    let mut shapes = vec![];
+
+    for command in gerber_commands(text) {
+        if let Some(extended) = command.strip_prefix('%') {
+            if let Some(name) = &defining
+                && extended.starts_with(|c: char| c.is_ascii_digit() || c == '$')
+            {
+                macros
+                    .entry(name.clone())
+                    .or_default()
+                    .push(extended.to_string());
+                continue;
+            }
+            defining = None;
+            if let Some(name) = extended.strip_prefix("AM") {
+                macros.insert(name.to_string(), vec![]);
+                defining = Some(name.to_string());
+            } else if let Some(spec) = extended.strip_prefix("FS") {
+                let omits_leading = !spec.starts_with('T');
+                let digits = spec
+                    .split_once('X')
+                    .map(|(_, digits)| digits.chars().take(2).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                if let [integer, decimal] = digits[..] {
+                    format = NumberFormat {
+                        integer_digits: integer.to_digit(10).unwrap_or(3) as usize,
+                        decimal_digits: decimal.to_digit(10).unwrap_or(6) as usize,
+                        omits_leading,
+                    };
+                }
+            } else if extended == "MOMM" {
+                unit = 1.;
+            } else if extended == "MOIN" {
+                unit = MM_PER_INCH;
+            } else if let Some(definition) = extended.strip_prefix("ADD") {
+                let split = definition
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(definition.len());
+                let (code, template) = definition.split_at(split);
+                let (name, parameters) = template.split_once(',').unwrap_or((template, ""));
+                let parameters = parameters
+                    .split('X')
+                    .filter_map(|parameter| parameter.parse::<f64>().ok())
+                    .collect::<Vec<_>>();
+                let sizes = parameters
+                    .iter()
+                    .map(|parameter| parameter * unit)
+                    .collect::<Vec<_>>();
+                let definition = match (name, sizes.as_slice()) {
+                    ("C", [diameter, ..]) => Aperture::Circle(*diameter),
+                    ("R", [width, height, ..]) => Aperture::Rectangle([*width, *height]),
+                    ("O", [width, height, ..]) => Aperture::Obround([*width, *height]),
+                    ("P", [diameter, ..]) => {
+                        let vertices = parameters.get(1).copied().unwrap_or_default();
+                        let rotation = parameters.get(2).copied().unwrap_or_default();
+                        Aperture::Shapes(vec![Shape::Polygon(
+                            regular_polygon([0., 0.], *diameter, vertices)
+                                .into_iter()
+                                .map(|point| rotate(point, rotation))
+                                .collect(),
+                        )])
+                    }
+                    _ => match macros.get(name) {
+                        Some(body) => Aperture::Shapes(expand_macro(body, &parameters, unit)?),
+                        None => {
+                            return Err(format!(
+                                "Aperture D{code} has the unknown template {name}"
+                            ));
+                        }
+                    },
+                };
+                apertures.insert(code.parse::<u32>().unwrap_or_default(), definition);
+            } else if extended.starts_with("LPC") && unsupported.insert("clear polarity") {
+                warn!("Clear polarity is not supported, so cutouts in copper are left out");
+            }
+            continue;
+        }
+        if command.starts_with("G04") {
+            continue;
+        }
+
+        let mut to = [None, None];
+        let mut offset = [0., 0.];
+        let mut operates = false;
+        for (letter, value) in words(&command) {
+            match letter {
+                'G' => match value.parse::<u32>().unwrap_or_default() {
+                    1 => interpolation = Interpolation::Linear,
+                    2 => interpolation = Interpolation::Clockwise,
+                    3 => interpolation = Interpolation::Counterclockwise,
+                    36 => region = Some(vec![]),
+                    37 => {
+                        if let Some(points) = region.take().filter(|points| points.len() > 2) {
+                            shapes.push(Shape::Polygon(points));
+                        }
+                    }
+                    70 => unit = MM_PER_INCH,
+                    71 => unit = 1.,
+                    74 if unsupported.insert("single quadrant") => {
+                        warn!(
+                            "Single quadrant arcs are read as multi quadrant ones, which may misplace them"
+                        );
+                    }
+                    _ => {}
+                },
+                'X' => to[0] = Some(format.parse(value)? * unit),
+                'Y' => to[1] = Some(format.parse(value)? * unit),
+                'I' => offset[0] = format.parse(value)? * unit,
+                'J' => offset[1] = format.parse(value)? * unit,
+                'D' => match value.parse::<u32>().unwrap_or_default() {
+                    code @ 1..=3 => {
+                        operation = Some(code);
+                        operates = true;
+                    }
+                    code => {
+                        aperture =
+                            Some(apertures.get(&code).cloned().ok_or_else(|| {
+                                format!("Aperture D{code} is used but not defined")
+                            })?);
+                    }
+                },
+                'M' if value.parse::<u32>() == Ok(2) => return Ok(shapes),
+                _ => {}
+            }
+        }
+        // Coordinates without an operation repeat the last one, as older files do
+        if to == [None, None] && offset == [0., 0.] && !operates {
+            continue;
+        }
+        let target = [to[0].unwrap_or(position[0]), to[1].unwrap_or(position[1])];
+        match operation {
+            Some(1) => {
+                let points = match interpolation {
+                    Interpolation::Linear => vec![target],
+                    _ => arc_points(
+                        position,
+                        target,
+                        offset,
+                        interpolation == Interpolation::Counterclockwise,
+                    ),
+                };
+                match &mut region {
+                    Some(region) => {
+                        if region.is_empty() {
+                            region.push(position);
+                        }
+                        region.extend(points);
+                    }
+                    None => {
+                        let aperture = aperture.as_ref().ok_or_else(|| {
+                            format!("{command} draws before selecting an aperture")
+                        })?;
+                        let mut from = position;
+                        for to in points {
+                            shapes.extend(stroke(aperture, from, to)?);
+                            from = to;
+                        }
+                    }
+                }
+            }
+            Some(2) => {
+                if let Some(region) = &mut region {
+                    // A new contour of the same region
+                    if region.len() > 2 {
+                        shapes.push(Shape::Polygon(std::mem::take(region)));
+                    }
+                    region.clear();
+                }
+            }
+            Some(3) => match aperture.as_ref() {
+                None => return Err(format!("{command} flashes before selecting an aperture")),
+                Some(&Aperture::Circle(diameter)) => shapes.push(Shape::Circle {
+                    center: target,
+                    radius: diameter / 2.,
+                }),
+                Some(&Aperture::Rectangle([width, height])) => shapes.push(Shape::Rectangle {
+                    center: target,
+                    half_size: [width / 2., height / 2.],
+                }),
+                Some(&Aperture::Obround([width, height])) => {
+                    let radius = width.min(height) / 2.;
+                    let [dx, dy] = [width / 2. - radius, height / 2. - radius];
+                    shapes.push(Shape::Stroke {
+                        from: [target[0] - dx, target[1] - dy],
+                        to: [target[0] + dx, target[1] + dy],
+                        radius,
+                    });
+                }
+                Some(Aperture::Shapes(aperture)) => {
+                    shapes.extend(aperture.iter().map(|shape| shape.translated(target)));
+                }
+            },
+            _ => {}
+        }
+        position = target;
+    }
+    Ok(shapes)
+}
+
+/// Copper of a line drawn with an aperture, which is the aperture swept from one end of the line to the other
+fn stroke(aperture: &Aperture, from: [f64; 2], to: [f64; 2]) -> Result<Vec<Shape>, String> {
+    let ends = |[dx, dy]: [f64; 2], corners: &[[f64; 2]]| {
+        convex_hull(
+            [from, to]
+                .iter()
+                .flat_map(|[x, y]| {
+                    corners
+                        .iter()
+                        .map(move |[cx, cy]| [x + cx * dx, y + cy * dy])
+                })
+                .collect(),
+        )
+    };
+    match aperture {
+        Aperture::Circle(diameter) => Ok(vec![Shape::Stroke {
+            from,
+            to,
+            radius: diameter / 2.,
+        }]),
+        Aperture::Rectangle([width, height]) => Ok(vec![Shape::Polygon(ends(
+            [width / 2., height / 2.],
+            &[[-1., -1.], [1., -1.], [1., 1.], [-1., 1.]],
+        ))]),
+        Aperture::Obround([width, height]) => {
+            // The straight middle of the obround swept along the line, rounded by the ends of the obround
+            let radius = width.min(*height) / 2.;
+            let hull = ends(
+                [width / 2. - radius, height / 2. - radius],
+                &[[-1., -1.], [1., 1.]],
+            );
+            let mut shapes = hull
+                .iter()
+                .zip(hull.iter().cycle().skip(1))
+                .map(|(from, to)| Shape::Stroke {
+                    from: *from,
+                    to: *to,
+                    radius,
+                })
+                .collect::<Vec<_>>();
+            if hull.len() > 2 {
+                shapes.push(Shape::Polygon(hull));
+            }
+            Ok(shapes)
+        }
+        Aperture::Shapes(_) => {
+            Err("Lines can only be drawn with circle, rectangle and obround apertures".to_string())
+        }
+    }
+}
+
+/// Points along an arc from `start` to `end` around `start + offset`, not including `start`
+///
+/// An arc that ends where it starts is a full circle.
+fn arc_points(
+    start: [f64; 2],
+    end: [f64; 2],
+    offset: [f64; 2],
+    counterclockwise: bool,
+) -> Vec<[f64; 2]> {
+    let center = [start[0] + offset[0], start[1] + offset[1]];
+    let radius = offset[0].hypot(offset[1]);
+    let angle = |[x, y]: [f64; 2]| (y - center[1]).atan2(x - center[0]);
+    let start_angle = angle(start);
+    let mut sweep = angle(end) - start_angle;
+    if counterclockwise {
+        while sweep <= 0. {
+            sweep += TAU;
+        }
+    } else {
+        while sweep >= 0. {
+            sweep -= TAU;
+        }
+    }
+    let steps = (sweep.abs() / TAU * ARC_SEGMENTS).ceil().max(1.) as usize;
+    let mut points = (1..steps)
+        .map(|i| {
+            let angle = start_angle + sweep * i as f64 / steps as f64;
+            [
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    points.push(end);
+    points
+}
+
+/// Drill of an Excellon file, with the holes it drills in millimeters
+#[derive(Debug)]
+struct Tool {
+    /// Name of the tool, i.e. T1
+    name: String,
+    diameter: f64,
+    holes: Vec<[f64; 2]>,
+}
+
+fn parse_excellon(text: &str) -> Result<Vec<Tool>, String> {
+    let mut unit = MM_PER_INCH;
+    let mut format = NumberFormat {
+        integer_digits: 2,
+        decimal_digits: 4,
+        omits_leading: true,
+    };
+    let mut diameters = HashMap::new();
+    let mut tools: Vec<Tool> = vec![];
+    let mut tool = None;
+    let mut position = [0., 0.];
+    let mut warned = false;
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with("METRIC") || line.starts_with("INCH") {
+            let metric = line.starts_with("METRIC");
+            unit = if metric { 1. } else { MM_PER_INCH };
+            // Leading zeros included means trailing zeros are left out
+            let omits_leading = !line.contains("LZ");
+            let (integer_digits, decimal_digits) = line
+                .split(',')
+                .find_map(|part| part.split_once('.'))
+                .map(|(integer, decimal)| (integer.len(), decimal.len()))
+                .unwrap_or(if metric { (3, 3) } else { (2, 4) });
+            format = NumberFormat {
+                integer_digits,
+                decimal_digits,
+                omits_leading,
+            };
+            continue;
+        }
+        match line {
+            "M71" => unit = 1.,
+            "M72" => unit = MM_PER_INCH,
+            "M30" => break,
+            _ => {}
+        }
+        if line.starts_with('T') {
+            let words = words(line);
+            let code = words
+                .first()
+                .map(|(_, value)| value.parse::<u32>().unwrap_or_default())
+                .unwrap_or_default();
+            match words.iter().find(|(letter, _)| *letter == 'C') {
+                Some((_, diameter)) => {
+                    let diameter = diameter
+                        .parse::<f64>()
+                        .map_err(|_| format!("{line} does not have a valid tool diameter"))?;
+                    diameters.insert(code, diameter * unit);
+                }
+                None => tool = Some(code),
+            }
+            continue;
+        }
+        if line.starts_with('X') || line.starts_with('Y') {
+            if line.contains("G85") && !warned {
+                warn!("Slots are not supported, so only their starts are drilled");
+                warned = true;
+            }
+            for (letter, value) in words(line.split("G85").next().unwrap_or(line)) {
+                match letter {
+                    'X' => position[0] = format.parse(value)? * unit,
+                    'Y' => position[1] = format.parse(value)? * unit,
+                    _ => {}
+                }
+            }
+            let Some(code) = tool else {
+                return Err(format!("{line} drills a hole before selecting a tool"));
+            };
+            let name = format!("T{code}");
+            match tools.iter_mut().find(|tool| tool.name == name) {
+                Some(tool) => tool.holes.push(position),
+                None => tools.push(Tool {
+                    name,
+                    diameter: diameters.get(&code).copied().unwrap_or_default(),
+                    holes: vec![position],
+                }),
+            }
+        }
+    }
+    Ok(tools)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Whether a point is covered by any of the copper
+    fn covered(shapes: &[Shape], point: [f64; 2]) -> bool {
+        shapes.iter().any(|shape| shape.distance(point) < 0.)
+    }
+
+    fn assert_near(a: [f64; 2], b: [f64; 2]) {
+        assert!(
+            (a[0] - b[0]).hypot(a[1] - b[1]) < 1e-9,
+            "{a:?} is not {b:?}"
+        );
+    }
+
+    #[test]
+    fn coordinates_follow_the_format_spec() {
+        let leading = NumberFormat {
+            integer_digits: 2,
+            decimal_digits: 4,
+            omits_leading: true,
+        };
+        assert_eq!(leading.parse("15000").unwrap(), 1.5);
+        assert_eq!(leading.parse("-25").unwrap(), -0.0025);
+        assert_eq!(leading.parse("1.25").unwrap(), 1.25);
+        let trailing = NumberFormat {
+            omits_leading: false,
+            ..leading
+        };
+        assert_eq!(trailing.parse("15").unwrap(), 15.);
+        assert_eq!(trailing.parse("+0015").unwrap(), 0.15);
+        assert!(leading.parse("1E5").is_err());
+
+        let shapes = parse_gerber("%FSTAX24Y24*%%MOMM*%%ADD10C,0.5*%D10*X01Y0025D03*M02*").unwrap();
+        let [Shape::Circle { center, radius }] = shapes.as_slice() else {
+            panic!("expected a circle, got {shapes:?}");
+        };
+        assert_near(*center, [1., 0.25]);
+        assert_eq!(*radius, 0.25);
+    }
+
+    #[test]
+    fn inches_are_converted_to_millimeters() {
+        let shapes =
+            parse_gerber("%FSLAX24Y24*%%MOIN*%%ADD10C,0.1*%D10*X10000Y5000D03*M02*").unwrap();
+        let [Shape::Circle { center, radius }] = shapes.as_slice() else {
+            panic!("expected a circle, got {shapes:?}");
+        };
+        assert_near(*center, [25.4, 12.7]);
+        assert!((radius - 1.27).abs() < 1e-9);
+    }
+
+    #[test]
+    fn operations_are_modal() {
+        // D02 moves, D01 draws, coordinates alone repeat D01, and D03 flashes
+        let shapes = parse_gerber(
+            "%FSLAX33Y33*%%MOMM*%%ADD10C,0.5*%D10*X0Y0D02*X1000D01*Y1000*X2000D03*Y2000D02*M02*",
+        )
+        .unwrap();
+        let [
+            Shape::Stroke {
+                from: from_a,
+                to: to_a,
+                ..
+            },
+            Shape::Stroke {
+                from: from_b,
+                to: to_b,
+                ..
+            },
+            Shape::Circle { center, .. },
+        ] = shapes.as_slice()
+        else {
+            panic!("expected two lines and a flash, got {shapes:?}");
+        };
+        assert_near(*from_a, [0., 0.]);
+        assert_near(*to_a, [1., 0.]);
+        assert_near(*from_b, [1., 0.]);
+        assert_near(*to_b, [1., 1.]);
+        assert_near(*center, [2., 1.]);
+    }
+
+    #[test]
+    fn arcs_are_flattened_around_their_center() {
+        let shapes = parse_gerber(
+            "%FSLAX33Y33*%%MOMM*%%ADD10C,0.1*%D10*G75*X1000Y0D02*G03X0Y1000I-1000J0D01*M02*",
+        )
+        .unwrap();
+        assert_eq!(shapes.len(), (ARC_SEGMENTS / 4.) as usize);
+        for shape in &shapes {
+            let Shape::Stroke { from, to, .. } = shape else {
+                panic!("expected a line, got {shape:?}");
+            };
+            for [x, y] in [from, to] {
+                assert!((x.hypot(*y) - 1.).abs() < 1e-9);
+                assert!(
+                    *x >= -1e-9 && *y >= -1e-9,
+                    "({x}, {y}) is off the quarter circle"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn regions_are_filled_with_a_polygon_for_each_contour() {
+        let shapes = parse_gerber(
+            "%FSLAX33Y33*%%MOMM*%G36*X0Y0D02*X1000D01*Y1000D01*X0D01*Y0D01*\
+             X3000Y0D02*X4000D01*Y1000D01*X3000D01*Y0D01*G37*M02*",
+        )
+        .unwrap();
+        assert_eq!(shapes.len(), 2);
+        assert!(
+            shapes
+                .iter()
+                .all(|shape| matches!(shape, Shape::Polygon(_)))
+        );
+        assert!(covered(&shapes, [0.5, 0.5]));
+        assert!(covered(&shapes, [3.5, 0.5]));
+        assert!(!covered(&shapes, [2., 0.5]));
+    }
+
+    #[test]
+    fn rectangles_and_obrounds_are_swept_along_lines() {
+        let rectangle =
+            parse_gerber("%FSLAX33Y33*%%MOMM*%%ADD10R,1X0.2*%D10*X0Y0D02*Y2000D01*M02*").unwrap();
+        // Its full width, rather than the circle that fits inside of it
+        assert!(covered(&rectangle, [0.45, 1.]));
+        assert!(covered(&rectangle, [0.45, -0.05]));
+        assert!(!covered(&rectangle, [0.55, 1.]));
+        assert!(!covered(&rectangle, [0., -0.15]));
+
+        let obround =
+            parse_gerber("%FSLAX33Y33*%%MOMM*%%ADD10O,1X0.2*%D10*X0Y0D02*X1000Y1000D01*M02*")
+                .unwrap();
+        assert!(covered(&obround, [-0.45, 0.]));
+        assert!(covered(&obround, [1.45, 1.]));
+        assert!(covered(&obround, [0., 0.5]));
+        assert!(!covered(&obround, [-0.65, 0.]));
+        assert!(!covered(&obround, [1., 0.]));
+    }
+
+    #[test]
+    fn aperture_macros_are_flashed() {
+        // Like KiCad's rounded rectangles, with the corners and sizes as parameters
+        let shapes = parse_gerber(
+            "%FSLAX33Y33*%%MOMM*%\
+             %AMRoundRect*0 Rounded rectangle*$10=$1x2*\
+             4,1,4,$2,$3,$4,$5,$6,$7,$8,$9,$2,$3,0*1,1,$10,$2,$3*1,1,$10,$4,$5*1,1,$10,$6,$7*1,1,$10,$8,$9*\
+             20,1,$1+$1,$2,$3,$4,$5,0*20,1,$1+$1,$4,$5,$6,$7,0*\
+             20,1,$1+$1,$6,$7,$8,$9,0*20,1,$1+$1,$8,$9,$2,$3,0*%\
+             %AMTriangle*5,1,3,0,0,$1,90*21,1,1,0.2,0,-1,0*%\
+             %ADD10RoundRect,0.25X-1X-0.5X1X-0.5X1X0.5X-1X0.5*%%ADD11Triangle,2*%\
+             D10*X10000Y0D03*D11*X20000Y0D03*M02*",
+        )
+        .unwrap();
+        assert!(covered(&shapes, [10., 0.]));
+        assert!(covered(&shapes, [11.2, 0.]));
+        assert!(covered(&shapes, [8.8, 0.4]));
+        // The rounded corner
+        assert!(!covered(&shapes, [11.24, 0.74]));
+
+        // Pointing up, with the bar below it
+        assert!(covered(&shapes, [20., 0.9]));
+        assert!(!covered(&shapes, [20.8, 0.5]));
+        assert!(covered(&shapes, [20.4, -1.05]));
+    }
+
+    #[test]
+    fn unsupported_apertures_are_errors() {
+        for gerber in [
+            // Thermal
+            "%FSLAX33Y33*%%MOMM*%%AMThermal*7,0,0,1,0.8,0.1,45*%%ADD10Thermal*%D10*X0Y0D03*M02*",
+            // Clearing copper
+            "%FSLAX33Y33*%%MOMM*%%AMHole*1,0,1,0,0*%%ADD10Hole*%D10*X0Y0D03*M02*",
+            // Never defined
+            "%FSLAX33Y33*%%MOMM*%%ADD10Missing*%D10*X0Y0D03*M02*",
+            "%FSLAX33Y33*%%MOMM*%D11*X0Y0D03*M02*",
+            // Drawing a line with a polygon
+            "%FSLAX33Y33*%%MOMM*%%ADD10P,1X6*%D10*X0Y0D02*X1000D01*M02*",
+        ] {
+            assert!(parse_gerber(gerber).is_err(), "{gerber} was read");
+        }
+    }
+
+    #[test]
+    fn macro_expressions_follow_precedence() {
+        let variables = HashMap::from([(1, 2.), (2, 0.5)]);
+        assert_eq!(evaluate("$1+$2x2", &variables).unwrap(), 3.);
+        assert_eq!(evaluate("($1+$2)x2", &variables).unwrap(), 5.);
+        assert_eq!(evaluate("-$1/4-1", &variables).unwrap(), -1.5);
+        assert_eq!(evaluate("$3", &variables).unwrap(), 0.);
+        assert!(evaluate("$1+", &variables).is_err());
+        assert!(evaluate("(1", &variables).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fs, path::Path};
+
+use roxmltree::Document;
+use svg2gcode::{ConversionOptions, Float, PreviewTurtle, Settings, svg2polylines, svg2turtle};
+
+/// Write a preview of the toolpath as SVG, or as PNG when the path ends in `.png`
+///
+/// The preview is drawn by the same conversion as the G-Code, with the same transforms and path order.
+pub fn write(
+    document: &Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    path: &Path,
+) -> Result<(), String> {
+    let svg = svg2turtle(
+        document,
+        &settings.conversion,
+        options,
+        PreviewTurtle::new(),
+    )
+    .map_err(|err| err.to_string())?
+    .to_string();
+    if path.extension().is_some_and(|extension| extension == "png") {
+        png(&svg, path)
+    } else {
+        fs::write(path, svg).map_err(|err| err.to_string())
+    }
+}
+
+/// Width of the terminal preview in characters, each of which holds 2×4 braille dots
+const TERMINAL_COLUMNS: usize = 80;
+/// Most lines of the terminal preview, so that tall drawings still fit on screen
+const TERMINAL_ROWS: usize = 40;
+
+/// Bits of the dots in a braille character, by row then column
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Plot the cuts of the toolpath in the terminal with braille characters, followed by the size of the job
+///
+/// It's written to standard error so that it doesn't mix with G-Code written to standard out.
+pub fn print_terminal(
+    document: &Document,
+    settings: &Settings,
+    options: ConversionOptions,
+) -> Result<(), String> {
+    let polylines =
+        svg2polylines(document, &settings.conversion, options).map_err(|err| err.to_string())?;
+    let extents =
+        polylines
+            .iter()
+            .flat_map(|polyline| &polyline.points)
+            .fold(None, |extents, point| {
+                let (min, max) = extents.unwrap_or((*point, *point));
+                Some((min.min(*point), max.max(*point)))
+            });
+    let Some((min, max)) = extents else {
+        eprintln!("Nothing to cut");
+        return Ok(());
+    };
+
+    let size = max - min;
+    let scale = ((TERMINAL_COLUMNS * 2 - 1) as Float / size.x)
+        .min((TERMINAL_ROWS * 4 - 1) as Float / size.y);
+    // Infinite when the job is a single point
+    let scale = if scale.is_finite() { scale } else { 0. };
+    let columns = (size.x * scale) as usize / 2 + 1;
+    let rows = (size.y * scale) as usize / 4 + 1;
+    let mut cells = vec![vec![0u8; columns]; rows];
+    // Y points up on the machine but down in the terminal
+    let mut plot = |x: Float, y: Float| {
+        let (x, y) = (
+            ((x - min.x) * scale).round() as usize,
+            ((max.y - y) * scale).round() as usize,
+        );
+        if let Some(cell) = cells.get_mut(y / 4).and_then(|row| row.get_mut(x / 2)) {
+            *cell |= BRAILLE_DOTS[y % 4][x % 2];
+        }
+    };
+    for polyline in &polylines {
+        if let [point] = polyline.points.as_slice() {
+            plot(point.x, point.y);
+        }
+        for segment in polyline.points.windows(2) {
+            let (from, to) = (segment[0], segment[1]);
+            let steps = ((to.x - from.x).abs().max((to.y - from.y).abs()) * scale)
+                .ceil()
+                .max(1.);
+            for step in 0..=steps as usize {
+                let point = from.lerp(to, step as Float / steps);
+                plot(point.x, point.y);
+            }
+        }
+    }
+
+    for row in cells {
+        eprintln!(
+            "{}",
+            row.into_iter()
+                .map(|dots| char::from_u32(0x2800 + u32::from(dots)).unwrap_or(' '))
+                .collect::<String>()
+        );
+    }
+    eprintln!("{:.3} × {:.3} mm", size.x, size.y);
+    Ok(())
+}
+
+/// Longest side of a PNG preview in pixels
+#[cfg(feature = "raster")]
+const PNG_SIZE: f32 = 1600.;
+
+#[cfg(feature = "raster")]
+fn png(svg: &str, path: &Path) -> Result<(), String> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree =
+        usvg::Tree::from_str(svg, &usvg::Options::default()).map_err(|err| err.to_string())?;
+    let size = tree.size();
+    let scale = PNG_SIZE / size.width().max(size.height());
+    let mut pixmap = tiny_skia::Pixmap::new(
+        (size.width() * scale).ceil() as u32,
+        (size.height() * scale).ceil() as u32,
+    )
+    .ok_or("the preview is empty")?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.save_png(path).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "raster"))]
+fn png(_svg: &str, _path: &Path) -> Result<(), String> {
+    Err("PNG previews require svg2gcode-cli to be built with the raster feature".to_string())
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+use log::{error, info};
+use svg2gcode::Settings;
+
+use crate::config;
+
+/// Managing named settings profiles, i.e. one for each machine
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List the saved and built-in profiles
+    List,
+    /// Print a saved or built-in profile
+    Show { name: String },
+    /// Save the settings given by the other arguments as a profile, replacing any with the same name
+    Save { name: String },
+}
+
+/// Run a profile subcommand, with the settings to save for [ProfileCommand::Save]
+///
+/// Errors are logged, exiting with an error.
+pub fn run(command: ProfileCommand, settings: &Settings) -> io::Result<()> {
+    if let Err(err) = try_run(command, settings) {
+        error!("{err}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn try_run(command: ProfileCommand, settings: &Settings) -> io::Result<()> {
+    match command {
+        ProfileCommand::List => {
+            for name in list()? {
+                println!("{name}");
+            }
+        }
+        ProfileCommand::Show { name } => print!("{}", text(&name)?),
+        ProfileCommand::Save { name } => {
+            let path = save(&name, settings)?;
+            info!("Saved profile {name} to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Profiles that come with svg2gcode as TOML settings, which a saved profile with the same name replaces
+const BUILT_IN: &[(&str, &str)] = &[
+    ("axidraw", include_str!("../profiles/axidraw.toml")),
+    ("klipper", include_str!("../profiles/klipper.toml")),
+];
+
+/// Settings of a saved or built-in profile
+pub fn load(name: &str) -> io::Result<Settings> {
+    config::parse(&text(name)?, true)
+}
+
+/// TOML of a saved profile, or else of a built-in one
+fn text(name: &str) -> io::Result<String> {
+    if !path(name)?.exists()
+        && let Some((_, text)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name)
+    {
+        return Ok(text.to_string());
+    }
+    fs::read_to_string(saved_path(name)?)
+}
+
+/// Save settings as a profile, replacing any with the same name, returning where it was saved
+pub fn save(name: &str, settings: &Settings) -> io::Result<PathBuf> {
+    let path = path(name)?;
+    fs::create_dir_all(dir()?)?;
+    config::export(settings, &path)?;
+    Ok(path)
+}
+
+/// Whether there is a saved profile with this name
+pub fn exists(name: &str) -> io::Result<bool> {
+    Ok(path(name)?.exists())
+}
+
+/// Names of the saved and built-in profiles, in order
+fn list() -> io::Result<Vec<String>> {
+    let mut names = BUILT_IN
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    let dir = dir()?;
+    if dir.exists() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+                && let Some(name) = path.file_stem()
+            {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Profiles are kept as TOML settings files in the user's config directory, i.e. `~/.config/svg2gcode/profiles` on Linux
+fn dir() -> io::Result<PathBuf> {
+    dirs::config_dir()
+        .map(|config| config.join("svg2gcode").join("profiles"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory was found"))
+}
+
+fn path(name: &str) -> io::Result<PathBuf> {
+    let valid = !name.is_empty()
+        && Path::new(name).file_name() == Some(name.as_ref())
+        && !name.contains(['/', '\\']);
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name} is not a valid profile name"),
+        ));
+    }
+    Ok(dir()?.join(name).with_extension("toml"))
+}
+
+/// Path of a profile that has been saved
+fn saved_path(name: &str) -> io::Result<PathBuf> {
+    let path = path(name)?;
+    if !path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("There is no profile named {name}, see `svg2gcode profile list`"),
+        ));
+    }
+    Ok(path)
+}
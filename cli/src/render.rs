@@ -0,0 +1,177 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use g_code::parse::ParseError;
+use log::error;
+
+use crate::{
+    EXIT_PARSE_ERROR, is_stdio,
+    simulate::{Motion, Move, simulate},
+};
+
+const CUT_COLOR: &str = "#d62728";
+const TRAVEL_COLOR: &str = "#7f7f7f";
+const DRILL_COLOR: &str = "#1f77b4";
+/// Opacity of a cut at the least power, so that faint cuts still show
+const MIN_OPACITY: f64 = 0.2;
+
+/// Read a G-Code file, or standard in, and write it drawn as an SVG to `out`, or standard out
+///
+/// Exits with an error if the G-Code can't be parsed.
+pub fn write(file: Option<&Path>, out: Option<&Path>) -> io::Result<()> {
+    let input = match file.filter(|file| !is_stdio(file)) {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let svg = match render(&input) {
+        Ok(svg) => svg,
+        Err(err) => {
+            error!("The G-Code could not be parsed: {err}");
+            std::process::exit(EXIT_PARSE_ERROR);
+        }
+    };
+    match out.filter(|out| !is_stdio(out)) {
+        Some(out) => fs::write(out, svg),
+        None => io::stdout().write_all(svg.as_bytes()),
+    }
+}
+
+/// Draw any program as an SVG of what it cuts, to inspect G-Code the same way as generated previews
+///
+/// Cuts are solid and fade with their power relative to the most powerful cut, travel moves and cuts with the tool
+/// turned off are dashed, and canned cycles are circled. Coordinates are in millimeters with the Y axis pointing up,
+/// as the machine sees them. Moves from positions the program hasn't set yet are left out.
+fn render(gcode: &str) -> Result<String, ParseError> {
+    let moves = simulate(gcode)?
+        .into_iter()
+        .filter(|m| m.from[0].is_some() && m.from[1].is_some())
+        .filter(|m| m.to[0].is_some() && m.to[1].is_some())
+        .collect::<Vec<_>>();
+    let is_cut =
+        |m: &Move| matches!(m.motion, Motion::Line | Motion::Arc(_)) && m.power != Some(0.);
+    let max_power = moves
+        .iter()
+        .filter(|m| is_cut(m))
+        .filter_map(|m| m.power)
+        .fold(0., f64::max);
+
+    let mut min = [f64::INFINITY; 2];
+    let mut max = [f64::NEG_INFINITY; 2];
+    for m in &moves {
+        let mut reached = vec![xy(m.from), xy(m.to)];
+        if let Motion::Arc(arc) = &m.motion {
+            reached.extend(arc.extremes());
+        }
+        for [x, y] in reached {
+            min = [min[0].min(x), min[1].min(y)];
+            max = [max[0].max(x), max[1].max(y)];
+        }
+    }
+    if moves.is_empty() {
+        (min, max) = ([0.; 2], [0.; 2]);
+    }
+    let stroke_width = (max[0] - min[0]).max(max[1] - min[1]).max(1.) / 400.;
+    let margin = stroke_width * 4.;
+    let (min, max) = (
+        [min[0] - margin, min[1] - margin],
+        [max[0] + margin, max[1] + margin],
+    );
+    let size = [max[0] - min[0], max[1] - min[1]];
+
+    let mut svg = String::new();
+    // Writing to a string can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}mm" height="{}mm" viewBox="{} {} {} {}">"#,
+        size[0], size[1], min[0], -max[1], size[0], size[1]
+    );
+    // Flip so that Y points up
+    let _ = writeln!(
+        svg,
+        r#"<g transform="scale(1 -1)" fill="none" stroke-width="{stroke_width}" stroke-linejoin="round" stroke-linecap="round">"#
+    );
+
+    let _ = writeln!(
+        svg,
+        r#"<g stroke="{TRAVEL_COLOR}" stroke-dasharray="{margin} {margin}">"#
+    );
+    for m in moves.iter().filter(|m| !is_cut(m)) {
+        let ([x0, y0], [x1, y1]) = (xy(m.from), xy(m.to));
+        let _ = writeln!(svg, r#"<line x1="{x0}" y1="{y0}" x2="{x1}" y2="{y1}"/>"#);
+    }
+    let _ = writeln!(svg, "</g>");
+
+    // Consecutive cuts at the same power are drawn as one path
+    let _ = writeln!(svg, r#"<g stroke="{CUT_COLOR}">"#);
+    let mut cuts = moves.iter().filter(|m| is_cut(m)).peekable();
+    while let Some(first) = cuts.next() {
+        let [x, y] = xy(first.from);
+        let mut data = format!("M{x},{y}");
+        let mut current = first;
+        loop {
+            path_data(&mut data, current);
+            match cuts.peek() {
+                Some(next) if next.from == current.to && next.power == first.power => {
+                    current = cuts.next().expect("peeked");
+                }
+                _ => break,
+            }
+        }
+        let opacity = match first.power {
+            Some(power) if max_power > 0. => MIN_OPACITY + (1. - MIN_OPACITY) * power / max_power,
+            _ => 1.,
+        };
+        let _ = writeln!(svg, r#"<path d="{data}" stroke-opacity="{opacity:.3}"/>"#);
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(svg, r#"<g stroke="{DRILL_COLOR}">"#);
+    for m in moves.iter().filter(|m| matches!(m.motion, Motion::Cycle)) {
+        let [x, y] = xy(m.to);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{x}" cy="{y}" r="{}"/>"#,
+            stroke_width * 3.
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(svg, "</g>");
+    let _ = writeln!(svg, "</svg>");
+    Ok(svg)
+}
+
+/// Continue SVG path data with a cut, which is known to start where the data left off
+fn path_data(data: &mut String, cut: &Move) {
+    let [x, y] = xy(cut.to);
+    match &cut.motion {
+        Motion::Arc(arc) => {
+            // In halves, since an SVG arc can't be a full circle
+            let [mid_x, mid_y] = arc.point(arc.start_angle + arc.sweep / 2.);
+            let (r, sweep) = (arc.radius, u8::from(arc.sweep > 0.));
+            let _ = write!(
+                data,
+                " A{r},{r} 0 0 {sweep} {mid_x},{mid_y} A{r},{r} 0 0 {sweep} {x},{y}"
+            );
+        }
+        _ => {
+            let _ = write!(data, " L{x},{y}");
+        }
+    }
+}
+
+/// XY of a position that is known to be set
+fn xy(position: [Option<f64>; 3]) -> [f64; 2] {
+    [
+        position[0].unwrap_or_default(),
+        position[1].unwrap_or_default(),
+    ]
+}
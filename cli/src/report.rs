@@ -0,0 +1,64 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use log::{Level, Log, Metadata, Record};
+use svg2gcode::ConversionStatistics;
+
+use crate::is_stdio;
+
+/// Everything logged as a warning so far, for the report
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Logger that keeps the warnings it is given, then passes everything on to [env_logger]
+struct WarningLogger(env_logger::Logger);
+
+impl Log for WarningLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Warn {
+            WARNINGS
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(record.args().to_string());
+        }
+        self.0.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Set up logging as configured by `RUST_LOG`, keeping warnings for [write] even when they are filtered out
+pub fn init_logger() {
+    let logger = env_logger::Builder::from_default_env().build();
+    log::set_max_level(logger.filter().max(Level::Warn.to_level_filter()));
+    log::set_boxed_logger(Box::new(WarningLogger(logger))).expect("logger is only set once");
+}
+
+/// Number of warnings logged so far
+pub fn warning_count() -> usize {
+    WARNINGS.lock().unwrap_or_else(|err| err.into_inner()).len()
+}
+
+/// Write the statistics of a program and the warnings logged while converting it as JSON, to standard out for `-`
+pub fn write(statistics: &ConversionStatistics, path: &Path) -> io::Result<()> {
+    let warnings = WARNINGS.lock().unwrap_or_else(|err| err.into_inner());
+    let report = serde_json::json!({
+        "statistics": statistics,
+        "warnings": &*warnings,
+    });
+    let bytes = serde_json::to_vec_pretty(&report)?;
+    if is_stdio(path) {
+        io::stdout().write_all(&bytes)
+    } else {
+        fs::write(path, bytes)
+    }
+}
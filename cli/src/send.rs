@@ -0,0 +1,440 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use tungstenite::{Message, WebSocket, http::Uri};
+
+/// Size of GRBL's receive buffer, which is never sent more than it can hold
+const RX_BUFFER_SIZE: usize = 128;
+/// How often the machine is asked for its state while streaming
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the machine has to greet the sender after a reset
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a feed hold has to bring the machine to a stop
+const HOLD_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a read waits for the machine to say something
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Realtime commands, which GRBL acts on as soon as they arrive instead of queueing them
+const STATUS_QUERY: u8 = b'?';
+const FEED_HOLD: u8 = b'!';
+const SOFT_RESET: u8 = 0x18;
+
+/// Stream G-Code to a GRBL machine on a serial port, stopping with a feed hold on Ctrl+C
+///
+/// A `ws://` URL is connected to as a WebSocket controller instead, i.e. FluidNC or ESP3D, and the baud rate is
+/// ignored.
+pub fn send(port: &str, baud: u32, gcode: &str) -> io::Result<()> {
+    if port.starts_with("ws://") {
+        let connection = WebSocketConnection::connect(port)?;
+        info!("Connected to {port}");
+        return stream(connection, gcode);
+    }
+    let connection = serialport::new(port, baud).timeout(READ_TIMEOUT).open()?;
+    info!("Connected to {port} at {baud} baud");
+    stream(connection, gcode)
+}
+
+/// Stream G-Code over a connection to a GRBL machine, stopping with a feed hold on Ctrl+C
+///
+/// Reads from the connection should time out after a short while rather than block until the machine says
+/// something.
+pub fn stream(connection: impl Read + Write, gcode: &str) -> io::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let stop = stop.clone();
+        move || stop.store(true, Ordering::Relaxed)
+    })
+    .map_err(io::Error::other)?;
+
+    let mut grbl = Grbl::new(connection);
+    grbl.reset()?;
+    let lines = gcode
+        .lines()
+        .map(strip_comments)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    grbl.stream(&lines, &stop)
+}
+
+/// Line without its `;` and `(...)` comments or surrounding whitespace, since GRBL's line buffer is short
+fn strip_comments(line: &str) -> String {
+    let line = line.split(';').next().unwrap_or_default();
+    let mut stripped = String::with_capacity(line.len());
+    let mut in_comment = false;
+    for c in line.chars() {
+        match c {
+            '(' => in_comment = true,
+            ')' if in_comment => in_comment = false,
+            c if !in_comment => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped.trim().to_string()
+}
+
+/// Connection to a machine running GRBL
+struct Grbl<T> {
+    connection: T,
+    /// What has been read but is not yet a complete line
+    received: Vec<u8>,
+    /// Last state reported by the machine, i.e. `Run` or `Hold:0`, cleared when it may have changed since
+    state: String,
+    last_status_query: Option<Instant>,
+}
+
+impl<T: Read + Write> Grbl<T> {
+    fn new(connection: T) -> Self {
+        Self {
+            connection,
+            received: vec![],
+            state: String::new(),
+            last_status_query: None,
+        }
+    }
+
+    /// Ask the machine for its state, unless it was asked recently
+    fn query_status(&mut self) -> io::Result<()> {
+        if self
+            .last_status_query
+            .is_none_or(|last| last.elapsed() >= STATUS_INTERVAL)
+        {
+            self.connection.write_all(&[STATUS_QUERY])?;
+            self.last_status_query = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Keep the state from a status report like `<Run|MPos:1.000,2.000,0.000|FS:500,0>`, returning whether it was one
+    fn read_status(&mut self, response: &str) -> bool {
+        let Some(status) = response.strip_prefix('<') else {
+            return false;
+        };
+        self.state = status
+            .split(['|', '>'])
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        true
+    }
+
+    /// Reset the machine to clear anything left over from before, waiting until it greets the sender
+    fn reset(&mut self) -> io::Result<()> {
+        self.connection.write_all(&[SOFT_RESET])?;
+        let started = Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Some(line) = self.read_line()?
+                && line.starts_with("Grbl")
+            {
+                info!("{line}");
+                return Ok(());
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the machine did not respond, is it running GRBL and is the baud rate right?",
+        ))
+    }
+
+    /// Send the lines with GRBL's character-counting protocol, then wait for the machine to finish moving
+    ///
+    /// As many lines are sent as fit in the machine's receive buffer, and each `ok` or `error` frees the space of
+    /// the oldest one. Lines the machine rejects are reported, and streaming carries on.
+    fn stream(&mut self, lines: &[String], stop: &AtomicBool) -> io::Result<()> {
+        // Indices of the lines that were sent but not yet answered, with the bytes they take up in the buffer
+        let mut in_flight = VecDeque::<(usize, usize)>::new();
+        let mut next = 0;
+        let mut rejected = 0;
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return self.hold();
+            }
+
+            let buffered = in_flight.iter().map(|(_, len)| len).sum::<usize>();
+            if let Some(line) = lines.get(next) {
+                let len = line.len() + 1;
+                // A line too long for the buffer is sent once the buffer is empty, for the machine to reject
+                if in_flight.is_empty() || buffered + len <= RX_BUFFER_SIZE {
+                    self.connection.write_all(format!("{line}\n").as_bytes())?;
+                    in_flight.push_back((next, len));
+                    next += 1;
+                    continue;
+                }
+            } else if in_flight.is_empty() && self.state == "Idle" {
+                break;
+            }
+
+            self.query_status()?;
+            while let Some(response) = self.read_line()? {
+                if response == "ok" {
+                    in_flight.pop_front();
+                    self.state.clear();
+                } else if let Some(code) = response.strip_prefix("error:") {
+                    self.state.clear();
+                    if let Some((line, _)) = in_flight.pop_front() {
+                        error!(
+                            "Line {} `{}` was rejected with error {code}",
+                            line + 1,
+                            lines[line]
+                        );
+                    }
+                    rejected += 1;
+                } else if response.starts_with("ALARM:") {
+                    return Err(io::Error::other(format!(
+                        "the machine stopped with {response}"
+                    )));
+                } else if self.read_status(&response) {
+                    eprint!(
+                        "\r{} of {} lines sent, {}   ",
+                        next,
+                        lines.len(),
+                        self.state
+                    );
+                } else {
+                    info!("{response}");
+                }
+            }
+        }
+        eprintln!();
+
+        if rejected > 0 {
+            Err(io::Error::other(format!(
+                "the machine rejected {rejected} of {} lines",
+                lines.len()
+            )))
+        } else {
+            info!("Sent {} lines", lines.len());
+            Ok(())
+        }
+    }
+
+    /// Bring the machine to a controlled stop, then reset it to discard the rest of the job
+    ///
+    /// Resetting once the hold is complete keeps the machine's position.
+    fn hold(&mut self) -> io::Result<()> {
+        eprintln!();
+        warn!("Stopping with a feed hold");
+        self.connection.write_all(&[FEED_HOLD])?;
+        self.state.clear();
+        let started = Instant::now();
+        while self.state != "Hold:0" && started.elapsed() < HOLD_TIMEOUT {
+            self.query_status()?;
+            while let Some(response) = self.read_line()? {
+                self.read_status(&response);
+            }
+        }
+        self.connection.write_all(&[SOFT_RESET])?;
+        Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "stopped before the job was finished",
+        ))
+    }
+
+    /// Next complete line from the machine, or [None] if the read timed out first
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.received.iter().position(|&byte| byte == b'\n') {
+                let line = self.received.drain(..=end).collect::<Vec<_>>();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            let mut buf = [0; 256];
+            match self.connection.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(len) => self.received.extend_from_slice(&buf[..len]),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// WebSocket connection to a controller, read and written like a serial port so it can be streamed to the same way
+///
+/// The controller's output comes in binary messages, while text messages are its own, i.e. ESP3D's `PING:` and
+/// `CURRENT_ID:`, and are ignored.
+struct WebSocketConnection {
+    socket: WebSocket<TcpStream>,
+    /// Output of the controller that has been received but not yet read
+    received: VecDeque<u8>,
+}
+
+impl WebSocketConnection {
+    fn connect(url: &str) -> io::Result<Self> {
+        let uri = url
+            .parse::<Uri>()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let host = uri.host().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{url} has no host"))
+        })?;
+        let stream = TcpStream::connect((host, uri.port_u16().unwrap_or(80)))?;
+        let (socket, _) = tungstenite::client(uri.clone(), stream).map_err(io::Error::other)?;
+        // Only once the handshake is done, so it isn't interrupted by a timeout
+        socket.get_ref().set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(Self {
+            socket,
+            received: VecDeque::new(),
+        })
+    }
+}
+
+impl Read for WebSocketConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.received.is_empty() {
+            match self.socket.read() {
+                Ok(Message::Binary(data)) => self.received.extend(data.iter()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err)) => return Err(err),
+                Err(
+                    err
+                    @ (tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed),
+                ) => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, err));
+                }
+                Err(err) => return Err(io::Error::other(err)),
+            }
+        }
+        self.received.read(buf)
+    }
+}
+
+impl Write for WebSocketConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket
+            .send(Message::binary(buf.to_vec()))
+            .map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.socket.flush().map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Machine that answers like GRBL, taking one line out of its receive buffer each time it is read from
+    #[derive(Default)]
+    struct MockGrbl {
+        /// Lines in the receive buffer
+        buffer: VecDeque<String>,
+        /// Most bytes that were ever in the receive buffer at once
+        most_buffered: usize,
+        /// What has been written but is not yet a complete line
+        line: Vec<u8>,
+        /// Every line that was received, in order
+        lines: Vec<String>,
+        responses: VecDeque<u8>,
+    }
+
+    impl Read for MockGrbl {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(line) = self.buffer.pop_front() {
+                // Work coordinate systems stand in for unsupported commands
+                let response: &[u8] = if line.starts_with("G5") {
+                    b"error:20\r\n"
+                } else {
+                    b"ok\r\n"
+                };
+                self.responses.extend(response);
+            }
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for MockGrbl {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                match byte {
+                    SOFT_RESET => self.responses.extend(b"\r\nGrbl 1.1h ['$' for help]\r\n"),
+                    STATUS_QUERY => {
+                        let state = if self.buffer.is_empty() {
+                            "Idle"
+                        } else {
+                            "Run"
+                        };
+                        self.responses
+                            .extend(format!("<{state}|MPos:0.000,0.000,0.000|FS:0,0>\r\n").bytes());
+                    }
+                    b'\n' => {
+                        let line = String::from_utf8(std::mem::take(&mut self.line)).unwrap();
+                        self.lines.push(line.clone());
+                        self.buffer.push_back(line);
+                        let buffered = self.buffer.iter().map(|line| line.len() + 1).sum();
+                        self.most_buffered = self.most_buffered.max(buffered);
+                    }
+                    byte => self.line.push(byte),
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn lines(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| format!("G1 X{i}.000 Y{i}.000 F1000"))
+            .collect()
+    }
+
+    #[test]
+    fn streaming_never_overflows_the_receive_buffer() {
+        let lines = lines(40);
+        let mut grbl = Grbl::new(MockGrbl::default());
+        grbl.reset().unwrap();
+        grbl.stream(&lines, &AtomicBool::new(false)).unwrap();
+
+        let machine = grbl.connection;
+        assert_eq!(machine.lines, lines);
+        assert!(machine.buffer.is_empty());
+        // Several lines at a time, but no more than fit
+        assert!(machine.most_buffered > lines[0].len() + 1);
+        assert!(machine.most_buffered <= RX_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn rejected_lines_are_counted_and_streaming_carries_on() {
+        let mut lines = lines(10);
+        lines[3] = "G55".to_string();
+        lines[7] = "G59".to_string();
+        let mut grbl = Grbl::new(MockGrbl::default());
+        grbl.reset().unwrap();
+        let err = grbl.stream(&lines, &AtomicBool::new(false)).unwrap_err();
+
+        assert_eq!(err.to_string(), "the machine rejected 2 of 10 lines");
+        assert_eq!(grbl.connection.lines, lines);
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        assert_eq!(
+            strip_comments("G1 X1 (move) Y2 ; to the corner"),
+            "G1 X1  Y2"
+        );
+        assert_eq!(strip_comments("  (only a comment)  "), "");
+        assert_eq!(strip_comments(";G0 X0"), "");
+    }
+}
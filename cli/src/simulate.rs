@@ -0,0 +1,347 @@
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use g_code::{
+    emit::Token,
+    parse::{ParseError, snippet_parser},
+};
+
+/// Millimeters in an inch, for programs in `G20` units
+const MM_PER_INCH: f64 = 25.4;
+
+/// A move of the machine, in millimeters
+#[derive(Debug, Clone)]
+pub struct Move {
+    /// Line of the program that the move is on, counting from 1
+    pub line: usize,
+    pub motion: Motion,
+    /// Axes are [None] until the program moves them to a known position
+    pub from: [Option<f64>; 3],
+    pub to: [Option<f64>; 3],
+    pub feedrate: Option<f64>,
+    /// Laser power or spindle speed (S word), zero while the tool is turned off with `M5`
+    pub power: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Motion {
+    /// `G0`
+    Rapid,
+    /// `G1`, or a `G2`/`G3` arc that starts at an unknown position so that its center is unknown too
+    Line,
+    /// `G2` or `G3`
+    Arc(Arc),
+    /// A canned cycle, i.e. `G81` drilling, which ends where it started
+    Cycle,
+}
+
+/// Arc in the XY plane
+#[derive(Debug, Clone)]
+pub struct Arc {
+    pub center: [f64; 2],
+    pub radius: f64,
+    /// Radians from the positive X axis to the start
+    pub start_angle: f64,
+    /// Radians swept from the start, positive counterclockwise
+    pub sweep: f64,
+}
+
+impl Arc {
+    /// Point at the given angle around the center
+    pub fn point(&self, angle: f64) -> [f64; 2] {
+        [
+            self.center[0] + self.radius * angle.cos(),
+            self.center[1] + self.radius * angle.sin(),
+        ]
+    }
+
+    /// Points where the arc is furthest left, right, down or up, since it can bulge past both of its ends
+    pub fn extremes(&self) -> impl Iterator<Item = [f64; 2]> + '_ {
+        let end_angle = self.start_angle + self.sweep;
+        let first = (self.start_angle.min(end_angle) / FRAC_PI_2).ceil() as i32;
+        let last = (self.start_angle.max(end_angle) / FRAC_PI_2).floor() as i32;
+        (first..=last).map(|quarter| self.point(f64::from(quarter) * FRAC_PI_2))
+    }
+
+    /// Arc from `start` to `end` around a center given by `I`/`J` offsets from the start, or by an `R` radius that
+    /// is negative for the longer way around
+    ///
+    /// An arc that ends where it starts is a full circle.
+    fn new(
+        start: [f64; 2],
+        end: [f64; 2],
+        [i, j, r]: [Option<f64>; 3],
+        counterclockwise: bool,
+    ) -> Self {
+        let center = match (i, j, r) {
+            (None, None, Some(r)) => {
+                let chord = [end[0] - start[0], end[1] - start[1]];
+                let length = chord[0].hypot(chord[1]).max(f64::EPSILON);
+                let offset = (r * r - length * length / 4.).max(0.).sqrt();
+                // Left of the chord for a counterclockwise arc the short way around
+                let side = if counterclockwise == (r > 0.) {
+                    1.
+                } else {
+                    -1.
+                };
+                [
+                    (start[0] + end[0]) / 2. - side * offset * chord[1] / length,
+                    (start[1] + end[1]) / 2. + side * offset * chord[0] / length,
+                ]
+            }
+            (i, j, _) => [start[0] + i.unwrap_or(0.), start[1] + j.unwrap_or(0.)],
+        };
+        let angle = |[x, y]: [f64; 2]| (y - center[1]).atan2(x - center[0]);
+        let start_angle = angle(start);
+        let mut sweep = angle(end) - start_angle;
+        if counterclockwise {
+            while sweep <= 0. {
+                sweep += TAU;
+            }
+        } else {
+            while sweep >= 0. {
+                sweep -= TAU;
+            }
+        }
+        Self {
+            center,
+            radius: (start[0] - center[0]).hypot(start[1] - center[1]),
+            start_angle,
+            sweep,
+        }
+    }
+}
+
+/// Modal state of the machine while a program is simulated, in millimeters
+#[derive(Debug, Default)]
+struct State {
+    position: [Option<f64>; 3],
+    /// Motion mode set by the last `G0`-`G3` or canned cycle
+    motion: Option<usize>,
+    relative: bool,
+    inches: bool,
+    feedrate: Option<f64>,
+    /// Last S word
+    speed: Option<f64>,
+    /// Whether `M3`/`M4` or `M5` came last, if either did
+    tool_on: Option<bool>,
+}
+
+impl State {
+    /// Millimeters per unit of the program
+    fn unit(&self) -> f64 {
+        if self.inches { MM_PER_INCH } else { 1. }
+    }
+
+    fn power(&self) -> Option<f64> {
+        match self.tool_on {
+            Some(false) => Some(0.),
+            _ => self.speed,
+        }
+    }
+}
+
+/// Follow a program line by line to find the moves it makes, see [Simulation]
+pub fn simulate(gcode: &str) -> Result<Vec<Move>, ParseError> {
+    let mut simulation = Simulation::default();
+    let mut moves = vec![];
+    for line in gcode.lines() {
+        moves.extend(simulation.step(line)?);
+    }
+    Ok(moves)
+}
+
+/// Follows a program one line at a time, keeping track of its modal state, so that it does not have to be in memory
+/// all at once
+///
+/// Coordinates, units, distance modes, feedrates and power are followed, while anything else (i.e. subprogram calls)
+/// is passed over. Homing with `G28` leaves the position unknown.
+#[derive(Debug, Default)]
+pub struct Simulation {
+    state: State,
+    /// Lines stepped through so far
+    lines: usize,
+    /// Bytes stepped through so far, counting a newline after each line
+    offset: usize,
+}
+
+impl Simulation {
+    /// Move made by the next line of the program, if it makes one
+    ///
+    /// Parse errors are located in the whole program rather than in the line.
+    pub fn step(&mut self, line: &str) -> Result<Option<Move>, ParseError> {
+        self.lines += 1;
+        let line_start = self.offset;
+        self.offset += line.len() + 1;
+        let snippet = snippet_parser(line).map_err(|mut err| {
+            err.location.line += self.lines - 1;
+            err.location.offset += line_start;
+            err
+        })?;
+
+        let state = &mut self.state;
+        let mut words = [None; 3];
+        let mut arc = [None; 3];
+        let mut has_words = false;
+        let mut homes = false;
+        for field in snippet
+            .iter()
+            .flat_map(|line| line.iter_emit_tokens())
+            .filter_map(|token| match token {
+                Token::Field(field) => Some(field),
+                _ => None,
+            })
+        {
+            let Some(value) = field.value.as_f64() else {
+                continue;
+            };
+            match field.letters.to_ascii_uppercase().as_str() {
+                "G" if [0., 1., 2., 3.].contains(&value) || (80.0..90.0).contains(&value) => {
+                    state.motion = Some(value as usize).filter(|&motion| motion != 80);
+                }
+                "G" if value == 20. => state.inches = true,
+                "G" if value == 21. => state.inches = false,
+                "G" if value == 90. => state.relative = false,
+                "G" if value == 91. => state.relative = true,
+                "G" if value == 28. => homes = true,
+                "F" => state.feedrate = Some(value * state.unit()),
+                "S" => state.speed = Some(value),
+                "M" if value == 3. || value == 4. => state.tool_on = Some(true),
+                "M" if value == 5. => state.tool_on = Some(false),
+                axis @ ("X" | "Y" | "Z") => {
+                    words[usize::from(axis.as_bytes()[0] - b'X')] = Some(value * state.unit());
+                    has_words = true;
+                }
+                offset @ ("I" | "J") => {
+                    arc[usize::from(offset.as_bytes()[0] - b'I')] = Some(value * state.unit())
+                }
+                "R" => arc[2] = Some(value * state.unit()),
+                _ => {}
+            }
+        }
+        if homes {
+            state.position = [None; 3];
+            return Ok(None);
+        }
+        let Some(mode) = state.motion.filter(|_| has_words) else {
+            return Ok(None);
+        };
+
+        let from = state.position;
+        for (axis, word) in words.iter().enumerate() {
+            if let Some(word) = word {
+                state.position[axis] = if state.relative {
+                    from[axis].map(|from| from + word)
+                } else {
+                    Some(*word)
+                };
+            }
+        }
+        let to = state.position;
+        let motion = match (mode, from, to) {
+            (0, ..) => Motion::Rapid,
+            (2 | 3, [Some(x0), Some(y0), _], [Some(x1), Some(y1), _]) => {
+                Motion::Arc(Arc::new([x0, y0], [x1, y1], arc, mode == 3))
+            }
+            (1..=3, ..) => Motion::Line,
+            _ => {
+                // Canned cycles return to where they started
+                state.position = [to[0], to[1], from[2]];
+                Motion::Cycle
+            }
+        };
+        Ok(Some(Move {
+            line: self.lines,
+            motion,
+            from,
+            to,
+            feedrate: state.feedrate,
+            power: state.power(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ends(gcode: &str) -> Vec<[Option<f64>; 3]> {
+        simulate(gcode)
+            .unwrap()
+            .into_iter()
+            .map(|step| step.to)
+            .collect()
+    }
+
+    #[test]
+    fn axes_are_unknown_until_moved() {
+        assert_eq!(
+            ends("G90\nG0 X1\nG0 Y2 Z3\nG28\nG0 X4\n"),
+            [
+                [Some(1.), None, None],
+                [Some(1.), Some(2.), Some(3.)],
+                [Some(4.), None, None],
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_moves_and_inches_are_followed() {
+        assert_eq!(
+            ends("G20\nG90\nG0 X1 Y1 Z0\nG91\nG1 X1 Y-2 F10\nG21\nX10\n"),
+            [
+                [Some(25.4), Some(25.4), Some(0.)],
+                [Some(50.8), Some(-25.4), Some(0.)],
+                [Some(60.8), Some(-25.4), Some(0.)],
+            ]
+        );
+    }
+
+    #[test]
+    fn modal_motion_carries_feedrate_and_power() {
+        let moves = simulate("G21\nG90\nG0 X0 Y0\nM3 S800\nG1 X10 F300\nY10\nM5\nG0 X0\n").unwrap();
+        assert!(matches!(moves[1].motion, Motion::Line));
+        assert!(matches!(moves[2].motion, Motion::Line));
+        assert!(matches!(moves[3].motion, Motion::Rapid));
+        assert_eq!(moves[2].line, 6);
+        assert_eq!(moves[2].feedrate, Some(300.));
+        assert_eq!(moves[2].power, Some(800.));
+        assert_eq!(moves[3].power, Some(0.));
+    }
+
+    #[test]
+    fn canned_cycles_return_to_where_they_started() {
+        let moves =
+            simulate("G21\nG90\nG0 X0 Y0 Z5\nG81 X10 Y10 Z-2 R1 F100\nX20\nG80\nG0 X0\n").unwrap();
+        assert!(matches!(moves[1].motion, Motion::Cycle));
+        assert_eq!(moves[1].to, [Some(10.), Some(10.), Some(-2.)]);
+        assert_eq!(moves[2].from, [Some(10.), Some(10.), Some(5.)]);
+        assert!(matches!(moves[3].motion, Motion::Rapid));
+        assert_eq!(moves[3].from, [Some(20.), Some(10.), Some(5.)]);
+    }
+
+    #[test]
+    fn arcs_bulge_past_their_ends() {
+        // Counterclockwise half circle from the right of the center to its left, over the top
+        let moves = simulate("G21\nG90\nG0 X10 Y0\nG3 X-10 Y0 I-10 J0 F100\n").unwrap();
+        let Motion::Arc(arc) = &moves[1].motion else {
+            panic!("expected an arc, got {:?}", moves[1].motion);
+        };
+        assert!((arc.radius - 10.).abs() < 1e-9);
+        assert!((arc.sweep - std::f64::consts::PI).abs() < 1e-9);
+        let extremes = arc.extremes().collect::<Vec<_>>();
+        assert_eq!(extremes.len(), 3);
+        assert!((extremes[1][0]).abs() < 1e-9 && (extremes[1][1] - 10.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn radius_arcs_go_the_short_way_unless_negative() {
+        let sweep = |gcode: &str| match &simulate(gcode).unwrap()[1].motion {
+            Motion::Arc(arc) => arc.sweep,
+            motion => panic!("expected an arc, got {motion:?}"),
+        };
+        let short = sweep("G21\nG90\nG0 X0 Y0\nG2 X10 Y10 R10 F100\n");
+        let long = sweep("G21\nG90\nG0 X0 Y0\nG2 X10 Y10 R-10 F100\n");
+        assert!((short + FRAC_PI_2).abs() < 1e-9);
+        assert!((long + 3. * FRAC_PI_2).abs() < 1e-9);
+    }
+}
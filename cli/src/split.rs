@@ -0,0 +1,95 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use log::{error, info};
+use roxmltree::Document;
+use svg2gcode::{ConversionOptions, Machine, Settings, SplitBy, format_program_io, svg2programs};
+
+use crate::EXIT_CONVERSION_ERROR;
+
+/// Write a program for each layer or color named after `out_path`, i.e. `job-cut.gcode` for `job.gcode`
+///
+/// A manifest of the group and statistics of each file is written alongside them as `job-manifest.json`.
+pub fn write(
+    document: &Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    machine: Machine,
+    split_by: SplitBy,
+    out_path: &Path,
+) -> io::Result<()> {
+    let programs = match svg2programs(document, &settings.conversion, options, split_by, machine) {
+        Ok(programs) => programs,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_CONVERSION_ERROR);
+        }
+    };
+    let stem = out_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = out_path.extension().unwrap_or_default().to_string_lossy();
+    let group_key = match split_by {
+        SplitBy::Layer => "layer",
+        SplitBy::Color => "color",
+    };
+
+    let mut used = HashSet::new();
+    let mut files = vec![];
+    for (name, program) in &programs {
+        let base = format!("{stem}-{}", file_name_part(name.as_deref()));
+        let mut file_name = base.clone();
+        for i in 2.. {
+            if used.insert(file_name.clone()) {
+                break;
+            }
+            file_name = format!("{base}-{i}");
+        }
+        if !extension.is_empty() {
+            file_name += ".";
+            file_name += &extension;
+        }
+        info!("Writing {file_name}");
+        format_program_io(
+            &program.tokens,
+            &settings.postprocess,
+            BufWriter::new(File::create(out_path.with_file_name(&file_name))?),
+        )?;
+        files.push(serde_json::json!({
+            "file": file_name,
+            group_key: name,
+            "statistics": program.statistics,
+        }));
+    }
+
+    let manifest = serde_json::json!({ "files": files });
+    fs::write(
+        out_path.with_file_name(format!("{stem}-manifest.json")),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+}
+
+/// Layer label or color made safe for a file name, i.e. `ff0000` for `#ff0000`
+///
+/// Shapes outside of any layer or without a stroke go in `other`.
+fn file_name_part(name: Option<&str>) -> String {
+    let part = name
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let part = part.trim_matches('_');
+    if part.is_empty() {
+        "other".to_string()
+    } else {
+        part.to_string()
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde::Deserialize;
+
+/// Separates the parts of the form the file is uploaded with, which is unlikely to appear in G-Code
+const BOUNDARY: &str = "svg2gcode-upload-e6a1c5f04b2d";
+
+/// Server that the G-Code is uploaded to, read from [path]
+#[derive(Debug, Deserialize)]
+struct UploadConfig {
+    server: Server,
+    /// Base URL of the server, i.e. `http://octopi.local`
+    host: String,
+    /// Sent as `X-Api-Key`, which OctoPrint needs and Moonraker only needs with authorization enabled
+    api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Server {
+    OctoPrint,
+    Moonraker,
+}
+
+impl Server {
+    /// Endpoint that takes a file as the `file` field of a multipart form
+    fn endpoint(self) -> &'static str {
+        match self {
+            Self::OctoPrint => "api/files/local",
+            Self::Moonraker => "server/files/upload",
+        }
+    }
+}
+
+/// Upload a G-Code file to the OctoPrint or Moonraker server configured in [path], keeping its name
+pub fn upload(file: &Path) -> io::Result<()> {
+    let config = load()?;
+    let name = file
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to upload"))?
+        .to_string_lossy();
+
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        name.replace('"', "'")
+    )
+    .into_bytes();
+    body.extend(fs::read(file)?);
+    body.extend(format!("\r\n--{BOUNDARY}--\r\n").into_bytes());
+
+    let url = format!(
+        "{}/{}",
+        config.host.trim_end_matches('/'),
+        config.server.endpoint()
+    );
+    let mut request = ureq::post(&url).set(
+        "Content-Type",
+        &format!("multipart/form-data; boundary={BOUNDARY}"),
+    );
+    if let Some(api_key) = &config.api_key {
+        request = request.set("X-Api-Key", api_key);
+    }
+    match request.send_bytes(&body) {
+        Ok(_) => {
+            info!("Uploaded {name} to {}", config.host);
+            Ok(())
+        }
+        Err(ureq::Error::Status(status, response)) => Err(io::Error::other(format!(
+            "{} refused the upload with {status} {}",
+            config.host,
+            response.status_text()
+        ))),
+        Err(err) => Err(io::Error::other(err)),
+    }
+}
+
+fn load() -> io::Result<UploadConfig> {
+    let path = path()?;
+    if !path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "--upload needs a server, please set it up in {}",
+                path.display()
+            ),
+        ));
+    }
+    toml::from_str(&fs::read_to_string(&path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// The server is kept in the user's config directory, i.e. `~/.config/svg2gcode/upload.toml` on Linux
+fn path() -> io::Result<PathBuf> {
+    dirs::config_dir()
+        .map(|config| config.join("svg2gcode").join("upload.toml"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory was found"))
+}
@@ -0,0 +1,199 @@
+use log::{error, info};
+use roxmltree::Document;
+use svg2gcode::{
+    ConversionError, ConversionOptions, Float, Machine, Settings, svg2polylines, svg2program_io,
+};
+
+use crate::{
+    EXIT_CHECK_FAILED, EXIT_CONVERSION_ERROR,
+    simulate::{Motion, simulate},
+};
+
+/// Leeway for coordinates being rounded when they are formatted
+const ROUNDING: f64 = 1e-3;
+/// How many segments past the last match are searched for the next point, since both follow the same order
+const SEARCH_WINDOW: usize = 256;
+/// Deviations that are logged before the rest are only counted
+const MAX_REPORTED: usize = 10;
+
+type Segment = [[Float; 2]; 2];
+
+/// Convert an SVG, then parse the G-Code again and check that it cuts the same geometry as was planned
+///
+/// Every planned point must be within `max_deviation` millimeters of a cut in the G-Code and the other way around,
+/// which defaults to the leeway that curve flattening and rounding allow. Exits with an error if not.
+pub fn verify(
+    document: &Document,
+    settings: &Settings,
+    options: ConversionOptions,
+    machine: Machine,
+    max_deviation: Option<f64>,
+) {
+    if settings.conversion.subprograms || settings.conversion.framing {
+        error!("verify can't follow subprograms or framing, please turn them off");
+        std::process::exit(1);
+    }
+    let max_deviation = max_deviation.unwrap_or_else(|| {
+        let tolerance = settings.conversion.tolerance.max(
+            settings
+                .machine
+                .supported_functionality
+                .arc_tolerance
+                .unwrap_or_default(),
+        );
+        2. * tolerance + ROUNDING
+    });
+
+    let planned = match svg2polylines(document, &settings.conversion, options.clone()) {
+        Ok(polylines) => polylines,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_CONVERSION_ERROR);
+        }
+    };
+    let mut gcode = vec![];
+    match svg2program_io(
+        document,
+        &settings.conversion,
+        options,
+        machine,
+        &settings.postprocess,
+        &mut gcode,
+    ) {
+        Ok(_) => {}
+        Err(ConversionError::Io(err)) => unreachable!("writing to memory can't fail: {err}"),
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(EXIT_CONVERSION_ERROR);
+        }
+    }
+    let moves = match simulate(&String::from_utf8_lossy(&gcode)) {
+        Ok(moves) => moves,
+        Err(err) => {
+            error!("The G-Code could not be parsed again: {err}");
+            std::process::exit(EXIT_CHECK_FAILED);
+        }
+    };
+
+    // Planned paths and holes, with their place in the cutting order
+    let mut planned_points = vec![];
+    let mut planned_segments = vec![];
+    for (i, polyline) in planned.iter().enumerate() {
+        let points = polyline
+            .points
+            .iter()
+            .map(|point| [point.x, point.y])
+            .collect::<Vec<_>>();
+        planned_points.extend(points.iter().map(|point| (*point, i + 1)));
+        match points.as_slice() {
+            [point] => planned_segments.push([*point, *point]),
+            points => planned_segments.extend(points.windows(2).map(|pair| [pair[0], pair[1]])),
+        }
+    }
+
+    // Cuts and holes of the G-Code, with their line
+    let mut cut_points = vec![];
+    let mut cut_segments = vec![];
+    for cut in &moves {
+        let ([Some(x0), Some(y0), _], [Some(x1), Some(y1), _]) = (cut.from, cut.to) else {
+            continue;
+        };
+        let from = [x0 as Float, y0 as Float];
+        let to = [x1 as Float, y1 as Float];
+        let mut points = match &cut.motion {
+            Motion::Rapid => continue,
+            Motion::Line => vec![from, to],
+            Motion::Cycle => vec![to, to],
+            Motion::Arc(arc) => {
+                // Flattened finely enough not to add to the deviation
+                let step = 2. * (1. - max_deviation / 4. / arc.radius).clamp(-1., 1.).acos();
+                let steps = (arc.sweep.abs() / step.max(1e-3)).ceil().max(1.) as usize;
+                (0..=steps)
+                    .map(|i| {
+                        let [x, y] =
+                            arc.point(arc.start_angle + arc.sweep * i as f64 / steps as f64);
+                        [x as Float, y as Float]
+                    })
+                    .collect()
+            }
+        };
+        // The ends are exact, rather than as computed from the center
+        points[0] = from;
+        *points.last_mut().expect("at least two points") = to;
+        cut_points.extend(points.iter().map(|point| (*point, cut.line)));
+        cut_segments.extend(points.windows(2).map(|pair| [pair[0], pair[1]]));
+    }
+
+    let deviations = deviations(&planned_points, &cut_segments, max_deviation as Float)
+        .into_iter()
+        .map(|(path, distance)| {
+            format!("Path {path} is {distance:.3} mm from what the G-Code cuts")
+        })
+        .chain(
+            deviations(&cut_points, &planned_segments, max_deviation as Float)
+                .into_iter()
+                .map(|(line, distance)| {
+                    format!("Line {line} cuts {distance:.3} mm from the planned paths")
+                }),
+        )
+        .collect::<Vec<_>>();
+    if deviations.is_empty() {
+        info!(
+            "The G-Code cuts the {} planned paths to within {max_deviation} mm",
+            planned.len()
+        );
+        return;
+    }
+    for deviation in deviations.iter().take(MAX_REPORTED) {
+        error!("{deviation}");
+    }
+    if deviations.len() > MAX_REPORTED {
+        error!("...and {} more", deviations.len() - MAX_REPORTED);
+    }
+    error!("The G-Code strays more than {max_deviation} mm from the planned paths");
+    std::process::exit(EXIT_CHECK_FAILED);
+}
+
+/// Points that are further than `max_deviation` from all of the segments, by the number they are given with and how far
+/// they are
+///
+/// Points are expected to follow the segments in order, so each is searched for from where the last one was found.
+fn deviations(
+    points: &[([Float; 2], usize)],
+    segments: &[Segment],
+    max_deviation: Float,
+) -> Vec<(usize, Float)> {
+    let nearest = |segments: &[Segment], point| {
+        segments
+            .iter()
+            .map(|segment| distance(point, segment))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    };
+    let mut deviations = vec![];
+    let mut cursor = 0;
+    for (point, number) in points {
+        let window = &segments[cursor..segments.len().min(cursor + SEARCH_WINDOW)];
+        match nearest(window, *point) {
+            Some((i, distance)) if distance <= max_deviation => cursor += i,
+            _ => match nearest(segments, *point) {
+                Some((i, distance)) if distance <= max_deviation => cursor = i,
+                Some((_, distance)) => deviations.push((*number, distance)),
+                None => deviations.push((*number, Float::INFINITY)),
+            },
+        }
+    }
+    deviations
+}
+
+/// Distance from a point to the closest point of a segment
+fn distance([x, y]: [Float; 2], [[x0, y0], [x1, y1]]: &Segment) -> Float {
+    let [dx, dy] = [x1 - x0, y1 - y0];
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0. {
+        (((x - x0) * dx + (y - y0) * dy) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (x - (x0 + t * dx)).hypot(y - (y0 + t * dy))
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
+use svg2gcode::{ConversionOptions, Settings};
+
+use crate::convert_file;
+
+/// How long a file has to stay unchanged before it is converted, as editors often save in several steps
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Convert each SVG file to its G-Code file and the preview, then again whenever the SVG changes
+///
+/// Directories are watched rather than the files themselves, since many editors save by replacing the file.
+/// Failures are logged and watching carries on, so the SVG can be fixed and saved again.
+pub fn watch(
+    jobs: &[(PathBuf, PathBuf)],
+    preview_path: Option<&Path>,
+    settings: &Settings,
+    options: &ConversionOptions,
+) -> io::Result<()> {
+    let mut modified = Vec::with_capacity(jobs.len());
+    for (file, out_path) in jobs {
+        modified.push(modified_time(file));
+        convert_file(file, out_path, preview_path, settings, options);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, sender).map_err(io::Error::other)?;
+    let mut watched = Vec::with_capacity(jobs.len());
+    let mut dirs = BTreeSet::new();
+    for (file, _) in jobs {
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+        .canonicalize()?;
+        watched.push(dir.join(file.file_name().unwrap_or_default()));
+        dirs.insert(dir);
+    }
+    for dir in &dirs {
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+    }
+    info!("Watching for changes, press Ctrl+C to stop");
+
+    for events in receiver {
+        match events {
+            Ok(events) => {
+                for (((file, out_path), watched), modified) in
+                    jobs.iter().zip(&watched).zip(&mut modified)
+                {
+                    if !events.iter().any(|event| &event.path == watched) {
+                        continue;
+                    }
+                    // Reading the SVG to convert it is an event too
+                    let last_modified = modified_time(watched);
+                    if last_modified.is_some() && last_modified != *modified {
+                        *modified = last_modified;
+                        convert_file(file, out_path, preview_path, settings, options);
+                    }
+                }
+            }
+            Err(err) => warn!("Could not watch for changes: {err}"),
+        }
+    }
+    Ok(())
+}
+
+fn modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
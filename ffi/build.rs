@@ -0,0 +1,5 @@
+fn main() {
+    // Lets Node.js provide the N-API symbols when it loads the library
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
@@ -0,0 +1,61 @@
+#ifndef SVG2GCODE_H
+#define SVG2GCODE_H
+
+/* Generated with cbindgen from src/c.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What [svg2gcode_convert] gives back, to be freed with [svg2gcode_result_free]
+//
+// Either `gcode` and `report` are set, or `error` is.
+typedef struct Svg2GcodeResult {
+  // Formatted G-Code
+  char *gcode;
+  // JSON object with the `statistics` of the program and the `warnings` logged while converting it
+  char *report;
+  // Message for the user when the conversion failed
+  char *error;
+} Svg2GcodeResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Convert an SVG to G-Code
+//
+// `settings_json` is a JSON object like the settings that the CLI and web interface export, see
+// [svg2gcode_default_settings]. `options_json` are the [ConversionOptions] for this SVG. Either may be null for the
+// defaults.
+//
+// # Safety
+//
+// `svg` must be a valid, NUL-terminated string, as must `settings_json` and `options_json` unless they are null.
+struct Svg2GcodeResult svg2gcode_convert(const char *svg,
+                                         const char *settings_json,
+                                         const char *options_json);
+
+// Settings to start from as a JSON object, to be freed with [svg2gcode_string_free]
+char *svg2gcode_default_settings(void);
+
+// Free the strings of a result from [svg2gcode_convert]
+//
+// # Safety
+//
+// `result` must have come from [svg2gcode_convert] and must not be used after this.
+void svg2gcode_result_free(struct Svg2GcodeResult result);
+
+// Free a string given by this library, doing nothing for null
+//
+// # Safety
+//
+// `string` must be null or have come from this library, and must not be used after this.
+void svg2gcode_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SVG2GCODE_H */
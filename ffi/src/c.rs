@@ -0,0 +1,181 @@
+use std::{
+    ffi::{CStr, CString, c_char},
+    panic::{AssertUnwindSafe, catch_unwind},
+    ptr,
+};
+
+use serde::Serialize;
+
+use svg2gcode::{ConversionOptions, ConversionStatistics, Settings};
+
+use crate::embed;
+
+/// What [svg2gcode_convert] gives back, to be freed with [svg2gcode_result_free]
+///
+/// Either `gcode` and `report` are set, or `error` is.
+#[repr(C)]
+pub struct Svg2GcodeResult {
+    /// Formatted G-Code
+    pub gcode: *mut c_char,
+    /// JSON object with the `statistics` of the program and the `warnings` logged while converting it
+    pub report: *mut c_char,
+    /// Message for the user when the conversion failed
+    pub error: *mut c_char,
+}
+
+/// [embed::Converted] without the G-Code, which is given separately
+#[derive(Serialize)]
+struct Report<'a> {
+    statistics: &'a ConversionStatistics,
+    warnings: &'a [String],
+}
+
+/// Convert an SVG to G-Code
+///
+/// `settings_json` is a JSON object like the settings that the CLI and web interface export, see
+/// [svg2gcode_default_settings]. `options_json` are the [ConversionOptions] for this SVG. Either may be null for the
+/// defaults.
+///
+/// # Safety
+///
+/// `svg` must be a valid, NUL-terminated string, as must `settings_json` and `options_json` unless they are null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn svg2gcode_convert(
+    svg: *const c_char,
+    settings_json: *const c_char,
+    options_json: *const c_char,
+) -> Svg2GcodeResult {
+    // SAFETY: upheld by the caller
+    let (svg, settings_json, options_json) =
+        unsafe { (string(svg), string(settings_json), string(options_json)) };
+    let converted = catch_unwind(AssertUnwindSafe(|| {
+        let svg = svg?.ok_or("svg must not be null")?;
+        let settings: Settings = match settings_json? {
+            Some(json) => serde_json::from_str(json).map_err(|err| err.to_string())?,
+            None => Settings::default(),
+        };
+        let options: ConversionOptions = match options_json? {
+            Some(json) => serde_json::from_str(json).map_err(|err| err.to_string())?,
+            None => ConversionOptions::default(),
+        };
+        let converted = embed::convert(svg, settings, options)?;
+        let report = serde_json::to_string(&Report {
+            statistics: &converted.statistics,
+            warnings: &converted.warnings,
+        })
+        .map_err(|err| err.to_string())?;
+        Ok::<_, String>((converted.gcode, report))
+    }))
+    .unwrap_or_else(|_| Err("conversion panicked".to_string()));
+
+    match converted {
+        Ok((gcode, report)) => Svg2GcodeResult {
+            gcode: into_raw(gcode),
+            report: into_raw(report),
+            error: ptr::null_mut(),
+        },
+        Err(error) => Svg2GcodeResult {
+            gcode: ptr::null_mut(),
+            report: ptr::null_mut(),
+            error: into_raw(error),
+        },
+    }
+}
+
+/// Settings to start from as a JSON object, to be freed with [svg2gcode_string_free]
+#[unsafe(no_mangle)]
+pub extern "C" fn svg2gcode_default_settings() -> *mut c_char {
+    into_raw(serde_json::to_string(&Settings::default()).expect("settings are valid JSON"))
+}
+
+/// Free the strings of a result from [svg2gcode_convert]
+///
+/// # Safety
+///
+/// `result` must have come from [svg2gcode_convert] and must not be used after this.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn svg2gcode_result_free(result: Svg2GcodeResult) {
+    // SAFETY: upheld by the caller
+    unsafe {
+        svg2gcode_string_free(result.gcode);
+        svg2gcode_string_free(result.report);
+        svg2gcode_string_free(result.error);
+    }
+}
+
+/// Free a string given by this library, doing nothing for null
+///
+/// # Safety
+///
+/// `string` must be null or have come from this library, and must not be used after this.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn svg2gcode_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        // SAFETY: upheld by the caller
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Borrow a string from C, with [None] for null
+///
+/// # Safety
+///
+/// `string` must be null or a valid, NUL-terminated string that outlives the borrow.
+unsafe fn string<'a>(string: *const c_char) -> Result<Option<&'a str>, String> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: upheld by the caller
+    unsafe { CStr::from_ptr(string) }
+        .to_str()
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+fn into_raw(string: String) -> *mut c_char {
+    // Interior NULs can't be given to C, so the string is cut off at the first one
+    let mut bytes = string.into_bytes();
+    if let Some(nul) = bytes.iter().position(|&byte| byte == 0) {
+        bytes.truncate(nul);
+    }
+    CString::new(bytes)
+        .expect("interior NULs were removed")
+        .into_raw()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn take(string: *mut c_char) -> Option<String> {
+        (!string.is_null()).then(|| {
+            unsafe { CStr::from_ptr(string) }
+                .to_str()
+                .unwrap()
+                .to_string()
+        })
+    }
+
+    #[test]
+    fn convert_through_c_strings() {
+        let svg = c"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"20mm\" height=\"20mm\" viewBox=\"0 0 20 20\"><path d=\"M2,3 H12 V8\"/></svg>";
+        let settings = svg2gcode_default_settings();
+        let result = unsafe { svg2gcode_convert(svg.as_ptr(), settings, ptr::null()) };
+        unsafe { svg2gcode_string_free(settings) };
+
+        assert!(take(result.error).is_none());
+        assert!(take(result.gcode).unwrap().contains("G1"));
+        let report: serde_json::Value =
+            serde_json::from_str(&take(result.report).unwrap()).unwrap();
+        assert_eq!(report["statistics"]["paths"], 1);
+        unsafe { svg2gcode_result_free(result) };
+    }
+
+    #[test]
+    fn convert_reports_errors() {
+        let result = unsafe { svg2gcode_convert(c"<svg".as_ptr(), c"{}".as_ptr(), ptr::null()) };
+        assert!(take(result.gcode).is_none());
+        assert!(take(result.error).unwrap().starts_with("missing field"));
+        unsafe { svg2gcode_result_free(result) };
+    }
+}
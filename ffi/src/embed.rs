@@ -0,0 +1,118 @@
+use std::{cell::RefCell, sync::Once};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use svg2gcode::{ConversionOptions, ConversionStatistics, Settings};
+
+/// What an application embedding the converter gets back from [convert]
+#[derive(Debug, Serialize)]
+pub(crate) struct Converted {
+    pub gcode: String,
+    pub statistics: ConversionStatistics,
+    /// Everything that was logged as a warning while converting, i.e. unsupported elements
+    pub warnings: Vec<String>,
+}
+
+/// Convert an SVG to formatted G-Code with [svg2gcode::convert], keeping the warnings logged along the way
+///
+/// Any error is given as a message to show to the user.
+pub(crate) fn convert(
+    svg: &str,
+    settings: Settings,
+    options: ConversionOptions,
+) -> Result<Converted, String> {
+    let (converted, warnings) = collect_warnings(|| svg2gcode::convert(svg, &settings, options));
+    let (gcode, statistics) = converted.map_err(|err| err.to_string())?;
+    Ok(Converted {
+        gcode,
+        statistics,
+        warnings,
+    })
+}
+
+thread_local! {
+    /// Warnings logged on this thread during [collect_warnings]
+    static WARNINGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Logger that keeps warnings for [Converted::warnings]
+///
+/// It is only installed when the application has not set a logger of its own, in which case warnings go there
+/// instead.
+struct WarningCollector;
+
+impl Log for WarningCollector {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            WARNINGS.with_borrow_mut(|warnings| {
+                if let Some(warnings) = warnings {
+                    warnings.push(record.args().to_string());
+                }
+            });
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static COLLECTOR: WarningCollector = WarningCollector;
+static INSTALL_COLLECTOR: Once = Once::new();
+
+/// Run `f`, keeping the warnings it logs on this thread
+fn collect_warnings<R>(f: impl FnOnce() -> R) -> (R, Vec<String>) {
+    INSTALL_COLLECTOR.call_once(|| {
+        if log::set_logger(&COLLECTOR).is_ok() {
+            log::set_max_level(LevelFilter::Warn);
+        }
+    });
+    WARNINGS.set(Some(vec![]));
+    let result = f();
+    (result, WARNINGS.take().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use svg2gcode::ConversionConfig;
+
+    #[test]
+    fn convert_gives_gcode_and_warnings() {
+        let converted = convert(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <rect width="5"/>
+                <path d="M2,3 H12 V8"/>
+            </svg>"#,
+            Settings::default(),
+            ConversionOptions::default(),
+        )
+        .unwrap();
+        assert!(converted.gcode.contains("G1"));
+        assert_eq!(converted.statistics.paths, 1);
+        assert!(
+            converted
+                .warnings
+                .iter()
+                .any(|warning| warning.starts_with("Invalid rectangle node"))
+        );
+    }
+
+    #[test]
+    fn convert_rejects_invalid_settings() {
+        let settings = Settings {
+            conversion: ConversionConfig {
+                feedrate: 0.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            convert("<svg/>", settings, ConversionOptions::default()).unwrap_err(),
+            "feedrate must be greater than zero, but is 0"
+        );
+    }
+}
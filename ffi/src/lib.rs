@@ -0,0 +1,11 @@
+/// Bindings for C and C++ through an `extern "C"` API, see `cbindgen.toml` for generating the header
+#[cfg(feature = "c")]
+pub mod c;
+/// Converts straight from strings to strings, for the bindings to each language
+mod embed;
+/// Bindings for Node.js through N-API, for Electron applications that would otherwise run the CLI
+#[cfg(feature = "node")]
+pub mod node;
+/// Bindings for JavaScript through wasm-bindgen
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,35 @@
+use napi::{Error, Result};
+use napi_derive::napi;
+use serde_json::Value;
+
+use svg2gcode::{ConversionOptions, Settings};
+
+use crate::embed;
+
+/// Convert an SVG to G-Code
+///
+/// `settings` is an object like the settings that the CLI and web interface export, see [default_settings].
+/// `options` are the [ConversionOptions] for this SVG, or `undefined` for the defaults.
+///
+/// Gives an object with the formatted `gcode`, the `statistics` of the program, and the `warnings` logged while
+/// converting it, or throws an error with a message for the user.
+#[napi]
+pub fn convert(svg: String, settings: Value, options: Option<Value>) -> Result<Value> {
+    let settings: Settings = serde_json::from_value(settings).map_err(invalid)?;
+    let options = match options {
+        Some(Value::Null) | None => ConversionOptions::default(),
+        Some(options) => serde_json::from_value(options).map_err(invalid)?,
+    };
+    let converted = embed::convert(&svg, settings, options).map_err(Error::from_reason)?;
+    serde_json::to_value(converted).map_err(invalid)
+}
+
+/// Settings to start from, as taken by [convert]
+#[napi]
+pub fn default_settings() -> Result<Value> {
+    serde_json::to_value(Settings::default()).map_err(invalid)
+}
+
+fn invalid(err: serde_json::Error) -> Error {
+    Error::from_reason(err.to_string())
+}
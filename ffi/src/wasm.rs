@@ -0,0 +1,30 @@
+use wasm_bindgen::prelude::*;
+
+use svg2gcode::{ConversionOptions, Settings};
+
+use crate::embed;
+
+/// Convert an SVG to G-Code
+///
+/// `settings` is an object like the settings that the CLI and web interface export, see [defaultSettings]. `options`
+/// are the [ConversionOptions] for this SVG, or `undefined` for the defaults.
+///
+/// Gives an object with the formatted `gcode`, the `statistics` of the program, and the `warnings` logged while
+/// converting it, or throws an error with a message for the user.
+#[wasm_bindgen]
+pub fn convert(svg: &str, settings: JsValue, options: JsValue) -> Result<JsValue, JsError> {
+    let settings: Settings = serde_wasm_bindgen::from_value(settings)?;
+    let options = if options.is_undefined() || options.is_null() {
+        ConversionOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+    let converted = embed::convert(svg, settings, options).map_err(|err| JsError::new(&err))?;
+    Ok(serde_wasm_bindgen::to_value(&converted)?)
+}
+
+/// Settings to start from, as taken by [convert]
+#[wasm_bindgen(js_name = defaultSettings)]
+pub fn default_settings() -> Result<JsValue, JsError> {
+    Ok(serde_wasm_bindgen::to_value(&Settings::default())?)
+}
@@ -1,8 +1,13 @@
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
 use euclid::Angle;
 use lyon_geom::{
     ArcFlags, CubicBezierSegment, Line, LineSegment, Point, Scalar, SvgArc, Transform, Vector,
 };
 
+/// Piece of a curve that has been approximated with [FlattenWithArcs]
+#[derive(Debug, Clone, Copy)]
 pub enum ArcOrLineSegment<S> {
     Arc(SvgArc<S>),
     Line(LineSegment<S>),
@@ -77,6 +82,7 @@ fn arc_from_endpoints_and_tangents<S: Scalar>(
     })
 }
 
+/// Approximate a curve with circular arcs and lines, within `tolerance` of it
 pub trait FlattenWithArcs<S> {
     fn flattened(&self, tolerance: S) -> Vec<ArcOrLineSegment<S>>;
 }
@@ -185,6 +191,7 @@ where
     }
 }
 
+/// Apply an affine transform to an arc, which may change its radii and rotation
 pub trait Transformed<S> {
     fn transformed(&self, transform: &Transform<S>) -> Self;
 }
@@ -0,0 +1,412 @@
+use std::{error::Error, fmt, ops::Range};
+
+use svgtypes::Length;
+
+use crate::{
+    ArcFormat, Cancellation, CommentStyle, CommentVerbosity, ConversionConfig, ConversionOptions,
+    DrillCycle, Drilling, FeedratePolicy, Flip, Group, GroupOverride, Hooks, Kinematics,
+    MachineConfig, ParkPosition, PathOrder, PenServo, ProgramEnd, ProgressMarkers,
+    RefinementBudget, Sphere, SupportedFunctionality,
+};
+
+/// A setting that would make conversion fail or produce a program that can't be run
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsError {
+    /// The setting must be greater than zero
+    NotPositive { setting: &'static str, value: f64 },
+    /// The setting must not be less than zero
+    Negative { setting: &'static str, value: f64 },
+    /// The setting must be at least one
+    Zero { setting: &'static str },
+    /// [ConversionConfig::safe_z] is not above [ConversionConfig::cut_z], so travel moves would drag the tool
+    SafeZNotAboveCutZ { safe_z: f64, cut_z: f64 },
+    /// [ConversionOptions::path_range] starts after it ends
+    InvalidPathRange(Range<usize>),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotPositive { setting, value } => {
+                write!(f, "{setting} must be greater than zero, but is {value}")
+            }
+            Self::Negative { setting, value } => {
+                write!(f, "{setting} must not be negative, but is {value}")
+            }
+            Self::Zero { setting } => write!(f, "{setting} must be at least 1"),
+            Self::SafeZNotAboveCutZ { safe_z, cut_z } => {
+                write!(f, "safe_z ({safe_z}) must be above cut_z ({cut_z})")
+            }
+            Self::InvalidPathRange(range) => {
+                write!(
+                    f,
+                    "path_range {}..{} starts after it ends",
+                    range.start, range.end
+                )
+            }
+        }
+    }
+}
+
+impl Error for SettingsError {}
+
+fn positive(setting: &'static str, value: f64) -> Result<(), SettingsError> {
+    // Written this way so that NaN is rejected too
+    if value > 0. {
+        Ok(())
+    } else {
+        Err(SettingsError::NotPositive { setting, value })
+    }
+}
+
+fn non_negative(setting: &'static str, value: f64) -> Result<(), SettingsError> {
+    if value >= 0. {
+        Ok(())
+    } else {
+        Err(SettingsError::Negative { setting, value })
+    }
+}
+
+fn at_least_one(setting: &'static str, value: usize) -> Result<(), SettingsError> {
+    if value == 0 {
+        Err(SettingsError::Zero { setting })
+    } else {
+        Ok(())
+    }
+}
+
+impl ConversionConfig {
+    /// Start from the [Default] configuration, see [ConversionConfigBuilder]
+    pub fn builder() -> ConversionConfigBuilder {
+        ConversionConfigBuilder::default()
+    }
+
+    /// Check for settings that are out of range, i.e. after deserializing them
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        positive("tolerance", self.tolerance)?;
+        positive("feedrate", self.feedrate)?;
+        positive("dpi", self.dpi)?;
+        non_negative("corner_slowdown", self.corner_slowdown)?;
+        if let (Some(safe_z), Some(cut_z)) = (self.safe_z, self.cut_z)
+            && safe_z <= cut_z
+        {
+            return Err(SettingsError::SafeZNotAboveCutZ { safe_z, cut_z });
+        }
+        for (setting, power) in [
+            ("power", self.power),
+            ("min_power", self.min_power),
+            ("framing_power", self.framing_power),
+        ] {
+            if let Some(power) = power {
+                non_negative(setting, power)?;
+            }
+        }
+        at_least_one("progress_interval", self.progress_interval)?;
+        if let Some(checkpoint_interval) = self.checkpoint_interval {
+            at_least_one("checkpoint_interval", checkpoint_interval)?;
+        }
+        if let Some(drilling) = &self.drilling {
+            positive("drilling.max_diameter", drilling.max_diameter)?;
+            if let DrillCycle::Peck { peck } = drilling.cycle {
+                positive("drilling.cycle.peck", peck)?;
+            }
+        }
+        at_least_one("passes", self.passes)?;
+        positive("scale", self.scale)?;
+        if let Some([width, height]) = self.fit_to {
+            positive("fit_to.width", width)?;
+            positive("fit_to.height", height)?;
+        }
+        if let Some(sphere) = &self.sphere {
+            positive("sphere.diameter", sphere.diameter)?;
+        }
+        if let Some(grid) = self.grid {
+            positive("grid", grid)?;
+        }
+        for group_override in &self.overrides {
+            if let Some(feedrate) = group_override.feedrate {
+                positive("overrides.feedrate", feedrate)?;
+            }
+            if let Some(power) = group_override.power {
+                non_negative("overrides.power", power)?;
+            }
+        }
+        match self.path_refinement {
+            Some(RefinementBudget::Iterations(iterations)) => {
+                at_least_one("path_refinement.iterations", iterations)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ConversionOptions {
+    /// Start from the [Default] options, see [ConversionOptionsBuilder]
+    pub fn builder() -> ConversionOptionsBuilder {
+        ConversionOptionsBuilder::default()
+    }
+
+    /// Check for options that are out of range
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        for (setting, dimension) in ["dimensions.width", "dimensions.height"]
+            .into_iter()
+            .zip(self.dimensions)
+        {
+            if let Some(dimension) = dimension {
+                positive(setting, dimension.number)?;
+            }
+        }
+        match &self.path_range {
+            Some(range) if range.start > range.end => {
+                Err(SettingsError::InvalidPathRange(range.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MachineConfig {
+    /// Start from the [Default] configuration, see [MachineConfigBuilder]
+    pub fn builder() -> MachineConfigBuilder {
+        MachineConfigBuilder::default()
+    }
+
+    /// Check for settings that are out of range, i.e. after deserializing them
+    ///
+    /// G-Code sequences are not parsed here, since that is needed to create a [crate::Machine] anyway.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if let Some(arc_tolerance) = self.supported_functionality.arc_tolerance {
+            positive("supported_functionality.arc_tolerance", arc_tolerance)?;
+        }
+        let kinematics = &self.kinematics;
+        if let Some(acceleration) = kinematics.acceleration {
+            positive("kinematics.acceleration", acceleration)?;
+        }
+        non_negative(
+            "kinematics.junction_deviation",
+            kinematics.junction_deviation,
+        )?;
+        for (setting, feedrate) in [
+            ("kinematics.rapid_feedrate", kinematics.rapid_feedrate),
+            ("kinematics.max_feedrate", kinematics.max_feedrate),
+        ] {
+            if let Some(feedrate) = feedrate {
+                positive(setting, feedrate)?;
+            }
+        }
+        if let Some(pen_servo) = &self.pen_servo {
+            non_negative("pen_servo.dwell", pen_servo.dwell)?;
+            if let Some(rate) = pen_servo.rate {
+                positive("pen_servo.rate", rate)?;
+            }
+        }
+        if let Some([width, height]) = self.work_area {
+            positive("work_area.width", width)?;
+            positive("work_area.height", height)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates a builder method for each field, which replaces the field's value
+macro_rules! setters {
+    ($config:ident { $($field:ident: $ty:ty,)* }) => {
+        $(
+            #[doc = concat!("See [", stringify!($config), "::", stringify!($field), "]")]
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.0.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+/// Fluent construction of a [ConversionConfig], which is checked with [ConversionConfig::validate] when built
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConversionConfigBuilder(ConversionConfig);
+
+impl ConversionConfigBuilder {
+    setters!(ConversionConfig {
+        tolerance: f64,
+        feedrate: f64,
+        dpi: f64,
+        origin: [Option<f64>; 2],
+        extra_attribute_name: Option<String>,
+        feedrate_policy: FeedratePolicy,
+        comment_style: CommentStyle,
+        comment_verbosity: CommentVerbosity,
+        metadata_header: bool,
+        corner_slowdown: f64,
+        safe_z: Option<f64>,
+        cut_z: Option<f64>,
+        retract_between_paths: bool,
+        skip_redundant_commands: bool,
+        dry_run: bool,
+        framing: bool,
+        framing_power: Option<f64>,
+        park: Option<ParkPosition>,
+        power: Option<f64>,
+        power_ramping: bool,
+        min_power: Option<f64>,
+        progress_markers: ProgressMarkers,
+        progress_interval: usize,
+        checkpoint_interval: Option<usize>,
+        subprograms: bool,
+        drilling: Option<Drilling>,
+        passes: usize,
+        overrides: Vec<GroupOverride>,
+        path_order: PathOrder,
+        path_refinement: Option<RefinementBudget>,
+        flip: Flip,
+        rotation: f64,
+        scale: f64,
+        fit_to: Option<[f64; 2]>,
+        sphere: Option<Sphere>,
+        grid: Option<f64>,
+    });
+
+    /// Add to [ConversionConfig::overrides]
+    pub fn group_override(mut self, group_override: GroupOverride) -> Self {
+        self.0.overrides.push(group_override);
+        self
+    }
+
+    pub fn build(self) -> Result<ConversionConfig, SettingsError> {
+        self.0.validate()?;
+        Ok(self.0)
+    }
+}
+
+/// Fluent construction of [ConversionOptions], which are checked with [ConversionOptions::validate] when built
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConversionOptionsBuilder(ConversionOptions);
+
+impl ConversionOptionsBuilder {
+    setters!(ConversionOptions {
+        dimensions: [Option<Length>; 2],
+        source_name: Option<String>,
+        group: Option<Group>,
+        path_range: Option<Range<usize>>,
+        cancellation: Option<Cancellation>,
+        hooks: Hooks,
+    });
+
+    pub fn build(self) -> Result<ConversionOptions, SettingsError> {
+        self.0.validate()?;
+        Ok(self.0)
+    }
+}
+
+/// Fluent construction of a [MachineConfig], which is checked with [MachineConfig::validate] when built
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MachineConfigBuilder(MachineConfig);
+
+impl MachineConfigBuilder {
+    setters!(MachineConfig {
+        supported_functionality: SupportedFunctionality,
+        kinematics: Kinematics,
+        pen_servo: Option<PenServo>,
+        work_area: Option<[f64; 2]>,
+        tool_on_sequence: Option<String>,
+        tool_off_sequence: Option<String>,
+        begin_sequence: Option<String>,
+        end_sequence: Option<String>,
+        program_end: Option<ProgramEnd>,
+    });
+
+    /// See [SupportedFunctionality::circular_interpolation]
+    pub fn circular_interpolation(mut self, circular_interpolation: bool) -> Self {
+        self.0.supported_functionality.circular_interpolation = circular_interpolation;
+        self
+    }
+
+    /// See [SupportedFunctionality::arc_format]
+    pub fn arc_format(mut self, arc_format: ArcFormat) -> Self {
+        self.0.supported_functionality.arc_format = arc_format;
+        self
+    }
+
+    pub fn build(self) -> Result<MachineConfig, SettingsError> {
+        self.0.validate()?;
+        Ok(self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use svgtypes::LengthUnit;
+
+    use super::*;
+
+    #[test]
+    fn builders_reject_out_of_range_settings() {
+        assert_eq!(
+            ConversionConfig::builder().tolerance(0.).build(),
+            Err(SettingsError::NotPositive {
+                setting: "tolerance",
+                value: 0.
+            })
+        );
+        assert!(
+            ConversionConfig::builder()
+                .feedrate(f64::NAN)
+                .build()
+                .is_err()
+        );
+        assert_eq!(
+            ConversionConfig::builder()
+                .safe_z(Some(-1.))
+                .cut_z(Some(0.))
+                .build(),
+            Err(SettingsError::SafeZNotAboveCutZ {
+                safe_z: -1.,
+                cut_z: 0.
+            })
+        );
+        assert_eq!(
+            ConversionOptions::builder()
+                .dimensions([Some(Length::new(-10., LengthUnit::Mm)), None])
+                .build(),
+            Err(SettingsError::NotPositive {
+                setting: "dimensions.width",
+                value: -10.
+            })
+        );
+        assert_eq!(
+            MachineConfig::builder()
+                .kinematics(Kinematics {
+                    acceleration: Some(0.),
+                    ..Default::default()
+                })
+                .build(),
+            Err(SettingsError::NotPositive {
+                setting: "kinematics.acceleration",
+                value: 0.
+            })
+        );
+    }
+
+    #[test]
+    fn builders_set_fields() {
+        let config = ConversionConfig::builder()
+            .feedrate(1200.)
+            .passes(2)
+            .build()
+            .unwrap();
+        assert_eq!(
+            config,
+            ConversionConfig {
+                feedrate: 1200.,
+                passes: 2,
+                ..Default::default()
+            }
+        );
+        let machine = MachineConfig::builder()
+            .circular_interpolation(true)
+            .end_sequence(Some("M5".to_string()))
+            .build()
+            .unwrap();
+        assert!(machine.supported_functionality.circular_interpolation);
+        assert_eq!(machine.end_sequence.as_deref(), Some("M5"));
+    }
+}
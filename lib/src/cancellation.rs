@@ -0,0 +1,35 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Shared flag for stopping a conversion part way through, see [crate::ConversionOptions::cancellation]
+///
+/// Clones share the same flag, so one can be handed to the conversion while another is kept to cancel it from
+/// elsewhere, i.e. when the settings change before the conversion finishes. The flag is checked as each SVG element
+/// is visited and while paths are being ordered, after which the conversion returns
+/// [crate::ConversionError::Cancelled].
+#[derive(Debug, Default, Clone)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every conversion using this flag to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Flags are equal when they are clones of each other
+impl PartialEq for Cancellation {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+use g_code::emit::{FormatOptions, Token, format_gcode_fmt};
+use log::warn;
+use roxmltree::Document;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ConversionConfig, ConversionError, ConversionOptions, Program, ProgressMarkers, convert,
+};
+use crate::Machine;
+
+/// Largest program [svg2chunks] may produce, measured before postprocessing (i.e. line numbers)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChunkLimit {
+    Bytes(usize),
+    Lines(usize),
+}
+
+impl ChunkLimit {
+    fn max(&self) -> usize {
+        match self {
+            Self::Bytes(max) | Self::Lines(max) => *max,
+        }
+    }
+
+    fn measure(&self, tokens: &[Token<'_>]) -> usize {
+        let mut gcode = String::new();
+        format_gcode_fmt(tokens, FormatOptions::default(), &mut gcode)
+            .expect("formatting into a string does not fail");
+        match self {
+            Self::Bytes(_) => gcode.len(),
+            Self::Lines(_) => gcode.lines().count(),
+        }
+    }
+}
+
+/// Convert an SVG [`Document`] into as few programs as possible that each fit within `limit`
+///
+/// Programs are split between paths. Each one has the machine's full begin and end sequences,
+/// so that it starts from a known state and can be run on its own. A single path that exceeds
+/// the limit is given a program of its own.
+pub fn svg2chunks<'a, 'input: 'a>(
+    doc: &'a Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    machine: Machine<'input>,
+    limit: ChunkLimit,
+) -> Result<Vec<Program<'input>>, ConversionError> {
+    // Estimate the size of each path from a single program, without additions whose size varies by chunk
+    let measurement_config = ConversionConfig {
+        metadata_header: false,
+        progress_markers: ProgressMarkers::None,
+        framing: false,
+        ..config.clone()
+    };
+    let whole = convert(
+        doc,
+        &measurement_config,
+        options.clone(),
+        machine.clone(),
+        None,
+    )?;
+    let base = options.path_range.as_ref().map_or(0, |range| range.start);
+    let first_path_start = whole
+        .path_starts
+        .first()
+        .copied()
+        .unwrap_or(whole.paths_end);
+    let overhead = limit.measure(&whole.program[..first_path_start])
+        + limit.measure(&whole.program[whole.paths_end..]);
+
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut size = overhead;
+    for (i, &path_start) in whole.path_starts.iter().enumerate() {
+        let path_end = whole
+            .path_starts
+            .get(i + 1)
+            .copied()
+            .unwrap_or(whole.paths_end);
+        let path_size = limit.measure(&whole.program[path_start..path_end]);
+        if i > start && size + path_size > limit.max() {
+            ranges.push(base + start..base + i);
+            start = i;
+            size = overhead;
+        }
+        size += path_size;
+    }
+    ranges.push(base + start..base + whole.path_starts.len());
+
+    let mut programs = vec![];
+    for range in ranges {
+        programs.extend(chunk(doc, config, &options, &machine, limit, range)?);
+    }
+    Ok(programs)
+}
+
+/// Convert a range of paths, halving it until each program fits if the estimate was too optimistic
+fn chunk<'input>(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: &ConversionOptions,
+    machine: &Machine<'input>,
+    limit: ChunkLimit,
+    range: Range<usize>,
+) -> Result<Vec<Program<'input>>, ConversionError> {
+    let program = Program::from_turtle(convert(
+        doc,
+        config,
+        ConversionOptions {
+            path_range: Some(range.clone()),
+            ..options.clone()
+        },
+        machine.clone(),
+        None,
+    )?);
+    if limit.measure(&program.tokens) <= limit.max() {
+        Ok(vec![program])
+    } else if range.len() <= 1 {
+        warn!("Path {} does not fit within {limit:?}", range.start);
+        Ok(vec![program])
+    } else {
+        let middle = range.start + range.len() / 2;
+        let mut programs = chunk(doc, config, options, machine, limit, range.start..middle)?;
+        programs.extend(chunk(
+            doc,
+            config,
+            options,
+            machine,
+            limit,
+            middle..range.end,
+        )?);
+        Ok(programs)
+    }
+}
@@ -0,0 +1,173 @@
+use roxmltree::Document;
+
+use super::{ConversionConfig, ConversionError, ConversionOptions, PathSource, draw};
+use crate::{
+    GroupOverride, Turtle,
+    turtle::{Command, RecordingTurtle},
+};
+
+/// Paths of an SVG [`Document`] in millimeters and in document order, before they are ordered or flattened
+///
+/// Conversion goes through three phases, each of which can be run separately:
+///
+/// 1. [svg2drawing] parses the document into a [Drawing]
+/// 2. [super::drawing2geometry] orders its paths and flattens their curves into [super::Geometry]
+/// 3. [super::geometry2program] emits g-code for the [super::Geometry]
+///
+/// [Self::paths] can be reordered, removed, or changed in between, i.e. to order them some other way than
+/// [super::PathOrder] does, which should then be [super::PathOrder::Document].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawing {
+    pub paths: Vec<DrawingPath>,
+    /// Comments and markers after the last path
+    trailing: Vec<Command>,
+    options: ConversionOptions,
+    /// See [crate::ConversionStatistics::passes]
+    passes: usize,
+}
+
+/// One path of a [Drawing], along with the settings it is drawn with
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawingPath {
+    pub source: PathSource,
+    pub group_override: Option<GroupOverride>,
+    /// Comments and instance, layer, and pass markers for the path, then the [Command::MoveTo] or [Command::Drill]
+    /// that starts it and the rest of what is drawn
+    pub commands: Vec<Command>,
+    /// Position in document order, to tell whether paths were reordered
+    index: usize,
+    /// From the last [Command::ChangeFeedrate] before the path
+    feedrate: Option<f64>,
+    /// From the last [Command::ChangePower] before the path
+    power: Option<Option<f64>>,
+}
+
+/// Parse an SVG [`Document`] into a [Drawing]
+///
+/// Shapes are already drawn once per pass, relative to [ConversionConfig::origin].
+pub fn svg2drawing(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+) -> Result<Drawing, ConversionError> {
+    let (turtle, passes) = draw(doc, config, &options, RecordingTurtle::new(None))?;
+    Ok(Drawing::new(turtle.commands, options, passes))
+}
+
+impl Drawing {
+    /// Split recorded commands into paths, taking the [GroupOverride], [PathSource], feedrate, and power of each from
+    /// the commands before it
+    fn new(commands: Vec<Command>, options: ConversionOptions, passes: usize) -> Self {
+        let mut paths: Vec<DrawingPath> = vec![];
+        let mut pending = vec![];
+        let mut group_override = None;
+        let mut source = PathSource::default();
+        let mut feedrate = None;
+        let mut power = None;
+        for command in commands {
+            match command {
+                Command::OverrideSettings(next) => group_override = next,
+                Command::Source(next) => source = next,
+                Command::ChangeFeedrate(next) => feedrate = Some(next),
+                Command::ChangePower(next) => power = Some(next),
+                Command::Comment(_)
+                | Command::BeginInstance
+                | Command::EndInstance
+                | Command::BeginLayer(_)
+                | Command::EndLayer
+                | Command::BeginPass(..)
+                | Command::EndPass => pending.push(command),
+                Command::MoveTo(_) | Command::Drill(_) => {
+                    pending.push(command);
+                    paths.push(DrawingPath {
+                        source: source.clone(),
+                        group_override: group_override.clone(),
+                        commands: std::mem::take(&mut pending),
+                        index: paths.len(),
+                        feedrate,
+                        power,
+                    });
+                }
+                Command::LineTo(_) | Command::Arc(_) | Command::CubicBezier(_) => {
+                    // The [crate::turtle::Terrarium] always begins a path with a move, so there is one to add to
+                    if let Some(path) = paths.last_mut() {
+                        path.commands.append(&mut pending);
+                        path.commands.push(command);
+                    }
+                }
+            }
+        }
+        Self {
+            paths,
+            trailing: pending,
+            options,
+            passes,
+        }
+    }
+
+    pub(super) fn options(&self) -> &ConversionOptions {
+        &self.options
+    }
+
+    pub(super) fn passes(&self) -> usize {
+        self.passes
+    }
+
+    /// Draw the paths with a turtle, from [Turtle::begin] to [Turtle::end]
+    pub(super) fn replay<T: Turtle>(&self, mut turtle: T) -> T {
+        let reordered = self
+            .paths
+            .windows(2)
+            .any(|pair| pair[0].index >= pair[1].index);
+        // Markers would no longer surround what they did
+        let replay = |command: &Command, turtle: &mut T| {
+            let marker = matches!(
+                command,
+                Command::BeginInstance
+                    | Command::EndInstance
+                    | Command::BeginLayer(_)
+                    | Command::EndLayer
+                    | Command::BeginPass(..)
+                    | Command::EndPass
+            );
+            if !(reordered && marker) {
+                command.replay(turtle);
+            }
+        };
+        let mut group_override = None;
+        let mut source = None;
+        let mut feedrate = None;
+        let mut power = None;
+        turtle.begin();
+        for path in &self.paths {
+            if path.group_override.as_ref() != group_override {
+                turtle.override_settings(path.group_override.as_ref());
+                group_override = path.group_override.as_ref();
+            }
+            if source != Some(&path.source) {
+                turtle.source(&path.source);
+                source = Some(&path.source);
+            }
+            if let Some(path_feedrate) = path.feedrate
+                && feedrate != path.feedrate
+            {
+                turtle.change_feedrate(path_feedrate);
+                feedrate = path.feedrate;
+            }
+            if let Some(path_power) = path.power
+                && power != path.power
+            {
+                turtle.change_power(path_power);
+                power = path.power;
+            }
+            for command in &path.commands {
+                replay(command, &mut turtle);
+            }
+        }
+        for command in &self.trailing {
+            replay(command, &mut turtle);
+        }
+        turtle.end();
+        turtle
+    }
+}
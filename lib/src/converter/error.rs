@@ -0,0 +1,57 @@
+use std::{error::Error, fmt, io};
+
+/// Reasons a conversion can fail
+#[derive(Debug)]
+pub enum ConversionError {
+    /// An element has an attribute that could not be parsed
+    InvalidAttribute {
+        /// Tag name and id of the element
+        element: String,
+        attribute: &'static str,
+        error: Box<dyn Error + Send + Sync>,
+    },
+    /// Writing the program failed, see [super::svg2program_fmt]
+    Fmt(fmt::Error),
+    /// Writing the program failed, see [super::svg2program_io]
+    Io(io::Error),
+    /// [super::ConversionOptions::cancellation] was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAttribute {
+                element,
+                attribute,
+                error,
+            } => write!(f, "could not parse {attribute} of {element}: {error}"),
+            Self::Fmt(error) => write!(f, "could not write program: {error}"),
+            Self::Io(error) => write!(f, "could not write program: {error}"),
+            Self::Cancelled => f.write_str("conversion was cancelled"),
+        }
+    }
+}
+
+impl Error for ConversionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidAttribute { error, .. } => Some(error.as_ref()),
+            Self::Fmt(error) => Some(error),
+            Self::Io(error) => Some(error),
+            Self::Cancelled => None,
+        }
+    }
+}
+
+impl From<fmt::Error> for ConversionError {
+    fn from(error: fmt::Error) -> Self {
+        Self::Fmt(error)
+    }
+}
+
+impl From<io::Error> for ConversionError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
@@ -0,0 +1,102 @@
+use roxmltree::Document;
+
+use super::{
+    ConversionConfig, ConversionError, ConversionOptions, Drawing, Program, arrange, finish,
+    svg2drawing,
+};
+use crate::{
+    Machine, Turtle,
+    turtle::{Command, Flattening, GCodeTurtle, RecordingTurtle},
+};
+
+/// Paths of an SVG [`Document`] in millimeters, ordered and with curves flattened, ready to be turned into g-code
+///
+/// This is the planned toolpath, see [Drawing] for how it fits between the other phases of conversion.
+/// Converting this with [geometry2program] skips parsing, transforming, ordering, and flattening, so it can be kept
+/// around and converted again when only the settings that apply while emitting g-code change, like the feedrate,
+/// power, or machine sequences. Changes to anything that affects which paths are drawn and where need a new
+/// [svg2geometry]: the tolerance, DPI, origin, path order, drilling, passes, comments, and the groups that
+/// [ConversionConfig::overrides] apply to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    options: ConversionOptions,
+    commands: Vec<Command>,
+    /// See [crate::ConversionStatistics::passes]
+    passes: usize,
+}
+
+/// Convert an SVG [`Document`] into [Geometry], the same as [drawing2geometry] on the [svg2drawing] of it
+pub fn svg2geometry(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    machine: &Machine,
+) -> Result<Geometry, ConversionError> {
+    drawing2geometry(&svg2drawing(doc, config, options)?, config, machine)
+}
+
+/// Order the paths of a [Drawing] and flatten their curves into [Geometry]
+///
+/// Paths are ordered by [ConversionConfig::path_order] and then limited to [ConversionOptions::path_range]. Curves
+/// are flattened the way they would be for `machine`, depending on whether it supports circular interpolation. If
+/// [Drawing::paths] are no longer in document order, instances are not marked, since their content may no longer be
+/// contiguous.
+pub fn drawing2geometry(
+    drawing: &Drawing,
+    config: &ConversionConfig,
+    machine: &Machine,
+) -> Result<Geometry, ConversionError> {
+    let options = drawing.options();
+    let flattening = Flattening::new(config, machine);
+    let reordering_turtle = arrange(config, options, RecordingTurtle::new(Some(flattening)));
+    #[cfg(feature = "rayon")]
+    let reordering_turtle = reordering_turtle.flatten_in_parallel(flattening);
+    let reordering_turtle = drawing.replay(reordering_turtle);
+    options.check_cancellation()?;
+    Ok(Geometry {
+        options: options.clone(),
+        commands: reordering_turtle.inner.inner.commands,
+        passes: drawing.passes(),
+    })
+}
+
+/// Convert [Geometry] into g-code, giving the same program as [super::svg2program] would for its SVG
+///
+/// Group overrides are looked up again in `config` by their group, so their feedrate, power, and depth can change too.
+pub fn geometry2program<'input>(
+    geometry: &Geometry,
+    config: &ConversionConfig,
+    machine: Machine<'input>,
+) -> Program<'input> {
+    let framing_machine = config.framing.then(|| machine.clone());
+    let mut turtle = GCodeTurtle {
+        first_path: geometry
+            .options
+            .path_range
+            .as_ref()
+            .map_or(0, |range| range.start),
+        ..GCodeTurtle::new(config, machine)
+    };
+    turtle.begin();
+    for command in &geometry.commands {
+        match command {
+            Command::OverrideSettings(group_override) => {
+                turtle.override_settings(group_override.as_ref().and_then(|recorded| {
+                    config
+                        .overrides
+                        .iter()
+                        .find(|group_override| group_override.group == recorded.group)
+                }))
+            }
+            command => command.replay(&mut turtle),
+        }
+    }
+    turtle.end();
+    Program::from_turtle(finish(
+        config,
+        &geometry.options,
+        turtle,
+        geometry.passes,
+        framing_machine,
+    ))
+}
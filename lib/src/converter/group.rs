@@ -0,0 +1,334 @@
+use std::{convert::Infallible, str::FromStr};
+
+use roxmltree::{Document, Node};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use svgtypes::Color;
+
+use super::visit::{INKSCAPE_NAMESPACE, XmlVisitor, depth_first_visit, is_layer, is_shape};
+
+/// How [super::svg2programs] divides a drawing into separate programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SplitBy {
+    /// One program per Inkscape layer, named after its label
+    Layer,
+    /// One program per stroke color, named after its hex code (i.e. `#ff0000`)
+    Color,
+}
+
+/// Elements of one layer or color, see [super::ConversionOptions::group]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Group {
+    pub split_by: SplitBy,
+    /// Layer label or stroke color, [None] for elements outside of any layer or without a stroke
+    pub name: Option<String>,
+}
+
+/// Settings that differ for the elements of one layer or color, see [super::ConversionConfig::overrides]
+///
+/// Settings that are not set keep their configured value.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GroupOverride {
+    pub group: Group,
+    /// Feedrate in millimeters / minute
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub feedrate: Option<f64>,
+    /// Laser power or spindle speed (S word)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub power: Option<f64>,
+    /// Number of times each path is cut, where 0 skips the group entirely
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub passes: Option<usize>,
+    /// Depth in millimeters that the tool plunges to
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cut_z: Option<f64>,
+}
+
+/// Tracks which group the element being visited belongs to
+///
+/// Both layers and stroke colors are inherited by descendants, including elements referenced by a `<use>`.
+#[derive(Debug)]
+pub struct GroupStack {
+    split_by: SplitBy,
+    stack: Vec<Option<String>>,
+}
+
+impl GroupStack {
+    pub fn new(split_by: SplitBy) -> Self {
+        Self {
+            split_by,
+            stack: vec![],
+        }
+    }
+
+    pub fn enter(&mut self, node: &Node) {
+        let inherited = self.stack.last().cloned().flatten();
+        let name = match self.split_by {
+            SplitBy::Layer if is_layer(node) => Some(layer_name(node)),
+            SplitBy::Color => stroke(node).or(inherited),
+            SplitBy::Layer => inherited,
+        };
+        self.stack.push(name);
+    }
+
+    pub fn split_by(&self) -> SplitBy {
+        self.split_by
+    }
+
+    pub fn exit(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Group of the element most recently entered
+    pub fn current(&self) -> Option<&str> {
+        self.stack.last().and_then(Option::as_deref)
+    }
+}
+
+fn layer_name(node: &Node) -> String {
+    node.attribute((INKSCAPE_NAMESPACE, "label"))
+        .or_else(|| node.attribute("id"))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Stroke color set directly on an element, normalized so that `black` and `#000` are the same group
+fn stroke(node: &Node) -> Option<String> {
+    let value = node
+        .attribute("style")
+        .and_then(|style| {
+            style.split(';').find_map(|declaration| {
+                let (property, value) = declaration.split_once(':')?;
+                (property.trim() == "stroke").then_some(value.trim())
+            })
+        })
+        .or_else(|| node.attribute("stroke"))?;
+    Some(match Color::from_str(value) {
+        Ok(Color {
+            red, green, blue, ..
+        }) => format!("#{red:02x}{green:02x}{blue:02x}"),
+        Err(_) => value.to_string(),
+    })
+}
+
+/// Groups that contain at least one shape, in document order
+pub fn groups(doc: &Document, split_by: SplitBy) -> Vec<Option<String>> {
+    struct GroupCollector {
+        stack: GroupStack,
+        groups: Vec<Option<String>>,
+    }
+
+    impl XmlVisitor for GroupCollector {
+        type Error = Infallible;
+
+        fn visit_enter(&mut self, node: Node) -> Result<bool, Infallible> {
+            self.stack.enter(&node);
+            let group = self.stack.current().map(str::to_string);
+            if is_shape(&node) && !self.groups.contains(&group) {
+                self.groups.push(group);
+            }
+            Ok(true)
+        }
+
+        fn visit_exit(&mut self, _node: Node) {
+            self.stack.exit();
+        }
+    }
+
+    let mut collector = GroupCollector {
+        stack: GroupStack::new(split_by),
+        groups: vec![],
+    };
+    let Ok(()) = depth_first_visit(doc, &mut collector);
+    collector.groups
+}
+
+/// A layer or top-level group of a drawing, see [svg2layers]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LayerSummary {
+    pub id: Option<String>,
+    /// Inkscape label, the name that [SplitBy::Layer] gives the group when set
+    pub label: Option<String>,
+    /// Whether Inkscape treats the group as a layer, rather than it being a plain top-level group
+    pub is_layer: bool,
+    /// Number of layers or groups that this one is nested in
+    pub depth: usize,
+    /// Number of shapes in the group, including those in nested layers
+    pub shapes: usize,
+    /// Stroke colors of the shapes in order of appearance, normalized as for [SplitBy::Color]
+    pub colors: Vec<String>,
+}
+
+/// Whether [svg2layers] lists this element: an Inkscape layer or a group at the top of the drawing
+pub(crate) fn is_layer_or_top_level_group(node: &Node) -> bool {
+    is_layer(node) || (node.has_tag_name("g") && is_top_level(node))
+}
+
+/// Whether this element is directly in the outermost `<svg>`
+pub(crate) fn is_top_level(node: &Node) -> bool {
+    node.parent_element()
+        .is_some_and(|parent| parent == node.document().root_element())
+}
+
+/// Whether this is a layer or top-level group with one of these names, either its Inkscape label or its id
+pub(crate) fn is_named_layer(node: &Node, names: &[String]) -> bool {
+    is_layer_or_top_level_group(node)
+        && [
+            node.attribute((INKSCAPE_NAMESPACE, "label")),
+            node.attribute("id"),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|name| names.iter().any(|n| n == name))
+}
+
+/// Summarize the Inkscape layers and top-level groups of an SVG [`Document`] in document order
+///
+/// Hidden elements are left out, as they are when converting.
+pub fn svg2layers(doc: &Document) -> Vec<LayerSummary> {
+    struct LayerCollector {
+        colors: GroupStack,
+        layers: Vec<LayerSummary>,
+        /// Index in `layers` of each element being visited that is summarized
+        open: Vec<Option<usize>>,
+    }
+
+    impl XmlVisitor for LayerCollector {
+        type Error = Infallible;
+
+        fn visit_enter(&mut self, node: Node) -> Result<bool, Infallible> {
+            self.colors.enter(&node);
+            if is_layer_or_top_level_group(&node) {
+                self.layers.push(LayerSummary {
+                    id: node.attribute("id").map(str::to_string),
+                    label: node
+                        .attribute((INKSCAPE_NAMESPACE, "label"))
+                        .map(str::to_string),
+                    is_layer: is_layer(&node),
+                    depth: self.open.iter().flatten().count(),
+                    shapes: 0,
+                    colors: vec![],
+                });
+                self.open.push(Some(self.layers.len() - 1));
+            } else {
+                self.open.push(None);
+            }
+            if is_shape(&node) {
+                let color = self.colors.current();
+                for &index in self.open.iter().flatten() {
+                    let layer = &mut self.layers[index];
+                    layer.shapes += 1;
+                    if let Some(color) = color
+                        && !layer.colors.iter().any(|c| c == color)
+                    {
+                        layer.colors.push(color.to_string());
+                    }
+                }
+            }
+            Ok(true)
+        }
+
+        fn visit_exit(&mut self, _node: Node) {
+            self.colors.exit();
+            self.open.pop();
+        }
+    }
+
+    let mut collector = LayerCollector {
+        colors: GroupStack::new(SplitBy::Color),
+        layers: vec![],
+        open: vec![],
+    };
+    let Ok(()) = depth_first_visit(doc, &mut collector);
+    collector.layers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn colors_are_normalized_and_inherited() {
+        let doc = Document::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg">
+                <path d="M0,0 L1,1" style="fill:none;stroke:#FF0000"/>
+                <g stroke="red"><path d="M0,0 L1,1"/></g>
+                <path d="M0,0 L1,1" stroke="black"/>
+                <line x1="0" y1="0" x2="1" y2="1"/>
+            </svg>"##,
+        )
+        .unwrap();
+        assert_eq!(
+            groups(&doc, SplitBy::Color),
+            [
+                Some("#ff0000".to_string()),
+                Some("#000000".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn layers_use_inkscape_label() {
+        let doc = Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+                <g inkscape:groupmode="layer" inkscape:label="cut" id="layer1"><path d="M0,0 L1,1"/></g>
+                <g inkscape:groupmode="layer" id="layer2"><g><path d="M0,0 L1,1"/></g></g>
+                <g inkscape:groupmode="layer" inkscape:label="empty"/>
+            </svg>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            groups(&doc, SplitBy::Layer),
+            [Some("cut".to_string()), Some("layer2".to_string())]
+        );
+    }
+
+    #[test]
+    fn layers_are_summarized_with_shapes_and_colors() {
+        let doc = Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+                <g inkscape:groupmode="layer" inkscape:label="cut" id="layer1">
+                    <path d="M0,0 L1,1" stroke="red"/>
+                    <g inkscape:groupmode="layer" id="layer2"><rect width="1" height="1" stroke="blue"/></g>
+                </g>
+                <g id="marks"><circle r="1"/></g>
+                <g inkscape:groupmode="layer" inkscape:label="hidden" style="display:none"><path d="M0,0 L1,1"/></g>
+            </svg>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            svg2layers(&doc),
+            [
+                LayerSummary {
+                    id: Some("layer1".to_string()),
+                    label: Some("cut".to_string()),
+                    is_layer: true,
+                    depth: 0,
+                    shapes: 2,
+                    colors: vec!["#ff0000".to_string(), "#0000ff".to_string()],
+                },
+                LayerSummary {
+                    id: Some("layer2".to_string()),
+                    label: None,
+                    is_layer: true,
+                    depth: 1,
+                    shapes: 1,
+                    colors: vec!["#0000ff".to_string()],
+                },
+                LayerSummary {
+                    id: Some("marks".to_string()),
+                    label: None,
+                    is_layer: false,
+                    depth: 0,
+                    shapes: 1,
+                    colors: vec![],
+                },
+            ]
+        );
+    }
+}
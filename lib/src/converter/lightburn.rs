@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+use log::warn;
+use roxmltree::Document;
+
+use super::{
+    ConversionConfig, ConversionError, ConversionOptions, GroupOverride, PathSource, SplitBy,
+    svg2polylines,
+};
+
+/// Most cut settings a LightBurn project can hold, one for each of its layer colors
+const MAX_CUT_SETTINGS: usize = 30;
+
+/// Layer of a LightBurn project, with the speed and power the shapes on it are cut with
+#[derive(Debug, PartialEq)]
+struct CutSetting<'a> {
+    name: Option<&'a str>,
+    group_override: Option<&'a GroupOverride>,
+}
+
+/// Convert an SVG [`Document`] into a LightBurn project (`.lbrn2`), for finishing a job in LightBurn
+///
+/// Paths are flattened, transformed and ordered as they would be cut, then put on a LightBurn layer for each of the
+/// [ConversionConfig::overrides] they fall under, or else for their Inkscape layer. Each layer gets the feedrate,
+/// power and passes that the paths would be cut with, where power is a percentage of `max_power`, the S word at full
+/// power (i.e. GRBL's `$30`). Holes to drill are left out.
+pub fn svg2lightburn(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    max_power: f64,
+) -> Result<String, ConversionError> {
+    // Passes are a setting of each layer, rather than repeated shapes
+    let single_pass = ConversionConfig {
+        passes: 1,
+        overrides: config
+            .overrides
+            .iter()
+            .map(|group_override| GroupOverride {
+                passes: group_override.passes.map(|passes| passes.min(1)),
+                ..group_override.clone()
+            })
+            .collect(),
+        ..config.clone()
+    };
+    let polylines = svg2polylines(doc, &single_pass, options)?;
+
+    let mut cut_settings = vec![];
+    let mut crowded = false;
+    let mut shapes = String::new();
+    for polyline in polylines.iter().filter(|polyline| !polyline.drill) {
+        let [first, .., last] = polyline.points.as_slice() else {
+            continue;
+        };
+        let cut_setting = cut_setting(config, &polyline.source);
+        let index = match cut_settings.iter().position(|other| *other == cut_setting) {
+            Some(index) => index,
+            None if cut_settings.len() < MAX_CUT_SETTINGS => {
+                cut_settings.push(cut_setting);
+                cut_settings.len() - 1
+            }
+            None => {
+                crowded = true;
+                MAX_CUT_SETTINGS - 1
+            }
+        };
+
+        // A closed path ends with a line back to its first vertex, rather than a vertex repeated
+        let closed = polyline.points.len() > 2 && first == last;
+        let vertices = if closed {
+            &polyline.points[..polyline.points.len() - 1]
+        } else {
+            &polyline.points[..]
+        };
+        let mut vert_list = String::new();
+        let mut prim_list = String::new();
+        for (i, vertex) in vertices.iter().enumerate() {
+            // Writing to a string can't fail
+            let _ = write!(vert_list, "V{} {}", vertex.x, vertex.y);
+            if i > 0 {
+                let _ = write!(prim_list, "L{} {i}", i - 1);
+            }
+        }
+        if closed {
+            let _ = write!(prim_list, "L{} 0", vertices.len() - 1);
+        }
+        let _ = writeln!(
+            shapes,
+            "    <Shape Type=\"Path\" CutIndex=\"{index}\">\n        <XForm>1 0 0 1 0 0</XForm>\n        <VertList>{vert_list}</VertList>\n        <PrimList>{prim_list}</PrimList>\n    </Shape>"
+        );
+    }
+    if crowded {
+        warn!(
+            "LightBurn projects hold up to {MAX_CUT_SETTINGS} layers, the rest were put on the last one"
+        );
+    }
+
+    let mut project = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<LightBurnProject FormatVersion=\"1\" MaterialHeight=\"0\" MirrorX=\"False\" MirrorY=\"False\">\n",
+    );
+    for (index, cut_setting) in cut_settings.iter().enumerate() {
+        let group_override = cut_setting.group_override;
+        let feedrate = group_override
+            .and_then(|group_override| group_override.feedrate)
+            .unwrap_or(config.feedrate);
+        let power = group_override
+            .and_then(|group_override| group_override.power)
+            .or(config.power);
+        let passes = group_override
+            .and_then(|group_override| group_override.passes)
+            .unwrap_or(config.passes);
+
+        let _ = writeln!(project, "    <CutSetting type=\"Cut\">");
+        let _ = writeln!(project, "        <index Value=\"{index}\"/>");
+        let _ = writeln!(
+            project,
+            "        <name Value=\"{}\"/>",
+            escape(
+                &cut_setting
+                    .name
+                    .map_or_else(|| format!("C{index:02}"), str::to_string)
+            )
+        );
+        // LightBurn keeps speeds in mm/s
+        let _ = writeln!(project, "        <speed Value=\"{}\"/>", feedrate / 60.);
+        if let Some(power) = power {
+            let _ = writeln!(
+                project,
+                "        <maxPower Value=\"{}\"/>",
+                percentage(power, max_power)
+            );
+            let min_power = config.min_power.unwrap_or(power);
+            let _ = writeln!(
+                project,
+                "        <minPower Value=\"{}\"/>",
+                percentage(min_power, max_power)
+            );
+        }
+        let _ = writeln!(project, "        <numPasses Value=\"{passes}\"/>");
+        let _ = writeln!(project, "        <priority Value=\"{index}\"/>");
+        let _ = writeln!(project, "    </CutSetting>");
+    }
+    project += &shapes;
+    project += "</LightBurnProject>\n";
+    Ok(project)
+}
+
+/// The override a path falls under, as the conversion finds it, or else its Inkscape layer
+fn cut_setting<'a>(config: &'a ConversionConfig, source: &'a PathSource) -> CutSetting<'a> {
+    let group_override = config.overrides.iter().find(|group_override| {
+        let name = match group_override.group.split_by {
+            SplitBy::Layer => source.layer.as_deref(),
+            SplitBy::Color => source.stroke.as_deref(),
+        };
+        name == group_override.group.name.as_deref()
+    });
+    match group_override {
+        Some(group_override) => CutSetting {
+            name: group_override.group.name.as_deref(),
+            group_override: Some(group_override),
+        },
+        None => CutSetting {
+            name: source.layer.as_deref(),
+            group_override: None,
+        },
+    }
+}
+
+fn percentage(power: f64, max_power: f64) -> f64 {
+    (power / max_power * 100.).clamp(0., 100.)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,22 +1,61 @@
-use std::fmt::Debug;
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    ops::Range,
+    str::FromStr,
+};
 
 use g_code::emit::Token;
-use lyon_geom::euclid::default::Transform2D;
-use roxmltree::{Document, Node};
+use log::warn;
+use lyon_geom::{
+    Box2D,
+    euclid::{Angle, default::Transform2D},
+    point,
+};
+use roxmltree::{Document, Node, NodeId};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use svgtypes::Length;
+use svgtypes::{Length, ViewBox, ViewBoxError};
 use uom::si::{
     f64::Length as UomLength,
     length::{inch, millimeter},
 };
 
-use self::units::CSS_DEFAULT_DPI;
-use crate::{Machine, turtle::*};
+use self::group::GroupStack;
+pub(crate) use self::group::{is_layer_or_top_level_group, is_named_layer, is_top_level};
+pub(crate) use self::visit::is_in_definitions;
+pub use self::{
+    chunk::{ChunkLimit, svg2chunks},
+    drawing::{Drawing, DrawingPath, svg2drawing},
+    error::ConversionError,
+    geometry::{Geometry, drawing2geometry, geometry2program, svg2geometry},
+    group::{Group, GroupOverride, LayerSummary, SplitBy, svg2layers},
+    lightburn::svg2lightburn,
+    polyline::{PathSource, Polyline, svg2polylines},
+    units::{
+        CSS_DEFAULT_DPI, DimensionHint, LengthError, UserUnits, length_to_user_units, parse_length,
+    },
+};
+use crate::{
+    Cancellation, Float, Hooks, Machine, PostprocessConfig,
+    postprocess::{IoAdapter, ProgramWriter},
+    sphere::wrap_around_sphere,
+    statistics::ConversionStatistics,
+    subprogram::extract_subprograms,
+    turtle::*,
+    widen,
+};
 
+mod chunk;
+mod drawing;
+mod error;
+mod geometry;
+mod group;
 #[cfg(feature = "serde")]
 mod length_serde;
+mod lightburn;
 mod path;
+mod polyline;
 mod transform;
 mod units;
 mod visit;
@@ -36,6 +75,306 @@ pub struct ConversionConfig {
     pub origin: [Option<f64>; 2],
     /// Set extra attribute to add when printing node name
     pub extra_attribute_name: Option<String>,
+    /// Controls which motion commands carry an F word
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub feedrate_policy: FeedratePolicy,
+    /// Syntax used for comments
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub comment_style: CommentStyle,
+    /// How much of the SVG structure is annotated with comments
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub comment_verbosity: CommentVerbosity,
+    /// Start the program with a comment block describing the job
+    ///
+    /// Includes the source, key settings, bounding box, cut/travel lengths and an estimated duration.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata_header: bool,
+    /// Slow down for sharp corners to reduce overshoot and ringing
+    ///
+    /// The feedrate of a move is divided by `1 + corner_slowdown × angle / 180°`, where angle is the change in
+    /// direction going into the next move. 0 disables slowdown.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub corner_slowdown: f64,
+    /// Height in millimeters that rapid moves happen at, for CNC routers and mills
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub safe_z: Option<f64>,
+    /// Depth in millimeters that the tool plunges to at the start of each path
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cut_z: Option<f64>,
+    /// Retract to [Self::safe_z] before every rapid move, rather than only at the start and end of the program
+    #[cfg_attr(feature = "serde", serde(default = "default_retract_between_paths"))]
+    pub retract_between_paths: bool,
+    /// Carry the state of the tool over from one path to the next, skipping commands that would not change it
+    ///
+    /// A path that starts where the last one ended is cut without turning the tool off and moving there, and
+    /// [Self::power] is only emitted again once it changes, including when a tool on sequence already set it. This
+    /// avoids lasers flickering between adjacent paths.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skip_redundant_commands: bool,
+    /// Trace every move with the tool left off, to check placement before cutting material
+    ///
+    /// Tool on sequences and pen down are skipped, power is zero and the tool stays at [Self::safe_z].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dry_run: bool,
+    /// Instead of the job, trace the perimeter of its bounding box to check material alignment
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub framing: bool,
+    /// Power while [Self::framing], i.e. a low power so the laser is visible without marking
+    ///
+    /// The tool stays off if this is not set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub framing_power: Option<f64>,
+    /// Where to move the tool once the job is done, so that it is out of the way for unloading
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub park: Option<ParkPosition>,
+    /// Laser power or spindle speed (S word) for cutting moves, emitted at the start of each path
+    ///
+    /// With [Self::skip_redundant_commands], it is only emitted on the first cutting move and whenever it changes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub power: Option<f64>,
+    /// Scale [Self::power] down where the machine is slowed by acceleration, i.e. corners
+    ///
+    /// Avoids overburning on constant-power (M3) lasers. Every cutting move gets an S word proportional to its
+    /// average speed, modeled with [crate::Kinematics::acceleration]. Without an acceleration, this has no effect and
+    /// a warning is logged.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub power_ramping: bool,
+    /// Lowest power at which the tool fires, i.e. a laser tube's threshold
+    ///
+    /// Any nonzero power below this, whether configured or from [Self::power_ramping], is raised to it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min_power: Option<f64>,
+    /// Periodically report how far along the job is
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub progress_markers: ProgressMarkers,
+    /// Minimum advance in percent between [Self::progress_markers]
+    #[cfg_attr(feature = "serde", serde(default = "one_percent"))]
+    pub progress_interval: usize,
+    /// Comment the state needed to restart the job at every this many paths, see [ConversionOptions::path_range]
+    ///
+    /// Each checkpoint reads `checkpoint path=<index> x=<mm> y=<mm> f=<mm/min>`, followed by `s=<power>` and
+    /// `z=<mm>` when [Self::power] and the tool height are known. It is written just before the rapid move
+    /// to the start of the path.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checkpoint_interval: Option<usize>,
+    /// Emit content that is repeated with `<use>` once as a subprogram, and call it wherever it is drawn
+    ///
+    /// Uses Fanuc-style `M98`/`M99` calls with a `G52` local offset, supported by LinuxCNC, Mach3 and most industrial
+    /// controllers, but not GRBL or Marlin. Only instances that differ by a translation share a subprogram, and
+    /// comments inside it are those of the first instance.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub subprograms: bool,
+    /// Drill small circles at their center instead of tracing their outline
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub drilling: Option<Drilling>,
+    /// Number of times each path is cut, i.e. to get through thicker material
+    #[cfg_attr(feature = "serde", serde(default = "one_pass"))]
+    pub passes: usize,
+    /// Different settings for some layers or colors, i.e. to engrave and cut in one job
+    ///
+    /// An element takes the first override that matches its layer or color.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub overrides: Vec<GroupOverride>,
+    /// Order that paths are drawn in
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub path_order: PathOrder,
+    /// Keep improving on [Self::path_order] with 2-opt and Or-opt moves within this budget
+    ///
+    /// Helps with thousands of strokes, where greedy ordering leaves obvious crossings. Has no effect with
+    /// [PathOrder::Document]. [None] keeps the order as [Self::path_order] leaves it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub path_refinement: Option<RefinementBudget>,
+    /// Mirror the drawing, before it is rotated and placed at [Self::origin]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flip: Flip,
+    /// Rotate the drawing counterclockwise by this many degrees, before it is placed at [Self::origin]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rotation: f64,
+    /// Scale the drawing by this factor, before it is placed at [Self::origin]
+    #[cfg_attr(feature = "serde", serde(default = "unscaled"))]
+    pub scale: f64,
+    /// Scale the drawing up or down to fit within this width and height in millimeters, keeping its aspect ratio
+    ///
+    /// Happens after [Self::flip], [Self::rotation] and [Self::scale].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fit_to: Option<[f64; 2]>,
+    /// Draw on an egg or sphere that turns under a pen on a pivoting arm, i.e. an EggBot or Sphere-O-Bot
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sphere: Option<Sphere>,
+    /// Snap every coordinate in the program to a grid this many millimeters apart, i.e. the resolution of the machine
+    ///
+    /// Keeps the program the same between runs despite rounding, and moves that snap to where the tool already is are
+    /// left out. Heights are snapped too, from [Self::safe_z] and [Self::cut_z] to the depth and retract plane of
+    /// [Self::drilling].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub grid: Option<f64>,
+}
+
+/// Where feedrate (F) words are emitted
+///
+/// Feedrate is modal in G-Code, but some senders and firmwares expect it on every
+/// motion command while others choke on the redundancy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FeedratePolicy {
+    /// Every cutting move carries an F word
+    #[default]
+    EveryMove,
+    /// Only the first cutting move of each path carries an F word
+    PerPath,
+    /// An F word is only emitted when the feedrate differs from the last one emitted
+    OnChange,
+}
+
+/// Syntax used for comments
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CommentStyle {
+    /// `; comment` until the end of the line
+    #[default]
+    Semicolon,
+    /// `(comment)`, for parsers that only understand parenthesized comments
+    Parentheses,
+}
+
+/// How much of the SVG structure is annotated with comments
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CommentVerbosity {
+    /// No comments at all, for strict parsers
+    None,
+    /// A comment at the start of each Inkscape layer
+    PerLayer,
+    /// A comment for each drawn element, prefixed by its ancestors
+    #[default]
+    PerElement,
+    /// Same as [CommentVerbosity::PerElement], plus a comment for each path segment
+    PerSegment,
+}
+
+/// Holes for [ConversionConfig::drilling]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Drilling {
+    /// Circles with a diameter up to this many millimeters are drilled
+    pub max_diameter: f64,
+    /// Depth of the bottom of the hole in millimeters
+    pub depth: f64,
+    /// Height in millimeters that the drill starts from and returns to, i.e. the R plane of a canned cycle
+    pub retract: f64,
+    pub cycle: DrillCycle,
+}
+
+/// How a hole is drilled
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DrillCycle {
+    /// `G81` canned cycle, straight down and back up
+    #[default]
+    Simple,
+    /// `G83` canned cycle, retracting after every `peck` millimeters to clear chips
+    Peck { peck: f64 },
+    /// Plain `G1`/`G0` moves, for controllers without canned cycles (i.e. GRBL)
+    Plunge,
+}
+
+/// Egg or sphere for [ConversionConfig::sphere]
+///
+/// The drawing is wrapped around it with X along the equator and Y toward the poles, so that Y = 0 is the equator.
+/// X words become the rotation of the egg and Y words the angle of the pen arm, both in degrees, so the machine's
+/// steps per unit must be set in steps per degree. Curves are cut as lines since arcs don't carry over to the surface,
+/// and [ConversionConfig::subprograms] are not extracted. Statistics are of the flat drawing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sphere {
+    /// Diameter of the egg at its equator in millimeters
+    pub diameter: f64,
+    /// Turn the egg further away from the equator, where its surface is narrower, so that the drawing keeps its
+    /// proportions
+    ///
+    /// Without this, the drawing is squeezed toward the poles as it is on a globe.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compensate_latitude: bool,
+}
+
+/// Order that paths are drawn in, see [ConversionConfig::path_order]
+///
+/// Paths are always drawn in the direction they are defined. When reordering, [ConversionOptions::path_range] and
+/// checkpoints count paths in the new order, and [ConversionConfig::subprograms] are not extracted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PathOrder {
+    /// The order they appear in the SVG
+    #[default]
+    Document,
+    /// Repeatedly draw the path that starts closest to where the tool is, which shortens travel on scattered artwork
+    NearestNeighbor,
+}
+
+/// Limit on [ConversionConfig::path_refinement], which stops early once no move shortens travel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RefinementBudget {
+    /// Number of candidate moves to try, where making a move also counts once for every path
+    Iterations(usize),
+    /// Wall-clock time to spend
+    ///
+    /// How far refinement gets depends on the computer and what else it is doing, so the order can differ between
+    /// runs. [Self::Iterations] gives the same order every time. WebAssembly has no clock, so there refinement runs
+    /// until no move shortens travel.
+    Milliseconds(u64),
+}
+
+/// Mirroring of the drawing, see [ConversionConfig::flip]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Flip {
+    #[default]
+    None,
+    /// Mirror left to right
+    Horizontal,
+    /// Mirror top to bottom
+    Vertical,
+    /// Mirror both ways, which is the same as rotating by 180°
+    Both,
+}
+
+/// Position the tool is parked at after the job
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ParkPosition {
+    /// X0 and the far Y edge of the job
+    BehindJob,
+    /// Fixed X and Y coordinates in millimeters
+    Custom([f64; 2]),
+}
+
+/// Marker inserted into the program as the job progresses
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProgressMarkers {
+    #[default]
+    None,
+    /// `M73 P<percent>`, shown as a progress bar by Marlin, Prusa and Klipper firmwares
+    M73,
+    /// A `Progress: <percent>%` comment
+    Comment,
+}
+
+const fn default_retract_between_paths() -> bool {
+    true
+}
+
+const fn one_percent() -> usize {
+    1
+}
+
+const fn one_pass() -> usize {
+    1
+}
+
+const fn unscaled() -> f64 {
+    1.
 }
 
 const fn zero_origin() -> [Option<f64>; 2] {
@@ -50,6 +389,37 @@ impl Default for ConversionConfig {
             dpi: 96.0,
             origin: zero_origin(),
             extra_attribute_name: None,
+            feedrate_policy: FeedratePolicy::default(),
+            comment_style: CommentStyle::default(),
+            comment_verbosity: CommentVerbosity::default(),
+            metadata_header: false,
+            corner_slowdown: 0.,
+            safe_z: None,
+            cut_z: None,
+            retract_between_paths: default_retract_between_paths(),
+            skip_redundant_commands: false,
+            dry_run: false,
+            framing: false,
+            framing_power: None,
+            park: None,
+            power: None,
+            power_ramping: false,
+            min_power: None,
+            progress_markers: ProgressMarkers::default(),
+            progress_interval: one_percent(),
+            checkpoint_interval: None,
+            subprograms: false,
+            drilling: None,
+            passes: one_pass(),
+            overrides: vec![],
+            path_order: PathOrder::default(),
+            path_refinement: None,
+            flip: Flip::default(),
+            rotation: 0.,
+            scale: unscaled(),
+            fit_to: None,
+            sphere: None,
+            grid: None,
         }
     }
 }
@@ -65,6 +435,46 @@ pub struct ConversionOptions {
     /// Useful when an SVG does not have a set width and height or you want to override it.
     #[cfg_attr(feature = "serde", serde(with = "length_serde"))]
     pub dimensions: [Option<Length>; 2],
+    /// Name of the SVG being converted, shown in the [ConversionConfig::metadata_header]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub source_name: Option<String>,
+    /// Only convert the elements in this layer or of this color, see [svg2programs]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub group: Option<Group>,
+    /// Only convert the paths with these indices, counting each subpath in document order from zero
+    ///
+    /// The program still has its begin and end sequences, so a long job can be split up or resumed part way through.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub path_range: Option<Range<usize>>,
+    /// Stop converting as soon as this is cancelled, returning [ConversionError::Cancelled]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cancellation: Option<Cancellation>,
+    /// Called as each element is converted, i.e. to skip some or draw them with other settings
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub hooks: Hooks,
+}
+
+impl ConversionOptions {
+    fn check_cancellation(&self) -> Result<(), ConversionError> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(Cancellation::is_cancelled)
+        {
+            Err(ConversionError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// Maps SVG [`Node`]s and their attributes into operations on a [`Terrarium`]
@@ -76,17 +486,160 @@ struct ConversionVisitor<'a, T: Turtle> {
     viewport_dim_stack: Vec<[f64; 2]>,
     _config: &'a ConversionConfig,
     options: ConversionOptions,
+    /// Present when converting only one [ConversionOptions::group]
+    group_stack: Option<GroupStack>,
+    /// One for each [SplitBy], to find the [ConversionConfig::overrides] and [PathSource] of shapes
+    group_stacks: [GroupStack; 2],
+    /// Override currently given to the turtle
+    active_override: Option<GroupOverride>,
+    /// [crate::HookAction::Override] given for each element being visited, if any
+    hook_overrides: Vec<Option<GroupOverride>>,
+    /// Most passes given to any shape so far
+    passes: usize,
+    /// Feedrate currently given to the turtle, see [Turtle::change_feedrate]
+    feedrate: f64,
+    /// Power currently given to the turtle, see [Turtle::change_power]
+    power: Option<f64>,
+    /// Layers that the turtle was told have begun, innermost last
+    layers: Vec<NodeId>,
 }
 
 impl<'a, T: Turtle> ConversionVisitor<'a, T> {
+    fn invalid_attribute(
+        &self,
+        node: &Node,
+        attribute: &'static str,
+        error: impl Error + Send + Sync + 'static,
+    ) -> ConversionError {
+        ConversionError::InvalidAttribute {
+            element: node_name(node, &self._config.extra_attribute_name),
+            attribute,
+            error: Box::new(error),
+        }
+    }
+
+    /// Parse the `viewBox` of an element, ignoring one with a negative or zero size
+    fn view_box(&self, node: &Node) -> Result<Option<ViewBox>, ConversionError> {
+        match node.attribute("viewBox").map(ViewBox::from_str) {
+            Some(Err(ViewBoxError::InvalidSize)) => {
+                warn!(
+                    "Invalid viewBox on {}",
+                    node_name(node, &self._config.extra_attribute_name)
+                );
+                Ok(None)
+            }
+            view_box => view_box
+                .transpose()
+                .map_err(|error| self.invalid_attribute(node, "viewBox", error)),
+        }
+    }
+
+    /// Comment the element about to be drawn, if [ConversionConfig::comment_verbosity] calls for it
     fn comment(&mut self, node: &Node) {
-        let mut comment = String::new();
+        if matches!(
+            self._config.comment_verbosity,
+            CommentVerbosity::PerElement | CommentVerbosity::PerSegment
+        ) {
+            self.node_comment(node);
+        }
+    }
+
+    /// Tell the turtle an Inkscape layer has begun, commenting it if [ConversionConfig::comment_verbosity] calls for it
+    fn begin_layer(&mut self, node: &Node) {
+        if self._config.comment_verbosity == CommentVerbosity::PerLayer {
+            self.node_comment(node);
+        }
+        self.terrarium
+            .turtle
+            .begin_layer(node.attribute((visit::INKSCAPE_NAMESPACE, "label")));
+        self.layers.push(node.id());
+    }
+
+    /// Whether a circle is small enough to drill, see [ConversionConfig::drilling]
+    fn should_drill(&self, radius: f64) -> bool {
+        let Some(drilling) = self._config.drilling else {
+            return false;
+        };
+        self.terrarium
+            .transform_radius(radius as Float)
+            .is_some_and(|radius| {
+                UomLength::new::<inch>(2. * widen(radius) / self._config.dpi).get::<millimeter>()
+                    <= drilling.max_diameter
+            })
+    }
+
+    /// Whether the element most recently entered is part of [ConversionOptions::group]
+    fn in_group(&self) -> bool {
+        match (&self.group_stack, &self.options.group) {
+            (Some(group_stack), Some(group)) => group_stack.current() == group.name.as_deref(),
+            _ => true,
+        }
+    }
+
+    /// Switch the turtle to the override and source of the shape most recently entered and return how many passes
+    /// it gets
+    fn begin_shape(&mut self, node: &Node) -> usize {
+        let group_override = self
+            .hook_overrides
+            .iter()
+            .rev()
+            .find_map(Option::as_ref)
+            .or_else(|| {
+                self._config.overrides.iter().find(|group_override| {
+                    self.group_stacks.iter().any(|stack| {
+                        stack.split_by() == group_override.group.split_by
+                            && stack.current() == group_override.group.name.as_deref()
+                    })
+                })
+            });
+        if group_override != self.active_override.as_ref() {
+            self.terrarium.turtle.override_settings(group_override);
+            self.active_override = group_override.cloned();
+        }
+        let feedrate = group_override
+            .and_then(|group_override| group_override.feedrate)
+            .unwrap_or(self._config.feedrate);
+        if feedrate != self.feedrate {
+            self.terrarium.turtle.change_feedrate(feedrate);
+            self.feedrate = feedrate;
+        }
+        let power = group_override
+            .and_then(|group_override| group_override.power)
+            .or(self._config.power);
+        if power != self.power {
+            self.terrarium.turtle.change_power(power);
+            self.power = power;
+        }
+        let [layer, stroke] = self
+            .group_stacks
+            .each_ref()
+            .map(|stack| stack.current().map(str::to_string));
+        self.terrarium.turtle.source(&PathSource {
+            element: self.node_path(node),
+            id: node.attribute("id").map(str::to_string),
+            layer,
+            stroke,
+        });
+        let passes = group_override
+            .and_then(|group_override| group_override.passes)
+            .unwrap_or(self._config.passes);
+        self.passes = self.passes.max(passes);
+        passes
+    }
+
+    /// Name of the element after the names of its ancestors
+    fn node_path(&self, node: &Node) -> String {
+        let mut path = String::new();
         self.name_stack.iter().for_each(|name| {
-            comment += name;
-            comment += " > ";
+            path += name;
+            path += " > ";
         });
-        comment += &node_name(node, &self._config.extra_attribute_name);
+        path += &node_name(node, &self._config.extra_attribute_name);
+        path
+    }
 
+    fn node_comment(&mut self, node: &Node) {
+        let comment = self.node_path(node);
         self.terrarium.turtle.comment(comment);
     }
 
@@ -94,6 +647,8 @@ impl<'a, T: Turtle> ConversionVisitor<'a, T> {
         // Part 1 of converting from SVG to GCode coordinates
         self.terrarium.push_transform(Transform2D::scale(1., -1.));
         self.terrarium.turtle.begin();
+        self.terrarium.turtle.change_feedrate(self.feedrate);
+        self.terrarium.turtle.change_power(self.power);
     }
 
     fn end(&mut self) {
@@ -102,30 +657,213 @@ impl<'a, T: Turtle> ConversionVisitor<'a, T> {
     }
 }
 
+/// G-Code converted from an SVG, see [svg2program]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program<'input> {
+    pub tokens: Vec<Token<'input>>,
+    pub statistics: ConversionStatistics,
+}
+
+impl<'input> Program<'input> {
+    fn from_turtle(turtle: GCodeTurtle<'input>) -> Self {
+        Self {
+            tokens: turtle.program,
+            statistics: turtle.statistics,
+        }
+    }
+}
+
+/// Draw an SVG [`Document`] with any [Turtle], in millimeters and in the order that [svg2program] would cut it
+///
+/// The turtle is given curves as they are, without flattening them.
+pub fn svg2turtle<T: Turtle>(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    turtle: T,
+) -> Result<T, ConversionError> {
+    let (reordering_turtle, _) = draw(doc, config, &options, arrange(config, &options, turtle))?;
+    Ok(reordering_turtle.inner.inner)
+}
+
 /// Top-level function for converting an SVG [`Document`] into g-code
 pub fn svg2program<'a, 'input: 'a>(
     doc: &'a Document,
     config: &ConversionConfig,
     options: ConversionOptions,
     machine: Machine<'input>,
-) -> Vec<Token<'input>> {
-    let bounding_box_generator = || {
+) -> Result<Program<'input>, ConversionError> {
+    Ok(Program::from_turtle(convert(
+        doc, config, options, machine, None,
+    )?))
+}
+
+/// Convert an SVG [`Document`] into g-code, writing it out as it is generated rather than keeping it all in memory
+///
+/// The output is the same as formatting the result of [svg2program] with [crate::format_program_fmt]. Framing, power
+/// ramping, progress markers, subprograms, the metadata header, and drawing on a sphere all revise the program once it
+/// is complete, so with any of those it is written out at the end instead.
+///
+/// When the conversion fails part way through, what was written up to that point is left as is.
+pub fn svg2program_fmt<'a, 'input: 'a, W: fmt::Write>(
+    doc: &'a Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    machine: Machine<'input>,
+    postprocess: &PostprocessConfig,
+    mut w: W,
+) -> Result<ConversionStatistics, ConversionError> {
+    let writer = ProgramWriter::new(postprocess, &mut w as &mut dyn fmt::Write)?;
+    let mut turtle = convert(doc, config, options, machine, Some(writer))?;
+    let mut writer = turtle
+        .writer
+        .take()
+        .expect("conversion hands back the writer");
+    writer.write(&turtle.program)?;
+    writer.finish()?;
+    Ok(turtle.statistics)
+}
+
+/// Same as [svg2program_fmt], but for an [std::io::Write]
+pub fn svg2program_io<'a, 'input: 'a, W: std::io::Write>(
+    doc: &'a Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    machine: Machine<'input>,
+    postprocess: &PostprocessConfig,
+    w: W,
+) -> Result<ConversionStatistics, ConversionError> {
+    let mut adapter = IoAdapter::new(w);
+    let result = svg2program_fmt(doc, config, options, machine, postprocess, &mut adapter);
+    let written = match &result {
+        Ok(_) => Ok(()),
+        Err(ConversionError::Fmt(error)) => Err(*error),
+        Err(_) => return result,
+    };
+    adapter.finish(written)?;
+    result
+}
+
+/// Run a conversion, keeping the [GCodeTurtle] for what it learned along the way
+///
+/// Paths are written to the `writer` as they are generated if nothing needs the complete program, see
+/// [svg2program_fmt]. Either way, the writer is handed back in [GCodeTurtle::writer].
+fn convert<'a, 'input: 'a>(
+    doc: &'a Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    machine: Machine<'input>,
+    writer: Option<ProgramWriter<&'input mut dyn fmt::Write>>,
+) -> Result<GCodeTurtle<'input>, ConversionError> {
+    phase!("convert");
+    let framing_machine = config.framing.then(|| machine.clone());
+    let power_ramping = config.power_ramping && machine.kinematics().acceleration.is_some();
+    if config.power_ramping && !power_ramping {
+        warn!(
+            "Power ramping has no effect without an acceleration to model the machine's speed with"
+        );
+    }
+    let revises_program = config.framing
+        || power_ramping
+        || config.progress_markers != ProgressMarkers::None
+        || config.subprograms
+        || config.sphere.is_some()
+        || config.metadata_header;
+    let (streaming_writer, writer) = if revises_program {
+        (None, writer)
+    } else {
+        (writer, None)
+    };
+    #[cfg(feature = "rayon")]
+    let flattening = Flattening::new(config, &machine);
+    #[cfg(feature = "rayon")]
+    let streaming = streaming_writer.is_some();
+    let reordering_turtle = arrange(
+        config,
+        &options,
+        GCodeTurtle {
+            first_path: options.path_range.as_ref().map_or(0, |range| range.start),
+            writer: streaming_writer,
+            ..GCodeTurtle::new(config, machine)
+        },
+    );
+    // Holding back every path to flatten them together would defeat streaming
+    #[cfg(feature = "rayon")]
+    let reordering_turtle = if streaming {
+        reordering_turtle
+    } else {
+        reordering_turtle.flatten_in_parallel(flattening)
+    };
+    let (reordering_turtle, passes) = draw(doc, config, &options, reordering_turtle)?;
+    let mut turtle = finish(
+        config,
+        &options,
+        reordering_turtle.inner.inner,
+        passes,
+        framing_machine,
+    );
+    if writer.is_some() {
+        turtle.writer = writer;
+    }
+    Ok(turtle)
+}
+
+/// Wrap a turtle in the turtles that choose which paths it draws and in what order
+fn arrange<T: Turtle>(
+    config: &ConversionConfig,
+    options: &ConversionOptions,
+    turtle: T,
+) -> ReorderingTurtle<PathRangeTurtle<T>> {
+    ReorderingTurtle::new(
+        PathRangeTurtle::new(turtle, options.path_range.clone()),
+        config.path_order,
+        config.path_refinement,
+        options.cancellation.clone(),
+    )
+}
+
+/// Draw an SVG [`Document`] with a turtle, in millimeters and relative to [ConversionConfig::origin]
+///
+/// Also gives the most passes that any shape was drawn with.
+fn draw<T: Turtle>(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: &ConversionOptions,
+    turtle: T,
+) -> Result<(T, usize), ConversionError> {
+    // Starts from the box the drawing is measured from, which is the origin unless it is empty
+    let bounding_box_generator = |placement: &Transform2D<f64>, bounding_box: Box2D<Float>| {
+        phase!("measure");
         let mut visitor = ConversionVisitor {
             terrarium: Terrarium::new(DpiConvertingTurtle {
-                inner: PreprocessTurtle::default(),
+                inner: PreprocessTurtle { bounding_box },
                 dpi: config.dpi,
             }),
             _config: config,
-            options: options.clone(),
+            // Every group and path range is placed relative to the whole drawing so that they line up
+            options: ConversionOptions {
+                group: None,
+                ..options.clone()
+            },
             name_stack: vec![],
             viewport_dim_stack: vec![],
+            group_stack: None,
+            group_stacks: group_stacks(),
+            active_override: None,
+            hook_overrides: vec![],
+            passes: 0,
+            feedrate: config.feedrate,
+            power: config.power,
+            layers: vec![],
         };
 
+        visitor.terrarium.push_transform(placement.cast());
         visitor.begin();
-        visit::depth_first_visit(doc, &mut visitor);
+        visit::depth_first_visit(doc, &mut visitor)?;
         visitor.end();
+        visitor.terrarium.pop_transform();
 
-        visitor.terrarium.turtle.inner.bounding_box
+        Ok::<_, ConversionError>(visitor.terrarium.turtle.inner.bounding_box)
     };
 
     // Convert from millimeters to user units
@@ -133,47 +871,229 @@ pub fn svg2program<'a, 'input: 'a>(
         .origin
         .map(|dim| dim.map(|d| UomLength::new::<millimeter>(d).get::<inch>() * CSS_DEFAULT_DPI));
 
+    let mut placement = placement_transform(config);
+    if let Some([width, height]) = config.fit_to {
+        // Both are in millimeters, and only the drawing is measured, not the origin
+        let empty = Box2D::new(
+            point(Float::INFINITY, Float::INFINITY),
+            point(Float::NEG_INFINITY, Float::NEG_INFINITY),
+        );
+        let size = bounding_box_generator(&placement, empty)?.size();
+        // Not positive for an empty drawing, and infinite along an axis that a straight line has no extent in
+        let factor = (width / widen(size.width)).min(height / widen(size.height));
+        if factor.is_finite() && factor > 0. {
+            placement = placement.then_scale(factor, factor);
+        }
+    }
+
     let origin_transform = match origin {
         [None, Some(origin_y)] => {
-            let bb = bounding_box_generator();
-            Transform2D::translation(0., origin_y - bb.min.y)
+            let bb = bounding_box_generator(&placement, Box2D::zero())?;
+            Transform2D::translation(0., origin_y - widen(bb.min.y))
         }
         [Some(origin_x), None] => {
-            let bb = bounding_box_generator();
-            Transform2D::translation(origin_x - bb.min.x, 0.)
+            let bb = bounding_box_generator(&placement, Box2D::zero())?;
+            Transform2D::translation(origin_x - widen(bb.min.x), 0.)
         }
         [Some(origin_x), Some(origin_y)] => {
-            let bb = bounding_box_generator();
-            Transform2D::translation(origin_x - bb.min.x, origin_y - bb.min.y)
+            let bb = bounding_box_generator(&placement, Box2D::zero())?;
+            Transform2D::translation(origin_x - widen(bb.min.x), origin_y - widen(bb.min.y))
         }
         [None, None] => Transform2D::identity(),
     };
 
     let mut conversion_visitor = ConversionVisitor {
         terrarium: Terrarium::new(DpiConvertingTurtle {
-            inner: GCodeTurtle {
-                machine,
-                tolerance: config.tolerance,
-                feedrate: config.feedrate,
-                program: vec![],
-            },
+            inner: turtle,
             dpi: config.dpi,
         }),
         _config: config,
-        options,
+        group_stack: options
+            .group
+            .as_ref()
+            .map(|group| GroupStack::new(group.split_by)),
+        group_stacks: group_stacks(),
+        active_override: None,
+        hook_overrides: vec![],
+        passes: 0,
+        feedrate: config.feedrate,
+        power: config.power,
+        layers: vec![],
+        options: options.clone(),
         name_stack: vec![],
         viewport_dim_stack: vec![],
     };
 
+    phase!("visit");
+    // The drawing is flipped and rotated first, then placed at the origin
     conversion_visitor
         .terrarium
-        .push_transform(origin_transform);
+        .push_transform(origin_transform.cast());
+    conversion_visitor
+        .terrarium
+        .push_transform(placement.cast());
     conversion_visitor.begin();
-    visit::depth_first_visit(doc, &mut conversion_visitor);
+    visit::depth_first_visit(doc, &mut conversion_visitor)?;
     conversion_visitor.end();
     conversion_visitor.terrarium.pop_transform();
+    conversion_visitor.terrarium.pop_transform();
+    // Ordering stops early when cancelled, so what was drawn may be incomplete
+    options.check_cancellation()?;
+
+    Ok((
+        conversion_visitor.terrarium.turtle.inner,
+        conversion_visitor.passes,
+    ))
+}
+
+/// Transform for [ConversionConfig::flip], [ConversionConfig::rotation] and [ConversionConfig::scale], about the
+/// origin of the drawing
+fn placement_transform(config: &ConversionConfig) -> Transform2D<f64> {
+    let (x, y) = match config.flip {
+        Flip::None => (1., 1.),
+        Flip::Horizontal => (-1., 1.),
+        Flip::Vertical => (1., -1.),
+        Flip::Both => (-1., -1.),
+    };
+    Transform2D::scale(x, y)
+        .then_rotate(Angle::degrees(config.rotation))
+        .then_scale(config.scale, config.scale)
+}
+
+/// Make the changes to a drawn program that need all of it
+///
+/// With `framing_machine`, the program is replaced by one that traces the outline of the drawing.
+fn finish<'input>(
+    config: &ConversionConfig,
+    options: &ConversionOptions,
+    mut turtle: GCodeTurtle<'input>,
+    passes: usize,
+    framing_machine: Option<Machine<'input>>,
+) -> GCodeTurtle<'input> {
+    phase!("finish");
+    turtle.statistics.passes = passes;
+    if let Some(machine) = framing_machine {
+        turtle = frame(config, machine, turtle.statistics.bounding_box);
+    }
+    turtle.finish_estimate(config.progress_markers, config.progress_interval);
+    if let Some(sphere) = &config.sphere {
+        let program = std::mem::take(&mut turtle.program);
+        turtle.program = wrap_around_sphere(program, sphere, config.tolerance);
+    } else if turtle.subprograms {
+        let program = std::mem::take(&mut turtle.program);
+        turtle.program = extract_subprograms(program, &turtle.instances);
+    }
+    if config.metadata_header {
+        let program = std::mem::take(&mut turtle.program);
+        for line in metadata_header(config, options, &turtle.statistics) {
+            turtle.comment(line);
+        }
+        turtle.program.extend(program);
+    }
+    turtle
+}
+
+/// Stacks for [ConversionVisitor::group_stacks]
+fn group_stacks() -> [GroupStack; 2] {
+    [SplitBy::Layer, SplitBy::Color].map(GroupStack::new)
+}
 
-    conversion_visitor.terrarium.turtle.inner.program
+/// Name of each group with its program, see [svg2programs]
+type GroupPrograms<'input> = Vec<(Option<String>, Program<'input>)>;
+
+/// Convert each layer or color of an SVG [`Document`] into its own program, see [SplitBy]
+///
+/// Every program has the machine's full begin and end sequences so that it can be run on its own,
+/// and they share a coordinate system so that running them one after another lines up.
+pub fn svg2programs<'a, 'input: 'a>(
+    doc: &'a Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+    split_by: SplitBy,
+    machine: Machine<'input>,
+) -> Result<GroupPrograms<'input>, ConversionError> {
+    group::groups(doc, split_by)
+        .into_iter()
+        .map(|name| {
+            let options = ConversionOptions {
+                group: Some(Group {
+                    split_by,
+                    name: name.clone(),
+                }),
+                ..options.clone()
+            };
+            let program = svg2program(doc, config, options, machine.clone())?;
+            Ok((name, program))
+        })
+        .collect()
+}
+
+/// Trace the perimeter of the job instead, see [ConversionConfig::framing]
+fn frame<'input>(
+    config: &ConversionConfig,
+    machine: Machine<'input>,
+    bounding_box: Option<Box2D<f64>>,
+) -> GCodeTurtle<'input> {
+    let framing_config = ConversionConfig {
+        dry_run: config.framing_power.is_none(),
+        power: config.framing_power,
+        power_ramping: false,
+        cut_z: None,
+        corner_slowdown: 0.,
+        ..config.clone()
+    };
+    let mut turtle = GCodeTurtle::new(&framing_config, machine);
+    turtle.begin();
+    if let Some(Box2D { min, max }) = bounding_box {
+        turtle.statistics.passes = 1;
+        turtle.move_to(min.cast());
+        for corner in [point(max.x, min.y), max, point(min.x, max.y), min] {
+            turtle.line_to(corner.cast());
+        }
+    }
+    turtle.end();
+    turtle
+}
+
+/// Lines of the [ConversionConfig::metadata_header]
+fn metadata_header(
+    config: &ConversionConfig,
+    options: &ConversionOptions,
+    statistics: &ConversionStatistics,
+) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Generated by svg2gcode {}",
+        env!("CARGO_PKG_VERSION")
+    )];
+    if let Some(source_name) = &options.source_name {
+        lines.push(format!("Source: {source_name}"));
+    }
+    lines.push(format!(
+        "Tolerance: {} mm, feedrate: {} mm/min, DPI: {}",
+        config.tolerance, config.feedrate, config.dpi
+    ));
+    if config.passes != 1 {
+        lines.push(format!("Passes: {}", config.passes));
+    }
+    for group_override in &config.overrides {
+        let Group { split_by, name } = &group_override.group;
+        let mut line = format!("{split_by:?} {}:", name.as_deref().unwrap_or("(none)"));
+        if let Some(feedrate) = group_override.feedrate {
+            line += &format!(" feedrate {feedrate} mm/min");
+        }
+        if let Some(power) = group_override.power {
+            line += &format!(" power {power}");
+        }
+        if let Some(passes) = group_override.passes {
+            line += &format!(" passes {passes}");
+        }
+        if let Some(cut_z) = group_override.cut_z {
+            line += &format!(" Z{cut_z} mm");
+        }
+        lines.push(line);
+    }
+    lines.extend(statistics.to_string().lines().map(str::to_string));
+    lines
 }
 
 fn node_name(node: &Node, attr_to_print: &Option<String>) -> String {
@@ -1,32 +1,40 @@
 use euclid::Angle;
 use log::debug;
-use lyon_geom::{ArcFlags, point, vector};
+use lyon_geom::{ArcFlags, Point, point, vector};
 use svgtypes::PathSegment;
 
 use super::Terrarium;
-use crate::Turtle;
+use crate::{Float, Turtle};
 
 /// Maps [`PathSegment`]s into concrete operations on the [`Terrarium`]
 ///
 /// Performs a [`Terrarium::reset`] on each call
 pub fn apply_path<T: Turtle>(
     terrarium: &mut Terrarium<T>,
+    comment_segments: bool,
     path: impl IntoIterator<Item = PathSegment>,
 ) {
     use PathSegment::*;
 
     terrarium.reset();
-    path.into_iter().for_each(|segment| {
+    let mut path = path.into_iter().peekable();
+    while let Some(segment) = path.next() {
         debug!("Drawing {:?}", &segment);
+        if matches!(path.peek(), None | Some(MoveTo { .. } | ClosePath { .. })) {
+            terrarium.next_ends_subpath();
+        }
+        if comment_segments {
+            terrarium.turtle.comment(segment_comment(&segment));
+        }
         match segment {
-            MoveTo { abs, x, y } => terrarium.move_to(abs, x, y),
+            MoveTo { abs, x, y } => terrarium.move_to(abs, x as Float, y as Float),
             ClosePath { abs: _ } => {
                 // Ignore abs, should have identical effect: [9.3.4. The "closepath" command]("https://www.w3.org/TR/SVG/paths.html#PathDataClosePathCommand)
                 terrarium.close()
             }
-            LineTo { abs, x, y } => terrarium.line(abs, x, y),
-            HorizontalLineTo { abs, x } => terrarium.line(abs, x, None),
-            VerticalLineTo { abs, y } => terrarium.line(abs, None, y),
+            LineTo { abs, x, y } => terrarium.line(abs, x as Float, y as Float),
+            HorizontalLineTo { abs, x } => terrarium.line(abs, x as Float, None),
+            VerticalLineTo { abs, y } => terrarium.line(abs, None, y as Float),
             CurveTo {
                 abs,
                 x1,
@@ -35,14 +43,21 @@ pub fn apply_path<T: Turtle>(
                 y2,
                 x,
                 y,
-            } => terrarium.cubic_bezier(abs, point(x1, y1), point(x2, y2), point(x, y)),
+            } => terrarium.cubic_bezier(
+                abs,
+                float_point(x1, y1),
+                float_point(x2, y2),
+                float_point(x, y),
+            ),
             SmoothCurveTo { abs, x2, y2, x, y } => {
-                terrarium.smooth_cubic_bezier(abs, point(x2, y2), point(x, y))
+                terrarium.smooth_cubic_bezier(abs, float_point(x2, y2), float_point(x, y))
             }
             Quadratic { abs, x1, y1, x, y } => {
-                terrarium.quadratic_bezier(abs, point(x1, y1), point(x, y))
+                terrarium.quadratic_bezier(abs, float_point(x1, y1), float_point(x, y))
+            }
+            SmoothQuadratic { abs, x, y } => {
+                terrarium.smooth_quadratic_bezier(abs, float_point(x, y))
             }
-            SmoothQuadratic { abs, x, y } => terrarium.smooth_quadratic_bezier(abs, point(x, y)),
             EllipticalArc {
                 abs,
                 rx,
@@ -54,11 +69,65 @@ pub fn apply_path<T: Turtle>(
                 y,
             } => terrarium.elliptical(
                 abs,
-                vector(rx, ry),
-                Angle::degrees(x_axis_rotation),
+                vector(rx as Float, ry as Float),
+                Angle::degrees(x_axis_rotation as Float),
                 ArcFlags { large_arc, sweep },
-                point(x, y),
+                float_point(x, y),
             ),
         }
-    });
+    }
+}
+
+/// Point from coordinates parsed by [`svgtypes`], which are always [`f64`]
+fn float_point(x: f64, y: f64) -> Point<Float> {
+    point(x as Float, y as Float)
+}
+
+/// Describe a [`PathSegment`] using SVG path data syntax
+fn segment_comment(segment: &PathSegment) -> String {
+    use PathSegment::*;
+
+    let command = |abs: bool, letter: char| {
+        if abs {
+            letter
+        } else {
+            letter.to_ascii_lowercase()
+        }
+    };
+    match *segment {
+        MoveTo { abs, x, y } => format!("{} {x} {y}", command(abs, 'M')),
+        ClosePath { abs } => command(abs, 'Z').to_string(),
+        LineTo { abs, x, y } => format!("{} {x} {y}", command(abs, 'L')),
+        HorizontalLineTo { abs, x } => format!("{} {x}", command(abs, 'H')),
+        VerticalLineTo { abs, y } => format!("{} {y}", command(abs, 'V')),
+        CurveTo {
+            abs,
+            x1,
+            y1,
+            x2,
+            y2,
+            x,
+            y,
+        } => format!("{} {x1} {y1} {x2} {y2} {x} {y}", command(abs, 'C')),
+        SmoothCurveTo { abs, x2, y2, x, y } => {
+            format!("{} {x2} {y2} {x} {y}", command(abs, 'S'))
+        }
+        Quadratic { abs, x1, y1, x, y } => format!("{} {x1} {y1} {x} {y}", command(abs, 'Q')),
+        SmoothQuadratic { abs, x, y } => format!("{} {x} {y}", command(abs, 'T')),
+        EllipticalArc {
+            abs,
+            rx,
+            ry,
+            x_axis_rotation,
+            large_arc,
+            sweep,
+            x,
+            y,
+        } => format!(
+            "{} {rx} {ry} {x_axis_rotation} {} {} {x} {y}",
+            command(abs, 'A'),
+            large_arc as u8,
+            sweep as u8
+        ),
+    }
 }
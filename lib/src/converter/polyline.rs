@@ -0,0 +1,52 @@
+use lyon_geom::Point;
+use roxmltree::Document;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{ConversionConfig, ConversionError, ConversionOptions, svg2turtle};
+use crate::{
+    Float,
+    turtle::{Flattening, PolylineTurtle},
+};
+
+/// Element of the SVG that a path is drawn from
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PathSource {
+    /// Name of the element after the names of its ancestors, as in comments, i.e. `svg > g#layer1 > path#outline`
+    pub element: String,
+    /// `id` attribute of the element
+    pub id: Option<String>,
+    /// Label of the Inkscape layer that the element is in, see [super::SplitBy::Layer]
+    pub layer: Option<String>,
+    /// Stroke color of the element, inherited from its ancestors, see [super::SplitBy::Color]
+    pub stroke: Option<String>,
+}
+
+/// Path in millimeters with its curves flattened into lines, see [svg2polylines]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub source: PathSource,
+    /// Points in the coordinates they would be cut at, starting with the one the tool moves to before cutting
+    pub points: Vec<Point<Float>>,
+    /// Whether this is a hole to drill rather than a path to cut, in which case [Self::points] only holds its center
+    ///
+    /// See [ConversionConfig::drilling].
+    pub drill: bool,
+}
+
+/// Convert an SVG [`Document`] into the polylines that would be cut, in the order they would be cut in
+///
+/// This is the same geometry as [super::svg2program] gives, except that curves are always flattened into lines to
+/// within [ConversionConfig::tolerance]. Paths are repeated for each of their passes.
+pub fn svg2polylines(
+    doc: &Document,
+    config: &ConversionConfig,
+    options: ConversionOptions,
+) -> Result<Vec<Polyline>, ConversionError> {
+    let flattening = Flattening {
+        tolerance: config.tolerance as Float,
+        arc_tolerance: None,
+    };
+    Ok(svg2turtle(doc, config, options, PolylineTurtle::new(flattening))?.polylines)
+}
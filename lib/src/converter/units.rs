@@ -1,6 +1,12 @@
+use std::{error::Error, fmt, str::FromStr};
+
 use log::warn;
 use roxmltree::Node;
-use svgtypes::{Length, LengthListParser};
+use svgtypes::{Length, LengthListParser, LengthUnit};
+use uom::si::{
+    f64::Length as UomLength,
+    length::{inch, millimeter},
+};
 
 use super::ConversionVisitor;
 use crate::Turtle;
@@ -13,13 +19,108 @@ pub const CSS_DEFAULT_DPI: f64 = 96.;
 /// Used to compute percentages correctly
 ///
 /// <https://www.w3.org/TR/SVG/coords.html#Units>
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DimensionHint {
     Horizontal,
     Vertical,
     Other,
 }
 
+/// A length in the user units of an SVG, which are CSS pixels at [CSS_DEFAULT_DPI]
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct UserUnits(pub f64);
+
+impl UserUnits {
+    /// Length in millimeters as the converter would cut it, see [crate::ConversionConfig::dpi]
+    pub fn to_millimeters(self, dpi: f64) -> f64 {
+        UomLength::new::<inch>(self.0 / dpi).get::<millimeter>()
+    }
+}
+
+/// Reasons [parse_length] can fail
+#[derive(Debug)]
+pub enum LengthError {
+    Parse(svgtypes::Error),
+    /// Percentages are of the viewport of an SVG, so they can't be converted without one
+    Percentage,
+}
+
+impl fmt::Display for LengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(error) => error.fmt(f),
+            Self::Percentage => f.write_str("percentages are not supported outside of an SVG"),
+        }
+    }
+}
+
+impl Error for LengthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(error) => Some(error),
+            Self::Percentage => None,
+        }
+    }
+}
+
+/// Parse a length such as `10mm` or `2.5in` into user units, the same way the converter reads lengths in an SVG
+///
+/// A number without a unit is in pixels.
+pub fn parse_length(length: &str) -> Result<UserUnits, LengthError> {
+    let length = Length::from_str(length).map_err(LengthError::Parse)?;
+    if length.unit == LengthUnit::Percent {
+        return Err(LengthError::Percentage);
+    }
+    Ok(length_to_user_units(length, None, DimensionHint::Other))
+}
+
+/// Convert a [`Length`] to user units
+///
+/// Percentages are of the `viewport` width and height, picked between by the `hint`.
+///
+/// Absolute lengths are listed in [CSS 4 §6.2](https://www.w3.org/TR/css-values/#absolute-lengths).
+/// Relative lengths in [CSS 4 §6.1](https://www.w3.org/TR/css-values/#relative-lengths) are not supported and will simply be interpreted as millimeters.
+///
+/// A default DPI of 96 is used as per [CSS 4 §7.4](https://www.w3.org/TR/css-values/#resolution)
+pub fn length_to_user_units(
+    l: Length,
+    viewport: Option<[f64; 2]>,
+    hint: DimensionHint,
+) -> UserUnits {
+    use svgtypes::LengthUnit::*;
+    use uom::si::length::*;
+
+    UserUnits(match l.unit {
+        Cm => UomLength::new::<centimeter>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
+        Mm => UomLength::new::<millimeter>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
+        In => UomLength::new::<inch>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
+        Pc => UomLength::new::<pica_computer>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
+        Pt => UomLength::new::<point_computer>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
+        // https://www.w3.org/TR/SVG/coords.html#ViewportSpace says None should be treated as Px
+        Px | None => l.number,
+        Em | Ex => {
+            warn!("Converting from em/ex to millimeters assumes 1em/ex = 16px");
+            16. * l.number
+        }
+        // https://www.w3.org/TR/SVG/coords.html#Units
+        Percent => {
+            if let Some([width, height]) = viewport {
+                let scale = match hint {
+                    DimensionHint::Horizontal => width,
+                    DimensionHint::Vertical => height,
+                    DimensionHint::Other => {
+                        (width.powi(2) + height.powi(2)).sqrt() / 2.0_f64.sqrt()
+                    }
+                };
+                l.number / 100. * scale
+            } else {
+                warn!("A percentage without an established viewport is not valid!");
+                l.number / 100.
+            }
+        }
+    })
+}
+
 impl<'a, T: Turtle> ConversionVisitor<'a, T> {
     /// Convenience function for converting a length attribute to user units
     pub fn length_attr_to_user_units(&self, node: &Node, attr: &str) -> Option<f64> {
@@ -40,44 +141,9 @@ impl<'a, T: Turtle> ConversionVisitor<'a, T> {
             },
         ))
     }
-    /// Convenience function for converting [`Length`] to user units
-    ///
-    /// Absolute lengths are listed in [CSS 4 §6.2](https://www.w3.org/TR/css-values/#absolute-lengths).
-    /// Relative lengths in [CSS 4 §6.1](https://www.w3.org/TR/css-values/#relative-lengths) are not supported and will simply be interpreted as millimeters.
-    ///
-    /// A default DPI of 96 is used as per [CSS 4 §7.4](https://www.w3.org/TR/css-values/#resolution)
-    pub fn length_to_user_units(&self, l: Length, hint: DimensionHint) -> f64 {
-        use svgtypes::LengthUnit::*;
-        use uom::si::{f64::Length, length::*};
 
-        match l.unit {
-            Cm => Length::new::<centimeter>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
-            Mm => Length::new::<millimeter>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
-            In => Length::new::<inch>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
-            Pc => Length::new::<pica_computer>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
-            Pt => Length::new::<point_computer>(l.number).get::<inch>() * CSS_DEFAULT_DPI,
-            // https://www.w3.org/TR/SVG/coords.html#ViewportSpace says None should be treated as Px
-            Px | None => l.number,
-            Em | Ex => {
-                warn!("Converting from em/ex to millimeters assumes 1em/ex = 16px");
-                16. * l.number
-            }
-            // https://www.w3.org/TR/SVG/coords.html#Units
-            Percent => {
-                if let Some([width, height]) = self.viewport_dim_stack.last() {
-                    let scale = match hint {
-                        DimensionHint::Horizontal => *width,
-                        DimensionHint::Vertical => *height,
-                        DimensionHint::Other => {
-                            (width.powi(2) + height.powi(2)).sqrt() / 2.0_f64.sqrt()
-                        }
-                    };
-                    l.number / 100. * scale
-                } else {
-                    warn!("A percentage without an established viewport is not valid!");
-                    l.number / 100.
-                }
-            }
-        }
+    /// Convenience function for converting [`Length`] to user units in the current viewport
+    pub fn length_to_user_units(&self, l: Length, hint: DimensionHint) -> f64 {
+        length_to_user_units(l, self.viewport_dim_stack.last().copied(), hint).0
     }
 }
@@ -2,16 +2,17 @@ use std::str::FromStr;
 
 use euclid::default::Transform2D;
 use log::{debug, warn};
+use lyon_geom::point;
 use roxmltree::{Document, Node};
 use svgtypes::{AspectRatio, PathParser, PathSegment, PointsParser, TransformListParser, ViewBox};
 
 use super::{
-    ConversionVisitor,
+    CommentVerbosity, ConversionError, ConversionVisitor,
     path::apply_path,
     transform::{get_viewport_transform, svg_transform_into_euclid_transform},
     units::DimensionHint,
 };
-use crate::{Turtle, converter::node_name};
+use crate::{Float, HookAction, Turtle, converter::node_name};
 
 const SVG_TAG_NAME: &str = "svg";
 const CLIP_PATH_TAG_NAME: &str = "clipPath";
@@ -28,8 +29,15 @@ const USE_TAG_NAME: &str = "use";
 const MARKER_TAG_NAME: &str = "marker";
 const SYMBOL_TAG_NAME: &str = "symbol";
 
+pub const INKSCAPE_NAMESPACE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
 pub trait XmlVisitor {
-    fn visit_enter(&mut self, node: Node);
+    type Error;
+
+    /// Stops the visit early when it fails, and skips what is in the node when it gives `false`
+    ///
+    /// [Self::visit_exit] is still called for the node either way.
+    fn visit_enter(&mut self, node: Node) -> Result<bool, Self::Error>;
     fn visit_exit(&mut self, node: Node);
 }
 
@@ -45,6 +53,36 @@ fn should_render_node(node: Node) -> bool {
         && !matches!(node.tag_name().name(), DEFS_TAG_NAME | MARKER_TAG_NAME | SYMBOL_TAG_NAME)
 }
 
+/// Whether the element is in a `<defs>`, `<marker>` or `<symbol>`, so it is only drawn where a `<use>` references it
+pub fn is_in_definitions(node: &Node) -> bool {
+    node.ancestors().any(|ancestor| {
+        matches!(
+            ancestor.tag_name().name(),
+            DEFS_TAG_NAME | MARKER_TAG_NAME | SYMBOL_TAG_NAME
+        )
+    })
+}
+
+/// Whether this is a group that Inkscape treats as a layer
+pub fn is_layer(node: &Node) -> bool {
+    node.has_tag_name(GROUP_TAG_NAME)
+        && node.attribute((INKSCAPE_NAMESPACE, "groupmode")) == Some("layer")
+}
+
+/// Whether this element draws something
+pub fn is_shape(node: &Node) -> bool {
+    matches!(
+        node.tag_name().name(),
+        PATH_TAG_NAME
+            | POLYLINE_TAG_NAME
+            | POLYGON_TAG_NAME
+            | RECT_TAG_NAME
+            | CIRCLE_TAG_NAME
+            | ELLIPSE_TAG_NAME
+            | LINE_TAG_NAME
+    )
+}
+
 /// Resolve `href` or `xlink:href` on a `<use>` element to a document node.
 /// Only fragment references (`#id`) within the same document are supported.
 fn resolve_use_href<'a, 'input: 'a>(
@@ -60,50 +98,69 @@ fn resolve_use_href<'a, 'input: 'a>(
         .find(|n| n.attribute("id") == Some(id))
 }
 
-pub fn depth_first_visit(doc: &Document, visitor: &mut impl XmlVisitor) {
-    fn visit_node<V: XmlVisitor>(doc: &Document, node: Node, visitor: &mut V) {
+pub fn depth_first_visit<V: XmlVisitor>(doc: &Document, visitor: &mut V) -> Result<(), V::Error> {
+    fn visit_node<V: XmlVisitor>(
+        doc: &Document,
+        node: Node,
+        visitor: &mut V,
+    ) -> Result<(), V::Error> {
         if !should_render_node(node) {
-            return;
+            return Ok(());
         }
-        visitor.visit_enter(node);
-        if node.tag_name().name() == USE_TAG_NAME
-            && let Some(referenced) = resolve_use_href(doc, node)
-        {
-            visit_use_referenced_node(doc, referenced, visitor);
-        } else {
-            node.children()
-                .for_each(|child| visit_node(doc, child, visitor));
+        if visitor.visit_enter(node)? {
+            if node.tag_name().name() == USE_TAG_NAME
+                && let Some(referenced) = resolve_use_href(doc, node)
+            {
+                visit_use_referenced_node(doc, referenced, visitor)?;
+            } else {
+                for child in node.children() {
+                    visit_node(doc, child, visitor)?;
+                }
+            }
         }
         visitor.visit_exit(node);
+        Ok(())
     }
 
     /// Special-cased [visit_node] for a node referenced by a `<use>` element to get
     /// around the [`should_render_node`] filter that usually prevents symbols from being rendered.
-    fn visit_use_referenced_node<V: XmlVisitor>(doc: &Document, node: Node, visitor: &mut V) {
+    fn visit_use_referenced_node<V: XmlVisitor>(
+        doc: &Document,
+        node: Node,
+        visitor: &mut V,
+    ) -> Result<(), V::Error> {
         if !node.is_element() {
-            return;
+            return Ok(());
         }
         if node
             .attribute("style")
             .is_some_and(|s| s.contains("display:none"))
         {
-            return;
+            return Ok(());
+        }
+        if visitor.visit_enter(node)? {
+            for child in node.children() {
+                visit_node(doc, child, visitor)?;
+            }
         }
-        visitor.visit_enter(node);
-        node.children()
-            .for_each(|child| visit_node(doc, child, visitor));
         visitor.visit_exit(node);
+        Ok(())
     }
 
-    doc.root()
-        .children()
-        .for_each(|child| visit_node(doc, child, visitor));
+    for child in doc.root().children() {
+        visit_node(doc, child, visitor)?;
+    }
+    Ok(())
 }
 
 impl<'a, T: Turtle> XmlVisitor for ConversionVisitor<'a, T> {
-    fn visit_enter(&mut self, node: Node) {
+    type Error = ConversionError;
+
+    fn visit_enter(&mut self, node: Node) -> Result<bool, ConversionError> {
         use PathSegment::*;
 
+        self.options.check_cancellation()?;
+
         if node.tag_name().name() == CLIP_PATH_TAG_NAME {
             warn!("Clip paths are not supported: {:?}", node);
         }
@@ -114,34 +171,25 @@ impl<'a, T: Turtle> XmlVisitor for ConversionVisitor<'a, T> {
             warn!("transform-origin not supported yet");
         }
 
-        let mut flattened_transform = if let Some(transform) = node.attribute("transform") {
+        let mut flattened_transform = Transform2D::identity();
+        if let Some(transform) = node.attribute("transform") {
             // https://stackoverflow.com/questions/18582935/the-applying-order-of-svg-transforms
-            TransformListParser::from(transform)
-                .map(|token| token.expect("could not parse a transform in a list of transforms"))
-                .map(svg_transform_into_euclid_transform)
-                .fold(Transform2D::identity(), |acc, t| t.then(&acc))
-        } else {
-            Transform2D::identity()
-        };
+            for token in TransformListParser::from(transform) {
+                let token =
+                    token.map_err(|error| self.invalid_attribute(&node, "transform", error))?;
+                flattened_transform =
+                    svg_transform_into_euclid_transform(token).then(&flattened_transform);
+            }
+        }
 
         // https://www.w3.org/TR/SVG/coords.html#EstablishingANewSVGViewport
         if node.has_tag_name(SVG_TAG_NAME) {
-            let view_box = node
-                .attribute("viewBox")
-                .map(ViewBox::from_str)
+            let view_box = self.view_box(&node)?;
+            let preserve_aspect_ratio = node
+                .attribute("preserveAspectRatio")
+                .map(AspectRatio::from_str)
                 .transpose()
-                .expect("could not parse viewBox")
-                .filter(|view_box| {
-                    if view_box.w <= 0. || view_box.h <= 0. {
-                        warn!("Invalid viewBox: {view_box:?}");
-                        false
-                    } else {
-                        true
-                    }
-                });
-            let preserve_aspect_ratio = node.attribute("preserveAspectRatio").map(|attr| {
-                AspectRatio::from_str(attr).expect("could not parse preserveAspectRatio")
-            });
+                .map_err(|error| self.invalid_attribute(&node, "preserveAspectRatio", error))?;
             let mut viewport_size =
                 ["width", "height"].map(|attr| self.length_attr_to_user_units(&node, attr));
 
@@ -212,22 +260,12 @@ impl<'a, T: Turtle> XmlVisitor for ConversionVisitor<'a, T> {
             let y = self.length_attr_to_user_units(&node, "y").unwrap_or(0.);
             flattened_transform = flattened_transform.then(&Transform2D::translation(x, y));
         } else if node.has_tag_name(SYMBOL_TAG_NAME) {
-            let view_box = node
-                .attribute("viewBox")
-                .map(ViewBox::from_str)
+            let view_box = self.view_box(&node)?;
+            let preserve_aspect_ratio = node
+                .attribute("preserveAspectRatio")
+                .map(AspectRatio::from_str)
                 .transpose()
-                .expect("could not parse viewBox on symbol")
-                .filter(|view_box| {
-                    if view_box.w <= 0. || view_box.h <= 0. {
-                        warn!("Invalid viewBox: {view_box:?}");
-                        false
-                    } else {
-                        true
-                    }
-                });
-            let preserve_aspect_ratio = node.attribute("preserveAspectRatio").map(|attr| {
-                AspectRatio::from_str(attr).expect("could not parse preserveAspectRatio")
-            });
+                .map_err(|error| self.invalid_attribute(&node, "preserveAspectRatio", error))?;
             // Viewport size: symbol's own width/height, or fallback to viewBox dims, or parent viewport
             let viewport_size = match (
                 self.length_attr_to_user_units(&node, "width"),
@@ -253,213 +291,291 @@ impl<'a, T: Turtle> XmlVisitor for ConversionVisitor<'a, T> {
             warn!("View box is not supported on a {}", node.tag_name().name());
         }
 
-        self.terrarium.push_transform(flattened_transform);
-
-        match node.tag_name().name() {
-            PATH_TAG_NAME => {
-                if let Some(d) = node.attribute("d") {
-                    self.comment(&node);
-                    apply_path(
-                        &mut self.terrarium,
-                        PathParser::from(d)
-                            .map(|segment| segment.expect("could not parse path segment")),
-                    );
-                } else {
-                    warn!("There is a path node containing no actual path: {node:?}");
-                }
+        self.terrarium.push_transform(flattened_transform.cast());
+        if let Some(group_stack) = &mut self.group_stack {
+            group_stack.enter(&node);
+        }
+        for stack in &mut self.group_stacks {
+            stack.enter(&node);
+        }
+        let action = if self.terrarium.is_degenerate() {
+            warn!(
+                "Skipping {}, its transform leaves nothing to draw",
+                node_name(&node, &self._config.extra_attribute_name)
+            );
+            HookAction::Skip
+        } else {
+            self.options.hooks.enter(&node, self.terrarium.transform())
+        };
+        let group_override = match action {
+            HookAction::Continue => None,
+            HookAction::Skip => {
+                self.name_stack
+                    .push(node_name(&node, &self._config.extra_attribute_name));
+                self.hook_overrides.push(None);
+                return Ok(false);
             }
-            name @ (POLYLINE_TAG_NAME | POLYGON_TAG_NAME) => {
-                if let Some(points) = node.attribute("points") {
-                    self.comment(&node);
-
-                    let mut pp = PointsParser::from(points).peekable();
-                    let path = pp
-                        .peek()
-                        .copied()
-                        .map(|(x, y)| MoveTo { abs: true, x, y })
-                        .into_iter()
-                        .chain(pp.map(|(x, y)| LineTo { abs: true, x, y }))
-                        .chain(
-                            // Path must be closed if this is a polygon
-                            if name == POLYGON_TAG_NAME {
-                                Some(ClosePath { abs: true })
-                            } else {
-                                None
-                            },
-                        );
+            HookAction::Override(group_override) => Some(group_override),
+        };
+        self.hook_overrides.push(group_override);
 
-                    apply_path(&mut self.terrarium, path);
-                } else {
-                    warn!("There is a {name} node containing no actual path: {node:?}");
-                }
+        let comment_segments = self._config.comment_verbosity == CommentVerbosity::PerSegment;
+
+        // Shapes outside of the group being converted are skipped, the rest are drawn once per pass
+        let handler = self.options.hooks.element(&node).cloned();
+        let shape = is_shape(&node) || handler.is_some();
+        let passes = if !shape {
+            1
+        } else if !self.in_group() {
+            0
+        } else {
+            self.begin_shape(&node)
+        };
+        for pass in 0..passes {
+            if shape {
+                self.terrarium.turtle.begin_pass(pass, passes);
             }
-            RECT_TAG_NAME => {
-                let x = self.length_attr_to_user_units(&node, "x").unwrap_or(0.);
-                let y = self.length_attr_to_user_units(&node, "y").unwrap_or(0.);
-                let width = self.length_attr_to_user_units(&node, "width");
-                let height = self.length_attr_to_user_units(&node, "height");
-                let rx = self.length_attr_to_user_units(&node, "rx").unwrap_or(0.);
-                let ry = self.length_attr_to_user_units(&node, "ry").unwrap_or(0.);
-                let has_radius = rx > 0. && ry > 0.;
-
-                match (width, height) {
-                    (Some(width), Some(height)) => {
+            match node.tag_name().name() {
+                PATH_TAG_NAME => {
+                    if let Some(d) = node.attribute("d") {
+                        let path = PathParser::from(d)
+                            .collect::<Result<Vec<_>, _>>()
+                            .map_err(|error| self.invalid_attribute(&node, "d", error))?;
                         self.comment(&node);
-                        apply_path(
-                            &mut self.terrarium,
-                            [
-                                MoveTo {
-                                    abs: true,
-                                    x: x + rx,
-                                    y,
-                                },
-                                HorizontalLineTo {
-                                    abs: true,
-                                    x: x + width - rx,
-                                },
-                                EllipticalArc {
-                                    abs: true,
-                                    rx,
-                                    ry,
-                                    x_axis_rotation: 0.,
-                                    large_arc: false,
-                                    sweep: true,
-                                    x: x + width,
-                                    y: y + ry,
-                                },
-                                VerticalLineTo {
-                                    abs: true,
-                                    y: y + height - ry,
-                                },
-                                EllipticalArc {
-                                    abs: true,
-                                    rx,
-                                    ry,
-                                    x_axis_rotation: 0.,
-                                    large_arc: false,
-                                    sweep: true,
-                                    x: x + width - rx,
-                                    y: y + height,
-                                },
-                                HorizontalLineTo {
-                                    abs: true,
-                                    x: x + rx,
-                                },
-                                EllipticalArc {
-                                    abs: true,
-                                    rx,
-                                    ry,
-                                    x_axis_rotation: 0.,
-                                    large_arc: false,
-                                    sweep: true,
-                                    x,
-                                    y: y + height - ry,
-                                },
-                                VerticalLineTo {
-                                    abs: true,
-                                    y: y + ry,
-                                },
-                                EllipticalArc {
-                                    abs: true,
-                                    rx,
-                                    ry,
-                                    x_axis_rotation: 0.,
-                                    large_arc: false,
-                                    sweep: true,
-                                    x: x + rx,
-                                    y,
-                                },
-                                ClosePath { abs: true },
-                            ]
-                            .into_iter()
-                            .filter(|p| has_radius || !matches!(p, EllipticalArc { .. })),
-                        )
-                    }
-                    _other => {
-                        warn!("Invalid rectangle node: {node:?}");
+                        apply_path(&mut self.terrarium, comment_segments, path);
+                    } else {
+                        warn!("There is a path node containing no actual path: {node:?}");
                     }
                 }
-            }
-            CIRCLE_TAG_NAME | ELLIPSE_TAG_NAME => {
-                let cx = self.length_attr_to_user_units(&node, "cx").unwrap_or(0.);
-                let cy = self.length_attr_to_user_units(&node, "cy").unwrap_or(0.);
-                let r = self.length_attr_to_user_units(&node, "r").unwrap_or(0.);
-                let rx = self.length_attr_to_user_units(&node, "rx").unwrap_or(r);
-                let ry = self.length_attr_to_user_units(&node, "ry").unwrap_or(r);
-                if rx > 0. && ry > 0. {
-                    self.comment(&node);
-                    apply_path(
-                        &mut self.terrarium,
-                        std::iter::once(MoveTo {
-                            abs: true,
-                            x: cx + rx,
-                            y: cy,
-                        })
-                        .chain(
-                            [(cx, cy + ry), (cx - rx, cy), (cx, cy - ry), (cx + rx, cy)].map(
-                                |(x, y)| EllipticalArc {
-                                    abs: true,
-                                    rx,
-                                    ry,
-                                    x_axis_rotation: 0.,
-                                    large_arc: false,
-                                    sweep: true,
-                                    x,
-                                    y,
+                name @ (POLYLINE_TAG_NAME | POLYGON_TAG_NAME) => {
+                    if let Some(points) = node.attribute("points") {
+                        self.comment(&node);
+
+                        let mut pp = PointsParser::from(points).peekable();
+                        let path = pp
+                            .peek()
+                            .copied()
+                            .map(|(x, y)| MoveTo { abs: true, x, y })
+                            .into_iter()
+                            .chain(pp.map(|(x, y)| LineTo { abs: true, x, y }))
+                            .chain(
+                                // Path must be closed if this is a polygon
+                                if name == POLYGON_TAG_NAME {
+                                    Some(ClosePath { abs: true })
+                                } else {
+                                    None
                                 },
-                            ),
-                        )
-                        .chain(std::iter::once(ClosePath { abs: true })),
-                    );
-                } else {
-                    warn!("Invalid {} node: {node:?}", node.tag_name().name());
+                            );
+
+                        apply_path(&mut self.terrarium, comment_segments, path);
+                    } else {
+                        warn!("There is a {name} node containing no actual path: {node:?}");
+                    }
                 }
-            }
-            LINE_TAG_NAME => {
-                let x1 = self.length_attr_to_user_units(&node, "x1");
-                let y1 = self.length_attr_to_user_units(&node, "y1");
-                let x2 = self.length_attr_to_user_units(&node, "x2");
-                let y2 = self.length_attr_to_user_units(&node, "y2");
-                match (x1, y1, x2, y2) {
-                    (Some(x1), Some(y1), Some(x2), Some(y2)) => {
+                RECT_TAG_NAME => {
+                    let x = self.length_attr_to_user_units(&node, "x").unwrap_or(0.);
+                    let y = self.length_attr_to_user_units(&node, "y").unwrap_or(0.);
+                    let width = self.length_attr_to_user_units(&node, "width");
+                    let height = self.length_attr_to_user_units(&node, "height");
+                    let rx = self.length_attr_to_user_units(&node, "rx").unwrap_or(0.);
+                    let ry = self.length_attr_to_user_units(&node, "ry").unwrap_or(0.);
+                    let has_radius = rx > 0. && ry > 0.;
+
+                    match (width, height) {
+                        (Some(width), Some(height)) => {
+                            self.comment(&node);
+                            apply_path(
+                                &mut self.terrarium,
+                                comment_segments,
+                                [
+                                    MoveTo {
+                                        abs: true,
+                                        x: x + rx,
+                                        y,
+                                    },
+                                    HorizontalLineTo {
+                                        abs: true,
+                                        x: x + width - rx,
+                                    },
+                                    EllipticalArc {
+                                        abs: true,
+                                        rx,
+                                        ry,
+                                        x_axis_rotation: 0.,
+                                        large_arc: false,
+                                        sweep: true,
+                                        x: x + width,
+                                        y: y + ry,
+                                    },
+                                    VerticalLineTo {
+                                        abs: true,
+                                        y: y + height - ry,
+                                    },
+                                    EllipticalArc {
+                                        abs: true,
+                                        rx,
+                                        ry,
+                                        x_axis_rotation: 0.,
+                                        large_arc: false,
+                                        sweep: true,
+                                        x: x + width - rx,
+                                        y: y + height,
+                                    },
+                                    HorizontalLineTo {
+                                        abs: true,
+                                        x: x + rx,
+                                    },
+                                    EllipticalArc {
+                                        abs: true,
+                                        rx,
+                                        ry,
+                                        x_axis_rotation: 0.,
+                                        large_arc: false,
+                                        sweep: true,
+                                        x,
+                                        y: y + height - ry,
+                                    },
+                                    VerticalLineTo {
+                                        abs: true,
+                                        y: y + ry,
+                                    },
+                                    EllipticalArc {
+                                        abs: true,
+                                        rx,
+                                        ry,
+                                        x_axis_rotation: 0.,
+                                        large_arc: false,
+                                        sweep: true,
+                                        x: x + rx,
+                                        y,
+                                    },
+                                    ClosePath { abs: true },
+                                ]
+                                .into_iter()
+                                .filter(|p| has_radius || !matches!(p, EllipticalArc { .. })),
+                            )
+                        }
+                        _other => {
+                            warn!("Invalid rectangle node: {node:?}");
+                        }
+                    }
+                }
+                CIRCLE_TAG_NAME | ELLIPSE_TAG_NAME => {
+                    let cx = self.length_attr_to_user_units(&node, "cx").unwrap_or(0.);
+                    let cy = self.length_attr_to_user_units(&node, "cy").unwrap_or(0.);
+                    let r = self.length_attr_to_user_units(&node, "r").unwrap_or(0.);
+                    let rx = self.length_attr_to_user_units(&node, "rx").unwrap_or(r);
+                    let ry = self.length_attr_to_user_units(&node, "ry").unwrap_or(r);
+                    if rx > 0. && ry > 0. && rx == ry && self.should_drill(rx) {
+                        self.comment(&node);
+                        self.terrarium.drill(point(cx as Float, cy as Float));
+                    } else if rx > 0. && ry > 0. {
                         self.comment(&node);
                         apply_path(
                             &mut self.terrarium,
-                            [
-                                MoveTo {
-                                    abs: true,
-                                    x: x1,
-                                    y: y1,
-                                },
-                                LineTo {
-                                    abs: true,
-                                    x: x2,
-                                    y: y2,
-                                },
-                            ],
+                            comment_segments,
+                            std::iter::once(MoveTo {
+                                abs: true,
+                                x: cx + rx,
+                                y: cy,
+                            })
+                            .chain(
+                                [(cx, cy + ry), (cx - rx, cy), (cx, cy - ry), (cx + rx, cy)].map(
+                                    |(x, y)| EllipticalArc {
+                                        abs: true,
+                                        rx,
+                                        ry,
+                                        x_axis_rotation: 0.,
+                                        large_arc: false,
+                                        sweep: true,
+                                        x,
+                                        y,
+                                    },
+                                ),
+                            )
+                            .chain(std::iter::once(ClosePath { abs: true })),
                         );
+                    } else {
+                        warn!("Invalid {} node: {node:?}", node.tag_name().name());
                     }
-                    _other => {
-                        warn!("Invalid line node: {node:?}");
+                }
+                LINE_TAG_NAME => {
+                    let x1 = self.length_attr_to_user_units(&node, "x1");
+                    let y1 = self.length_attr_to_user_units(&node, "y1");
+                    let x2 = self.length_attr_to_user_units(&node, "x2");
+                    let y2 = self.length_attr_to_user_units(&node, "y2");
+                    match (x1, y1, x2, y2) {
+                        (Some(x1), Some(y1), Some(x2), Some(y2)) => {
+                            self.comment(&node);
+                            apply_path(
+                                &mut self.terrarium,
+                                comment_segments,
+                                [
+                                    MoveTo {
+                                        abs: true,
+                                        x: x1,
+                                        y: y1,
+                                    },
+                                    LineTo {
+                                        abs: true,
+                                        x: x2,
+                                        y: y2,
+                                    },
+                                ],
+                            );
+                        }
+                        _other => {
+                            warn!("Invalid line node: {node:?}");
+                        }
+                    }
+                }
+                GROUP_TAG_NAME => {
+                    if is_layer(&node) {
+                        self.begin_layer(&node);
                     }
                 }
+                USE_TAG_NAME => self.terrarium.turtle.begin_instance(),
+                // No-op tags
+                SVG_TAG_NAME | SYMBOL_TAG_NAME => {}
+                _ => match &handler {
+                    Some(handler) => {
+                        self.comment(&node);
+                        self.terrarium
+                            .with_dyn(|terrarium| handler(&node, terrarium));
+                    }
+                    None => debug!("Unknown node: {}", node.tag_name().name()),
+                },
             }
-            // No-op tags
-            SVG_TAG_NAME | GROUP_TAG_NAME | USE_TAG_NAME | SYMBOL_TAG_NAME => {}
-            _ => {
-                debug!("Unknown node: {}", node.tag_name().name());
+            if shape {
+                self.terrarium.turtle.end_pass();
             }
         }
 
         self.name_stack
             .push(node_name(&node, &self._config.extra_attribute_name));
+        Ok(true)
     }
 
     fn visit_exit(&mut self, node: Node) {
+        self.options.hooks.exit(&node, self.terrarium.transform());
+        if self.layers.last() == Some(&node.id()) {
+            self.layers.pop();
+            self.terrarium.turtle.end_layer();
+        }
         self.terrarium.pop_transform();
         self.name_stack.pop();
+        self.hook_overrides.pop();
+        if let Some(group_stack) = &mut self.group_stack {
+            group_stack.exit();
+        }
+        for stack in &mut self.group_stacks {
+            stack.exit();
+        }
         if matches!(node.tag_name().name(), SVG_TAG_NAME | SYMBOL_TAG_NAME) {
             self.viewport_dim_stack.pop();
         }
+        if node.has_tag_name(USE_TAG_NAME) {
+            self.terrarium.turtle.end_instance();
+        }
     }
 }
@@ -0,0 +1,123 @@
+use std::fmt::Write;
+
+use crate::{Polyline, widen};
+
+/// Command set of a vinyl cutter that doesn't take G-Code, see [polylines2cutter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CutterDialect {
+    /// Graphtec GP-GL, which expects the cutter's step size to be set to 0.1 mm
+    GpGl,
+    /// Houston Instrument DM/PL as spoken by Summa and Roland cutters, in steps of 0.001 inch
+    DmPl,
+}
+
+impl CutterDialect {
+    /// Length of a step in millimeters
+    fn step(self) -> f64 {
+        match self {
+            Self::GpGl => 0.1,
+            Self::DmPl => 0.0254,
+        }
+    }
+}
+
+/// End of a GP-GL command
+const ETX: char = '\u{3}';
+
+/// Write toolpaths in the command set of a vinyl cutter, to send to it as is
+///
+/// Each path becomes a move to its start with the knife up, then cuts through the rest of its points, rounded to the
+/// cutter's steps. Holes to drill can't be cut with a knife, so they are left out. The cutter returns to its origin
+/// once done.
+pub fn polylines2cutter(polylines: &[Polyline], dialect: CutterDialect) -> String {
+    let paths = polylines
+        .iter()
+        .filter(|polyline| !polyline.drill && polyline.points.len() > 1)
+        .map(|polyline| {
+            let mut steps = polyline
+                .points
+                .iter()
+                .map(|point| {
+                    [
+                        (widen(point.x) / dialect.step()).round() as i64,
+                        (widen(point.y) / dialect.step()).round() as i64,
+                    ]
+                })
+                .collect::<Vec<_>>();
+            steps.dedup();
+            steps
+        })
+        .filter(|steps| steps.len() > 1);
+
+    let mut commands = String::new();
+    // Writing to a string can't fail
+    match dialect {
+        CutterDialect::GpGl => {
+            let _ = write!(commands, "H{ETX}");
+            for steps in paths {
+                let [x, y] = steps[0];
+                let _ = write!(commands, "M{x},{y}{ETX}D");
+                for (i, [x, y]) in steps[1..].iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    let _ = write!(commands, "{separator}{x},{y}");
+                }
+                let _ = writeln!(commands, "{ETX}");
+            }
+            let _ = write!(commands, "H{ETX}");
+        }
+        CutterDialect::DmPl => {
+            let _ = writeln!(commands, ";: H A EC1");
+            for steps in paths {
+                let [x, y] = steps[0];
+                let _ = write!(commands, "U{x},{y}");
+                for [x, y] in &steps[1..] {
+                    let _ = write!(commands, " D{x},{y}");
+                }
+                let _ = writeln!(commands);
+            }
+            let _ = writeln!(commands, "U0,0 @");
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use lyon_geom::point;
+
+    use super::*;
+    use crate::PathSource;
+
+    fn polylines() -> Vec<Polyline> {
+        [
+            (vec![(0., 0.), (10., 0.), (10., 5.02), (0., 0.)], false),
+            (vec![(5., 5.)], true),
+        ]
+        .into_iter()
+        .map(|(points, drill)| Polyline {
+            source: PathSource::default(),
+            points: points
+                .into_iter()
+                .map(|(x, y)| point(x as crate::Float, y as crate::Float))
+                .collect(),
+            drill,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn gp_gl_cuts_in_tenths_of_a_millimeter() {
+        assert_eq!(
+            polylines2cutter(&polylines(), CutterDialect::GpGl),
+            "H\u{3}M0,0\u{3}D100,0,100,50,0,0\u{3}\nH\u{3}"
+        );
+    }
+
+    #[test]
+    fn dm_pl_cuts_in_thousandths_of_an_inch() {
+        assert_eq!(
+            polylines2cutter(&polylines(), CutterDialect::DmPl),
+            ";: H A EC1\nU0,0 D394,0 D394,198 D0,0\nU0,0 @\n"
+        );
+    }
+}
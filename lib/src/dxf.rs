@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use crate::{Polyline, widen};
+
+/// Layer that DXF readers put entities in when none is given
+const DEFAULT_LAYER: &str = "0";
+
+/// Write toolpaths as a DXF drawing for CAD and CAM programs, in millimeters
+///
+/// Each path becomes an `LWPOLYLINE` on a layer named after the Inkscape layer it came from, closed if it ends where it
+/// starts, and each hole to drill becomes a `POINT`. Paths outside of any layer go on layer `0`.
+pub fn polylines2dxf(polylines: &[Polyline]) -> String {
+    let layers = polylines.iter().fold(vec![], |mut layers, polyline| {
+        let layer = layer_name(polyline.source.layer.as_deref());
+        if !layers.contains(&layer) {
+            layers.push(layer);
+        }
+        layers
+    });
+
+    let mut dxf = String::new();
+    let mut group = |code: u16, value: &dyn std::fmt::Display| {
+        // Writing to a string can't fail
+        let _ = write!(dxf, "{code}\n{value}\n");
+    };
+    group(0, &"SECTION");
+    group(2, &"HEADER");
+    group(9, &"$ACADVER");
+    group(1, &"AC1015");
+    group(9, &"$INSUNITS");
+    // Millimeters
+    group(70, &4);
+    group(0, &"ENDSEC");
+
+    group(0, &"SECTION");
+    group(2, &"TABLES");
+    group(0, &"TABLE");
+    group(2, &"LAYER");
+    group(70, &layers.len());
+    for layer in &layers {
+        group(0, &"LAYER");
+        group(2, layer);
+        group(70, &0);
+        // White, or black on a light background
+        group(62, &7);
+        group(6, &"CONTINUOUS");
+    }
+    group(0, &"ENDTAB");
+    group(0, &"ENDSEC");
+
+    group(0, &"SECTION");
+    group(2, &"ENTITIES");
+    for polyline in polylines {
+        let layer = layer_name(polyline.source.layer.as_deref());
+        match polyline.points.as_slice() {
+            [] => {}
+            [center, ..] if polyline.drill => {
+                group(0, &"POINT");
+                group(8, &layer);
+                group(10, &format_coordinate(center.x));
+                group(20, &format_coordinate(center.y));
+                group(30, &0);
+            }
+            [_] => {}
+            points => {
+                let closed = points.len() > 2 && points.first() == points.last();
+                let points = if closed {
+                    &points[..points.len() - 1]
+                } else {
+                    points
+                };
+                group(0, &"LWPOLYLINE");
+                group(100, &"AcDbEntity");
+                group(8, &layer);
+                group(100, &"AcDbPolyline");
+                group(90, &points.len());
+                group(70, &u8::from(closed));
+                for point in points {
+                    group(10, &format_coordinate(point.x));
+                    group(20, &format_coordinate(point.y));
+                }
+            }
+        }
+    }
+    group(0, &"ENDSEC");
+    group(0, &"EOF");
+    dxf
+}
+
+/// Inkscape layer label made valid as a DXF layer name, which can't hold some punctuation
+fn layer_name(layer: Option<&str>) -> String {
+    let name = layer
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .map(|c| {
+            if "<>/\\\":;?*|=`".contains(c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        DEFAULT_LAYER.to_string()
+    } else {
+        name
+    }
+}
+
+/// Coordinate rounded to a tenth of a micron, without trailing zeros
+fn format_coordinate(value: crate::Float) -> String {
+    let formatted = format!("{:.4}", widen(value));
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lyon_geom::point;
+
+    use super::*;
+    use crate::PathSource;
+
+    fn polyline(layer: Option<&str>, points: &[(f64, f64)], drill: bool) -> Polyline {
+        Polyline {
+            source: PathSource {
+                layer: layer.map(str::to_string),
+                ..Default::default()
+            },
+            points: points
+                .iter()
+                .map(|(x, y)| point(*x as crate::Float, *y as crate::Float))
+                .collect(),
+            drill,
+        }
+    }
+
+    #[test]
+    fn paths_are_written_per_layer() {
+        let dxf = polylines2dxf(&[
+            polyline(
+                Some("cut"),
+                &[(0., 0.), (10., 0.), (10., 10.), (0., 0.)],
+                false,
+            ),
+            polyline(None, &[(1.5, 2.25), (3., 4.)], false),
+            polyline(Some("holes: 3mm"), &[(5., 5.)], true),
+        ]);
+
+        assert!(dxf.starts_with("0\nSECTION\n2\nHEADER\n"));
+        assert!(dxf.ends_with("0\nENDSEC\n0\nEOF\n"));
+        assert!(dxf.contains("0\nTABLE\n2\nLAYER\n70\n3\n"));
+        assert!(dxf.contains("0\nLAYER\n2\ncut\n"));
+        assert!(dxf.contains("0\nLAYER\n2\n0\n"));
+        assert!(dxf.contains("0\nLAYER\n2\nholes_ 3mm\n"));
+        // The closing point is left out of a closed path, which is flagged instead
+        assert!(dxf.contains(
+            "8\ncut\n100\nAcDbPolyline\n90\n3\n70\n1\n10\n0\n20\n0\n10\n10\n20\n0\n10\n10\n20\n10\n0\n"
+        ));
+        assert!(
+            dxf.contains(
+                "8\n0\n100\nAcDbPolyline\n90\n2\n70\n0\n10\n1.5\n20\n2.25\n10\n3\n20\n4\n"
+            )
+        );
+        assert!(dxf.contains("0\nPOINT\n8\nholes_ 3mm\n10\n5\n20\n5\n30\n0\n"));
+    }
+}
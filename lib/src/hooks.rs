@@ -0,0 +1,219 @@
+use std::{fmt, sync::Arc};
+
+use euclid::default::Transform2D;
+use roxmltree::Node;
+
+use crate::{
+    Float, GroupOverride, Turtle,
+    converter::{is_in_definitions, is_layer_or_top_level_group, is_named_layer, is_top_level},
+    turtle::Terrarium,
+};
+
+/// What to do with an element, as decided by a hook given to [Hooks::on_enter]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum HookAction {
+    /// Convert the element as usual
+    #[default]
+    Continue,
+    /// Leave out the element and everything in it
+    Skip,
+    /// Draw the shapes in the element with these settings, instead of any from [crate::ConversionConfig::overrides]
+    ///
+    /// [GroupOverride::group] is not used.
+    Override(GroupOverride),
+}
+
+type EnterHook = dyn Fn(&Node, &Transform2D<Float>) -> HookAction + Send + Sync;
+type ExitHook = dyn Fn(&Node, &Transform2D<Float>) + Send + Sync;
+type ElementHandler = dyn Fn(&Node, &mut Terrarium<&mut dyn Turtle>) + Send + Sync;
+
+/// Tag an element handler is for, with [None] matching any namespace
+type Tag = (Option<String>, String);
+
+/// Callbacks for applications to step in as each SVG element is converted, see [crate::ConversionOptions::hooks]
+///
+/// Hooks are given the element and the transform from its coordinates to those of the drawing, which are in pixels
+/// with the Y axis pointing up (see [crate::ConversionConfig::dpi]). They may see an element more than once, i.e. when
+/// the drawing is measured to place it at [crate::ConversionConfig::origin], so they should decide the same way
+/// each time.
+#[derive(Default, Clone)]
+pub struct Hooks {
+    enter: Vec<Arc<EnterHook>>,
+    exit: Vec<Arc<ExitHook>>,
+    elements: Vec<(Tag, Arc<ElementHandler>)>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `hook` as each element is entered, before it is drawn
+    ///
+    /// When there are several, an element is skipped if any of them skip it, otherwise the last override is used.
+    pub fn on_enter(
+        mut self,
+        hook: impl Fn(&Node, &Transform2D<Float>) -> HookAction + Send + Sync + 'static,
+    ) -> Self {
+        self.enter.push(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` as each element is left, after everything in it is drawn
+    pub fn on_exit(
+        mut self,
+        hook: impl Fn(&Node, &Transform2D<Float>) + Send + Sync + 'static,
+    ) -> Self {
+        self.exit.push(Arc::new(hook));
+        self
+    }
+
+    /// Draw elements with the given tag using `handler`, for tags that are not otherwise converted
+    ///
+    /// A `namespace` of [None] matches the tag in any namespace, so custom tags in other namespaces can be given by
+    /// their URI. Elements with the tag are treated like shapes: they are left out when they aren't in
+    /// [crate::ConversionOptions::group], are drawn once for each of their passes, and have their [HookAction] and
+    /// [crate::ConversionConfig::overrides] applied.
+    ///
+    /// The handler draws in the element's coordinates through the [Terrarium], starting each path with
+    /// [Terrarium::move_to], and should pop any transform it pushes. When there are several for a tag, the last one is used.
+    pub fn on_element(
+        mut self,
+        namespace: Option<&str>,
+        name: &str,
+        handler: impl Fn(&Node, &mut Terrarium<&mut dyn Turtle>) + Send + Sync + 'static,
+    ) -> Self {
+        self.elements.push((
+            (namespace.map(str::to_string), name.to_string()),
+            Arc::new(handler),
+        ));
+        self
+    }
+
+    /// Only convert what is in the layers or top-level groups with these names, see [crate::svg2layers]
+    ///
+    /// Names are matched against the Inkscape label or the id. Shapes outside of any layer or top-level group are
+    /// left out.
+    pub fn only_layers(self, names: Vec<String>) -> Self {
+        self.on_enter(move |node, _| {
+            let selected = |node: Node| is_named_layer(&node, &names);
+            // Ancestors and descendants include the node itself
+            if node.ancestors().any(selected) || node.descendants().any(selected) {
+                HookAction::Continue
+            } else if is_layer_or_top_level_group(node) || is_top_level(node) {
+                HookAction::Skip
+            } else {
+                // Elements referenced by a `<use>` are only reached through one that was kept
+                HookAction::Continue
+            }
+        })
+    }
+
+    /// Leave out the layers or top-level groups with these names, matched against the Inkscape label or the id
+    pub fn skip_layers(self, names: Vec<String>) -> Self {
+        self.on_enter(move |node, _| {
+            if is_named_layer(node, &names) {
+                HookAction::Skip
+            } else {
+                HookAction::Continue
+            }
+        })
+    }
+
+    /// Only convert the elements with these ids and everything in them
+    pub fn only_ids(self, ids: Vec<String>) -> Self {
+        self.on_enter(move |node, _| {
+            let selected = |node: Node| {
+                node.attribute("id")
+                    .is_some_and(|id| ids.iter().any(|i| i == id))
+            };
+            // Ancestors and descendants include the node itself
+            if node.ancestors().any(selected)
+                || node.descendants().any(selected)
+                // Only reached through a `<use>` that was kept
+                || is_in_definitions(node)
+            {
+                HookAction::Continue
+            } else {
+                HookAction::Skip
+            }
+        })
+    }
+
+    /// Leave out the elements with these ids and everything in them, i.e. registration marks or construction lines
+    pub fn skip_ids(self, ids: Vec<String>) -> Self {
+        self.on_enter(move |node, _| {
+            if node
+                .attribute("id")
+                .is_some_and(|id| ids.iter().any(|i| i == id))
+            {
+                HookAction::Skip
+            } else {
+                HookAction::Continue
+            }
+        })
+    }
+
+    /// Handler for an element with the given tag, from [Self::on_element]
+    pub(crate) fn element(&self, node: &Node) -> Option<&Arc<ElementHandler>> {
+        let tag = node.tag_name();
+        self.elements
+            .iter()
+            .rev()
+            .find(|((namespace, name), _)| {
+                name == tag.name()
+                    && namespace
+                        .as_deref()
+                        .is_none_or(|namespace| tag.namespace() == Some(namespace))
+            })
+            .map(|(_, handler)| handler)
+    }
+
+    pub(crate) fn enter(&self, node: &Node, transform: &Transform2D<Float>) -> HookAction {
+        let mut action = HookAction::Continue;
+        for hook in &self.enter {
+            match hook(node, transform) {
+                HookAction::Continue => {}
+                HookAction::Skip => return HookAction::Skip,
+                group_override @ HookAction::Override(_) => action = group_override,
+            }
+        }
+        action
+    }
+
+    pub(crate) fn exit(&self, node: &Node, transform: &Transform2D<Float>) {
+        for hook in &self.exit {
+            hook(node, transform);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("enter", &self.enter.len())
+            .field("exit", &self.exit.len())
+            .field(
+                "elements",
+                &self.elements.iter().map(|(tag, _)| tag).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Hooks are equal when they hold the same callbacks, i.e. when they are clones of each other
+impl PartialEq for Hooks {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<T: ?Sized>(a: &[Arc<T>], b: &[Arc<T>]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| Arc::ptr_eq(a, b))
+        }
+        same(&self.enter, &other.enter)
+            && same(&self.exit, &other.exit)
+            && self.elements.len() == other.elements.len()
+            && self
+                .elements
+                .iter()
+                .zip(&other.elements)
+                .all(|((a_tag, a), (b_tag, b))| a_tag == b_tag && Arc::ptr_eq(a, b))
+    }
+}
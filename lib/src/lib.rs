@@ -1,22 +1,124 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+/// Enter a `tracing` span for a phase of conversion, which lasts until the end of the enclosing block
+///
+/// Does nothing without the `tracing` feature.
+#[cfg(feature = "std")]
+macro_rules! phase {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _phase = ::tracing::info_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
 /// Approximate [Bézier curves](https://en.wikipedia.org/wiki/B%C3%A9zier_curve) with [Circular arcs](https://en.wikipedia.org/wiki/Circular_arc)
 mod arc;
+/// Fluent construction of settings, checked before conversion starts
+#[cfg(feature = "std")]
+mod builder;
+/// Stops conversions that are no longer needed
+#[cfg(feature = "std")]
+mod cancellation;
 /// Converts an SVG to an internal representation
+#[cfg(feature = "std")]
 mod converter;
+/// Exports toolpaths to vinyl cutters that don't take G-Code
+#[cfg(feature = "std")]
+mod cutter;
+/// Exports toolpaths to CAD and CAM programs
+#[cfg(feature = "std")]
+mod dxf;
+/// Lets applications step in as SVG elements are converted
+#[cfg(feature = "std")]
+mod hooks;
 /// Emulates the state of an arbitrary machine that can run G-Code
+#[cfg(feature = "std")]
 mod machine;
+/// Converts SVG text to G-Code text in one call, sharing nothing between calls
+#[cfg(feature = "std")]
+mod oneshot;
+/// Chooses the order paths are drawn in
+#[cfg(feature = "std")]
+mod ordering;
 /// Operations that are easier to implement while/after G-Code is generated, or would
 /// otherwise over-complicate SVG conversion
+#[cfg(feature = "std")]
 mod postprocess;
+/// Typed view of generated programs, for editing them before they are formatted
+#[cfg(feature = "std")]
+mod program;
+/// Wraps programs around eggs and spheres
+#[cfg(feature = "std")]
+mod sphere;
+/// Summarizes generated programs
+#[cfg(feature = "std")]
+mod statistics;
+/// Shrinks programs by calling repeated content as subprograms
+#[cfg(feature = "std")]
+mod subprogram;
 /// Provides an interface for drawing lines in G-Code
 /// This concept is referred to as [Turtle graphics](https://en.wikipedia.org/wiki/Turtle_graphics).
 mod turtle;
 
-pub use converter::{ConversionConfig, ConversionOptions, svg2program};
-pub use machine::{Machine, MachineConfig, SupportedFunctionality};
-pub use postprocess::PostprocessConfig;
-pub use turtle::Turtle;
+/// Floating point type of the geometry pipeline, from parsed SVG coordinates through to flattened segments
+///
+/// This is `f64`, or `f32` with the `f32` feature, which trades micron precision for memory and speed on WebAssembly
+/// and embedded hosts. Settings and the emitted G-Code stay in `f64` either way.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+/// Widen a [Float] to meet settings and measurements, which are always `f64`
+#[allow(clippy::useless_conversion)]
+pub(crate) fn widen(value: Float) -> f64 {
+    value.into()
+}
+
+pub use arc::{ArcOrLineSegment, FlattenWithArcs, Transformed};
+#[cfg(feature = "std")]
+pub use builder::{
+    ConversionConfigBuilder, ConversionOptionsBuilder, MachineConfigBuilder, SettingsError,
+};
+#[cfg(feature = "std")]
+pub use cancellation::Cancellation;
+#[cfg(feature = "std")]
+pub use converter::{
+    CSS_DEFAULT_DPI, ChunkLimit, CommentStyle, CommentVerbosity, ConversionConfig, ConversionError,
+    ConversionOptions, DimensionHint, Drawing, DrawingPath, DrillCycle, Drilling, FeedratePolicy,
+    Flip, Geometry, Group, GroupOverride, LayerSummary, LengthError, ParkPosition, PathOrder,
+    PathSource, Polyline, Program, ProgressMarkers, RefinementBudget, Sphere, SplitBy, UserUnits,
+    drawing2geometry, geometry2program, length_to_user_units, parse_length, svg2chunks,
+    svg2drawing, svg2geometry, svg2layers, svg2lightburn, svg2polylines, svg2program,
+    svg2program_fmt, svg2program_io, svg2programs, svg2turtle,
+};
+#[cfg(feature = "std")]
+pub use cutter::{CutterDialect, polylines2cutter};
+#[cfg(feature = "std")]
+pub use dxf::polylines2dxf;
+#[cfg(feature = "std")]
+pub use hooks::{HookAction, Hooks};
+#[cfg(feature = "std")]
+pub use machine::{
+    ArcFormat, Kinematics, Machine, MachineConfig, PenServo, ProgramEnd, SupportedFunctionality,
+};
+#[cfg(feature = "std")]
+pub use oneshot::{ConvertError, convert};
+#[cfg(feature = "std")]
+pub use postprocess::{Homing, Klipper, PostprocessConfig, format_program_fmt, format_program_io};
+#[cfg(feature = "std")]
+pub use program::ProgramCommand;
+#[cfg(feature = "std")]
+pub use statistics::ConversionStatistics;
+pub use turtle::{BoundingBoxTurtle, LengthTurtle, Terrarium, Turtle};
+#[cfg(feature = "std")]
+pub use turtle::{Command, DebugEntry, DebugTurtle, PreviewTurtle, TurtleCall};
 
 /// A cross-platform type used to store all configuration types.
+#[cfg(feature = "std")]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Settings {
@@ -27,6 +129,7 @@ pub struct Settings {
     pub version: Version,
 }
 
+#[cfg(feature = "std")]
 impl Settings {
     /// Try to automatically upgrade the supported version.
     ///
@@ -55,6 +158,7 @@ impl Settings {
 /// Used to control breaking change behavior for [`Settings`].
 ///
 /// There were already 3 non-breaking version bumps (V1 -> V4) so versioning starts off with [`Version::V5`].
+#[cfg(feature = "std")]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Version {
@@ -66,6 +170,7 @@ pub enum Version {
     Unknown(String),
 }
 
+#[cfg(feature = "std")]
 impl Version {
     /// Returns the most recent [`Version`]. This is useful for asking users to upgrade externally-stored settings.
     pub const fn latest() -> Self {
@@ -78,6 +183,7 @@ impl Version {
     }
 }
 
+#[cfg(feature = "std")]
 impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -88,6 +194,7 @@ impl std::fmt::Display for Version {
     }
 }
 
+#[cfg(feature = "std")]
 impl Default for Version {
     fn default() -> Self {
         Self::latest()
@@ -112,8 +219,24 @@ mod test {
         circular_interpolation: bool,
         dimensions: [Option<Length>; 2],
     ) -> Vec<Token<'_>> {
-        let config = ConversionConfig::default();
-        let options = ConversionOptions { dimensions };
+        get_actual_with_config(
+            input,
+            &ConversionConfig::default(),
+            circular_interpolation,
+            dimensions,
+        )
+    }
+
+    fn get_actual_with_config<'input>(
+        input: &'input str,
+        config: &ConversionConfig,
+        circular_interpolation: bool,
+        dimensions: [Option<Length>; 2],
+    ) -> Vec<Token<'input>> {
+        let options = ConversionOptions {
+            dimensions,
+            ..Default::default()
+        };
         let document = roxmltree::Document::parse_with_options(
             input,
             ParsingOptions {
@@ -126,13 +249,17 @@ mod test {
         let machine = Machine::new(
             SupportedFunctionality {
                 circular_interpolation,
+                ..Default::default()
             },
+            Kinematics::default(),
             None,
             None,
             None,
             None,
         );
-        converter::svg2program(&document, &config, options, machine)
+        converter::svg2program(&document, config, options, machine)
+            .unwrap()
+            .tokens
     }
 
     fn assert_close(left: Vec<Token<'_>>, right: Vec<Token<'_>>) {
@@ -253,6 +380,109 @@ mod test {
         assert_close(actual, expected)
     }
 
+    #[test]
+    fn arc_format_controls_arc_words() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M0,10 A10,10 0 1 1 10,0"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let arcs = |arc_format| {
+            let machine = Machine::new(
+                SupportedFunctionality {
+                    circular_interpolation: true,
+                    arc_format,
+                    ..Default::default()
+                },
+                Kinematics::default(),
+                None,
+                None,
+                None,
+                None,
+            );
+            let program = converter::svg2program(
+                &document,
+                &ConversionConfig::default(),
+                ConversionOptions::default(),
+                machine,
+            )
+            .unwrap()
+            .tokens;
+            // Words of each G2/G3 command
+            let mut arcs: Vec<Vec<String>> = vec![];
+            let mut in_arc = false;
+            for token in program {
+                let Token::Field(field) = token else {
+                    continue;
+                };
+                if field.letters == "G" {
+                    in_arc = matches!(field.value.as_f64(), Some(2.) | Some(3.));
+                    if in_arc {
+                        arcs.push(vec![]);
+                    }
+                } else if in_arc && let Some(arc) = arcs.last_mut() {
+                    arc.push(field.letters.into_owned());
+                }
+            }
+            arcs
+        };
+
+        // Over 180°, so the R format needs two arcs
+        let radius = arcs(ArcFormat::Radius);
+        assert_eq!(radius.len(), 2);
+        assert!(radius.iter().all(|arc| arc.contains(&"R".to_string())));
+
+        let center = arcs(ArcFormat::Center);
+        assert_eq!(center.len(), 1);
+        assert!(center[0].contains(&"I".to_string()) && center[0].contains(&"J".to_string()));
+        assert!(!center[0].contains(&"R".to_string()));
+    }
+
+    #[test]
+    fn arc_tolerance_cuts_shallow_arcs_as_lines() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M0,10 A100,100 0 0 1 1,10"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let codes = |arc_tolerance| {
+            let machine = Machine::new(
+                SupportedFunctionality {
+                    circular_interpolation: true,
+                    arc_tolerance,
+                    ..Default::default()
+                },
+                Kinematics::default(),
+                None,
+                None,
+                None,
+                None,
+            );
+            converter::svg2program(
+                &document,
+                &ConversionConfig::default(),
+                ConversionOptions::default(),
+                machine,
+            )
+            .unwrap()
+            .tokens
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Field(field) if field.letters == "G" => field.value.as_f64(),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        // The arc deviates from its chord by about 0.00125mm
+        assert!(codes(None).contains(&2.));
+        let with_tolerance = codes(Some(0.002));
+        assert!(!with_tolerance.contains(&2.) && !with_tolerance.contains(&3.));
+        assert!(with_tolerance.contains(&1.));
+    }
+
     #[test]
     fn svg_with_smooth_curves_produces_expected_gcode() {
         let svg = include_str!("../tests/smooth_curves.svg");
@@ -327,6 +557,1962 @@ mod test {
         assert_close(actual, expected)
     }
 
+    #[test]
+    fn feedrate_policy_controls_f_words() {
+        let count_feedrates = |feedrate_policy| {
+            let config = ConversionConfig {
+                feedrate_policy,
+                ..Default::default()
+            };
+            get_actual_with_config(
+                include_str!("../tests/square.svg"),
+                &config,
+                false,
+                [None; 2],
+            )
+            .iter()
+            .filter(|token| matches!(token, Token::Field(field) if field.letters == "F"))
+            .count()
+        };
+
+        // Two paths, a square with 4 lines and a circle with 36 lines
+        assert_eq!(count_feedrates(FeedratePolicy::EveryMove), 40);
+        assert_eq!(count_feedrates(FeedratePolicy::PerPath), 2);
+        assert_eq!(count_feedrates(FeedratePolicy::OnChange), 1);
+    }
+
+    #[test]
+    fn comment_verbosity_and_style_control_comments() {
+        let comments = |comment_style, comment_verbosity| {
+            let config = ConversionConfig {
+                comment_style,
+                comment_verbosity,
+                ..Default::default()
+            };
+            get_actual_with_config(
+                include_str!("../tests/square.svg"),
+                &config,
+                false,
+                [None; 2],
+            )
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Comment { is_inline, inner } => Some((is_inline, inner.into_owned())),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            comments(CommentStyle::Semicolon, CommentVerbosity::None),
+            vec![]
+        );
+        assert_eq!(
+            comments(CommentStyle::Parentheses, CommentVerbosity::PerLayer),
+            vec![(true, "svg#svg8 > g#layer1".to_string())]
+        );
+        assert_eq!(
+            comments(CommentStyle::Semicolon, CommentVerbosity::PerElement).len(),
+            2
+        );
+        assert_eq!(
+            comments(CommentStyle::Semicolon, CommentVerbosity::PerSegment)[1],
+            (false, "M 1 1".to_string())
+        );
+    }
+
+    #[test]
+    fn metadata_header_describes_job() {
+        let config = ConversionConfig {
+            metadata_header: true,
+            ..Default::default()
+        };
+        let header = get_actual_with_config(
+            include_str!("../tests/square.svg"),
+            &config,
+            false,
+            [None; 2],
+        )
+        .into_iter()
+        .map_while(|token| match token {
+            Token::Comment { inner, .. } => Some(inner.into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        assert!(header.contains(&"Bounding box: X1.000..9.000 Y1.000..9.000 mm".to_string()));
+        assert!(header.contains(&"Paths: 2".to_string()));
+        // From the corner of the square to the rightmost point of the circle
+        assert!(header.contains(&"Travel length: 9.552 mm".to_string()));
+    }
+
+    #[test]
+    fn corner_slowdown_reduces_feedrate_before_corners() {
+        let config = ConversionConfig {
+            corner_slowdown: 1.,
+            feedrate_policy: FeedratePolicy::OnChange,
+            ..Default::default()
+        };
+        let feedrates = get_actual_with_config(
+            include_str!("../tests/square.svg"),
+            &config,
+            false,
+            [None; 2],
+        )
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Field(field) if field.letters == "F" => field.value.as_f64(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        // Sides leading into a right angle run at 2/3 speed, the last side ends the path at full speed
+        assert_eq!(&feedrates[..2], &[200., 300.]);
+    }
+
+    #[test]
+    fn power_ramping_reduces_power_on_slow_moves() {
+        let powers = |power_ramping, min_power, acceleration| {
+            let config = ConversionConfig {
+                power: Some(1000.),
+                power_ramping,
+                min_power,
+                ..Default::default()
+            };
+            let document = roxmltree::Document::parse(include_str!("../tests/square.svg")).unwrap();
+            let machine = Machine::new(
+                SupportedFunctionality::default(),
+                Kinematics {
+                    acceleration,
+                    ..Default::default()
+                },
+                None,
+                None,
+                None,
+                None,
+            );
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) if field.letters == "S" => field.value.as_f64(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // One S word per path
+        assert_eq!(powers(false, None, Some(100.)), vec![1000., 1000.]);
+        // Nothing to model the speed with
+        assert_eq!(powers(true, None, None), vec![1000., 1000.]);
+
+        let ramped = powers(true, None, Some(100.));
+        assert_eq!(ramped.len(), 40);
+        assert!(ramped.iter().all(|power| *power <= 1000.));
+        assert!(ramped.iter().any(|power| *power < 900.));
+
+        let floored = powers(true, Some(900.), Some(100.));
+        assert!(floored.iter().all(|power| *power >= 900.));
+    }
+
+    #[test]
+    fn grid_snaps_coordinates() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height="10mm" viewBox="0 0 10 10">
+                <path d="M 1.234,1.234 L 5.678,1.234 L 5.6801,1.2345 L 5.678,8.76"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let config = ConversionConfig {
+            origin: [None, None],
+            grid: Some(0.1),
+            ..Default::default()
+        };
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let mut moves = vec![];
+        for token in
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap()
+                .tokens
+        {
+            match token {
+                Token::Field(field) if field.letters == "G" => moves.push(vec![]),
+                Token::Field(field) if matches!(field.letters.as_ref(), "X" | "Y") => moves
+                    .last_mut()
+                    .unwrap()
+                    .push(field.value.as_f64().unwrap()),
+                _ => {}
+            }
+        }
+        moves.retain(|coordinates| !coordinates.is_empty());
+
+        // The third point is within a step of the second
+        assert_eq!(moves, [[1.2, 8.8], [5.7, 8.8], [5.7, 1.2]]);
+    }
+
+    #[test]
+    fn grid_snaps_heights() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height="10mm" viewBox="0 0 10 10">
+                <path d="M 1,1 L 5,1"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let config = ConversionConfig {
+            grid: Some(0.1),
+            safe_z: Some(5.04),
+            cut_z: Some(-1.26),
+            ..Default::default()
+        };
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let heights =
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) if field.letters == "Z" => field.value.as_f64(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+        assert_eq!(heights, [5., -1.3, 5.]);
+    }
+
+    #[test]
+    fn safe_z_retracts_between_paths() {
+        let z_heights = |retract_between_paths| {
+            let config = ConversionConfig {
+                safe_z: Some(5.),
+                cut_z: Some(-1.),
+                retract_between_paths,
+                ..Default::default()
+            };
+            get_actual_with_config(
+                include_str!("../tests/square.svg"),
+                &config,
+                false,
+                [None; 2],
+            )
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Field(field) if field.letters == "Z" => field.value.as_f64(),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(z_heights(true), vec![5., -1., 5., -1., 5.]);
+        assert_eq!(z_heights(false), vec![5., -1., 5.]);
+    }
+
+    #[test]
+    fn park_moves_clear_of_job() {
+        let park_position = |park| {
+            let config = ConversionConfig {
+                park: Some(park),
+                ..Default::default()
+            };
+            let program = get_actual_with_config(
+                include_str!("../tests/square.svg"),
+                &config,
+                false,
+                [None; 2],
+            );
+            // Coordinates of the last rapid move
+            let rapid = program
+                .iter()
+                .rposition(|token| match token {
+                    Token::Field(field) => field.letters == "G" && field.value.as_f64() == Some(0.),
+                    _ => false,
+                })
+                .unwrap();
+            match &program[rapid + 1..rapid + 3] {
+                [Token::Field(x), Token::Field(y)] => {
+                    [x.value.as_f64().unwrap(), y.value.as_f64().unwrap()]
+                }
+                other => panic!("not a rapid move: {other:?}"),
+            }
+        };
+
+        let [x, y] = park_position(ParkPosition::BehindJob);
+        assert!(x.abs() < TOLERANCE && (y - 9.).abs() < TOLERANCE);
+        assert_eq!(park_position(ParkPosition::Custom([100., 5.])), [100., 5.]);
+    }
+
+    #[test]
+    fn dry_run_never_turns_tool_on() {
+        let config = ConversionConfig {
+            dry_run: true,
+            power: Some(1000.),
+            safe_z: Some(5.),
+            cut_z: Some(-1.),
+            ..Default::default()
+        };
+        let document = roxmltree::Document::parse(include_str!("../tests/square.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            Some(g_code::parse::snippet_parser("M3").unwrap()),
+            Some(g_code::parse::snippet_parser("M5").unwrap()),
+            None,
+            None,
+        );
+        let fields =
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) => {
+                        Some((field.letters.into_owned(), field.value.as_f64()?))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+        assert!(!fields.contains(&("M".to_string(), 3.)));
+        assert!(
+            fields
+                .iter()
+                .all(|(letters, value)| match letters.as_str() {
+                    "S" => *value == 0.,
+                    "Z" => *value == 5.,
+                    _ => true,
+                })
+        );
+        assert!(fields.contains(&("G".to_string(), 1.)));
+    }
+
+    #[test]
+    fn adjacent_paths_keep_tool_on() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M0,0 L10,0"/>
+                <path d="M10,0 L10,10"/>
+                <path d="M15,15 L20,20"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let fields = |skip_redundant_commands| {
+            let config = ConversionConfig {
+                power: Some(1000.),
+                skip_redundant_commands,
+                ..Default::default()
+            };
+            let machine = Machine::new(
+                SupportedFunctionality::default(),
+                Kinematics::default(),
+                Some(g_code::parse::snippet_parser("M3 S1000").unwrap()),
+                Some(g_code::parse::snippet_parser("M5").unwrap()),
+                None,
+                None,
+            );
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) => {
+                        Some((field.letters.into_owned(), field.value.as_f64()?))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let count = |fields: &[(String, f64)], letters: &str, value: Option<f64>| {
+            fields
+                .iter()
+                .filter(|field| field.0 == letters && value.is_none_or(|value| field.1 == value))
+                .count()
+        };
+
+        // Every path turns the tool on, moves there, and sets the power for its first cut
+        let every_path = fields(false);
+        assert_eq!(count(&every_path, "M", Some(3.)), 3);
+        assert_eq!(count(&every_path, "G", Some(0.)), 3);
+        assert_eq!(count(&every_path, "S", None), 6);
+
+        // The second path carries on from the first, only the third needs a rapid
+        let skipped = fields(true);
+        assert_eq!(count(&skipped, "M", Some(3.)), 2);
+        assert_eq!(count(&skipped, "G", Some(0.)), 2);
+        // The tool on sequence already sets the power
+        assert_eq!(count(&skipped, "S", None), 2);
+    }
+
+    #[test]
+    fn layer_overrides_change_feedrate_power_and_passes() {
+        let config = ConversionConfig {
+            power: Some(1000.),
+            overrides: vec![
+                GroupOverride {
+                    group: Group {
+                        split_by: SplitBy::Layer,
+                        name: Some("engrave".to_string()),
+                    },
+                    feedrate: Some(3000.),
+                    power: Some(200.),
+                    passes: None,
+                    cut_z: None,
+                },
+                GroupOverride {
+                    group: Group {
+                        split_by: SplitBy::Layer,
+                        name: Some("cut".to_string()),
+                    },
+                    feedrate: None,
+                    power: None,
+                    passes: Some(2),
+                    cut_z: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g inkscape:groupmode="layer" inkscape:label="engrave"><path d="M0,0 L10,0"/></g>
+                <g inkscape:groupmode="layer" inkscape:label="cut"><path d="M0,10 L10,10"/></g>
+            </svg>"#,
+        )
+        .unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let program =
+            converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                .unwrap();
+        assert_eq!(program.statistics.paths, 3);
+        assert_eq!(program.statistics.passes, 2);
+        let fields = program
+            .tokens
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Field(field) if ["F", "S"].contains(&field.letters.as_ref()) => {
+                    Some((field.letters.into_owned(), field.value.as_f64()?))
+                }
+                Token::Field(field) if field.letters == "G" && field.value.as_f64() == Some(0.) => {
+                    Some(("G".to_string(), 0.))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let field = |letters: &str, value: f64| (letters.to_string(), value);
+
+        assert_eq!(
+            fields,
+            [
+                field("G", 0.),
+                field("F", 3000.),
+                field("S", 200.),
+                // Configured settings for the first pass...
+                field("G", 0.),
+                field("F", 300.),
+                field("S", 1000.),
+                // ...and the second
+                field("G", 0.),
+                field("F", 300.),
+                field("S", 1000.),
+            ]
+        );
+    }
+
+    #[test]
+    fn nearest_neighbor_order_shortens_travel() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M0,0 L1,0"/>
+                <path d="M15,0 L16,0"/>
+                <path d="M5,0 L6,0"/>
+                <path d="M10,0 L11,0"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let rapids = |path_order| {
+            let config = ConversionConfig {
+                path_order,
+                ..Default::default()
+            };
+            let machine = Machine::new(
+                SupportedFunctionality::default(),
+                Kinematics::default(),
+                None,
+                None,
+                None,
+                None,
+            );
+            let program =
+                converter::svg2program(&document, &config, ConversionOptions::default(), machine)
+                    .unwrap()
+                    .tokens;
+            program
+                .windows(2)
+                .filter_map(|pair| match pair {
+                    [Token::Field(g), Token::Field(x)]
+                        if g.letters == "G" && g.value.as_f64() == Some(0.) && x.letters == "X" =>
+                    {
+                        x.value.as_f64().map(f64::round)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(rapids(PathOrder::Document), [0., 15., 5., 10.]);
+        assert_eq!(rapids(PathOrder::NearestNeighbor), [0., 5., 10., 15.]);
+    }
+
+    #[test]
+    fn framing_traces_bounding_box() {
+        let config = ConversionConfig {
+            framing: true,
+            framing_power: Some(10.),
+            ..Default::default()
+        };
+        let program = get_actual_with_config(
+            include_str!("../tests/square.svg"),
+            &config,
+            false,
+            [None; 2],
+        );
+        let fields = program
+            .iter()
+            .filter_map(|token| match token {
+                Token::Field(field) => Some((field.letters.as_ref(), field.value.as_f64()?)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(fields.iter().filter(|f| *f == &("G", 1.)).count(), 4);
+        assert!(fields.iter().all(|(letters, value)| match *letters {
+            "X" | "Y" => (value - 1.).abs() < TOLERANCE || (value - 9.).abs() < TOLERANCE,
+            "S" => *value == 10.,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn flip_rotation_and_scale_place_the_drawing() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height="10mm" viewBox="0 0 10 10">
+                <path d="M 1,9 H 3"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let points = |config| {
+            svg2turtle(
+                &document,
+                &ConversionConfig {
+                    origin: [None, None],
+                    ..config
+                },
+                ConversionOptions::default(),
+                DebugTurtle::new(),
+            )
+            .unwrap()
+            .log
+            .into_iter()
+            .filter_map(|entry| match entry.call {
+                TurtleCall::MoveTo(point) | TurtleCall::LineTo(point) => {
+                    Some([point.x.round(), point.y.round()])
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(points(ConversionConfig::default()), [[1., 1.], [3., 1.]]);
+        assert_eq!(
+            points(ConversionConfig {
+                flip: Flip::Horizontal,
+                ..Default::default()
+            }),
+            [[-1., 1.], [-3., 1.]]
+        );
+        assert_eq!(
+            points(ConversionConfig {
+                rotation: 90.,
+                ..Default::default()
+            }),
+            [[-1., 1.], [-1., 3.]]
+        );
+        assert_eq!(
+            points(ConversionConfig {
+                scale: 2.,
+                ..Default::default()
+            }),
+            [[2., 2.], [6., 2.]]
+        );
+        // Only as wide as the 2mm line, which has no height
+        assert_eq!(
+            points(ConversionConfig {
+                fit_to: Some([4., 100.]),
+                ..Default::default()
+            }),
+            [[2., 2.], [6., 2.]]
+        );
+    }
+
+    #[test]
+    fn closed_paths_end_exactly_at_their_start() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100mm" height="100mm" viewBox="0 0 100 100">
+                <g transform="translate(13.7,21.3) rotate(33.3) scale(1.7)">
+                    <path d="M 10.1,20.3 l 7.3,1.1 l -2.9,6.7 l -4.4,-7.8 z"/>
+                </g>
+            </svg>"#,
+        )
+        .unwrap();
+        let points = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions::default(),
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .filter_map(|entry| match entry.call {
+            TurtleCall::MoveTo(point) | TurtleCall::LineTo(point) => Some(point),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        // No sliver of a line to close the gap left by rounding
+        assert_eq!(points.len(), 4);
+        assert_eq!(points.first(), points.last());
+    }
+
+    #[test]
+    fn degenerate_transforms_skip_their_elements() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height="10mm" viewBox="0 0 10 10">
+                <g transform="scale(0)"><path d="M 1,1 l 2,2"/></g>
+                <svg width="0" height="5" viewBox="0 0 5 5"><path d="M 1,1 l 2,2"/></svg>
+                <path transform="matrix(1e300,0,0,1e300,0,0) scale(1e300)" d="M 1,1 l 2,2"/>
+                <path d="M 1,9 h 2"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let points = svg2turtle(
+            &document,
+            &ConversionConfig {
+                origin: [None, None],
+                ..Default::default()
+            },
+            ConversionOptions::default(),
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .filter_map(|entry| match entry.call {
+            TurtleCall::MoveTo(point) | TurtleCall::LineTo(point) => {
+                Some([point.x.round(), point.y.round()])
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(points, [[1., 1.], [3., 1.]]);
+    }
+
+    #[test]
+    fn only_the_end_of_a_subpath_is_snapped_to_its_start() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100mm" height="100mm" viewBox="0 0 100 100">
+                <path d="M 0,0 L 10,0 L 0.000000001,0 L 0,10"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let points = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions::default(),
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .filter_map(|entry| match entry.call {
+            TurtleCall::MoveTo(point) | TurtleCall::LineTo(point) => Some(point),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        // Passing close to the start on the way is part of the drawing
+        assert_eq!(points.len(), 4);
+        assert_ne!(points[2], points[0]);
+    }
+
+    #[test]
+    fn split_by_layer_shares_origin() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="10mm" height="10mm" viewBox="0 0 10 10">
+                <g inkscape:groupmode="layer" inkscape:label="cut"><path d="M -9,9 H -8"/></g>
+                <g inkscape:groupmode="layer" inkscape:label="engrave"><path d="M -5,9 H -4"/></g>
+            </svg>"#,
+        )
+        .unwrap();
+        let config = ConversionConfig {
+            origin: [Some(0.), Some(0.)],
+            ..Default::default()
+        };
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let x_coordinates = |program: &[Token]| {
+            program
+                .iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) if field.letters == "X" => field.value.as_f64(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let whole = x_coordinates(
+            &converter::svg2program(
+                &document,
+                &config,
+                ConversionOptions::default(),
+                machine.clone(),
+            )
+            .unwrap()
+            .tokens,
+        );
+        let programs = converter::svg2programs(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            SplitBy::Layer,
+            machine,
+        )
+        .unwrap();
+
+        let names = programs
+            .iter()
+            .map(|(name, _)| name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("cut"), Some("engrave")]);
+        let split = programs
+            .iter()
+            .flat_map(|(_, program)| x_coordinates(&program.tokens))
+            .collect::<Vec<_>>();
+        assert_eq!(split, whole);
+    }
+
+    #[test]
+    fn chunks_repeat_begin_and_end_sequences() {
+        let document = roxmltree::Document::parse(include_str!("../tests/square.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            Some(g_code::parse::snippet_parser("M3").unwrap()),
+            Some(g_code::parse::snippet_parser("M5").unwrap()),
+            Some(g_code::parse::snippet_parser("G28").unwrap()),
+            Some(g_code::parse::snippet_parser("M2").unwrap()),
+        );
+        let config = ConversionConfig::default();
+        let cutting_moves = |program: &[Token]| {
+            program
+                .iter()
+                .filter(|token| match token {
+                    Token::Field(field) => field.letters == "G" && field.value.as_f64() == Some(1.),
+                    _ => false,
+                })
+                .count()
+        };
+        let whole = converter::svg2program(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            machine.clone(),
+        )
+        .unwrap()
+        .tokens;
+        let chunks = converter::svg2chunks(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            machine,
+            ChunkLimit::Lines(48),
+        )
+        .unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| cutting_moves(&chunk.tokens))
+                .sum::<usize>(),
+            cutting_moves(&whole)
+        );
+        for chunk in &chunks {
+            let mut gcode = String::new();
+            format_program_fmt(&chunk.tokens, &PostprocessConfig::default(), &mut gcode).unwrap();
+            assert!(gcode.lines().count() <= 48);
+            assert!(gcode.contains("G28"));
+            assert!(gcode.trim_end().ends_with("M2"));
+        }
+    }
+
+    #[test]
+    fn resume_rapids_to_first_remaining_path() {
+        let document = roxmltree::Document::parse(include_str!("../tests/square.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            Some(g_code::parse::snippet_parser("G28").unwrap()),
+            None,
+        );
+        let options = ConversionOptions {
+            path_range: Some(1..usize::MAX),
+            ..Default::default()
+        };
+        let program =
+            converter::svg2program(&document, &ConversionConfig::default(), options, machine)
+                .unwrap()
+                .tokens;
+        let fields = program
+            .iter()
+            .filter_map(|token| match token {
+                Token::Field(field) => Some((field.letters.as_ref(), field.value.as_f64()?)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Begin sequence, then straight to the start of the circle
+        let rapid = fields.iter().position(|f| *f == ("G", 0.)).unwrap();
+        assert!(fields[..rapid].contains(&("G", 28.)));
+        assert_eq!(fields[rapid + 1], ("X", 8.));
+        assert_eq!(fields.iter().filter(|f| **f == ("G", 0.)).count(), 1);
+    }
+
+    #[test]
+    fn checkpoints_count_paths_in_whole_drawing() {
+        let document = roxmltree::Document::parse(include_str!("../tests/square.svg")).unwrap();
+        let config = ConversionConfig {
+            checkpoint_interval: Some(1),
+            ..Default::default()
+        };
+        let checkpoints = |path_range| {
+            let machine = Machine::new(
+                SupportedFunctionality::default(),
+                Kinematics::default(),
+                None,
+                None,
+                None,
+                None,
+            );
+            let options = ConversionOptions {
+                path_range,
+                ..Default::default()
+            };
+            converter::svg2program(&document, &config, options, machine)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Comment { inner, .. } if inner.starts_with("checkpoint") => {
+                        Some(inner.into_owned())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            checkpoints(None),
+            [
+                "checkpoint path=0 x=1.000 y=9.000 f=300",
+                "checkpoint path=1 x=8.000 y=2.500 f=300"
+            ]
+        );
+        assert_eq!(
+            checkpoints(Some(1..usize::MAX)),
+            ["checkpoint path=1 x=8.000 y=2.500 f=300"]
+        );
+    }
+
+    #[test]
+    fn repeated_use_becomes_subprogram() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="30mm" height="10mm" viewBox="0 0 30 10">
+            <defs><path id="square" d="M 1,1 H 9 V 9 H 1 Z"/></defs>
+            <use href="#square"/>
+            <use href="#square" x="10"/>
+            <use href="#square" x="20"/>
+        </svg>"##;
+        let config = ConversionConfig {
+            subprograms: true,
+            ..Default::default()
+        };
+        let program = get_actual_with_config(svg, &config, false, [None; 2]);
+        let mut gcode = String::new();
+        format_program_fmt(&program, &PostprocessConfig::default(), &mut gcode).unwrap();
+
+        assert_eq!(gcode.matches("M98 P1000").count(), 3);
+        // Offsets for the second and third instances, each reset after the call
+        assert_eq!(gcode.matches("G52 X").count(), 4);
+        assert_eq!(gcode.matches("G52 X0 Y0").count(), 2);
+        assert_eq!(gcode.matches("G1 ").count(), 4);
+        assert!(gcode.trim_end().ends_with("M99"));
+    }
+
+    #[test]
+    fn small_circles_are_drilled() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <circle cx="5" cy="15" r="0.5"/>
+            <circle cx="10" cy="10" r="3"/>
+        </svg>"#;
+        let config = ConversionConfig {
+            drilling: Some(Drilling {
+                max_diameter: 2.,
+                depth: -2.,
+                retract: 1.,
+                cycle: DrillCycle::Peck { peck: 0.5 },
+            }),
+            ..Default::default()
+        };
+        let program = get_actual_with_config(svg, &config, false, [None; 2]);
+        let mut gcode = String::new();
+        format_program_fmt(&program, &PostprocessConfig::default(), &mut gcode).unwrap();
+
+        assert_eq!(gcode.matches("G83 X5 Y5 Z-2 R1 Q0.5 F300").count(), 1);
+        assert_eq!(gcode.matches("G80").count(), 1);
+        // The larger circle is still traced
+        assert!(gcode.matches("G1 ").count() > 4);
+    }
+
+    #[test]
+    fn streaming_matches_formatting_whole_program() {
+        let document = roxmltree::Document::parse(include_str!("../tests/shapes.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            Some(g_code::parse::snippet_parser("M3").unwrap()),
+            Some(g_code::parse::snippet_parser("M5").unwrap()),
+            None,
+            None,
+        );
+        let postprocess = PostprocessConfig {
+            line_numbers: true,
+            checksums: true,
+            homing: Some(Homing::G28),
+            ..Default::default()
+        };
+        for config in [
+            ConversionConfig::default(),
+            // Needs the whole program, so it is written at the end
+            ConversionConfig {
+                metadata_header: true,
+                ..Default::default()
+            },
+        ] {
+            let program = converter::svg2program(
+                &document,
+                &config,
+                ConversionOptions::default(),
+                machine.clone(),
+            )
+            .unwrap()
+            .tokens;
+            let mut expected = String::new();
+            format_program_fmt(&program, &postprocess, &mut expected).unwrap();
+
+            let mut streamed = String::new();
+            svg2program_fmt(
+                &document,
+                &config,
+                ConversionOptions::default(),
+                machine.clone(),
+                &postprocess,
+                &mut streamed,
+            )
+            .unwrap();
+            assert_eq!(streamed, expected);
+
+            let mut streamed = vec![];
+            svg2program_io(
+                &document,
+                &config,
+                ConversionOptions::default(),
+                machine.clone(),
+                &postprocess,
+                &mut streamed,
+            )
+            .unwrap();
+            assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn invalid_attributes_are_errors() {
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        for (svg, expected_attribute) in [
+            (
+                r#"<svg xmlns="http://www.w3.org/2000/svg"><path id="p" d="M0,0 L1,x"/></svg>"#,
+                "d",
+            ),
+            (
+                r#"<svg xmlns="http://www.w3.org/2000/svg"><g transform="rotate(oops)"/></svg>"#,
+                "transform",
+            ),
+            (
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 a b"/>"#,
+                "viewBox",
+            ),
+            (
+                r#"<svg xmlns="http://www.w3.org/2000/svg" preserveAspectRatio="sideways"/>"#,
+                "preserveAspectRatio",
+            ),
+        ] {
+            let document = roxmltree::Document::parse(svg).unwrap();
+            let result = converter::svg2program(
+                &document,
+                &ConversionConfig::default(),
+                ConversionOptions::default(),
+                machine.clone(),
+            );
+            assert!(
+                matches!(
+                    result,
+                    Err(ConversionError::InvalidAttribute { attribute, .. })
+                        if attribute == expected_attribute
+                ),
+                "{svg}"
+            );
+        }
+
+        // Ignored like browsers do
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 0 10"/>"#,
+        )
+        .unwrap();
+        assert!(
+            converter::svg2program(
+                &document,
+                &ConversionConfig::default(),
+                ConversionOptions::default(),
+                machine,
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn cancelled_conversion_stops() {
+        let document = roxmltree::Document::parse(include_str!("../tests/shapes.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let cancellation = Cancellation::new();
+        let options = ConversionOptions {
+            cancellation: Some(cancellation.clone()),
+            ..Default::default()
+        };
+        let config = ConversionConfig {
+            path_order: PathOrder::NearestNeighbor,
+            ..Default::default()
+        };
+        assert!(
+            converter::svg2program(&document, &config, options.clone(), machine.clone()).is_ok()
+        );
+
+        cancellation.cancel();
+        assert!(matches!(
+            converter::svg2program(&document, &config, options, machine),
+            Err(ConversionError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn refinement_leaves_document_order_alone() {
+        // Drawn out of order, so that refinement would go from a to b to c
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path id="a" d="M0,0 L1,0"/>
+                <path id="c" d="M10,0 L11,0"/>
+                <path id="b" d="M5,0 L6,0"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let convert = |path_refinement| {
+            let config = ConversionConfig {
+                path_order: PathOrder::Document,
+                path_refinement,
+                ..Default::default()
+            };
+            converter::svg2program(
+                &document,
+                &config,
+                ConversionOptions::default(),
+                machine.clone(),
+            )
+            .unwrap()
+            .tokens
+        };
+        assert_eq!(
+            convert(Some(RefinementBudget::Iterations(1_000_000))),
+            convert(None)
+        );
+    }
+
+    #[test]
+    fn geometry_converts_like_svg2program() {
+        let document =
+            roxmltree::Document::parse(include_str!("../tests/smooth_curves.svg")).unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality {
+                circular_interpolation: true,
+                ..Default::default()
+            },
+            Kinematics::default(),
+            Some(g_code::parse::snippet_parser("M3").unwrap()),
+            Some(g_code::parse::snippet_parser("M5").unwrap()),
+            None,
+            None,
+        );
+        let config = ConversionConfig {
+            path_order: PathOrder::NearestNeighbor,
+            ..Default::default()
+        };
+        let geometry =
+            svg2geometry(&document, &config, ConversionOptions::default(), &machine).unwrap();
+        for config in [
+            config.clone(),
+            ConversionConfig {
+                feedrate: 1200.,
+                power: Some(500.),
+                metadata_header: true,
+                ..config.clone()
+            },
+        ] {
+            assert_eq!(
+                geometry2program(&geometry, &config, machine.clone()).tokens,
+                converter::svg2program(
+                    &document,
+                    &config,
+                    ConversionOptions::default(),
+                    machine.clone()
+                )
+                .unwrap()
+                .tokens
+            );
+        }
+    }
+
+    #[test]
+    fn custom_order_between_phases() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path id="a" d="M0,0 L1,0"/>
+                <path id="b" d="M5,0 L6,0"/>
+                <path id="c" d="M10,0 L11,0"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let config = ConversionConfig::default();
+        let mut drawing = svg2drawing(&document, &config, ConversionOptions::default()).unwrap();
+        assert_eq!(
+            drawing
+                .paths
+                .iter()
+                .map(|path| path.source.id.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("a"), Some("b"), Some("c")]
+        );
+        drawing.paths.reverse();
+        let geometry = drawing2geometry(&drawing, &config, &machine).unwrap();
+        let program = geometry2program(&geometry, &config, machine);
+
+        assert_eq!(program.statistics.paths, 3);
+        // The first cut starts at the last path
+        let first_x = program
+            .tokens
+            .iter()
+            .find_map(|token| match token {
+                Token::Field(field) if field.letters == "X" => field.value.as_f64(),
+                _ => None,
+            })
+            .unwrap();
+        assert!((first_x - 10.).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn polylines_keep_their_source() {
+        let document = roxmltree::Document::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g inkscape:groupmode="layer" inkscape:label="outline" stroke="red">
+                    <path id="square" d="M0,0 H10 V10 H0 Z"/>
+                    <path d="M0,15 Q5,10 10,15"/>
+                </g>
+            </svg>"##,
+        )
+        .unwrap();
+        let polylines = converter::svg2polylines(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(polylines.len(), 2);
+        assert_eq!(
+            polylines[0].source,
+            PathSource {
+                element: "svg > g > path#square".to_string(),
+                id: Some("square".to_string()),
+                layer: Some("outline".to_string()),
+                stroke: Some("#ff0000".to_string()),
+            }
+        );
+        let close = |a: lyon_geom::Point<Float>, b: (Float, Float)| {
+            (a - lyon_geom::point(b.0, b.1)).length() < 1e-9
+        };
+        let square = [(0., 20.), (10., 20.), (10., 10.), (0., 10.), (0., 20.)];
+        assert_eq!(polylines[0].points.len(), square.len());
+        assert!(
+            polylines[0]
+                .points
+                .iter()
+                .zip(square)
+                .all(|(a, b)| close(*a, b))
+        );
+        assert!(!polylines[0].drill);
+        assert_eq!(polylines[1].source.id, None);
+        assert_eq!(polylines[1].source.layer.as_deref(), Some("outline"));
+        // The curve is flattened into lines, ending where it does
+        assert!(polylines[1].points.len() > 3);
+        assert!(close(*polylines[1].points.last().unwrap(), (10., 5.)));
+    }
+
+    #[test]
+    fn lightburn_layers_take_their_override() {
+        let document = roxmltree::Document::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g inkscape:groupmode="layer" inkscape:label="cut">
+                    <path d="M0,0 H10 V10 H0 Z"/>
+                </g>
+                <g inkscape:groupmode="layer" inkscape:label="engrave">
+                    <path d="M0,15 H10"/>
+                </g>
+            </svg>"##,
+        )
+        .unwrap();
+        let config = ConversionConfig {
+            feedrate: 600.,
+            power: Some(500.),
+            passes: 2,
+            overrides: vec![GroupOverride {
+                group: Group {
+                    split_by: SplitBy::Layer,
+                    name: Some("cut".to_string()),
+                },
+                feedrate: Some(120.),
+                power: Some(1000.),
+                passes: Some(3),
+                cut_z: None,
+            }],
+            ..Default::default()
+        };
+        let project =
+            converter::svg2lightburn(&document, &config, ConversionOptions::default(), 1000.)
+                .unwrap();
+
+        let cut_settings = project.split("<CutSetting").skip(1).collect::<Vec<_>>();
+        assert_eq!(cut_settings.len(), 2);
+        let cut_setting = |name: &str| {
+            *cut_settings
+                .iter()
+                .find(|cut_setting| cut_setting.contains(&format!(r#"<name Value="{name}"/>"#)))
+                .unwrap()
+        };
+        let cut = cut_setting("cut");
+        assert!(cut.contains(r#"<speed Value="2"/>"#));
+        assert!(cut.contains(r#"<maxPower Value="100"/>"#));
+        assert!(cut.contains(r#"<numPasses Value="3"/>"#));
+        let engrave = cut_setting("engrave");
+        assert!(engrave.contains(r#"<speed Value="10"/>"#));
+        assert!(engrave.contains(r#"<maxPower Value="50"/>"#));
+        assert!(engrave.contains(r#"<numPasses Value="2"/>"#));
+        // Passes are left to LightBurn rather than repeated, and the square is closed by its last line
+        assert_eq!(project.matches("<Shape ").count(), 2);
+        assert!(project.contains("<PrimList>L0 1L1 2L2 3L3 0</PrimList>"));
+    }
+
+    #[test]
+    fn bounding_box_covers_cuts_only() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M2,3 H12 V8"/>
+                <circle cx="15" cy="15" r="2"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let config = ConversionConfig {
+            origin: [None, None],
+            ..Default::default()
+        };
+        let bounding_box = svg2turtle(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            BoundingBoxTurtle::new(),
+        )
+        .unwrap()
+        .bounding_box
+        .unwrap();
+
+        for (actual, expected) in [(bounding_box.min, (2., 3.)), (bounding_box.max, (17., 17.))] {
+            assert!((actual - lyon_geom::point(expected.0, expected.1)).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn lengths_match_statistics() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M2,3 H12 V8"/>
+                <circle cx="15" cy="15" r="2"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let config = ConversionConfig::default();
+        let lengths = svg2turtle(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            LengthTurtle::new(config.tolerance as Float),
+        )
+        .unwrap();
+        assert!((lengths.cut_length - (15. + 4. * std::f64::consts::PI)).abs() < 1e-6);
+        assert!((lengths.travel_length - 74f64.sqrt()).abs() < 1e-6);
+
+        let machine = Machine::new(
+            SupportedFunctionality {
+                circular_interpolation: true,
+                ..Default::default()
+            },
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let statistics = svg2program(&document, &config, ConversionOptions::default(), machine)
+            .unwrap()
+            .statistics;
+        assert!((statistics.cut_length - lengths.cut_length).abs() < 1e-6);
+        assert!((statistics.travel_length - lengths.travel_length).abs() < 1e-6);
+    }
+
+    #[test]
+    fn preview_marks_cuts_travel_and_starts() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <path d="M2,3 H12 V8"/>
+                <circle cx="15" cy="15" r="2"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let preview = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions::default(),
+            PreviewTurtle::new(),
+        )
+        .unwrap()
+        .to_string();
+
+        let preview = roxmltree::Document::parse(&preview).unwrap();
+        let count = |name: &str| {
+            preview
+                .descendants()
+                .filter(|node| node.has_tag_name(name))
+                .count()
+        };
+        // One of the paths is the arrow marker
+        assert_eq!(count("path"), 3);
+        assert_eq!(count("line"), 1);
+        assert_eq!(count("circle"), 2);
+    }
+
+    #[test]
+    fn debug_log_names_elements() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g id="shapes">
+                    <path id="corner" d="M2,3 H12 V8"/>
+                </g>
+            </svg>"#,
+        )
+        .unwrap();
+        let log = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions::default(),
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log;
+
+        assert_eq!(log.first().unwrap().call, TurtleCall::Begin);
+        assert_eq!(log.last().unwrap().call, TurtleCall::End);
+        let lines = log
+            .iter()
+            .filter(|entry| matches!(entry.call, TurtleCall::LineTo(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines
+                .iter()
+                .all(|entry| entry.element.as_deref() == Some("svg > g#shapes > path#corner"))
+        );
+    }
+
+    #[test]
+    fn parse_length_matches_converter_units() {
+        assert_eq!(parse_length("96").unwrap(), UserUnits(96.));
+        assert_eq!(parse_length("1in").unwrap(), UserUnits(96.));
+        assert!((parse_length("25.4mm").unwrap().0 - 96.).abs() < 1e-9);
+        assert!(
+            (parse_length("10mm")
+                .unwrap()
+                .to_millimeters(CSS_DEFAULT_DPI)
+                - 10.)
+                .abs()
+                < 1e-9
+        );
+        assert!(matches!(parse_length("50%"), Err(LengthError::Percentage)));
+        assert!(matches!(parse_length("ten"), Err(LengthError::Parse(_))));
+        assert_eq!(
+            length_to_user_units(
+                svgtypes::Length::new(50., svgtypes::LengthUnit::Percent),
+                Some([200., 100.]),
+                DimensionHint::Horizontal
+            ),
+            UserUnits(100.)
+        );
+    }
+
+    #[test]
+    fn semantic_events_mark_layers_passes_and_settings() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g inkscape:groupmode="layer" inkscape:label="engrave">
+                    <path d="M2,3 H12"/>
+                </g>
+                <g inkscape:groupmode="layer" inkscape:label="cut">
+                    <path d="M2,8 H12"/>
+                </g>
+            </svg>"#,
+        )
+        .unwrap();
+        let layer = |name: &str| Group {
+            split_by: SplitBy::Layer,
+            name: Some(name.to_string()),
+        };
+        let config = ConversionConfig {
+            feedrate: 300.,
+            origin: [None; 2],
+            overrides: vec![
+                GroupOverride {
+                    group: layer("engrave"),
+                    feedrate: Some(3000.),
+                    power: Some(200.),
+                    passes: None,
+                    cut_z: None,
+                },
+                GroupOverride {
+                    group: layer("cut"),
+                    feedrate: None,
+                    power: None,
+                    passes: Some(2),
+                    cut_z: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let calls = svg2turtle(
+            &document,
+            &config,
+            ConversionOptions::default(),
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .map(|entry| entry.call)
+        .filter(|call| {
+            matches!(
+                call,
+                TurtleCall::ChangeFeedrate(_)
+                    | TurtleCall::ChangePower(_)
+                    | TurtleCall::BeginLayer(_)
+                    | TurtleCall::EndLayer
+                    | TurtleCall::BeginPass(..)
+                    | TurtleCall::EndPass
+            )
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            calls,
+            [
+                TurtleCall::ChangeFeedrate(300.),
+                TurtleCall::ChangePower(None),
+                TurtleCall::BeginLayer(Some("engrave".to_string())),
+                TurtleCall::ChangeFeedrate(3000.),
+                TurtleCall::ChangePower(Some(200.)),
+                TurtleCall::BeginPass(0, 1),
+                TurtleCall::EndPass,
+                TurtleCall::EndLayer,
+                TurtleCall::BeginLayer(Some("cut".to_string())),
+                TurtleCall::ChangeFeedrate(300.),
+                TurtleCall::ChangePower(None),
+                TurtleCall::BeginPass(0, 2),
+                TurtleCall::EndPass,
+                TurtleCall::BeginPass(1, 2),
+                TurtleCall::EndPass,
+                TurtleCall::EndLayer,
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks_skip_and_override_elements() {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <g id="hidden"><path d="M0,0 L10,0"/></g>
+                <g id="slow" transform="translate(5)"><path d="M0,10 L10,10"/></g>
+            </svg>"#,
+        )
+        .unwrap();
+        let slow = GroupOverride {
+            group: Group {
+                split_by: SplitBy::Layer,
+                name: None,
+            },
+            feedrate: Some(100.),
+            power: None,
+            passes: None,
+            cut_z: None,
+        };
+        let exits = Arc::new(AtomicUsize::new(0));
+        let hooks = Hooks::new()
+            .on_enter({
+                let slow = slow.clone();
+                move |node, transform| match node.attribute("id") {
+                    Some("hidden") => HookAction::Skip,
+                    Some("slow") => {
+                        // 5 user units at 1mm each, in pixels
+                        assert!((transform.m31 - 5. * 96. / 25.4).abs() < 1e-3);
+                        HookAction::Override(slow.clone())
+                    }
+                    _ => HookAction::Continue,
+                }
+            })
+            .on_exit({
+                let exits = exits.clone();
+                move |_, _| {
+                    exits.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        let log = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions {
+                hooks,
+                ..Default::default()
+            },
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log;
+
+        let calls = log.iter().map(|entry| &entry.call).collect::<Vec<_>>();
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| matches!(call, TurtleCall::MoveTo(_)))
+                .count(),
+            1
+        );
+        assert!(calls.contains(&&TurtleCall::OverrideSettings(Some(slow))));
+        // Every element is exited, including the skipped one: svg, both groups and the path that is drawn. This
+        // happens twice, since the drawing is measured first to place it at the origin.
+        assert_eq!(exits.load(Ordering::Relaxed), 2 * 4);
+    }
+
+    #[test]
+    fn hooks_select_and_skip_layers() {
+        let document = roxmltree::Document::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" xmlns:xlink="http://www.w3.org/1999/xlink">
+                <defs><path id="mark" d="M0,0 L1,0"/></defs>
+                <g inkscape:groupmode="layer" inkscape:label="cut"><path id="a" d="M0,0 L1,1"/><use id="b" xlink:href="#mark"/></g>
+                <g id="score"><path id="c" d="M0,0 L1,1"/></g>
+                <g inkscape:groupmode="layer" inkscape:label="engrave"><path id="d" d="M0,0 L1,1"/></g>
+                <path id="e" d="M0,0 L1,1"/>
+            </svg>"##,
+        )
+        .unwrap();
+        let drawn = |hooks| {
+            svg2turtle(
+                &document,
+                &ConversionConfig::default(),
+                ConversionOptions {
+                    hooks,
+                    ..Default::default()
+                },
+                DebugTurtle::new(),
+            )
+            .unwrap()
+            .log
+            .into_iter()
+            .filter(|entry| matches!(entry.call, TurtleCall::MoveTo(_)))
+            .filter_map(|entry| Some(entry.element?.rsplit_once('#')?.1.to_string()))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            drawn(Hooks::new().only_layers(vec!["cut".to_string(), "score".to_string()])),
+            ["a", "mark", "c"]
+        );
+        assert_eq!(
+            drawn(Hooks::new().skip_layers(vec!["cut".to_string(), "score".to_string()])),
+            ["d", "e"]
+        );
+    }
+
+    #[test]
+    fn hooks_select_ids() {
+        let document = roxmltree::Document::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+                <defs><path id="mark" d="M0,0 L1,0"/></defs>
+                <g id="part"><path id="a" d="M0,0 L1,1"/><use id="b" xlink:href="#mark"/></g>
+                <g><path id="c" d="M0,0 L1,1"/><path id="d" d="M0,0 L1,1"/></g>
+                <path id="e" d="M0,0 L1,1"/>
+            </svg>"##,
+        )
+        .unwrap();
+        let drawn = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions {
+                hooks: Hooks::new().only_ids(vec!["part".to_string(), "d".to_string()]),
+                ..Default::default()
+            },
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .filter(|entry| matches!(entry.call, TurtleCall::MoveTo(_)))
+        .filter_map(|entry| Some(entry.element?.rsplit_once('#')?.1.to_string()))
+        .collect::<Vec<_>>();
+
+        assert_eq!(drawn, ["a", "mark", "d"]);
+    }
+
+    #[test]
+    fn hooks_skip_ids() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">
+                <g id="marks"><path id="a" d="M0,0 L1,1"/></g>
+                <path id="b" d="M0,0 L1,1"/>
+                <path id="c" d="M0,0 L1,1"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let drawn = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions {
+                hooks: Hooks::new().skip_ids(vec!["marks".to_string(), "c".to_string()]),
+                ..Default::default()
+            },
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log
+        .into_iter()
+        .filter(|entry| matches!(entry.call, TurtleCall::MoveTo(_)))
+        .filter_map(|entry| Some(entry.element?.rsplit_once('#')?.1.to_string()))
+        .collect::<Vec<_>>();
+
+        assert_eq!(drawn, ["b"]);
+    }
+
+    #[test]
+    fn element_handlers_draw_custom_tags() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:x="urn:example" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <x:triangle id="custom" size="10" transform="translate(5,5)"/>
+                <triangle size="10"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let hooks = Hooks::new().on_element(Some("urn:example"), "triangle", |node, terrarium| {
+            let size = node.attribute("size").unwrap().parse::<Float>().unwrap();
+            terrarium.move_to(true, 0., 0.);
+            terrarium.line(true, size, 0.);
+            terrarium.line(true, 0., size);
+            terrarium.close();
+        });
+        let log = svg2turtle(
+            &document,
+            &ConversionConfig {
+                origin: [None, None],
+                ..Default::default()
+            },
+            ConversionOptions {
+                hooks,
+                ..Default::default()
+            },
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log;
+
+        // Only the tag in the namespace is drawn, as a shape of its own
+        let drawn = log
+            .iter()
+            .filter(|entry| matches!(entry.call, TurtleCall::MoveTo(_) | TurtleCall::LineTo(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(drawn.len(), 4);
+        assert!(
+            drawn
+                .iter()
+                .all(|entry| entry.element.as_deref() == Some("svg > triangle#custom"))
+        );
+        let TurtleCall::MoveTo(start) = drawn[0].call else {
+            panic!("path starts with a move");
+        };
+        assert!((start - lyon_geom::point(5., 15.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn element_handlers_draw_nothing_in_a_degenerate_transform() {
+        let document = roxmltree::Document::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:x="urn:example" width="20mm" height="20mm" viewBox="0 0 20 20">
+                <x:mark/>
+            </svg>"#,
+        )
+        .unwrap();
+        let hooks = Hooks::new().on_element(Some("urn:example"), "mark", |_, terrarium| {
+            terrarium.push_transform(euclid::default::Transform2D::scale(0., 0.));
+            assert!(terrarium.is_degenerate());
+            terrarium.move_to(false, 1., 1.);
+            terrarium.line(false, 5., 0.);
+            terrarium.elliptical(
+                false,
+                lyon_geom::vector(1., 1.),
+                euclid::Angle::zero(),
+                lyon_geom::ArcFlags::default(),
+                lyon_geom::point(2., 0.),
+            );
+            terrarium.close();
+            terrarium.pop_transform();
+        });
+        let log = svg2turtle(
+            &document,
+            &ConversionConfig::default(),
+            ConversionOptions {
+                hooks,
+                ..Default::default()
+            },
+            DebugTurtle::new(),
+        )
+        .unwrap()
+        .log;
+
+        assert!(!log.iter().any(|entry| matches!(
+            entry.call,
+            TurtleCall::MoveTo(_) | TurtleCall::LineTo(_) | TurtleCall::Arc(_)
+        )));
+    }
+
+    #[test]
+    fn progress_markers_increase_to_completion() {
+        let config = ConversionConfig {
+            progress_markers: ProgressMarkers::M73,
+            progress_interval: 25,
+            ..Default::default()
+        };
+        let program = get_actual_with_config(
+            include_str!("../tests/square.svg"),
+            &config,
+            false,
+            [None; 2],
+        );
+        let percents = program
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [Token::Field(m), Token::Field(p)] if m.letters == "M" && p.letters == "P" => {
+                    assert_eq!(m.value.as_f64(), Some(73.));
+                    p.value.as_f64()
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(percents.first(), Some(&0.));
+        assert_eq!(percents.last(), Some(&100.));
+        assert!(
+            percents[..percents.len() - 1]
+                .windows(2)
+                .all(|pair| pair[1] - pair[0] >= 25.)
+        );
+    }
+
+    #[test]
+    fn convert_from_many_threads() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<Settings>();
+        send_sync::<ConversionOptions>();
+        send_sync::<Machine>();
+        send_sync::<Program>();
+        send_sync::<ConvertError>();
+
+        let svg = include_str!("../tests/square.svg");
+        let settings = Settings::default();
+        let (expected, _) = convert(svg, &settings, ConversionOptions::default()).unwrap();
+        std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| scope.spawn(|| convert(svg, &settings, ConversionOptions::default())))
+                .collect::<Vec<_>>();
+            for handle in handles {
+                let (gcode, statistics) = handle.join().unwrap().unwrap();
+                assert_eq!(gcode, expected);
+                assert_eq!(statistics.paths, 2);
+            }
+        });
+
+        assert!(matches!(
+            convert("<svg", &settings, ConversionOptions::default()),
+            Err(ConvertError::Parse(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn phases_are_traced() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{
+            Event, Metadata, Subscriber,
+            span::{Attributes, Id, Record},
+        };
+
+        /// Keeps the names of spans in the order they are created
+        struct Phases(Arc<Mutex<Vec<&'static str>>>);
+
+        impl Subscriber for Phases {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name());
+                Id::from_u64(names.len() as u64)
+            }
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let names = Arc::new(Mutex::new(vec![]));
+        let settings = Settings {
+            conversion: ConversionConfig {
+                path_order: PathOrder::NearestNeighbor,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        tracing::subscriber::with_default(Phases(names.clone()), || {
+            convert(
+                include_str!("../tests/square.svg"),
+                &settings,
+                ConversionOptions::default(),
+            )
+            .unwrap();
+        });
+        let mut phases = vec![];
+        for name in names.lock().unwrap().iter() {
+            if !phases.contains(name) {
+                phases.push(*name);
+            }
+        }
+        let mut expected = vec!["parse", "convert", "measure", "visit"];
+        if cfg!(feature = "rayon") {
+            expected.push("flatten");
+        }
+        expected.extend(["optimize", "emit", "finish", "format"]);
+        assert_eq!(phases, expected);
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn deserialize_v1_config_succeeds() {
@@ -1,8 +1,11 @@
+use std::borrow::Cow;
+
 use g_code::{
     command,
-    emit::Token,
+    emit::{Field, Token, Value},
     parse::{ast::Snippet, snippet_parser},
 };
+use log::warn;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -25,12 +28,15 @@ pub enum Distance {
 #[derive(Debug, Clone)]
 pub struct Machine<'input> {
     supported_functionality: SupportedFunctionality,
+    kinematics: Kinematics,
+    pen_servo: Option<PenServo>,
     tool_state: Option<Tool>,
     distance_mode: Option<Distance>,
     tool_on_sequence: Snippet<'input>,
     tool_off_sequence: Snippet<'input>,
     program_begin_sequence: Snippet<'input>,
     program_end_sequence: Snippet<'input>,
+    program_end: Option<Snippet<'input>>,
     /// Empty snippet used to provide the same iterator type when a sequence must be empty
     empty_snippet: Snippet<'input>,
 }
@@ -39,10 +45,44 @@ pub struct Machine<'input> {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MachineConfig {
     pub supported_functionality: SupportedFunctionality,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kinematics: Kinematics,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pen_servo: Option<PenServo>,
+    /// Width and height in millimeters of the area the machine can reach from its origin, for checking programs
+    /// against
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub work_area: Option<[f64; 2]>,
     pub tool_on_sequence: Option<String>,
     pub tool_off_sequence: Option<String>,
     pub begin_sequence: Option<String>,
     pub end_sequence: Option<String>,
+    /// Terminates the program, after [Self::end_sequence]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub program_end: Option<ProgramEnd>,
+}
+
+/// Word that terminates a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProgramEnd {
+    /// `M2`
+    M2,
+    /// `M30`, which also rewinds the program on many controllers
+    M30,
+    /// Any other G-Code, i.e. a shutdown macro
+    Custom(String),
+}
+
+impl ProgramEnd {
+    /// G-Code for this terminator, to be parsed with [g_code::parse::snippet_parser]
+    pub fn gcode(&self) -> &str {
+        match self {
+            Self::M2 => "M2",
+            Self::M30 => "M30",
+            Self::Custom(gcode) => gcode,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -52,11 +92,156 @@ pub struct SupportedFunctionality {
     ///
     /// Most modern machines support this. Old ones like early MakerBot 3D printers do not.
     pub circular_interpolation: bool,
+    /// How the end of a G2/G3 arc is related to its center
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub arc_format: ArcFormat,
+    /// Largest deviation in millimeters between an arc and the curve it approximates, i.e. GRBL's `$12`
+    ///
+    /// Arcs shallower than this are cut as straight lines instead, since firmware may reject them with a radius error.
+    /// [None] fits arcs to [crate::ConversionConfig::tolerance] and never substitutes lines.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub arc_tolerance: Option<f64>,
+}
+
+/// Encoding of G2/G3 arcs, since some controllers only accept one of them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ArcFormat {
+    /// `R` word with the radius, where arcs over 180° are split in two because the center would be ambiguous
+    #[default]
+    Radius,
+    /// `I` and `J` words with the offset from the start of the arc to its center
+    Center,
+}
+
+/// Motion capabilities of the machine, used to estimate how long a job takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Kinematics {
+    /// Acceleration in millimeters / second², or [None] to assume instantaneous speed changes
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub acceleration: Option<f64>,
+    /// Junction deviation in millimeters, which limits cornering speed (GRBL's `$11`)
+    #[cfg_attr(feature = "serde", serde(default = "default_junction_deviation"))]
+    pub junction_deviation: f64,
+    /// Feedrate of rapid (G0) moves in millimeters / minute, or [None] to assume the cutting feedrate
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rapid_feedrate: Option<f64>,
+    /// Highest cutting feedrate the machine can achieve in millimeters / minute
+    ///
+    /// Requested feedrates above this are clamped.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_feedrate: Option<f64>,
+}
+
+const fn default_junction_deviation() -> f64 {
+    0.01
+}
+
+impl Default for Kinematics {
+    fn default() -> Self {
+        Self {
+            acceleration: None,
+            junction_deviation: default_junction_deviation(),
+            rapid_feedrate: None,
+            max_feedrate: None,
+        }
+    }
+}
+
+impl Kinematics {
+    /// Limit a cutting feedrate to [Self::max_feedrate], warning if it had to be reduced
+    pub fn clamp_feedrate(&self, feedrate: f64) -> f64 {
+        match self.max_feedrate {
+            Some(max_feedrate) if feedrate > max_feedrate => {
+                warn!(
+                    "Feedrate {feedrate} mm/min exceeds the machine maximum, clamping to {max_feedrate} mm/min"
+                );
+                max_feedrate
+            }
+            _ => feedrate,
+        }
+    }
+}
+
+/// Pen plotter that raises and lowers its pen with a hobby servo, positioned by `M3 S<angle>`
+///
+/// The servo needs time to move, so each command is followed by a dwell.
+/// With a [Self::rate], the servo is moved a few degrees at a time instead, dwelling after each step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PenServo {
+    /// Servo angle with the pen raised
+    pub up: f64,
+    /// Servo angle with the pen lowered onto the paper
+    pub down: f64,
+    /// Time to wait for the servo after each command in milliseconds
+    pub dwell: f64,
+    /// Speed of the servo in degrees / second, to lower the pen gently instead of letting it bounce on the paper
+    ///
+    /// [None] moves the servo as fast as it goes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rate: Option<f64>,
+}
+
+/// Most degrees the servo is moved at once when it is limited to [PenServo::rate]
+const PEN_SERVO_STEP: f64 = 5.;
+
+impl PenServo {
+    /// Seconds the pen takes to go up or down, which the machine spends standing still
+    pub fn transition_time(&self) -> f64 {
+        let travel = (self.up - self.down).abs();
+        let sweep = self
+            .rate
+            .filter(|rate| *rate > 0.)
+            .map_or(0., |rate| travel / rate);
+        sweep + self.dwell / 1000.
+    }
+
+    /// Move the servo from the angle `from` to `to`
+    fn tokens<'input>(&self, from: f64, to: f64) -> Vec<Token<'input>> {
+        let steps = match self.rate {
+            Some(rate) if rate > 0. => ((to - from).abs() / PEN_SERVO_STEP).ceil().max(1.) as usize,
+            _ => 1,
+        };
+        let mut tokens = vec![];
+        for step in 1..=steps {
+            tokens.extend([
+                Token::Field(Field {
+                    letters: Cow::Borrowed("M"),
+                    value: Value::Integer(3),
+                }),
+                Token::Field(Field {
+                    letters: Cow::Borrowed("S"),
+                    value: Value::Float(from + (to - from) * step as f64 / steps as f64),
+                }),
+            ]);
+            if let Some(rate) = self.rate.filter(|rate| *rate > 0.) {
+                tokens.append(
+                    &mut command!(Dwell {
+                        P: (to - from).abs() / steps as f64 / rate
+                    })
+                    .into_token_vec(),
+                );
+            }
+        }
+        if self.dwell > 0. {
+            // P is in seconds for GRBL and LinuxCNC
+            tokens.append(
+                &mut command!(Dwell {
+                    P: self.dwell / 1000.
+                })
+                .into_token_vec(),
+            );
+        }
+        tokens
+    }
 }
 
 impl<'input> Machine<'input> {
     pub fn new(
         supported_functionality: SupportedFunctionality,
+        kinematics: Kinematics,
         tool_on_sequence: Option<Snippet<'input>>,
         tool_off_sequence: Option<Snippet<'input>>,
         program_begin_sequence: Option<Snippet<'input>>,
@@ -65,6 +250,9 @@ impl<'input> Machine<'input> {
         let empty_snippet = snippet_parser("").expect("empty string is a valid snippet");
         Self {
             supported_functionality,
+            kinematics,
+            pen_servo: None,
+            program_end: None,
             tool_on_sequence: tool_on_sequence.unwrap_or_else(|| empty_snippet.clone()),
             tool_off_sequence: tool_off_sequence.unwrap_or_else(|| empty_snippet.clone()),
             program_begin_sequence: program_begin_sequence.unwrap_or_else(|| empty_snippet.clone()),
@@ -79,13 +267,37 @@ impl<'input> Machine<'input> {
         &self.supported_functionality
     }
 
+    /// Raise and lower a pen with a servo when the tool is turned off and on, after any user-defined sequence
+    pub fn with_pen_servo(mut self, pen_servo: Option<PenServo>) -> Self {
+        self.pen_servo = pen_servo;
+        self
+    }
+
+    /// Terminate the program with a [ProgramEnd] once the user-defined teardown is done
+    pub fn with_program_end(mut self, program_end: Option<Snippet<'input>>) -> Self {
+        self.program_end = program_end;
+        self
+    }
+
+    pub fn kinematics(&self) -> &Kinematics {
+        &self.kinematics
+    }
+
+    pub fn pen_servo(&self) -> Option<&PenServo> {
+        self.pen_servo.as_ref()
+    }
+
     /// Output gcode to turn the tool on.
     pub fn tool_on(&mut self) -> impl Iterator<Item = Token<'input>> + '_ {
         if self.tool_state == Some(Tool::Off) || self.tool_state.is_none() {
             self.tool_state = Some(Tool::On);
-            self.tool_on_sequence.iter_emit_tokens()
+            let pen = self
+                .pen_servo
+                .map(|pen| pen.tokens(pen.up, pen.down))
+                .unwrap_or_default();
+            self.tool_on_sequence.iter_emit_tokens().chain(pen)
         } else {
-            self.empty_snippet.iter_emit_tokens()
+            self.empty_snippet.iter_emit_tokens().chain(vec![])
         }
     }
 
@@ -93,9 +305,13 @@ impl<'input> Machine<'input> {
     pub fn tool_off(&mut self) -> impl Iterator<Item = Token<'input>> + '_ {
         if self.tool_state == Some(Tool::On) || self.tool_state.is_none() {
             self.tool_state = Some(Tool::Off);
-            self.tool_off_sequence.iter_emit_tokens()
+            let pen = self
+                .pen_servo
+                .map(|pen| pen.tokens(pen.down, pen.up))
+                .unwrap_or_default();
+            self.tool_off_sequence.iter_emit_tokens().chain(pen)
         } else {
-            self.empty_snippet.iter_emit_tokens()
+            self.empty_snippet.iter_emit_tokens().chain(vec![])
         }
     }
 
@@ -106,7 +322,9 @@ impl<'input> Machine<'input> {
 
     /// Output user-defined teardown gcode
     pub fn program_end(&self) -> impl Iterator<Item = Token<'input>> + '_ {
-        self.program_end_sequence.iter_emit_tokens()
+        self.program_end_sequence
+            .iter_emit_tokens()
+            .chain(self.program_end.iter().flat_map(Snippet::iter_emit_tokens))
     }
 
     /// Output absolute distance field if mode was relative or unknown.
@@ -129,3 +347,101 @@ impl<'input> Machine<'input> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn feedrate_is_clamped_to_maximum() {
+        let kinematics = Kinematics {
+            max_feedrate: Some(1000.),
+            ..Default::default()
+        };
+        assert_eq!(kinematics.clamp_feedrate(300.), 300.);
+        assert_eq!(kinematics.clamp_feedrate(5000.), 1000.);
+        assert_eq!(Kinematics::default().clamp_feedrate(5000.), 5000.);
+    }
+
+    #[test]
+    fn program_end_follows_end_sequence() {
+        let machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            Some(snippet_parser("M5").unwrap()),
+        )
+        .with_program_end(Some(snippet_parser(ProgramEnd::M30.gcode()).unwrap()));
+        let values = machine
+            .program_end()
+            .filter_map(|token| match token {
+                Token::Field(field) => field.value.as_f64(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(values, vec![5., 30.]);
+    }
+
+    #[test]
+    fn pen_servo_dwells_after_moving() {
+        let mut machine = Machine::new(
+            SupportedFunctionality::default(),
+            Kinematics::default(),
+            None,
+            None,
+            None,
+            None,
+        )
+        .with_pen_servo(Some(PenServo {
+            up: 90.,
+            down: 30.,
+            dwell: 250.,
+            rate: None,
+        }));
+        let values = |tokens: Vec<Token>| {
+            tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Field(field) => {
+                        Some((field.letters.into_owned(), field.value.as_f64()?))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let pen = |letters: &str, value: f64| (letters.to_string(), value);
+
+        assert_eq!(
+            values(machine.tool_on().collect()),
+            vec![pen("M", 3.), pen("S", 30.), pen("G", 4.), pen("P", 0.25)]
+        );
+        assert!(machine.tool_on().next().is_none());
+        assert_eq!(
+            values(machine.tool_off().collect()),
+            vec![pen("M", 3.), pen("S", 90.), pen("G", 4.), pen("P", 0.25)]
+        );
+    }
+
+    #[test]
+    fn pen_servo_rate_moves_in_steps() {
+        let pen_servo = PenServo {
+            up: 60.,
+            down: 30.,
+            dwell: 0.,
+            rate: Some(150.),
+        };
+        let angles = pen_servo
+            .tokens(pen_servo.up, pen_servo.down)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Field(field) if field.letters == "S" => field.value.as_f64(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(angles, vec![55., 50., 45., 40., 35., 30.]);
+        assert!((pen_servo.transition_time() - 0.2).abs() < 1e-9);
+    }
+}
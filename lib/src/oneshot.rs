@@ -0,0 +1,127 @@
+use std::{error::Error, fmt};
+
+use g_code::parse::{ast::Snippet, snippet_parser};
+use roxmltree::{Document, ParsingOptions};
+
+use crate::{
+    ConversionError, ConversionOptions, ConversionStatistics, Machine, MachineConfig, Settings,
+    SettingsError, format_program_fmt, svg2program,
+};
+
+/// Reasons [convert] can fail
+#[derive(Debug)]
+pub enum ConvertError {
+    /// The settings can't be upgraded automatically, see [Settings::try_upgrade]
+    Upgrade(&'static str),
+    Settings(SettingsError),
+    /// A G-Code sequence of the [MachineConfig] could not be parsed
+    InvalidGCode {
+        setting: &'static str,
+    },
+    /// The SVG is not well-formed XML
+    Parse(roxmltree::Error),
+    Conversion(ConversionError),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upgrade(reason) => f.write_str(reason),
+            Self::Settings(error) => error.fmt(f),
+            Self::InvalidGCode { setting } => write!(f, "{setting} is not valid G-Code"),
+            Self::Parse(error) => error.fmt(f),
+            Self::Conversion(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ConvertError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Upgrade(_) | Self::InvalidGCode { .. } => None,
+            Self::Settings(error) => Some(error),
+            Self::Parse(error) => Some(error),
+            Self::Conversion(error) => Some(error),
+        }
+    }
+}
+
+impl From<SettingsError> for ConvertError {
+    fn from(error: SettingsError) -> Self {
+        Self::Settings(error)
+    }
+}
+
+impl From<roxmltree::Error> for ConvertError {
+    fn from(error: roxmltree::Error) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl From<ConversionError> for ConvertError {
+    fn from(error: ConversionError) -> Self {
+        Self::Conversion(error)
+    }
+}
+
+/// Convert an SVG to formatted G-Code in one go, giving the G-Code and the statistics of the program
+///
+/// The [Settings] are upgraded and validated first. Nothing is kept between calls, so a service can convert many
+/// jobs at once from as many threads as it likes, sharing the same settings.
+pub fn convert(
+    svg: &str,
+    settings: &Settings,
+    options: ConversionOptions,
+) -> Result<(String, ConversionStatistics), ConvertError> {
+    let mut settings = settings.clone();
+    settings.try_upgrade().map_err(ConvertError::Upgrade)?;
+    settings.conversion.validate()?;
+    settings.machine.validate()?;
+    options.validate()?;
+    let machine = machine(&settings.machine)?;
+    let document = {
+        phase!("parse", bytes = svg.len());
+        Document::parse_with_options(
+            svg,
+            ParsingOptions {
+                allow_dtd: true,
+                ..Default::default()
+            },
+        )?
+    };
+
+    let program = svg2program(&document, &settings.conversion, options, machine)?;
+    let mut gcode = String::new();
+    format_program_fmt(&program.tokens, &settings.postprocess, &mut gcode)
+        .map_err(ConversionError::Fmt)?;
+    Ok((gcode, program.statistics))
+}
+
+/// Parse the G-Code sequences of a [MachineConfig]
+fn machine(config: &MachineConfig) -> Result<Machine<'_>, ConvertError> {
+    fn parse<'input>(
+        setting: &'static str,
+        gcode: Option<&'input str>,
+    ) -> Result<Option<Snippet<'input>>, ConvertError> {
+        gcode
+            .map(snippet_parser)
+            .transpose()
+            .map_err(|_| ConvertError::InvalidGCode { setting })
+    }
+    Ok(Machine::new(
+        config.supported_functionality.clone(),
+        config.kinematics,
+        parse("tool_on_sequence", config.tool_on_sequence.as_deref())?,
+        parse("tool_off_sequence", config.tool_off_sequence.as_deref())?,
+        parse("begin_sequence", config.begin_sequence.as_deref())?,
+        parse("end_sequence", config.end_sequence.as_deref())?,
+    )
+    .with_pen_servo(config.pen_servo)
+    .with_program_end(parse(
+        "program_end",
+        config
+            .program_end
+            .as_ref()
+            .map(|program_end| program_end.gcode()),
+    )?))
+}
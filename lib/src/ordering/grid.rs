@@ -0,0 +1,160 @@
+use lyon_geom::{Box2D, Point, vector};
+
+use crate::Float;
+
+/// Uniform grid over points, for finding those near a position without checking every one of them
+///
+/// Cells are sized so that evenly spread points average about one per cell.
+#[derive(Debug)]
+pub(crate) struct Grid {
+    min: Point<Float>,
+    cell_size: Float,
+    columns: usize,
+    rows: usize,
+    /// Indices of the points in each cell, row by row
+    cells: Vec<Vec<usize>>,
+    points: Vec<Point<Float>>,
+}
+
+impl Grid {
+    pub fn new(points: Vec<Point<Float>>) -> Self {
+        let bounds = Box2D::from_points(&points);
+        let size = bounds.size();
+        let cell_size = (size.width.max(size.height) / (points.len().max(1) as Float).sqrt())
+            .max(Float::EPSILON);
+        let columns = (size.width / cell_size) as usize + 1;
+        let rows = (size.height / cell_size) as usize + 1;
+        let mut grid = Self {
+            min: bounds.min,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![vec![]; columns * rows],
+            points,
+        };
+        for index in 0..grid.points.len() {
+            let cell = grid.cell(grid.points[index]);
+            grid.cells[cell].push(index);
+        }
+        grid
+    }
+
+    /// Stop returning a point from searches
+    pub fn remove(&mut self, index: usize) {
+        let cell = self.cell(self.points[index]);
+        self.cells[cell].retain(|other| *other != index);
+    }
+
+    /// Closest point to `at`, preferring the lowest index among equally close ones
+    pub fn nearest(&self, at: Point<Float>) -> Option<usize> {
+        self.k_nearest(at, 1).first().copied()
+    }
+
+    /// Up to `k` of the points closest to `at`, closest first
+    pub fn k_nearest(&self, at: Point<Float>, k: usize) -> Vec<usize> {
+        if k == 0 {
+            return vec![];
+        }
+        let [column, row] = self.coordinates(at);
+        // How far outside of the grid `at` is, if at all
+        let outside = {
+            let cell_min = self.min + vector(column as Float, row as Float) * self.cell_size;
+            let cell = Box2D::new(cell_min, cell_min + vector(1., 1.) * self.cell_size);
+            (at.clamp(cell.min, cell.max) - at).length()
+        };
+        let (column, row) = (column as isize, row as isize);
+        let mut found: Vec<(Float, usize)> = vec![];
+        for ring in 0..self.columns.max(self.rows) {
+            for (ring_column, ring_row) in ring_cells(column, row, ring) {
+                if ring_column < 0
+                    || ring_row < 0
+                    || ring_column as usize >= self.columns
+                    || ring_row as usize >= self.rows
+                {
+                    continue;
+                }
+                let cell = ring_row as usize * self.columns + ring_column as usize;
+                found.extend(
+                    self.cells[cell]
+                        .iter()
+                        .map(|&index| ((self.points[index] - at).length(), index)),
+                );
+            }
+            found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            // Points in rings further out are at least this far away
+            let reach = ring as Float * self.cell_size - outside;
+            if found.len() >= k && found[k - 1].0 <= reach {
+                break;
+            }
+        }
+        found.truncate(k);
+        found.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// Column and row of the cell closest to `at`
+    fn coordinates(&self, at: Point<Float>) -> [usize; 2] {
+        let offset = (at - self.min) / self.cell_size;
+        [
+            (offset.x.max(0.) as usize).min(self.columns - 1),
+            (offset.y.max(0.) as usize).min(self.rows - 1),
+        ]
+    }
+
+    fn cell(&self, at: Point<Float>) -> usize {
+        let [column, row] = self.coordinates(at);
+        row * self.columns + column
+    }
+}
+
+/// Cells at a Chebyshev distance of `ring` from a cell
+fn ring_cells(column: isize, row: isize, ring: usize) -> Vec<(isize, isize)> {
+    let ring = ring as isize;
+    if ring == 0 {
+        return vec![(column, row)];
+    }
+    let mut cells = vec![];
+    for offset in -ring..=ring {
+        cells.push((column + offset, row - ring));
+        cells.push((column + offset, row + ring));
+    }
+    for offset in 1 - ring..ring {
+        cells.push((column - ring, row + offset));
+        cells.push((column + ring, row + offset));
+    }
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use lyon_geom::point;
+
+    use super::*;
+
+    #[test]
+    fn k_nearest_matches_exhaustive_search() {
+        // Scattered points from a small linear congruential generator
+        let mut state = 1u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (state >> 33) as Float / (1u64 << 31) as Float * 100.
+        };
+        let points = (0..200).map(|_| point(next(), next())).collect::<Vec<_>>();
+        let mut grid = Grid::new(points.clone());
+        for index in (0..200).step_by(3) {
+            grid.remove(index);
+        }
+
+        for at in [point(50., 50.), point(-30., 120.), point(3., 97.)] {
+            let mut expected = (0..200)
+                .filter(|index| index % 3 != 0)
+                .map(|index| ((points[index] - at).length(), index))
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            let expected = expected[..5]
+                .iter()
+                .map(|(_, index)| *index)
+                .collect::<Vec<_>>();
+            assert_eq!(grid.k_nearest(at, 5), expected);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use lyon_geom::Point;
+
+use self::grid::Grid;
+use crate::{Cancellation, Float, RefinementBudget};
+
+mod grid;
+
+/// Number of nearby paths that [refine] considers moving next to each path
+const NEIGHBORS: usize = 8;
+
+/// Smallest reduction in travel, in millimeters, that counts as an improvement
+const IMPROVEMENT_THRESHOLD: Float = 1e-9;
+
+/// Amount of work between checks of the clock for [RefinementBudget::Milliseconds]
+#[cfg(not(target_arch = "wasm32"))]
+const CLOCK_INTERVAL: usize = 1024;
+
+/// Order paths so that each one starts as close as possible to where the previous one ended
+///
+/// `paths` holds the start and end of each path, and the tool begins at `from`. Paths keep their direction, and ties
+/// go to the path that comes first. The order is left incomplete if `cancellation` is cancelled.
+pub(crate) fn nearest_neighbor(
+    paths: &[[Point<Float>; 2]],
+    from: Point<Float>,
+    cancellation: Option<&Cancellation>,
+) -> Vec<usize> {
+    let mut starts = Grid::new(paths.iter().map(|[start, _]| *start).collect());
+    let mut order = Vec::with_capacity(paths.len());
+    let mut position = from;
+    while let Some(path) = starts.nearest(position) {
+        if cancellation.is_some_and(Cancellation::is_cancelled) {
+            break;
+        }
+        starts.remove(path);
+        order.push(path);
+        position = paths[path][1];
+    }
+    order
+}
+
+/// Improve an `order` from [nearest_neighbor] with 2-opt and Or-opt moves until none help or the `budget` runs out
+///
+/// A 2-opt move draws a run of paths in reverse order, which undoes crossings. An Or-opt move takes a run of up to
+/// three paths and draws it somewhere else. Neither changes the direction paths are drawn in. Only moves that bring
+/// a path next to one of its [NEIGHBORS] closest paths are tried, so that large jobs do not take quadratic time.
+/// Cancelling `cancellation` uses up the budget.
+pub(crate) fn refine(
+    paths: &[[Point<Float>; 2]],
+    from: Point<Float>,
+    order: &mut [usize],
+    budget: RefinementBudget,
+    cancellation: Option<&Cancellation>,
+) {
+    let mut budget = Budget::new(budget, cancellation);
+    let starts = Grid::new(paths.iter().map(|[start, _]| *start).collect());
+    let ends = Grid::new(paths.iter().map(|[_, end]| *end).collect());
+    // Travel from the end of one path to the start of another, where [None] is the starting position or, at the end
+    // of the order, nowhere
+    let travel = |a: Option<usize>, b: Option<usize>| match (a, b) {
+        (_, None) => 0.,
+        (None, Some(b)) => (paths[b][0] - from).length(),
+        (Some(a), Some(b)) => (paths[b][0] - paths[a][1]).length(),
+    };
+
+    let mut tour = Tour::new(order, &travel);
+    let mut improved = true;
+    while improved {
+        improved = false;
+
+        // 2-opt: reverse the run from i to j, so that the path before it is followed by the one at j
+        for i in 0..order.len() {
+            if budget.spend(1) {
+                return;
+            }
+            let before = i.checked_sub(1).map(|i| order[i]);
+            let end = before.map_or(from, |before| paths[before][1]);
+            for neighbor in starts.k_nearest(end, NEIGHBORS) {
+                let j = tour.position[neighbor];
+                if j <= i {
+                    continue;
+                }
+                if budget.spend(1) {
+                    return;
+                }
+                let after = order.get(j + 1).copied();
+                let current = travel(before, Some(order[i]))
+                    + (tour.forward[j] - tour.forward[i])
+                    + travel(Some(order[j]), after);
+                let candidate = travel(before, Some(order[j]))
+                    + (tour.reversed[j] - tour.reversed[i])
+                    + travel(Some(order[i]), after);
+                if candidate < current - IMPROVEMENT_THRESHOLD {
+                    order[i..=j].reverse();
+                    if budget.spend(order.len()) {
+                        return;
+                    }
+                    tour = Tour::new(order, &travel);
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        // Or-opt: move the run starting at i to just after a path that ends close to where the run starts
+        for length in 1..=3 {
+            let mut i = 0;
+            while i + length <= order.len() {
+                if budget.spend(1) {
+                    return;
+                }
+                let first = order[i];
+                let last = order[i + length - 1];
+                let before = i.checked_sub(1).map(|i| order[i]);
+                let after = order.get(i + length).copied();
+                let removed =
+                    travel(before, Some(first)) + travel(Some(last), after) - travel(before, after);
+
+                let mut best: Option<(usize, Float)> = None;
+                for neighbor in ends.k_nearest(paths[first][0], NEIGHBORS) {
+                    let x = tour.position[neighbor];
+                    // Inserting inside the run or where it already is changes nothing
+                    if (i.saturating_sub(1)..i + length).contains(&x) {
+                        continue;
+                    }
+                    if budget.spend(1) {
+                        return;
+                    }
+                    let y = order.get(x + 1).copied();
+                    let added = travel(Some(neighbor), Some(first)) + travel(Some(last), y)
+                        - travel(Some(neighbor), y);
+                    if added < removed - IMPROVEMENT_THRESHOLD
+                        && best.is_none_or(|(_, best_added)| added < best_added)
+                    {
+                        best = Some((x, added));
+                    }
+                }
+
+                if let Some((x, _)) = best {
+                    let run = order[i..i + length].to_vec();
+                    if x < i {
+                        order.copy_within(x + 1..i, x + 1 + length);
+                        order[x + 1..x + 1 + length].copy_from_slice(&run);
+                    } else {
+                        order.copy_within(i + length..=x, i);
+                        order[x + 1 - length..=x].copy_from_slice(&run);
+                    }
+                    if budget.spend(order.len()) {
+                        return;
+                    }
+                    tour = Tour::new(order, &travel);
+                    improved = true;
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Lookups for an order of paths, rebuilt whenever it changes
+struct Tour {
+    /// Index in the order of each path
+    position: Vec<usize>,
+    /// Travel from the first path to the one at each index, and the same with every hop made in reverse
+    forward: Vec<Float>,
+    reversed: Vec<Float>,
+}
+
+impl Tour {
+    fn new(order: &[usize], travel: &impl Fn(Option<usize>, Option<usize>) -> Float) -> Self {
+        let mut position = vec![0; order.len()];
+        let mut forward = vec![0.; order.len()];
+        let mut reversed = vec![0.; order.len()];
+        for (index, &path) in order.iter().enumerate() {
+            position[path] = index;
+            if index > 0 {
+                let previous = order[index - 1];
+                forward[index] = forward[index - 1] + travel(Some(previous), Some(path));
+                reversed[index] = reversed[index - 1] + travel(Some(path), Some(previous));
+            }
+        }
+        Self {
+            position,
+            forward,
+            reversed,
+        }
+    }
+}
+
+/// What is left of a [RefinementBudget]
+struct Budget<'a> {
+    iterations: Option<usize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    deadline: Option<Instant>,
+    cancellation: Option<&'a Cancellation>,
+    spent: usize,
+}
+
+impl<'a> Budget<'a> {
+    fn new(budget: RefinementBudget, cancellation: Option<&'a Cancellation>) -> Self {
+        Self {
+            iterations: match budget {
+                RefinementBudget::Iterations(iterations) => Some(iterations),
+                RefinementBudget::Milliseconds(_) => None,
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            deadline: match budget {
+                RefinementBudget::Iterations(_) => None,
+                RefinementBudget::Milliseconds(milliseconds) => {
+                    Some(Instant::now() + Duration::from_millis(milliseconds))
+                }
+            },
+            cancellation,
+            spent: 0,
+        }
+    }
+
+    /// Count `work`, roughly the number of paths looked at, returning whether the budget was already used up
+    fn spend(&mut self, work: usize) -> bool {
+        let before = self.spent;
+        self.spent += work;
+        if self
+            .iterations
+            .is_some_and(|iterations| self.spent > iterations)
+        {
+            return true;
+        }
+        if self.cancellation.is_some_and(Cancellation::is_cancelled) {
+            return true;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if before / CLOCK_INTERVAL != self.spent / CLOCK_INTERVAL
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lyon_geom::point;
+
+    use super::*;
+
+    fn travel(paths: &[[Point<Float>; 2]], order: &[usize]) -> Float {
+        let mut position = point(0., 0.);
+        let mut travel = 0.;
+        for &path in order {
+            travel += (paths[path][0] - position).length();
+            position = paths[path][1];
+        }
+        travel
+    }
+
+    #[test]
+    fn refinement_removes_detours() {
+        // Greedy ordering strands the dots to the left and has to come back for them
+        let paths = [0., 1., 2., 3., -1.5, 4., 5., -3.].map(|x| [point(x, 0.), point(x, 0.)]);
+        let mut order = nearest_neighbor(&paths, point(0., 0.), None);
+        let greedy = travel(&paths, &order);
+        refine(
+            &paths,
+            point(0., 0.),
+            &mut order,
+            RefinementBudget::Iterations(10_000),
+            None,
+        );
+
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..paths.len()).collect::<Vec<_>>());
+        assert!(travel(&paths, &order) < greedy);
+    }
+
+    #[test]
+    fn refinement_stops_when_budget_runs_out() {
+        let paths = [0., 1., 2., 3., -1.5, 4., 5., -3.].map(|x| [point(x, 0.), point(x, 0.)]);
+        let mut order = nearest_neighbor(&paths, point(0., 0.), None);
+        let greedy = order.clone();
+        refine(
+            &paths,
+            point(0., 0.),
+            &mut order,
+            RefinementBudget::Iterations(0),
+            None,
+        );
+        assert_eq!(order, greedy);
+    }
+
+    #[test]
+    fn visits_closest_start_next() {
+        let paths = [
+            [point(10., 0.), point(11., 0.)],
+            [point(1., 0.), point(2., 0.)],
+            [point(20., 0.), point(0., 0.)],
+            [point(3., 0.), point(9., 0.)],
+        ];
+        assert_eq!(nearest_neighbor(&paths, point(0., 0.), None), [1, 3, 0, 2]);
+    }
+}
@@ -1,16 +1,710 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+};
+
+use g_code::emit::{Field, FormatOptions, Token, Value, format_gcode_fmt};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PostprocessConfig {
-    /// Convenience field for [g_code::emit::FormatOptions] field
+    /// Append a checksum to the end of each line
     #[cfg_attr(feature = "serde", serde(default))]
     pub checksums: bool,
-    /// Convenience field for [g_code::emit::FormatOptions] field
+    /// Prefix each line with an N word
     #[cfg_attr(feature = "serde", serde(default))]
     pub line_numbers: bool,
     /// Convenience field for [g_code::emit::FormatOptions] field
     #[cfg_attr(feature = "serde", serde(default))]
     pub newline_before_comment: bool,
+    /// Number given to the first line when [Self::line_numbers] is enabled
+    #[cfg_attr(feature = "serde", serde(default = "one"))]
+    pub line_number_start: usize,
+    /// Amount added to the line number for each subsequent line
+    #[cfg_attr(feature = "serde", serde(default = "one"))]
+    pub line_number_increment: usize,
+    /// Format every line as `N<line> <command>*<checksum>` for Marlin's serial error detection
+    ///
+    /// Implies [Self::line_numbers] and [Self::checksums]. The program starts with an `M110`
+    /// to reset the firmware's line counter, numbering is consecutive, and comments are stripped.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub marlin_checksums: bool,
+    /// Home the machine before the job so that it starts from a known reference
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub homing: Option<Homing>,
+    /// Rewrite the program for a laser conversion running Klipper, see [Klipper]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub klipper: Option<Klipper>,
+}
+
+/// Klipper firmware driving a laser through macros, which rejects or ignores some of the G-Code other firmwares take
+///
+/// `M3` and `M4` become calls to [Self::laser_on] with the power of their S word, `M5` a call to [Self::laser_off], and
+/// a change of power between cuts calls [Self::laser_on] again since Klipper ignores S words on moves. `G17`, `G21`,
+/// `G94`, `M2` and `M30` are left out, dwells are given in milliseconds, and comments always start with `;`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Klipper {
+    /// Macro that turns the laser on, called with `POWER=<S>`
+    #[cfg_attr(feature = "serde", serde(default = "default_laser_on"))]
+    pub laser_on: String,
+    /// Macro that turns the laser off
+    #[cfg_attr(feature = "serde", serde(default = "default_laser_off"))]
+    pub laser_off: String,
+    /// Acceleration in millimeters / second² set with `SET_VELOCITY_LIMIT` before the job, i.e. the one it was
+    /// estimated with
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub acceleration: Option<f64>,
+    /// Feedrate of rapid moves in millimeters / minute
+    ///
+    /// Klipper moves at the last F word for `G0` too, so it is given on each `G0` and the cutting feedrate is given
+    /// again on the cut after it. [None] leaves rapid moves at the cutting feedrate.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rapid_feedrate: Option<f64>,
+}
+
+fn default_laser_on() -> String {
+    "LASER_ON".to_string()
+}
+
+fn default_laser_off() -> String {
+    "LASER_OFF".to_string()
+}
+
+impl Default for Klipper {
+    fn default() -> Self {
+        Self {
+            laser_on: default_laser_on(),
+            laser_off: default_laser_off(),
+            acceleration: None,
+            rapid_feedrate: None,
+        }
+    }
+}
+
+/// Homing command for a machine
+///
+/// Written as text at the top of the program because GRBL's `$H` is a system command rather than G-Code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Homing {
+    /// `$H`
+    Grbl,
+    /// `G28`, for Marlin, RepRapFirmware and LinuxCNC
+    G28,
+}
+
+impl Homing {
+    fn command(&self) -> &'static str {
+        match self {
+            Self::Grbl => "$H",
+            Self::G28 => "G28",
+        }
+    }
+}
+
+const fn one() -> usize {
+    1
+}
+
+impl Default for PostprocessConfig {
+    fn default() -> Self {
+        Self {
+            checksums: false,
+            line_numbers: false,
+            newline_before_comment: false,
+            line_number_start: one(),
+            line_number_increment: one(),
+            marlin_checksums: false,
+            homing: None,
+            klipper: None,
+        }
+    }
+}
+
+/// Format a program as G-Code text, applying line numbering and checksums according to [PostprocessConfig]
+pub fn format_program_fmt<W: Write>(
+    program: &[Token<'_>],
+    config: &PostprocessConfig,
+    w: W,
+) -> fmt::Result {
+    phase!("format", tokens = program.len());
+    let mut writer = ProgramWriter::new(config, w)?;
+    writer.write(program)?;
+    writer.finish()
+}
+
+/// Same as [format_program_fmt], but for an [std::io::Write]
+pub fn format_program_io<W: std::io::Write>(
+    program: &[Token<'_>],
+    config: &PostprocessConfig,
+    w: W,
+) -> std::io::Result<()> {
+    let mut adapter = IoAdapter::new(w);
+    let result = format_program_fmt(program, config, &mut adapter);
+    adapter.finish(result)
+}
+
+/// Formats a program one piece at a time, for writing it out while it is still being generated
+///
+/// Pieces must end where a command ends. Once a write fails, every later one does too.
+pub(crate) struct ProgramWriter<W: Write> {
+    output: Output<W>,
+    klipper: Option<KlipperLines>,
+    options: FormatOptions,
+    failed: bool,
+}
+
+enum Output<W: Write> {
+    Plain(W),
+    Numbered(NumberedLines<W>),
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self {
+            Self::Plain(w) => w.write_str(s),
+            Self::Numbered(numbered) => numbered.write_str(s),
+        }
+    }
+}
+
+impl<W: Write> ProgramWriter<W> {
+    /// Start the program, with anything [PostprocessConfig] puts before it
+    pub fn new(config: &PostprocessConfig, w: W) -> Result<Self, fmt::Error> {
+        let options = FormatOptions {
+            newline_before_comment: config.newline_before_comment,
+            ..Default::default()
+        };
+        let mut output = if config.line_numbers || config.checksums || config.marlin_checksums {
+            let mut numbered = NumberedLines::new(w, config);
+            if config.marlin_checksums {
+                numbered.write_str("M110 N0\n")?;
+            }
+            Output::Numbered(numbered)
+        } else {
+            Output::Plain(w)
+        };
+        if let Some(homing) = config.homing {
+            writeln!(output, "{}", homing.command())?;
+        }
+        if let Some(klipper) = &config.klipper {
+            klipper.write_velocity_limit(&mut output)?;
+        }
+        Ok(Self {
+            output,
+            klipper: config.klipper.clone().map(KlipperLines::new),
+            options,
+            failed: false,
+        })
+    }
+
+    pub fn write(&mut self, tokens: &[Token<'_>]) -> fmt::Result {
+        if self.failed {
+            return Err(fmt::Error);
+        }
+        let tokens = snap_to_zero(tokens);
+        let tokens = tokens.as_ref();
+        let result = match &mut self.klipper {
+            Some(klipper) => {
+                let mut gcode = String::new();
+                format_gcode_fmt(tokens, self.options.clone(), &mut gcode)
+                    .and_then(|()| klipper.write_str(&gcode, &mut self.output))
+            }
+            None => format_gcode_fmt(tokens, self.options.clone(), &mut self.output),
+        };
+        self.failed = result.is_err();
+        result
+    }
+
+    pub fn finish(mut self) -> fmt::Result {
+        if self.failed {
+            return Err(fmt::Error);
+        }
+        if let Some(klipper) = &mut self.klipper {
+            klipper.finish(&mut self.output)?;
+        }
+        match self.output {
+            Output::Plain(_) => Ok(()),
+            Output::Numbered(numbered) => numbered.finish(),
+        }
+    }
+}
+
+/// Magnitude below which numbers are written as `0`, since they are rounding noise that takes a long run of digits to
+/// write out
+const SNAP_TO_ZERO: f64 = 1e-9;
+
+/// Tokens with tiny numbers and negative zero replaced by `0`, borrowed as they are if there are none
+///
+/// Floats are formatted in plain decimal with a `.` whatever their magnitude or the system's locale, so this is all it
+/// takes for every number to be one that any firmware reads.
+fn snap_to_zero<'a, 'input>(tokens: &'a [Token<'input>]) -> Cow<'a, [Token<'input>]> {
+    let snaps = |token: &Token| {
+        matches!(
+            token,
+            Token::Field(Field { value: Value::Float(value), .. })
+                if value.abs() < SNAP_TO_ZERO && value.to_bits() != 0f64.to_bits()
+        )
+    };
+    if !tokens.iter().any(snaps) {
+        return Cow::Borrowed(tokens);
+    }
+    Cow::Owned(
+        tokens
+            .iter()
+            .map(|token| match token {
+                Token::Field(field) if snaps(token) => Token::Field(Field {
+                    letters: field.letters.clone(),
+                    value: Value::Float(0.),
+                }),
+                token => token.clone(),
+            })
+            .collect(),
+    )
+}
+
+impl<W: Write> fmt::Debug for ProgramWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgramWriter")
+            .field("failed", &self.failed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lets an [std::io::Write] be used where an [fmt::Write] is expected, keeping the [std::io::Error] that [fmt::Error]
+/// cannot carry
+pub(crate) struct IoAdapter<W: std::io::Write> {
+    inner: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: std::io::Write> IoAdapter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// Turn the `result` of formatting into the [std::io::Error] behind it, if any
+    pub fn finish(mut self, result: fmt::Result) -> std::io::Result<()> {
+        match (result, self.error.take()) {
+            (_, Some(error)) => Err(error),
+            (Err(_), None) => Err(std::io::Error::other("formatting G-Code failed")),
+            (Ok(()), None) => self.inner.flush(),
+        }
+    }
+}
+
+impl<W: std::io::Write> Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
+    }
+}
+
+impl Klipper {
+    /// `SET_VELOCITY_LIMIT` for [Self::acceleration], if it is set
+    fn write_velocity_limit(&self, w: &mut impl Write) -> fmt::Result {
+        match self.acceleration {
+            Some(acceleration) => writeln!(w, "SET_VELOCITY_LIMIT ACCEL={acceleration}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Rewrites formatted G-Code one line at a time for [Klipper]
+struct KlipperLines {
+    config: Klipper,
+    line: String,
+    laser_on: bool,
+    power: Option<f64>,
+    /// Last F word given to a cut
+    feedrate: Option<f64>,
+    /// Whether the last move was a rapid that set its own F word, so the next cut has to restore [Self::feedrate]
+    after_rapid: bool,
+}
+
+impl KlipperLines {
+    fn new(config: Klipper) -> Self {
+        Self {
+            config,
+            line: String::new(),
+            laser_on: false,
+            power: None,
+            feedrate: None,
+            after_rapid: false,
+        }
+    }
+
+    fn write_str(&mut self, s: &str, w: &mut impl Write) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                let line = std::mem::take(&mut self.line);
+                self.rewrite(&line, w)?;
+            } else {
+                self.line.push(c);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out any trailing line that was not terminated by a newline
+    fn finish(&mut self, w: &mut impl Write) -> fmt::Result {
+        if self.line.is_empty() {
+            return Ok(());
+        }
+        let line = std::mem::take(&mut self.line);
+        self.rewrite(&line, w)
+    }
+
+    fn rewrite(&mut self, line: &str, w: &mut impl Write) -> fmt::Result {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (code, comment) = match line.find([';', '(']) {
+            Some(i) => line.split_at(i),
+            None => (line, ""),
+        };
+        let comment = match comment.strip_prefix('(') {
+            Some(inner) => format!(";{}", inner.strip_suffix(')').unwrap_or(inner)),
+            None => comment.to_string(),
+        };
+        let mut words = code
+            .split_whitespace()
+            .filter_map(|word| {
+                let (letter, value) = word.split_at(word.chars().next()?.len_utf8());
+                Some((letter.to_ascii_uppercase(), value.to_string()))
+            })
+            .collect::<Vec<_>>();
+        let number = |words: &[(String, String)], letter: &str| {
+            words
+                .iter()
+                .find(|(l, _)| l == letter)
+                .and_then(|(_, value)| value.parse::<f64>().ok())
+        };
+        let has = |words: &[(String, String)], letter: &str, codes: &[f64]| {
+            words.iter().any(|(l, value)| {
+                l == letter
+                    && value
+                        .parse::<f64>()
+                        .is_ok_and(|value| codes.contains(&value))
+            })
+        };
+
+        let mut lines = vec![];
+        if has(&words, "M", &[3., 4.]) {
+            self.laser_on = true;
+            self.power = number(&words, "S").or(self.power);
+            lines.push(self.laser_on_call());
+            words.retain(|(letter, _)| letter != "M" && letter != "S");
+        } else if has(&words, "M", &[5.]) {
+            self.laser_on = false;
+            lines.push(self.config.laser_off.clone());
+            words.retain(|(letter, _)| letter != "M");
+        }
+        // Klipper has no program end, and only knows millimeters on the XY plane anyway
+        words.retain(|(letter, value)| {
+            let code = value.parse::<f64>().ok();
+            !matches!(
+                (letter.as_str(), code),
+                ("G", Some(17. | 21. | 94.)) | ("M", Some(2. | 30.))
+            )
+        });
+        if has(&words, "G", &[4.]) {
+            for (letter, value) in &mut words {
+                if letter == "P"
+                    && let Ok(seconds) = value.parse::<f64>()
+                {
+                    *value = (seconds * 1000.).to_string();
+                }
+            }
+        }
+        if has(&words, "G", &[0., 1., 2., 3.]) {
+            if let Some(power) = number(&words, "S") {
+                words.retain(|(letter, _)| letter != "S");
+                if self.power != Some(power) {
+                    self.power = Some(power);
+                    if self.laser_on {
+                        lines.push(self.laser_on_call());
+                    }
+                }
+            }
+            if has(&words, "G", &[0.]) {
+                if let Some(rapid_feedrate) = self.config.rapid_feedrate
+                    && number(&words, "F").is_none()
+                {
+                    words.push(("F".to_string(), rapid_feedrate.to_string()));
+                    self.after_rapid = true;
+                }
+            } else {
+                match number(&words, "F") {
+                    Some(feedrate) => self.feedrate = Some(feedrate),
+                    None if self.after_rapid => {
+                        if let Some(feedrate) = self.feedrate {
+                            words.push(("F".to_string(), feedrate.to_string()));
+                        }
+                    }
+                    None => {}
+                }
+                self.after_rapid = false;
+            }
+        }
+        if !words.is_empty() {
+            lines.push(
+                words
+                    .iter()
+                    .map(|(letter, value)| format!("{letter}{value}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+
+        // Comments go on the last line, or on their own if the code was left out
+        if let Some(last) = lines.last_mut() {
+            *last += &comment;
+        } else if !comment.is_empty() || code.trim().is_empty() {
+            lines.push(comment);
+        }
+        for line in lines {
+            writeln!(w, "{line}")?;
+        }
+        Ok(())
+    }
+
+    fn laser_on_call(&self) -> String {
+        format!(
+            "{} POWER={}",
+            self.config.laser_on,
+            self.power.unwrap_or_default()
+        )
+    }
+}
+
+/// Writer that buffers formatted G-Code one line at a time to prefix N words and append checksums
+struct NumberedLines<W: Write> {
+    inner: W,
+    line_numbers: bool,
+    checksums: bool,
+    strip_comments: bool,
+    line: String,
+    line_number: usize,
+    line_number_increment: usize,
+}
+
+impl<W: Write> NumberedLines<W> {
+    fn new(inner: W, config: &PostprocessConfig) -> Self {
+        if config.marlin_checksums {
+            // Numbering starts at 0 for the M110 that resets Marlin's line counter
+            Self {
+                inner,
+                line_numbers: true,
+                checksums: true,
+                strip_comments: true,
+                line: String::new(),
+                line_number: 0,
+                line_number_increment: 1,
+            }
+        } else {
+            Self {
+                inner,
+                line_numbers: config.line_numbers,
+                checksums: config.checksums,
+                strip_comments: false,
+                line: String::new(),
+                line_number: config.line_number_start,
+                line_number_increment: config.line_number_increment,
+            }
+        }
+    }
+
+    fn flush_line(&mut self) -> fmt::Result {
+        let line = std::mem::take(&mut self.line);
+        // Comments are left as-is so that checksums only cover commands
+        let (code, comment) = match line.find(';') {
+            Some(i) => line.split_at(i),
+            None => (line.as_str(), ""),
+        };
+        let code = code.trim_end();
+        let comment = if self.strip_comments { "" } else { comment };
+        if code.is_empty() {
+            return self.inner.write_str(comment);
+        }
+
+        let mut numbered = String::new();
+        if self.line_numbers {
+            write!(numbered, "N{} ", self.line_number)?;
+            self.line_number += self.line_number_increment;
+        }
+        numbered += code;
+        if self.checksums {
+            let checksum = numbered.bytes().fold(0u8, |acc, b| acc ^ b);
+            write!(numbered, "*{checksum}")?;
+        }
+        self.inner.write_str(&numbered)?;
+        self.inner.write_str(comment)
+    }
+
+    /// Write out any trailing line that was not terminated by a newline
+    fn finish(mut self) -> fmt::Result {
+        if self.line.is_empty() {
+            Ok(())
+        } else {
+            self.flush_line()
+        }
+    }
+}
+
+impl<W: Write> Write for NumberedLines<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                let carriage_return = self.line.ends_with('\r');
+                if carriage_return {
+                    self.line.pop();
+                }
+                // Comment-only lines disappear entirely when comments are stripped
+                if self.strip_comments && self.line.trim_start().starts_with(';') {
+                    self.line.clear();
+                    continue;
+                }
+                self.flush_line()?;
+                self.inner
+                    .write_str(if carriage_return { "\r\n" } else { "\n" })?;
+            } else {
+                self.line.push(c);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(gcode: &str, config: &PostprocessConfig) -> String {
+        let mut acc = String::new();
+        let mut numbered = NumberedLines::new(&mut acc, config);
+        numbered.write_str(gcode).unwrap();
+        numbered.finish().unwrap();
+        acc
+    }
+
+    #[test]
+    fn line_numbers_use_start_and_increment() {
+        let config = PostprocessConfig {
+            line_numbers: true,
+            line_number_start: 10,
+            line_number_increment: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            number("G21\nG90;comment\n;only a comment\nG0 X1 Y1", &config),
+            "N10 G21\nN15 G90;comment\n;only a comment\nN20 G0 X1 Y1"
+        );
+    }
+
+    #[test]
+    fn checksums_cover_line_number() {
+        let config = PostprocessConfig {
+            line_numbers: true,
+            checksums: true,
+            ..Default::default()
+        };
+        // XOR of every byte before the asterisk, see https://reprap.org/wiki/G-code#Checking
+        assert_eq!(number("M110 N123\n", &config), "N1 M110 N123*124\n");
+    }
+
+    #[test]
+    fn homing_comes_first() {
+        let program = g_code::parse::file_parser("G21\n")
+            .unwrap()
+            .iter_emit_tokens()
+            .collect::<Vec<_>>();
+        let format = |config: &PostprocessConfig| {
+            let mut acc = String::new();
+            format_program_fmt(&program, config, &mut acc).unwrap();
+            acc
+        };
+
+        let config = PostprocessConfig {
+            homing: Some(Homing::Grbl),
+            ..Default::default()
+        };
+        assert!(format(&config).starts_with("$H\nG21"));
+
+        let config = PostprocessConfig {
+            homing: Some(Homing::G28),
+            marlin_checksums: true,
+            ..Default::default()
+        };
+        assert!(format(&config).starts_with("N0 M110 N0*125\nN1 G28*"));
+    }
+
+    #[test]
+    fn marlin_checksums_are_consecutive_without_comments() {
+        let config = PostprocessConfig {
+            marlin_checksums: true,
+            line_number_start: 10,
+            line_number_increment: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            number(
+                "M110 N0\nG21\nG90;comment\n;only a comment\nG0 X1\n",
+                &config
+            ),
+            "N0 M110 N0*125\nN1 G21*27\nN2 G90*18\nN3 G0 X1*99\n"
+        );
+    }
+
+    #[test]
+    fn klipper_calls_laser_macros() {
+        let program = g_code::parse::file_parser(
+            "G21\nG90\n(begin)\nG0 X1 Y1\nM3 S100\nG1 X2 Y2 F300\nG1 X3 Y3 S50\nM5\nG4 P0.25\nG0 X0 Y0\nM3\nG1 X1 Y0\nM5\nM2\n",
+        )
+        .unwrap()
+        .iter_emit_tokens()
+        .collect::<Vec<_>>();
+        let config = PostprocessConfig {
+            klipper: Some(Klipper {
+                acceleration: Some(1000.),
+                rapid_feedrate: Some(6000.),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut gcode = String::new();
+        format_program_fmt(&program, &config, &mut gcode).unwrap();
+        assert_eq!(
+            gcode,
+            "SET_VELOCITY_LIMIT ACCEL=1000\nG90;begin\nG0 X1 Y1 F6000\nLASER_ON POWER=100\nG1 X2 Y2 F300\n\
+             LASER_ON POWER=50\nG1 X3 Y3\nLASER_OFF\nG4 P250\nG0 X0 Y0 F6000\nLASER_ON POWER=50\nG1 X1 Y0 F300\n\
+             LASER_OFF\n"
+        );
+    }
+
+    #[test]
+    fn numbers_are_plain_decimals() {
+        let field = |letters: &'static str, value: f64| {
+            Token::Field(Field {
+                letters: Cow::Borrowed(letters),
+                value: Value::Float(value),
+            })
+        };
+        let program = [
+            field("G", 1.),
+            field("X", 1e-12),
+            field("Y", -0.),
+            field("Z", -3e-7),
+            field("F", 1e21),
+        ];
+        let mut gcode = String::new();
+        format_program_fmt(&program, &PostprocessConfig::default(), &mut gcode).unwrap();
+        assert_eq!(
+            gcode.trim_end(),
+            "G1 X0 Y0 Z-0.0000003 F1000000000000000000000"
+        );
+    }
 }
@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+
+use g_code::{
+    emit::{Field, Token, Value},
+    parse::ast::Snippet,
+};
+
+use crate::Program;
+
+/// Letters of the fields that start a new command, matching where formatted G-Code starts a new line
+const COMMAND_LETTERS: [&str; 4] = ["G", "M", "T", "O"];
+
+/// One command of a [Program], i.e. `G1 X10 Y5 F300`, along with the comments after it on the same line
+///
+/// A comment that follows another one is a command of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramCommand<'input> {
+    tokens: Vec<Token<'input>>,
+}
+
+impl<'input> ProgramCommand<'input> {
+    /// A command word such as `M7`, followed by its arguments
+    pub fn new(
+        letters: &'static str,
+        number: usize,
+        arguments: impl IntoIterator<Item = Field<'input>>,
+    ) -> Self {
+        let word = Field {
+            letters: Cow::Borrowed(letters),
+            value: Value::Integer(number),
+        };
+        Self {
+            tokens: std::iter::once(Token::Field(word))
+                .chain(arguments.into_iter().map(Token::Field))
+                .collect(),
+        }
+    }
+
+    /// A comment, which goes at the end of the line of the command before it unless
+    /// [crate::PostprocessConfig::newline_before_comment] is set
+    pub fn comment(comment: impl Into<Cow<'input, str>>) -> Self {
+        Self {
+            tokens: vec![Token::Comment {
+                is_inline: false,
+                inner: comment.into(),
+            }],
+        }
+    }
+
+    /// Commands in a snippet of G-Code from [g_code::parse::snippet_parser], i.e. custom codes to insert
+    pub fn from_snippet(snippet: &Snippet<'input>) -> Vec<Self> {
+        split(snippet.iter_emit_tokens())
+    }
+
+    pub fn tokens(&self) -> &[Token<'input>] {
+        &self.tokens
+    }
+
+    /// The field that starts the command, or [None] for a comment or for arguments that continue the one before
+    pub fn word(&self) -> Option<&Field<'input>> {
+        match self.tokens.first() {
+            Some(Token::Field(field)) if COMMAND_LETTERS.contains(&field.letters.as_ref()) => {
+                Some(field)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this is the command with the given word, i.e. `is("G", 1)` for a linear move
+    pub fn is(&self, letters: &str, number: usize) -> bool {
+        self.word().is_some_and(|word| {
+            word.letters == letters && word.value.as_f64() == Some(number as f64)
+        })
+    }
+
+    /// Value of the argument with the given letters, i.e. `argument("X")`
+    pub fn argument(&self, letters: &str) -> Option<&Value<'input>> {
+        self.arguments()
+            .find(|field| field.letters == letters)
+            .map(|field| &field.value)
+    }
+
+    /// Change the value of an argument, adding it if the command does not have it yet
+    pub fn set_argument(&mut self, letters: &'static str, value: Value<'input>) {
+        let skip = usize::from(self.word().is_some());
+        let existing = self
+            .tokens
+            .iter_mut()
+            .skip(skip)
+            .find_map(|token| match token {
+                Token::Field(field) if field.letters == letters => Some(field),
+                _ => None,
+            });
+        match existing {
+            Some(field) => field.value = value,
+            None => self.tokens.push(Token::Field(Field {
+                letters: Cow::Borrowed(letters),
+                value,
+            })),
+        }
+    }
+
+    /// Remove an argument, returning its value if the command had it
+    pub fn remove_argument(&mut self, letters: &str) -> Option<Value<'input>> {
+        let skip = usize::from(self.word().is_some());
+        let index =
+            self.tokens.iter().skip(skip).position(
+                |token| matches!(token, Token::Field(field) if field.letters == letters),
+            )?;
+        match self.tokens.remove(index + skip) {
+            Token::Field(field) => Some(field.value),
+            Token::Flag(_) | Token::Comment { .. } => unreachable!("only a field is looked for"),
+        }
+    }
+
+    /// Fields after the [Self::word]
+    ///
+    /// Flags have no value, so they are not among them, see [Self::flags].
+    pub fn arguments(&self) -> impl Iterator<Item = &Field<'input>> {
+        self.tokens
+            .iter()
+            .skip(usize::from(self.word().is_some()))
+            .filter_map(|token| match token {
+                Token::Field(field) => Some(field),
+                Token::Flag(_) | Token::Comment { .. } => None,
+            })
+    }
+
+    /// Letters given without a value, i.e. the `X` of `G28 X` that homes only the X axis
+    pub fn flags(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().filter_map(|token| match token {
+            Token::Flag(flag) => Some(flag.letter.as_ref()),
+            Token::Field(_) | Token::Comment { .. } => None,
+        })
+    }
+}
+
+/// Split tokens into the commands they would be formatted as
+fn split<'input>(tokens: impl IntoIterator<Item = Token<'input>>) -> Vec<ProgramCommand<'input>> {
+    let mut commands = vec![];
+    let mut current: Vec<Token<'input>> = vec![];
+    for token in tokens {
+        let starts_command = matches!(&token, Token::Field(field) if COMMAND_LETTERS.contains(&field.letters.as_ref()));
+        if starts_command && !current.is_empty() {
+            commands.push(ProgramCommand {
+                tokens: std::mem::take(&mut current),
+            });
+        }
+        let ends_command = matches!(
+            token,
+            Token::Comment {
+                is_inline: false,
+                ..
+            }
+        );
+        current.push(token);
+        if ends_command {
+            commands.push(ProgramCommand {
+                tokens: std::mem::take(&mut current),
+            });
+        }
+    }
+    if !current.is_empty() {
+        commands.push(ProgramCommand { tokens: current });
+    }
+    commands
+}
+
+impl<'input> Program<'input> {
+    /// Split [Self::tokens] into commands, i.e. to edit them and give them back with [Self::set_commands]
+    pub fn commands(&self) -> Vec<ProgramCommand<'input>> {
+        split(self.tokens.iter().cloned())
+    }
+
+    /// Replace [Self::tokens] with the tokens of the commands
+    ///
+    /// [Self::statistics] are left as they were.
+    pub fn set_commands(&mut self, commands: impl IntoIterator<Item = ProgramCommand<'input>>) {
+        self.tokens = commands
+            .into_iter()
+            .flat_map(|command| command.tokens)
+            .collect();
+    }
+
+    /// Replace each command with the ones that `f` gives for it, to insert, remove, or change commands in place
+    ///
+    /// [Self::statistics] are left as they were.
+    pub fn transform<I>(&mut self, f: impl FnMut(ProgramCommand<'input>) -> I)
+    where
+        I: IntoIterator<Item = ProgramCommand<'input>>,
+    {
+        let commands = split(std::mem::take(&mut self.tokens));
+        self.set_commands(commands.into_iter().flat_map(f));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use g_code::{
+        emit::{FormatOptions, format_gcode_fmt},
+        parse::snippet_parser,
+    };
+
+    use super::*;
+    use crate::ConversionStatistics;
+
+    fn program(gcode: &str) -> Program<'_> {
+        Program {
+            tokens: snippet_parser(gcode).unwrap().iter_emit_tokens().collect(),
+            statistics: ConversionStatistics::default(),
+        }
+    }
+
+    fn format(program: &Program) -> String {
+        let mut gcode = String::new();
+        let options = FormatOptions {
+            newline_before_comment: true,
+            ..Default::default()
+        };
+        format_gcode_fmt(&program.tokens, options, &mut gcode).unwrap();
+        gcode
+    }
+
+    #[test]
+    fn commands_split_like_formatted_lines() {
+        let program = program("G21 G90\n;begin\nG0 X1 Y2\nM3 S1000\nG1 X3 Y4 F300\nM5");
+        let commands = program.commands();
+        assert_eq!(commands.len(), 6);
+        assert!(commands[0].is("G", 21));
+        assert!(commands[1].is("G", 90));
+        assert_eq!(commands[1].tokens().len(), 2);
+        assert_eq!(commands[1].arguments().count(), 0);
+        assert_eq!(
+            commands[4].argument("F").and_then(Value::as_f64),
+            Some(300.)
+        );
+
+        let mut copy = program.clone();
+        copy.set_commands(commands);
+        assert_eq!(copy, program);
+    }
+
+    #[test]
+    fn flags_are_not_arguments() {
+        let program = program("G28 X Y0");
+        let commands = program.commands();
+        assert_eq!(commands[0].flags().collect::<Vec<_>>(), ["X"]);
+        assert_eq!(commands[0].arguments().count(), 1);
+        assert_eq!(commands[0].clone().remove_argument("X"), None);
+    }
+
+    #[test]
+    fn transform_inserts_removes_and_edits_commands() {
+        let mut program = program("G0 X1 Y2\nM3 S1000\nG1 X3 Y4 F300\nM5");
+        let coolant = snippet_parser("M7").unwrap();
+        program.transform(|mut command| {
+            if command.is("M", 3) {
+                let mut commands = ProgramCommand::from_snippet(&coolant);
+                commands.push(command);
+                commands
+            } else if command.is("M", 5) {
+                vec![]
+            } else {
+                if command.is("G", 1) {
+                    command.set_argument("F", Value::Float(600.));
+                }
+                vec![command]
+            }
+        });
+        program.set_commands(
+            program
+                .commands()
+                .into_iter()
+                .chain([ProgramCommand::comment("end")]),
+        );
+        assert_eq!(
+            format(&program),
+            "G0 X1 Y2\nM7\nM3 S1000\nG1 X3 Y4 F600\n;end\n"
+        );
+    }
+}
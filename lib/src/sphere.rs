@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+
+use g_code::emit::{Field, Token, Value};
+use log::warn;
+
+use crate::Sphere;
+
+/// Move program coordinates from the flat drawing onto an egg or sphere, see [crate::ConversionConfig::sphere]
+///
+/// X and Y words of every command are replaced by the rotation of the egg and the angle of the pen arm, in degrees.
+/// With [Sphere::compensate_latitude], lines no longer map to lines, so cutting moves are split into pieces short
+/// enough that they stay within `tolerance` of the curve they follow on the surface.
+pub(crate) fn wrap_around_sphere<'input>(
+    program: Vec<Token<'input>>,
+    sphere: &Sphere,
+    tolerance: f64,
+) -> Vec<Token<'input>> {
+    let mut wrapper = Wrapper {
+        sphere: *sphere,
+        // A chord this long strays by the tolerance from a circle around the egg
+        max_segment: (8. * tolerance * sphere.diameter / 2.).sqrt(),
+        position: [0., 0.],
+        past_pole: false,
+        wrapped: Vec::with_capacity(program.len()),
+    };
+    let mut command = vec![];
+    for token in program {
+        let starts_command = match &token {
+            Token::Field(field) => matches!(field.letters.as_ref(), "G" | "M" | "O" | "T"),
+            // A letter without a value belongs to the command before it, as in `G28 X`
+            Token::Flag(_) => false,
+            Token::Comment { .. } => true,
+        };
+        if starts_command {
+            wrapper.command(std::mem::take(&mut command));
+        }
+        match token {
+            Token::Comment { .. } => wrapper.wrapped.push(token),
+            Token::Field(_) | Token::Flag(_) => command.push(token),
+        }
+    }
+    wrapper.command(command);
+    if wrapper.past_pole {
+        warn!(
+            "The drawing reaches past the poles of the egg, so it wraps around to the other side"
+        );
+    }
+    wrapper.wrapped
+}
+
+struct Wrapper<'input> {
+    sphere: Sphere,
+    max_segment: f64,
+    /// Last position in the drawing, in millimeters
+    position: [f64; 2],
+    past_pole: bool,
+    wrapped: Vec<Token<'input>>,
+}
+
+impl<'input> Wrapper<'input> {
+    fn command(&mut self, command: Vec<Token<'input>>) {
+        let word = |letters: &str| {
+            command.iter().find_map(|token| match token {
+                Token::Field(field) if field.letters == letters => field.value.as_f64(),
+                _ => None,
+            })
+        };
+        let (x, y) = (word("X"), word("Y"));
+        // Local offsets and arcs are relative, which doesn't carry over to the surface
+        if (x.is_none() && y.is_none()) || matches!(word("G"), Some(2. | 3. | 52.)) {
+            self.wrapped.extend(command);
+            return;
+        }
+        let from = self.position;
+        let to = [x.unwrap_or(from[0]), y.unwrap_or(from[1])];
+        self.position = to;
+
+        let split = self.sphere.compensate_latitude && word("G") == Some(1.);
+        let pieces = if split {
+            let length = (to[0] - from[0]).hypot(to[1] - from[1]);
+            (length / self.max_segment).ceil().max(1.) as usize
+        } else {
+            1
+        };
+        let (head, rest): (Vec<_>, Vec<_>) = command
+            .into_iter()
+            .filter(|token| !is_coordinate(token))
+            .enumerate()
+            .partition(|(i, _)| *i == 0);
+        let head = head.into_iter().map(|(_, token)| token).collect::<Vec<_>>();
+        for piece in 1..=pieces {
+            let t = piece as f64 / pieces as f64;
+            let [x, y] = self.angles([
+                from[0] + t * (to[0] - from[0]),
+                from[1] + t * (to[1] - from[1]),
+            ]);
+            self.wrapped.extend(head.iter().cloned());
+            self.wrapped
+                .extend([coordinate("X", x), coordinate("Y", y)]);
+            // Feedrate, power and other words only need to be given once
+            if piece == 1 {
+                self.wrapped
+                    .extend(rest.iter().map(|(_, token)| token.clone()));
+            }
+        }
+    }
+
+    /// Rotation of the egg and angle of the pen arm in degrees for a point of the drawing in millimeters
+    fn angles(&mut self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let radius = self.sphere.diameter / 2.;
+        let latitude = y / radius;
+        self.past_pole |= latitude.abs() >= std::f64::consts::FRAC_PI_2;
+        // The surface is narrower by the cosine of the latitude, so the egg turns further for the same width
+        let x = if self.sphere.compensate_latitude {
+            x / latitude.cos().abs().max(f64::EPSILON)
+        } else {
+            x
+        };
+        [(x / radius).to_degrees(), latitude.to_degrees()]
+    }
+}
+
+fn is_coordinate(token: &Token) -> bool {
+    matches!(token, Token::Field(field) if field.letters == "X" || field.letters == "Y")
+}
+
+fn coordinate<'input>(letters: &'static str, value: f64) -> Token<'input> {
+    Token::Field(Field {
+        letters: Cow::Borrowed(letters),
+        value: Value::Float(value),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use g_code::{
+        emit::{FormatOptions, format_gcode_fmt},
+        parse::snippet_parser,
+    };
+
+    use super::*;
+
+    fn format(tokens: &[Token]) -> String {
+        let mut gcode = String::new();
+        format_gcode_fmt(tokens, FormatOptions::default(), &mut gcode).unwrap();
+        gcode
+    }
+
+    /// Letters and values of the words on each line of the wrapped program
+    fn wrap(gcode: &str, compensate_latitude: bool) -> Vec<Vec<(String, f64)>> {
+        let program = snippet_parser(gcode).unwrap().iter_emit_tokens().collect();
+        let sphere = Sphere {
+            // A circumference of 360 mm makes a millimeter at the equator a degree
+            diameter: 360. / std::f64::consts::PI,
+            compensate_latitude,
+        };
+        let wrapped = format(&wrap_around_sphere(program, &sphere, 0.01));
+        wrapped
+            .lines()
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|word| (word[..1].to_string(), word[1..].parse().unwrap()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_words(line: &[(String, f64)], expected: &[(&str, f64)]) {
+        assert_eq!(line.len(), expected.len(), "{line:?}");
+        for ((letter, value), (expected_letter, expected_value)) in line.iter().zip(expected) {
+            assert_eq!(letter, expected_letter);
+            assert!((value - expected_value).abs() < 1e-9, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn millimeters_become_degrees() {
+        let lines = wrap("G21\nG0 X10 Y-5\nG1 X20 Y5 F300\nM2\n", false);
+        assert_eq!(lines.len(), 4);
+        assert_words(&lines[0], &[("G", 21.)]);
+        assert_words(&lines[1], &[("G", 0.), ("X", 10.), ("Y", -5.)]);
+        assert_words(&lines[2], &[("G", 1.), ("X", 20.), ("Y", 5.), ("F", 300.)]);
+        assert_words(&lines[3], &[("M", 2.)]);
+    }
+
+    #[test]
+    fn compensated_lines_are_split() {
+        let lines = wrap("G0 X0 Y30\nG1 X10 Y30 F300\n", true);
+        assert!(lines.len() > 2);
+        assert_eq!(lines[1].last().unwrap(), &("F".to_string(), 300.));
+        // The surface is narrower at 30° latitude, so the egg turns further than 10°
+        let expected = 10. / 30f64.to_radians().cos();
+        assert_words(
+            lines.last().unwrap(),
+            &[("G", 1.), ("X", expected), ("Y", 30.)],
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::{fmt, time::Duration};
+
+use lyon_geom::{Box2D, Point, Vector};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::machine::Kinematics;
+
+/// Summary of a generated program, for showing before it is run
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConversionStatistics {
+    /// Number of paths drawn, i.e. how many times the tool was turned on
+    pub paths: usize,
+    /// Most times that any shape is drawn, see [crate::ConversionConfig::passes]
+    pub passes: usize,
+    /// Total distance moved with the tool on in millimeters
+    pub cut_length: f64,
+    /// Total distance moved with the tool off in millimeters
+    pub travel_length: f64,
+    /// Extents of all cutting moves in machine coordinates (millimeters)
+    pub bounding_box: Option<Box2D<f64>>,
+    /// How long the machine is expected to take, accounting for acceleration if [Kinematics::acceleration] is known
+    pub estimated_duration: Duration,
+}
+
+impl ConversionStatistics {
+    pub(crate) fn extend_bounding_box(&mut self, points: impl IntoIterator<Item = Point<f64>>) {
+        let extents = Box2D::from_points(points);
+        self.bounding_box = Some(match self.bounding_box {
+            Some(bounding_box) => bounding_box.union(&extents),
+            None => extents,
+        });
+    }
+}
+
+/// One line for each statistic, as in the [crate::ConversionConfig::metadata_header]
+impl fmt::Display for ConversionStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(bounding_box) = self.bounding_box {
+            writeln!(
+                f,
+                "Bounding box: X{:.3}..{:.3} Y{:.3}..{:.3} mm",
+                bounding_box.min.x, bounding_box.max.x, bounding_box.min.y, bounding_box.max.y
+            )?;
+        }
+        writeln!(f, "Paths: {}", self.paths)?;
+        writeln!(f, "Cut length: {:.3} mm", self.cut_length)?;
+        writeln!(f, "Travel length: {:.3} mm", self.travel_length)?;
+        let seconds = self.estimated_duration.as_secs();
+        write!(
+            f,
+            "Estimated duration: {}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Estimates how long the machine spends on each move
+///
+/// Speed follows a trapezoidal profile limited by [Kinematics::acceleration], and corners are taken at the
+/// speed allowed by [Kinematics::junction_deviation] (the same model as GRBL's planner). The machine comes
+/// to a stop whenever it switches between cutting and rapid moves, since that usually involves
+/// toggling the tool, and for any pause pushed with [Self::push_dwell].
+#[derive(Debug, Clone)]
+pub(crate) struct TimeEstimator {
+    kinematics: Kinematics,
+    moves: Vec<EstimatedMove>,
+}
+
+#[derive(Debug, Clone)]
+struct EstimatedMove {
+    length: f64,
+    /// Unit vector from start to end
+    direction: Vector<f64>,
+    /// Requested speed in millimeters / second
+    speed: f64,
+    rapid: bool,
+    /// Seconds spent standing still after the move
+    dwell: f64,
+}
+
+impl TimeEstimator {
+    pub fn new(kinematics: Kinematics) -> Self {
+        Self {
+            kinematics,
+            moves: vec![],
+        }
+    }
+
+    /// Add a move of `length` millimeters at `feedrate` millimeters / minute
+    pub fn push(
+        &mut self,
+        from: Point<f64>,
+        to: Point<f64>,
+        length: f64,
+        feedrate: f64,
+        rapid: bool,
+    ) {
+        self.moves.push(EstimatedMove {
+            length,
+            direction: (to - from).try_normalize().unwrap_or_else(Vector::zero),
+            speed: feedrate / 60.,
+            rapid,
+            dwell: 0.,
+        });
+    }
+
+    /// Add a pause of `seconds` where the machine stands still, i.e. while a pen servo moves
+    pub fn push_dwell(&mut self, seconds: f64) {
+        self.moves.push(EstimatedMove {
+            length: 0.,
+            direction: Vector::zero(),
+            speed: 0.,
+            rapid: false,
+            dwell: seconds,
+        });
+    }
+
+    /// Duration of each move in seconds, in the order they were pushed
+    pub fn durations(&self) -> Vec<f64> {
+        let Some(acceleration) = self.kinematics.acceleration.filter(|a| *a > 0.) else {
+            return self
+                .moves
+                .iter()
+                .map(|m| if m.speed > 0. { m.length / m.speed } else { 0. } + m.dwell)
+                .collect();
+        };
+
+        // Speed at the start of each move, plus a final stop
+        let mut entry_speeds = vec![0.; self.moves.len() + 1];
+        for (i, pair) in self.moves.windows(2).enumerate() {
+            entry_speeds[i + 1] = self.junction_speed(&pair[0], &pair[1], acceleration);
+        }
+
+        // The machine must be able to decelerate in time for the next junction...
+        for (i, m) in self.moves.iter().enumerate().rev() {
+            entry_speeds[i] = entry_speeds[i]
+                .min((entry_speeds[i + 1].powi(2) + 2. * acceleration * m.length).sqrt());
+        }
+        // ...and accelerate in time from the previous one
+        for (i, m) in self.moves.iter().enumerate() {
+            entry_speeds[i + 1] = entry_speeds[i + 1]
+                .min((entry_speeds[i].powi(2) + 2. * acceleration * m.length).sqrt());
+        }
+
+        self.moves
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                trapezoid_duration(m, entry_speeds[i], entry_speeds[i + 1], acceleration) + m.dwell
+            })
+            .collect()
+    }
+
+    /// Average speed of each move in millimeters / minute given its duration, [None] for moves that take no time
+    pub fn average_speeds(&self, durations: &[f64]) -> Vec<Option<f64>> {
+        self.moves
+            .iter()
+            .zip(durations)
+            .map(|(m, duration)| (*duration > 0.).then(|| m.length / duration * 60.))
+            .collect()
+    }
+
+    /// Maximum speed when moving from one move into the next
+    fn junction_speed(
+        &self,
+        before: &EstimatedMove,
+        after: &EstimatedMove,
+        acceleration: f64,
+    ) -> f64 {
+        if before.rapid != after.rapid
+            || before.direction == Vector::zero()
+            || after.direction == Vector::zero()
+        {
+            return 0.;
+        }
+        let max_speed = before.speed.min(after.speed);
+        let cos_theta = -before.direction.dot(after.direction);
+        if cos_theta > 0.999999 {
+            // Reversal
+            0.
+        } else if cos_theta < -0.999999 {
+            // Straight line
+            max_speed
+        } else {
+            let sin_theta_d2 = (0.5 * (1. - cos_theta)).sqrt();
+            (acceleration * self.kinematics.junction_deviation * sin_theta_d2 / (1. - sin_theta_d2))
+                .sqrt()
+                .min(max_speed)
+        }
+    }
+}
+
+/// Time taken to accelerate from `entry_speed`, cruise, and decelerate to `exit_speed`
+fn trapezoid_duration(
+    m: &EstimatedMove,
+    entry_speed: f64,
+    exit_speed: f64,
+    acceleration: f64,
+) -> f64 {
+    if m.length <= 0. || m.speed <= 0. {
+        return 0.;
+    }
+    let accelerate_distance = (m.speed.powi(2) - entry_speed.powi(2)) / (2. * acceleration);
+    let decelerate_distance = (m.speed.powi(2) - exit_speed.powi(2)) / (2. * acceleration);
+    if accelerate_distance + decelerate_distance <= m.length {
+        (m.speed - entry_speed) / acceleration
+            + (m.speed - exit_speed) / acceleration
+            + (m.length - accelerate_distance - decelerate_distance) / m.speed
+    } else {
+        // Never reaches the requested speed
+        let peak_speed =
+            (acceleration * m.length + (entry_speed.powi(2) + exit_speed.powi(2)) / 2.).sqrt();
+        (peak_speed - entry_speed) / acceleration + (peak_speed - exit_speed) / acceleration
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lyon_geom::point;
+
+    use super::*;
+
+    fn estimate(acceleration: Option<f64>, points: &[Point<f64>]) -> f64 {
+        let mut estimator = TimeEstimator::new(Kinematics {
+            acceleration,
+            ..Default::default()
+        });
+        for pair in points.windows(2) {
+            estimator.push(pair[0], pair[1], (pair[1] - pair[0]).length(), 6000., false);
+        }
+        estimator.durations().iter().sum()
+    }
+
+    #[test]
+    fn without_acceleration_is_length_over_speed() {
+        let duration = estimate(None, &[point(0., 0.), point(100., 0.), point(100., 100.)]);
+        assert!((duration - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn short_move_never_reaches_speed() {
+        // Accelerates for half of the distance and decelerates for the other half
+        let duration = estimate(Some(100.), &[point(0., 0.), point(1., 0.)]);
+        assert!((duration - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn collinear_moves_do_not_slow_down() {
+        let split = estimate(
+            Some(100.),
+            &[point(0., 0.), point(50., 0.), point(100., 0.)],
+        );
+        let whole = estimate(Some(100.), &[point(0., 0.), point(100., 0.)]);
+        assert!((split - whole).abs() < 1e-9);
+    }
+
+    #[test]
+    fn corners_are_slower_than_straights() {
+        let corner = estimate(
+            Some(100.),
+            &[point(0., 0.), point(50., 0.), point(50., 50.)],
+        );
+        let straight = estimate(Some(100.), &[point(0., 0.), point(100., 0.)]);
+        assert!(corner > straight);
+    }
+
+    #[test]
+    fn dwells_stop_the_machine() {
+        let mut estimator = TimeEstimator::new(Kinematics {
+            acceleration: Some(100.),
+            ..Default::default()
+        });
+        estimator.push(point(0., 0.), point(50., 0.), 50., 6000., false);
+        estimator.push_dwell(0.5);
+        estimator.push(point(50., 0.), point(100., 0.), 50., 6000., false);
+        let paused: f64 = estimator.durations().iter().sum();
+        let stopped = 2. * estimate(Some(100.), &[point(0., 0.), point(50., 0.)]);
+        assert!((paused - stopped - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{borrow::Cow, ops::Range};
+
+use g_code::emit::{Field, Token, Value};
+
+/// Largest difference in millimeters between coordinates that are considered the same
+const COORDINATE_TOLERANCE: f64 = 1e-6;
+
+/// Number of the first subprogram, clear of the low numbers that controllers often reserve
+const FIRST_SUBPROGRAM: usize = 1000;
+
+/// Index of an instance and its offset from the subprogram
+type Call = (usize, [f64; 2]);
+
+/// Replace repeated content with calls to subprograms that are defined after the end of the program
+///
+/// Each range of `instances` is compared to the others, ignoring comments. Those that only differ by a translation
+/// become `M98 P<n>` calls inside a `G52` local offset, and the first of them becomes subprogram `O<n>`, ending with
+/// `M99`. Ranges must be sorted and must not overlap.
+pub(crate) fn extract_subprograms<'input>(
+    program: Vec<Token<'input>>,
+    instances: &[Range<usize>],
+) -> Vec<Token<'input>> {
+    // The instance each subprogram is made from, and every instance that calls it with an offset
+    let mut subprograms: Vec<(Range<usize>, Vec<Call>)> = vec![];
+    for (i, instance) in instances.iter().enumerate() {
+        let found = subprograms.iter_mut().find_map(|(body, calls)| {
+            offset(&program[body.clone()], &program[instance.clone()]).map(|offset| (calls, offset))
+        });
+        match found {
+            Some((calls, offset)) => calls.push((i, offset)),
+            None => subprograms.push((instance.clone(), vec![(i, [0., 0.])])),
+        }
+    }
+    subprograms.retain(|(_, calls)| calls.len() > 1);
+    if subprograms.is_empty() {
+        return program;
+    }
+
+    let mut calls = subprograms
+        .iter()
+        .enumerate()
+        .flat_map(|(number, (_, calls))| {
+            calls
+                .iter()
+                .map(move |(i, offset)| (*i, FIRST_SUBPROGRAM + number, *offset))
+        })
+        .collect::<Vec<_>>();
+    calls.sort_by_key(|(i, _, _)| *i);
+
+    let mut extracted = vec![];
+    let mut copied = 0;
+    for (i, number, [x, y]) in calls {
+        let instance = &instances[i];
+        extracted.extend_from_slice(&program[copied..instance.start]);
+        let offset = x != 0. || y != 0.;
+        if offset {
+            extracted.extend(local_offset(x, y));
+        }
+        extracted.push(field("M", Value::Integer(98)));
+        extracted.push(field("P", Value::Integer(number)));
+        if offset {
+            extracted.extend(local_offset(0., 0.));
+        }
+        copied = instance.end;
+    }
+    extracted.extend_from_slice(&program[copied..]);
+
+    for (number, (body, _)) in subprograms.iter().enumerate() {
+        let number = FIRST_SUBPROGRAM + number;
+        // Formatting only starts a new line at a G or M word or after a comment, and `O<n>` has to begin one
+        extracted.push(Token::Comment {
+            is_inline: false,
+            inner: Cow::Owned(format!("Subprogram {number}")),
+        });
+        extracted.push(field("O", Value::Integer(number)));
+        extracted.extend_from_slice(&program[body.clone()]);
+        extracted.push(field("M", Value::Integer(99)));
+    }
+    extracted
+}
+
+/// Translation that turns `body` into `instance`, if that is the only difference between them
+fn offset(body: &[Token], instance: &[Token]) -> Option<[f64; 2]> {
+    let fields = |tokens: &[Token]| -> Vec<(String, Option<f64>)> {
+        tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Field(field) => Some((field.letters.to_string(), field.value.as_f64())),
+                Token::Flag(flag) => Some((flag.letter.to_string(), None)),
+                Token::Comment { .. } => None,
+            })
+            .collect()
+    };
+    let (body, instance) = (fields(body), fields(instance));
+    if body.len() != instance.len() {
+        return None;
+    }
+
+    let mut offset = [None, None];
+    for ((letters, value), (instance_letters, instance_value)) in body.iter().zip(&instance) {
+        if letters != instance_letters {
+            return None;
+        }
+        let axis = match letters.as_str() {
+            "X" => 0,
+            "Y" => 1,
+            _ if value == instance_value => continue,
+            _ => return None,
+        };
+        let difference = (*instance_value)? - (*value)?;
+        match offset[axis] {
+            None => offset[axis] = Some(difference),
+            Some(expected) if (difference - expected).abs() <= COORDINATE_TOLERANCE => {}
+            Some(_) => return None,
+        }
+    }
+    Some(offset.map(Option::unwrap_or_default))
+}
+
+fn local_offset<'input>(x: f64, y: f64) -> [Token<'input>; 3] {
+    [
+        field("G", Value::Integer(52)),
+        field("X", Value::Float(x)),
+        field("Y", Value::Float(y)),
+    ]
+}
+
+fn field<'input>(letters: &'static str, value: Value<'input>) -> Token<'input> {
+    Token::Field(Field {
+        letters: Cow::Borrowed(letters),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use g_code::{
+        emit::{FormatOptions, format_gcode_fmt},
+        parse::snippet_parser,
+    };
+
+    use super::*;
+
+    fn tokens(gcode: &str) -> Vec<Token<'_>> {
+        snippet_parser(gcode).unwrap().iter_emit_tokens().collect()
+    }
+
+    fn format(tokens: &[Token]) -> String {
+        let mut gcode = String::new();
+        format_gcode_fmt(tokens, FormatOptions::default(), &mut gcode).unwrap();
+        gcode
+    }
+
+    #[test]
+    fn translated_instances_become_calls() {
+        let program =
+            tokens("G21\nG0 X1 Y1\nG1 X2 Y1\nG0 X11 Y6\nG1 X12 Y6\nG0 X0 Y0\nG1 X5 Y5\nM2\n");
+        let extracted = extract_subprograms(program, &[1..7, 7..13, 13..19]);
+        assert_eq!(
+            format(&extracted),
+            "G21\nM98 P1000\nG52 X10 Y5\nM98 P1000\nG52 X0 Y0\nG0 X0 Y0\nG1 X5 Y5\nM2;Subprogram 1000\nO1000\nG0 X1 Y1\nG1 X2 Y1\nM99\n"
+        );
+    }
+
+    #[test]
+    fn unique_instances_are_left_alone() {
+        let program = tokens("G0 X1 Y1\nG1 X2 Y1\nG0 X1 Y1\nG1 X2 Y3\n");
+        assert_eq!(
+            extract_subprograms(program.clone(), &[0..6, 6..12]),
+            program
+        );
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+use lyon_geom::{Box2D, CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::Turtle;
+use crate::Float;
+
+/// Turtle that only finds the extents of everything drawn, i.e. to check that a job fits on the machine
+///
+/// Use it with [crate::svg2turtle] to get the extents in millimeters without generating any g-code. Curves are
+/// measured exactly rather than flattened, and travel moves are not included.
+#[derive(Debug, Default, Clone)]
+pub struct BoundingBoxTurtle {
+    /// [None] until something is drawn
+    pub bounding_box: Option<Box2D<Float>>,
+    position: Point<Float>,
+}
+
+impl BoundingBoxTurtle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn extend(&mut self, extents: Box2D<Float>) {
+        self.bounding_box = Some(match self.bounding_box {
+            Some(bounding_box) => {
+                Box2D::from_points([bounding_box.min, bounding_box.max, extents.min, extents.max])
+            }
+            None => extents,
+        });
+    }
+}
+
+impl Turtle for BoundingBoxTurtle {
+    fn begin(&mut self) {}
+
+    fn end(&mut self) {}
+
+    fn comment(&mut self, _comment: String) {}
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.position = to;
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        self.extend(Box2D::from_points([self.position, to]));
+        self.position = to;
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        if svg_arc.is_straight_line() {
+            self.line_to(svg_arc.to);
+        } else {
+            self.extend(svg_arc.to_arc().bounding_box());
+            self.position = svg_arc.to;
+        }
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.extend(Box2D::new(at, at));
+        self.position = at;
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.extend(cbs.bounding_box());
+        self.position = cbs.to;
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.extend(qbs.bounding_box());
+        self.position = qbs.to;
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::Turtle;
+use crate::{Float, GroupOverride, PathSource};
+
+/// Turtle that logs every call made on it along with the element being drawn, to find out why something ended up
+/// where it did
+///
+/// Use it with [crate::svg2turtle] to see what the turtles that generate g-code would be asked to do, or format it
+/// with [fmt::Display] to get the log one call per line.
+#[derive(Debug, Default, Clone)]
+pub struct DebugTurtle {
+    pub log: Vec<DebugEntry>,
+    element: Option<String>,
+}
+
+/// One call on a [DebugTurtle]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugEntry {
+    /// [PathSource::element] of the path being drawn, [None] until the first one
+    pub element: Option<String>,
+    pub call: TurtleCall,
+}
+
+/// A [Turtle] method and its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum TurtleCall {
+    Begin,
+    End,
+    Comment(String),
+    MoveTo(Point<Float>),
+    LineTo(Point<Float>),
+    Arc(SvgArc<Float>),
+    CubicBezier(CubicBezierSegment<Float>),
+    QuadraticBezier(QuadraticBezierSegment<Float>),
+    Drill(Point<Float>),
+    BeginInstance,
+    EndInstance,
+    OverrideSettings(Option<GroupOverride>),
+    Source(PathSource),
+    ChangeFeedrate(f64),
+    ChangePower(Option<f64>),
+    BeginLayer(Option<String>),
+    EndLayer,
+    BeginPass(usize, usize),
+    EndPass,
+}
+
+impl DebugTurtle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, call: TurtleCall) {
+        self.log.push(DebugEntry {
+            element: self.element.clone(),
+            call,
+        });
+    }
+}
+
+impl Turtle for DebugTurtle {
+    fn begin(&mut self) {
+        self.push(TurtleCall::Begin);
+    }
+
+    fn end(&mut self) {
+        self.push(TurtleCall::End);
+    }
+
+    fn comment(&mut self, comment: String) {
+        self.push(TurtleCall::Comment(comment));
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.push(TurtleCall::MoveTo(to));
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        self.push(TurtleCall::LineTo(to));
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        self.push(TurtleCall::Arc(svg_arc));
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.push(TurtleCall::CubicBezier(cbs));
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.push(TurtleCall::QuadraticBezier(qbs));
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.push(TurtleCall::Drill(at));
+    }
+
+    fn begin_instance(&mut self) {
+        self.push(TurtleCall::BeginInstance);
+    }
+
+    fn end_instance(&mut self) {
+        self.push(TurtleCall::EndInstance);
+    }
+
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        self.push(TurtleCall::OverrideSettings(group_override.cloned()));
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        self.element = Some(source.element.clone());
+        self.push(TurtleCall::Source(source.clone()));
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        self.push(TurtleCall::ChangeFeedrate(feedrate));
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        self.push(TurtleCall::ChangePower(power));
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        self.push(TurtleCall::BeginLayer(label.map(str::to_string)));
+    }
+
+    fn end_layer(&mut self) {
+        self.push(TurtleCall::EndLayer);
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        self.push(TurtleCall::BeginPass(pass, passes));
+    }
+
+    fn end_pass(&mut self) {
+        self.push(TurtleCall::EndPass);
+    }
+}
+
+/// Formats as one line per call, prefixed by the element being drawn
+impl fmt::Display for DebugTurtle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.log {
+            writeln!(
+                f,
+                "{}: {:?}",
+                entry.element.as_deref().unwrap_or("-"),
+                entry.call
+            )?;
+        }
+        Ok(())
+    }
+}
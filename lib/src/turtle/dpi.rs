@@ -6,7 +6,7 @@ use uom::si::{
     length::{inch, millimeter},
 };
 
-use crate::Turtle;
+use crate::{Float, GroupOverride, PathSource, Turtle, widen};
 
 /// Wrapper turtle that converts from user units to millimeters at a given DPI
 #[derive(Debug)]
@@ -16,15 +16,15 @@ pub struct DpiConvertingTurtle<T: Turtle> {
 }
 
 impl<T: Turtle> DpiConvertingTurtle<T> {
-    fn to_mm(&self, value: f64) -> f64 {
-        Length::new::<inch>(value / self.dpi).get::<millimeter>()
+    fn to_mm(&self, value: Float) -> Float {
+        Length::new::<inch>(widen(value) / self.dpi).get::<millimeter>() as Float
     }
 
-    fn point_to_mm(&self, p: Point<f64>) -> Point<f64> {
+    fn point_to_mm(&self, p: Point<Float>) -> Point<Float> {
         point(self.to_mm(p.x), self.to_mm(p.y))
     }
 
-    fn vector_to_mm(&self, v: Vector<f64>) -> Vector<f64> {
+    fn vector_to_mm(&self, v: Vector<Float>) -> Vector<Float> {
         vector(self.to_mm(v.x), self.to_mm(v.y))
     }
 }
@@ -42,11 +42,55 @@ impl<T: Turtle> Turtle for DpiConvertingTurtle<T> {
         self.inner.comment(comment)
     }
 
-    fn move_to(&mut self, to: Point<f64>) {
+    fn drill(&mut self, at: Point<Float>) {
+        self.inner.drill(self.point_to_mm(at))
+    }
+
+    fn begin_instance(&mut self) {
+        self.inner.begin_instance()
+    }
+
+    fn end_instance(&mut self) {
+        self.inner.end_instance()
+    }
+
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        self.inner.override_settings(group_override)
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        self.inner.source(source)
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        self.inner.change_feedrate(feedrate)
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        self.inner.change_power(power)
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        self.inner.begin_layer(label)
+    }
+
+    fn end_layer(&mut self) {
+        self.inner.end_layer()
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        self.inner.begin_pass(pass, passes)
+    }
+
+    fn end_pass(&mut self) {
+        self.inner.end_pass()
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
         self.inner.move_to(self.point_to_mm(to))
     }
 
-    fn line_to(&mut self, to: Point<f64>) {
+    fn line_to(&mut self, to: Point<Float>) {
         self.inner.line_to(self.point_to_mm(to))
     }
 
@@ -58,7 +102,7 @@ impl<T: Turtle> Turtle for DpiConvertingTurtle<T> {
             radii,
             x_rotation,
             flags,
-        }: SvgArc<f64>,
+        }: SvgArc<Float>,
     ) {
         self.inner.arc(SvgArc {
             from: self.point_to_mm(from),
@@ -76,7 +120,7 @@ impl<T: Turtle> Turtle for DpiConvertingTurtle<T> {
             ctrl1,
             ctrl2,
             to,
-        }: CubicBezierSegment<f64>,
+        }: CubicBezierSegment<Float>,
     ) {
         self.inner.cubic_bezier(CubicBezierSegment {
             from: self.point_to_mm(from),
@@ -88,7 +132,7 @@ impl<T: Turtle> Turtle for DpiConvertingTurtle<T> {
 
     fn quadratic_bezier(
         &mut self,
-        QuadraticBezierSegment { from, ctrl, to }: QuadraticBezierSegment<f64>,
+        QuadraticBezierSegment { from, ctrl, to }: QuadraticBezierSegment<Float>,
     ) {
         self.inner.quadratic_bezier(QuadraticBezierSegment {
             from: self.point_to_mm(from),
@@ -0,0 +1,85 @@
+use lyon_geom::{CubicBezierSegment, LineSegment, Point, SvgArc};
+
+use crate::{
+    Float,
+    arc::{ArcOrLineSegment, FlattenWithArcs},
+    converter::ConversionConfig,
+    machine::Machine,
+};
+
+/// How curves are broken up into the lines and arcs a machine can cut
+///
+/// Shared by [super::GCodeTurtle] and, with the `rayon` feature, [super::ReorderingTurtle] so that flattening ahead
+/// of time gives the same program.
+#[derive(Debug, Clone, Copy)]
+pub struct Flattening {
+    /// See [crate::ConversionConfig::tolerance]
+    pub tolerance: Float,
+    /// Tolerance for fitting arcs to curves when the machine supports circular interpolation
+    pub arc_tolerance: Option<Float>,
+}
+
+impl Flattening {
+    pub fn new(config: &ConversionConfig, machine: &Machine) -> Self {
+        let supported_functionality = machine.supported_functionality();
+        Self {
+            tolerance: config.tolerance as Float,
+            // Arcs don't carry over to the surface of a sphere
+            arc_tolerance: (supported_functionality.circular_interpolation
+                && config.sphere.is_none())
+            .then(|| {
+                supported_functionality
+                    .arc_tolerance
+                    .unwrap_or(config.tolerance) as Float
+            }),
+        }
+    }
+
+    /// Append the segments to cut along an arc to `segments`
+    pub fn arc(&self, svg_arc: SvgArc<Float>, segments: &mut Vec<ArcOrLineSegment<Float>>) {
+        if svg_arc.is_straight_line() {
+            segments.push(ArcOrLineSegment::Line(LineSegment {
+                from: svg_arc.from,
+                to: svg_arc.to,
+            }));
+            return;
+        }
+        match self.arc_tolerance {
+            Some(arc_tolerance) => {
+                segments.extend(FlattenWithArcs::flattened(&svg_arc, arc_tolerance))
+            }
+            None => lines(
+                svg_arc.from,
+                svg_arc.to_arc().flattened(self.tolerance),
+                segments,
+            ),
+        }
+    }
+
+    /// Append the segments to cut along a cubic Bézier curve to `segments`
+    pub fn cubic_bezier(
+        &self,
+        cbs: CubicBezierSegment<Float>,
+        segments: &mut Vec<ArcOrLineSegment<Float>>,
+    ) {
+        match self.arc_tolerance {
+            Some(arc_tolerance) => {
+                segments.extend(FlattenWithArcs::<Float>::flattened(&cbs, arc_tolerance))
+            }
+            None => lines(cbs.from, cbs.flattened(self.tolerance), segments),
+        }
+    }
+}
+
+/// Join flattened points into lines starting at `from`, appending them to `segments`
+fn lines(
+    from: Point<Float>,
+    points: impl Iterator<Item = Point<Float>>,
+    segments: &mut Vec<ArcOrLineSegment<Float>>,
+) {
+    let mut from = from;
+    for to in points {
+        segments.push(ArcOrLineSegment::Line(LineSegment { from, to }));
+        from = to;
+    }
+}
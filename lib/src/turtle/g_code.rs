@@ -1,12 +1,27 @@
-use std::{borrow::Cow, fmt::Debug};
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug},
+    ops::Range,
+    time::Duration,
+};
 
-use ::g_code::{command, emit::Token};
-use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+use ::g_code::{
+    command,
+    emit::{Field, Token, Value},
+};
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc, Vector};
 
-use super::Turtle;
+use super::{Flattening, LengthTurtle, Turtle};
 use crate::{
-    arc::{ArcOrLineSegment, FlattenWithArcs},
-    machine::Machine,
+    Float,
+    arc::ArcOrLineSegment,
+    converter::{
+        CommentStyle, ConversionConfig, DrillCycle, Drilling, FeedratePolicy, GroupOverride,
+        ParkPosition, ProgressMarkers,
+    },
+    machine::{ArcFormat, Machine},
+    postprocess::ProgramWriter,
+    statistics::{ConversionStatistics, TimeEstimator},
 };
 
 /// Maps path segments into g-code operations
@@ -14,46 +29,642 @@ use crate::{
 pub struct GCodeTurtle<'input> {
     pub machine: Machine<'input>,
     pub tolerance: f64,
+    pub flattening: Flattening,
+    /// Buffer reused by every curve so that flattening one does not allocate
+    pub segments: Vec<ArcOrLineSegment<Float>>,
     pub feedrate: f64,
+    pub feedrate_policy: FeedratePolicy,
+    /// Feedrate of the most recently emitted F word, reset at the start of each path
+    /// for [FeedratePolicy::PerPath]
+    pub last_feedrate: Option<f64>,
+    pub comment_style: CommentStyle,
+    /// See [crate::ConversionConfig::corner_slowdown]
+    pub corner_slowdown: f64,
+    /// See [crate::ConversionConfig::safe_z]
+    pub safe_z: Option<f64>,
+    /// See [crate::ConversionConfig::cut_z]
+    pub cut_z: Option<f64>,
+    pub retract_between_paths: bool,
+    /// See [crate::ConversionConfig::skip_redundant_commands]
+    pub skip_redundant_commands: bool,
+    /// Last Z height the tool was moved to
+    pub z: Option<f64>,
+    pub park: Option<ParkPosition>,
+    /// Never turn the tool on, see [crate::ConversionConfig::dry_run]
+    pub dry_run: bool,
+    /// Laser power / spindle speed for cutting moves, see [crate::ConversionConfig::power]
+    pub power: Option<f64>,
+    pub power_ramping: bool,
+    /// See [crate::ConversionConfig::min_power]
+    pub min_power: Option<f64>,
+    /// Power of the most recently emitted S word, reset at the start of each path unless
+    /// [Self::skip_redundant_commands] is set, in which case the machine's tool sequences count too
+    pub last_power: Option<f64>,
+    /// S words to adjust for [Self::power_ramping]
+    pub power_marks: Vec<PowerMark>,
     pub program: Vec<Token<'input>>,
+    /// Linear move that has not been emitted yet, so that its feedrate can depend on the next move
+    pub pending_line: Option<(Point<f64>, Point<f64>)>,
+    /// Comments made while [Self::pending_line] was held back
+    pub deferred_comments: Vec<Token<'input>>,
+    /// Last position the tool was moved to
+    pub position: Option<Point<f64>>,
+    pub statistics: ConversionStatistics,
+    /// Measures the moves for [Self::statistics]
+    pub lengths: LengthTurtle,
+    pub estimator: TimeEstimator,
+    /// Program length after each move given to the [Self::estimator], used to place progress markers
+    pub timeline: Vec<usize>,
+    /// Program index where each path begins, including the comments describing it
+    pub path_starts: Vec<usize>,
+    /// Program index where the last path ends and the end sequence begins
+    pub paths_end: usize,
+    /// See [crate::ConversionConfig::checkpoint_interval]
+    pub checkpoint_interval: Option<usize>,
+    /// Index of the first path in the whole drawing, when converting a [crate::ConversionOptions::path_range]
+    pub first_path: usize,
+    /// See [crate::ConversionConfig::subprograms]
+    pub subprograms: bool,
+    /// See [crate::ConversionConfig::drilling]
+    pub drilling: Option<Drilling>,
+    /// See [crate::ConversionConfig::grid]
+    pub grid: Option<f64>,
+    /// Program ranges of each outermost [Turtle::begin_instance]
+    pub instances: Vec<Range<usize>>,
+    /// Depth of nested instances and the program index where the outermost one began
+    pub instance_start: Option<(usize, usize)>,
+    /// [Self::feedrate] from the [ConversionConfig], restored once a [GroupOverride] no longer applies
+    pub configured_feedrate: f64,
+    /// [Self::power] from the [ConversionConfig]
+    pub configured_power: Option<f64>,
+    /// [Self::cut_z] from the [ConversionConfig]
+    pub configured_cut_z: Option<f64>,
+    /// Where to write the program as each path starts, see [crate::svg2program_fmt]
+    ///
+    /// Written tokens are removed from [Self::program], so program indices recorded along the way no longer line up.
+    pub writer: Option<ProgramWriter<&'input mut dyn fmt::Write>>,
+}
+
+/// S word that [Self::power_ramping] may scale down, see [GCodeTurtle::power_marks]
+#[derive(Debug, Clone, Copy)]
+pub struct PowerMark {
+    /// Index of the S word in the program
+    pub index: usize,
+    /// Index of the move it belongs to in the [TimeEstimator]
+    pub move_index: usize,
+    /// Power and feedrate when the move was made
+    pub power: f64,
+    pub feedrate: f64,
 }
 
 impl<'input> GCodeTurtle<'input> {
-    fn circular_interpolation(&self, svg_arc: SvgArc<f64>) -> Vec<Token<'input>> {
+    pub fn new(config: &ConversionConfig, machine: Machine<'input>) -> Self {
+        let feedrate = machine.kinematics().clamp_feedrate(config.feedrate);
+        let estimator = TimeEstimator::new(*machine.kinematics());
+        let power = config
+            .power
+            .map(|power| if config.dry_run { 0. } else { power });
+        let cut_z = config
+            .cut_z
+            .filter(|_| !config.dry_run)
+            .map(|cut_z| on_grid(cut_z, config.grid));
+        let flattening = Flattening::new(config, &machine);
+        // Speeds can only be modeled with an acceleration
+        let power_ramping = config.power_ramping && machine.kinematics().acceleration.is_some();
+        Self {
+            machine,
+            tolerance: config.tolerance,
+            flattening,
+            segments: vec![],
+            feedrate,
+            feedrate_policy: config.feedrate_policy,
+            last_feedrate: None,
+            comment_style: config.comment_style,
+            corner_slowdown: config.corner_slowdown,
+            safe_z: config.safe_z.map(|safe_z| on_grid(safe_z, config.grid)),
+            cut_z,
+            retract_between_paths: config.retract_between_paths,
+            skip_redundant_commands: config.skip_redundant_commands,
+            z: None,
+            park: config.park,
+            power,
+            dry_run: config.dry_run,
+            power_ramping,
+            min_power: config.min_power,
+            last_power: None,
+            power_marks: vec![],
+            program: vec![],
+            pending_line: None,
+            deferred_comments: vec![],
+            position: None,
+            statistics: ConversionStatistics::default(),
+            lengths: LengthTurtle::new(config.tolerance as Float),
+            estimator,
+            timeline: vec![],
+            path_starts: vec![],
+            paths_end: 0,
+            checkpoint_interval: config.checkpoint_interval,
+            first_path: 0,
+            subprograms: config.subprograms,
+            drilling: config.drilling.map(|drilling| Drilling {
+                depth: on_grid(drilling.depth, config.grid),
+                retract: on_grid(drilling.retract, config.grid),
+                ..drilling
+            }),
+            grid: config.grid,
+            instances: vec![],
+            instance_start: None,
+            configured_feedrate: feedrate,
+            configured_power: power,
+            configured_cut_z: cut_z,
+            writer: None,
+        }
+    }
+
+    fn circular_interpolation(&mut self, svg_arc: SvgArc<f64>) {
         debug_assert!((svg_arc.radii.x.abs() - svg_arc.radii.y.abs()).abs() < f64::EPSILON);
-        match (svg_arc.flags.large_arc, svg_arc.flags.sweep) {
-            (false, true) => command!(CounterclockwiseCircularInterpolation {
+        let arc_format = self.machine.supported_functionality().arc_format;
+        if svg_arc.flags.large_arc && arc_format == ArcFormat::Radius {
+            let (left, right) = svg_arc.to_arc().split(0.5);
+            let (mut left, mut right) = (left.to_svg_arc(), right.to_svg_arc());
+            left.to = self.snap(left.to);
+            right.from = left.to;
+            self.circular_interpolation(left);
+            self.circular_interpolation(right);
+            return;
+        }
+
+        let arc = svg_arc.to_arc();
+        let bounding_box = arc.bounding_box();
+        self.lengths.arc(SvgArc {
+            from: svg_arc.from.cast(),
+            to: svg_arc.to.cast(),
+            radii: svg_arc.radii.cast(),
+            x_rotation: svg_arc.x_rotation.cast(),
+            flags: svg_arc.flags,
+        });
+        self.statistics
+            .extend_bounding_box([bounding_box.min, bounding_box.max]);
+        self.position = Some(svg_arc.to);
+
+        let center = self
+            .snap((arc.center - svg_arc.from).to_point())
+            .to_vector();
+        let tokens = match (arc_format, svg_arc.flags.sweep) {
+            (ArcFormat::Radius, true) => command!(CounterclockwiseCircularInterpolation {
                 X: svg_arc.to.x,
                 Y: svg_arc.to.y,
                 R: svg_arc.radii.x,
-                F: self.feedrate,
-            })
-            .into_token_vec(),
-            (false, false) => command!(ClockwiseCircularInterpolation {
+            }),
+            (ArcFormat::Radius, false) => command!(ClockwiseCircularInterpolation {
                 X: svg_arc.to.x,
                 Y: svg_arc.to.y,
                 R: svg_arc.radii.x,
-                F: self.feedrate,
-            })
-            .into_token_vec(),
-            (true, _) => {
-                let (left, right) = svg_arc.to_arc().split(0.5);
-                let mut token_vec = self.circular_interpolation(left.to_svg_arc());
-                token_vec.append(&mut self.circular_interpolation(right.to_svg_arc()));
-                token_vec
+            }),
+            (ArcFormat::Center, true) => command!(CounterclockwiseCircularInterpolation {
+                X: svg_arc.to.x,
+                Y: svg_arc.to.y,
+                I: center.x,
+                J: center.y,
+            }),
+            (ArcFormat::Center, false) => command!(ClockwiseCircularInterpolation {
+                X: svg_arc.to.x,
+                Y: svg_arc.to.y,
+                I: center.x,
+                J: center.y,
+            }),
+        };
+        self.cutting_move(tokens.into_token_vec(), self.feedrate);
+    }
+
+    /// Cut along the segments that `flatten` gives from the [Self::flattening]
+    fn cut(&mut self, flatten: impl FnOnce(&Flattening, &mut Vec<ArcOrLineSegment<Float>>)) {
+        let mut segments = std::mem::take(&mut self.segments);
+        flatten(&self.flattening, &mut segments);
+        for segment in segments.drain(..) {
+            match segment {
+                ArcOrLineSegment::Arc(arc) => self.cut_arc(SvgArc {
+                    from: arc.from.cast(),
+                    to: arc.to.cast(),
+                    radii: arc.radii.cast(),
+                    x_rotation: arc.x_rotation.cast(),
+                    flags: arc.flags,
+                }),
+                ArcOrLineSegment::Line(line) => self.cut_line(line.to.cast()),
+            }
+        }
+        self.segments = segments;
+    }
+
+    /// Cut in a straight line, holding it back in case the next one continues in the same direction
+    fn cut_line(&mut self, to: Point<f64>) {
+        let to = self.snap(to);
+        if self.grid.is_some() && self.position == Some(to) {
+            return;
+        }
+        self.tool_on();
+        let from = self.position.unwrap_or(to);
+        self.flush_pending_line(Some(to - from));
+        self.lengths.line_to(to.cast());
+        self.statistics.extend_bounding_box([from, to]);
+        self.position = Some(to);
+        self.pending_line = Some((from, to));
+    }
+
+    /// Cut along an arc, or along its chord if the arc is too shallow for the firmware to tell apart from a line
+    fn cut_arc(&mut self, svg_arc: SvgArc<f64>) {
+        let svg_arc = match (self.grid, self.position) {
+            (Some(_), Some(position)) => {
+                let svg_arc = SvgArc {
+                    from: position,
+                    to: self.snap(svg_arc.to),
+                    ..svg_arc
+                };
+                // Snapping can bring the ends of a short arc together
+                if svg_arc.is_straight_line() {
+                    self.cut_line(svg_arc.to);
+                    return;
+                }
+                svg_arc
+            }
+            _ => svg_arc,
+        };
+        let arc = svg_arc.to_arc();
+        if let Some(arc_tolerance) = self.machine.supported_functionality().arc_tolerance {
+            let sagitta = arc.radii.x.abs() * (1. - (arc.sweep_angle.radians / 2.).cos());
+            if sagitta < arc_tolerance {
+                self.cut_line(svg_arc.to);
+                return;
+            }
+        }
+
+        self.flush_pending_line(Some(arc.sample_tangent(0.)));
+        let cut_length = self.lengths.cut_length;
+        self.circular_interpolation(svg_arc);
+        self.record_move(
+            svg_arc.from,
+            svg_arc.to,
+            self.lengths.cut_length - cut_length,
+            self.feedrate,
+            false,
+        );
+    }
+
+    /// Emit a cutting move, adding F and S words as needed
+    ///
+    /// Words go straight into the [Self::program] since this runs for every segment of every path.
+    fn cutting_move(&mut self, tokens: Vec<Token<'input>>, feedrate: f64) {
+        self.program.extend(tokens);
+        self.push_feedrate(feedrate);
+        if let Some(power) = self.power
+            && (self.power_ramping || self.last_power != Some(power))
+        {
+            // Ramping adjusts the S word once move durations are known
+            self.power_marks.push(PowerMark {
+                index: self.program.len(),
+                move_index: self.timeline.len(),
+                power,
+                feedrate: self.feedrate,
+            });
+            self.program.push(Token::Field(Field {
+                letters: Cow::Borrowed("S"),
+                value: Value::Float(self.floor_power(power)),
+            }));
+            self.last_power = Some(power);
+        }
+    }
+
+    /// Raise power that is too low for the tool to fire up to [Self::min_power], leaving zero as-is
+    fn floor_power(&self, power: f64) -> f64 {
+        match self.min_power {
+            Some(min_power) if power > 0. && power < min_power => min_power,
+            _ => power,
+        }
+    }
+
+    /// Appends an F word to the cutting move just emitted if the [FeedratePolicy] calls for one
+    fn push_feedrate(&mut self, feedrate: f64) {
+        let emit = match self.feedrate_policy {
+            FeedratePolicy::EveryMove => true,
+            FeedratePolicy::PerPath | FeedratePolicy::OnChange => {
+                self.last_feedrate != Some(feedrate)
+            }
+        };
+        if emit {
+            self.program.push(Token::Field(Field {
+                letters: Cow::Borrowed("F"),
+                value: Value::Float(feedrate),
+            }));
+            self.last_feedrate = Some(feedrate);
+        }
+    }
+
+    /// Record a move from `from` to `to` for time estimation
+    fn record_move(
+        &mut self,
+        from: Point<f64>,
+        to: Point<f64>,
+        length: f64,
+        feedrate: f64,
+        rapid: bool,
+    ) {
+        self.estimator.push(from, to, length, feedrate, rapid);
+        self.timeline.push(self.program.len());
+    }
+
+    /// Emit the [Self::pending_line], slowing down if the next move turns sharply
+    fn flush_pending_line(&mut self, next_direction: Option<Vector<f64>>) {
+        let Some((from, to)) = self.pending_line.take() else {
+            return;
+        };
+        // Measuring the angle is only worth it when it can change the feedrate
+        let angle = next_direction
+            .filter(|_| self.corner_slowdown != 0.)
+            .map(|next| (to - from).angle_to(next).radians.abs())
+            .filter(|angle| angle.is_finite())
+            .unwrap_or(0.);
+        let feedrate = self.feedrate / (1. + self.corner_slowdown * angle / std::f64::consts::PI);
+
+        self.cutting_move(
+            command!(LinearInterpolation { X: to.x, Y: to.y }).into_token_vec(),
+            feedrate,
+        );
+        self.record_move(from, to, (to - from).length(), feedrate, false);
+        self.program.append(&mut self.deferred_comments);
+    }
+
+    /// Estimate the duration of the program and insert progress markers according to it
+    pub fn finish_estimate(&mut self, markers: ProgressMarkers, interval: usize) {
+        let durations = self.estimator.durations();
+        let total = durations.iter().sum::<f64>();
+        self.statistics.estimated_duration = Duration::from_secs_f64(total);
+        if self.power_ramping {
+            self.ramp_power(&durations);
+        }
+        self.insert_progress_markers(&durations, markers, interval);
+    }
+
+    /// Scale S words by how fast each move actually goes compared to the nominal feedrate,
+    /// so that slow sections like corners receive the same energy per length
+    fn ramp_power(&mut self, durations: &[f64]) {
+        let average_speeds = self.estimator.average_speeds(durations);
+        for mark in &self.power_marks {
+            if let Some(Some(speed)) = average_speeds.get(mark.move_index) {
+                let ratio = if mark.feedrate > 0. {
+                    (speed / mark.feedrate).min(1.)
+                } else {
+                    1.
+                };
+                self.program[mark.index] = Token::Field(Field {
+                    letters: Cow::Borrowed("S"),
+                    value: Value::Float(self.floor_power(mark.power * ratio)),
+                });
+            }
+        }
+    }
+
+    /// Insert a marker each time the job advances by at least `interval` percent, and once it completes
+    fn insert_progress_markers(
+        &mut self,
+        durations: &[f64],
+        markers: ProgressMarkers,
+        interval: usize,
+    ) {
+        let total = durations.iter().sum::<f64>();
+        if markers == ProgressMarkers::None || total <= 0. {
+            return;
+        }
+
+        let mut next_percent = 0;
+        let mut insertions = vec![];
+        let mut elapsed = 0.;
+        for (&index, duration) in self.timeline.iter().zip(durations) {
+            elapsed += duration;
+            let percent = ((elapsed / total * 100.).floor() as usize).min(100);
+            if percent >= next_percent {
+                insertions.push((index, percent));
+                // Always finish with a 100% marker
+                next_percent = if percent == 100 {
+                    usize::MAX
+                } else {
+                    (percent + interval.max(1)).min(100)
+                };
             }
         }
+
+        // Insert back to front so that earlier indices remain valid
+        for (index, percent) in insertions.into_iter().rev() {
+            let tokens = match markers {
+                ProgressMarkers::None => unreachable!(),
+                ProgressMarkers::M73 => vec![
+                    Token::Field(Field {
+                        letters: Cow::Borrowed("M"),
+                        value: Value::Integer(73),
+                    }),
+                    Token::Field(Field {
+                        letters: Cow::Borrowed("P"),
+                        value: Value::Integer(percent),
+                    }),
+                ],
+                ProgressMarkers::Comment => {
+                    vec![self.comment_token(format!("Progress: {percent}%"))]
+                }
+            };
+            let count = tokens.len();
+            self.program.splice(index..index, tokens);
+            self.shift_indices(index, count);
+        }
+    }
+
+    /// Keep recorded program indices valid after inserting `count` tokens at `index`
+    fn shift_indices(&mut self, index: usize, count: usize) {
+        let shift = |i: &mut usize, inclusive: bool| {
+            if *i > index || (inclusive && *i == index) {
+                *i += count;
+            }
+        };
+        for instance in &mut self.instances {
+            shift(&mut instance.start, true);
+            shift(&mut instance.end, false);
+        }
+        for path_start in &mut self.path_starts {
+            shift(path_start, true);
+        }
+        shift(&mut self.paths_end, false);
+    }
+
+    /// Comment where the job can be restarted from, if the [Self::checkpoint_interval] is due
+    fn checkpoint(&mut self, to: Point<f64>) {
+        let path = self.first_path + self.statistics.paths - 1;
+        if !self
+            .checkpoint_interval
+            .is_some_and(|interval| interval > 0 && path.is_multiple_of(interval))
+        {
+            return;
+        }
+        let mut checkpoint = format!(
+            "checkpoint path={path} x={:.3} y={:.3} f={}",
+            to.x, to.y, self.feedrate
+        );
+        if let Some(power) = self.power {
+            checkpoint += &format!(" s={power}");
+        }
+        if let Some(z) = self.z {
+            checkpoint += &format!(" z={z}");
+        }
+        let token = self.comment_token(checkpoint);
+        self.program.push(token);
+    }
+
+    fn comment_token(&self, comment: String) -> Token<'input> {
+        match self.comment_style {
+            CommentStyle::Semicolon => Token::Comment {
+                is_inline: false,
+                inner: Cow::Owned(comment),
+            },
+            // Nested parentheses would terminate the comment early
+            CommentStyle::Parentheses => Token::Comment {
+                is_inline: true,
+                inner: Cow::Owned(comment.replace('(', "[").replace(')', "]")),
+            },
+        }
+    }
+
+    /// Append a machine sequence, keeping track of any modal F and S words it sets for [Self::skip_redundant_commands]
+    fn sequence(&mut self, tokens: impl IntoIterator<Item = Token<'input>>) {
+        for token in tokens {
+            if self.skip_redundant_commands
+                && let Token::Field(field) = &token
+            {
+                match field.letters.as_ref() {
+                    "F" => self.last_feedrate = field.value.as_f64(),
+                    "S" => self.last_power = field.value.as_f64(),
+                    _ => {}
+                }
+            }
+            self.program.push(token);
+        }
     }
 
     fn tool_on(&mut self) {
-        self.program.extend(self.machine.tool_on());
+        if !self.dry_run {
+            let tokens = self.machine.tool_on().collect::<Vec<_>>();
+            let toggled = !tokens.is_empty();
+            self.sequence(tokens);
+            if toggled {
+                self.wait_for_pen();
+            }
+        }
         self.program.extend(self.machine.absolute());
+        if let Some(cut_z) = self.cut_z
+            && self.z != Some(cut_z)
+        {
+            self.program
+                .extend(command!(LinearInterpolation { Z: cut_z }).into_token_vec());
+            self.push_feedrate(self.feedrate);
+            self.z = Some(cut_z);
+        }
+    }
+
+    fn rapid_to(&mut self, to: Point<f64>) {
+        let to = self.snap(to);
+        self.program
+            .append(&mut command!(RapidPositioning { X: to.x, Y: to.y }).into_token_vec());
+        let from = self.position.unwrap_or(to);
+        let length = (to - from).length();
+        self.lengths.move_to(to.cast());
+        self.position = Some(to);
+        let rapid_feedrate = self
+            .machine
+            .kinematics()
+            .rapid_feedrate
+            .unwrap_or(self.feedrate);
+        self.record_move(from, to, length, rapid_feedrate, true);
+    }
+
+    /// Rapid to [Self::safe_z], unless the tool is already there
+    fn retract(&mut self) {
+        if let Some(safe_z) = self.safe_z
+            && self.z != Some(safe_z)
+        {
+            self.program
+                .append(&mut command!(RapidPositioning { Z: safe_z }).into_token_vec());
+            self.z = Some(safe_z);
+        }
     }
 
     fn tool_off(&mut self) {
-        self.program.extend(self.machine.tool_off());
+        let tokens = self.machine.tool_off().collect::<Vec<_>>();
+        let toggled = !tokens.is_empty();
+        self.sequence(tokens);
+        if toggled {
+            self.wait_for_pen();
+        }
         self.program.extend(self.machine.absolute());
     }
+
+    /// Record the time a [crate::PenServo] takes to lift or lower the pen, during which the machine stands still
+    fn wait_for_pen(&mut self) {
+        if let Some(pen_servo) = self.machine.pen_servo() {
+            self.estimator.push_dwell(pen_servo.transition_time());
+            self.timeline.push(self.program.len());
+        }
+    }
+
+    /// Begin a new path at `to`, turning the tool off and moving there unless the path `continues` from the last one
+    fn start_path(&mut self, to: Point<f64>, continues: bool) {
+        self.flush_pending_line(None);
+        self.write_program();
+        let start = self
+            .program
+            .iter()
+            .rposition(|token| !matches!(token, Token::Comment { .. }))
+            .map_or(0, |index| index + 1);
+        self.path_starts.push(start);
+        if !continues {
+            self.tool_off();
+            // Always retract before the first rapid, since the starting height is unknown
+            if self.retract_between_paths || self.z.is_none() {
+                self.retract();
+            }
+        }
+        if self.feedrate_policy == FeedratePolicy::PerPath {
+            self.last_feedrate = None;
+        }
+        if !self.skip_redundant_commands {
+            self.last_power = None;
+        }
+        self.statistics.paths += 1;
+        self.checkpoint(to);
+        if !continues {
+            self.rapid_to(to);
+        }
+    }
+
+    /// Hand everything generated so far to the [Self::writer], if there is one
+    ///
+    /// A failed write is reported when the conversion finishes.
+    fn write_program(&mut self) {
+        if let Some(writer) = &mut self.writer {
+            let _ = writer.write(&self.program);
+            self.program.clear();
+        }
+    }
+
+    /// `point` on the [Self::grid], if there is one
+    fn snap(&self, point: Point<f64>) -> Point<f64> {
+        Point::new(on_grid(point.x, self.grid), on_grid(point.y, self.grid))
+    }
+
+    /// Whether a path starting at `to` carries on from where the tool already is,
+    /// so that it can be cut without turning the tool off and back on
+    fn continues_from_position(&self, to: Point<f64>) -> bool {
+        self.skip_redundant_commands
+            && !(self.retract_between_paths && self.safe_z.is_some())
+            && self
+                .position
+                .is_some_and(|position| (to - position).length() <= self.tolerance)
+    }
 }
 
 impl<'input> Turtle for GCodeTurtle<'input> {
@@ -66,90 +677,191 @@ impl<'input> Turtle for GCodeTurtle<'input> {
     }
 
     fn end(&mut self) {
+        self.flush_pending_line(None);
+        self.paths_end = self.program.len();
         self.program.extend(self.machine.tool_off());
         self.program.extend(self.machine.absolute());
+        self.retract();
+        if let Some(park) = self.park {
+            let to = match park {
+                ParkPosition::BehindJob => Point::new(
+                    0.,
+                    self.statistics
+                        .bounding_box
+                        .map(|bounding_box| bounding_box.max.y)
+                        .unwrap_or_default(),
+                ),
+                ParkPosition::Custom([x, y]) => Point::new(x, y),
+            };
+            self.rapid_to(to);
+        }
         self.program.extend(self.machine.program_end());
+        self.statistics.cut_length = self.lengths.cut_length;
+        self.statistics.travel_length = self.lengths.travel_length;
     }
 
-    fn comment(&mut self, comment: String) {
-        self.program.push(Token::Comment {
-            is_inline: false,
-            inner: Cow::Owned(comment),
-        });
-    }
+    fn drill(&mut self, at: Point<Float>) {
+        let at = self.snap(at.cast());
+        let Some(drilling) = self.drilling else {
+            return;
+        };
+        // Holes always start from a retracted tool, even right where a cut ends
+        self.start_path(at, false);
+        self.statistics.extend_bounding_box([at]);
+        if self.dry_run {
+            return;
+        }
+        let tokens = self.machine.tool_on().collect::<Vec<_>>();
+        self.sequence(tokens);
+        self.program.extend(self.machine.absolute());
 
-    fn move_to(&mut self, to: Point<f64>) {
-        self.tool_off();
-        self.program
-            .append(&mut command!(RapidPositioning { X: to.x, Y: to.y }).into_token_vec());
+        let field = |letters, value| {
+            Token::Field(Field {
+                letters: Cow::Borrowed(letters),
+                value: Value::Float(value),
+            })
+        };
+        let canned_cycle = |number, peck: Option<f64>| {
+            [
+                Some(field("G", number)),
+                Some(field("X", at.x)),
+                Some(field("Y", at.y)),
+                Some(field("Z", drilling.depth)),
+                Some(field("R", drilling.retract)),
+                peck.map(|peck| field("Q", peck)),
+                Some(field("F", self.feedrate)),
+                Some(field("G", 80.)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+        };
+        let mut tokens = match drilling.cycle {
+            DrillCycle::Simple => canned_cycle(81., None),
+            DrillCycle::Peck { peck } => canned_cycle(83., Some(peck)),
+            DrillCycle::Plunge => [
+                command!(RapidPositioning {
+                    Z: drilling.retract
+                })
+                .into_token_vec(),
+                command!(LinearInterpolation {
+                    Z: drilling.depth,
+                    F: self.feedrate
+                })
+                .into_token_vec(),
+                command!(RapidPositioning {
+                    Z: drilling.retract
+                })
+                .into_token_vec(),
+            ]
+            .concat(),
+        };
+        self.program.append(&mut tokens);
+        // Canned cycles return to either the R plane or the starting height depending on G98/G99
+        self.z = match drilling.cycle {
+            DrillCycle::Plunge => Some(drilling.retract),
+            DrillCycle::Simple | DrillCycle::Peck { .. } => None,
+        };
+
+        let depth = (drilling.retract - drilling.depth).abs();
+        self.record_move(at, at, depth, self.feedrate, false);
+        self.last_feedrate = Some(self.feedrate);
     }
 
-    fn line_to(&mut self, to: Point<f64>) {
-        self.tool_on();
-        self.program.append(
-            &mut command!(LinearInterpolation {
-                X: to.x,
-                Y: to.y,
-                F: self.feedrate,
-            })
-            .into_token_vec(),
-        );
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        let feedrate = group_override
+            .and_then(|group_override| group_override.feedrate)
+            .map_or(self.configured_feedrate, |feedrate| {
+                self.machine.kinematics().clamp_feedrate(feedrate)
+            });
+        let power = group_override
+            .and_then(|group_override| group_override.power)
+            .map(|power| if self.dry_run { 0. } else { power })
+            .or(self.configured_power);
+        let cut_z = group_override
+            .and_then(|group_override| group_override.cut_z)
+            .filter(|_| !self.dry_run)
+            .map(|cut_z| on_grid(cut_z, self.grid))
+            .or(self.configured_cut_z);
+        if (feedrate, power, cut_z) == (self.feedrate, self.power, self.cut_z) {
+            return;
+        }
+        // The pending line belongs to the previous group
+        self.flush_pending_line(None);
+        self.feedrate = feedrate;
+        self.power = power;
+        self.cut_z = cut_z;
     }
 
-    fn arc(&mut self, svg_arc: SvgArc<f64>) {
-        if svg_arc.is_straight_line() {
-            self.line_to(svg_arc.to);
+    fn begin_instance(&mut self) {
+        if !self.subprograms {
             return;
         }
+        self.instance_start = Some(match self.instance_start {
+            Some((depth, start)) => (depth + 1, start),
+            None => {
+                self.flush_pending_line(None);
+                (1, self.program.len())
+            }
+        });
+    }
 
-        self.tool_on();
+    fn end_instance(&mut self) {
+        match self.instance_start {
+            Some((1, start)) => {
+                self.flush_pending_line(None);
+                if start < self.program.len() {
+                    self.instances.push(start..self.program.len());
+                }
+                self.instance_start = None;
+            }
+            Some((depth, start)) => self.instance_start = Some((depth - 1, start)),
+            None => {}
+        }
+    }
 
-        if self
-            .machine
-            .supported_functionality()
-            .circular_interpolation
-        {
-            FlattenWithArcs::flattened(&svg_arc, self.tolerance)
-                .into_iter()
-                .for_each(|segment| match segment {
-                    ArcOrLineSegment::Arc(arc) => {
-                        self.program.append(&mut self.circular_interpolation(arc))
-                    }
-                    ArcOrLineSegment::Line(line) => {
-                        self.line_to(line.to);
-                    }
-                });
+    fn comment(&mut self, comment: String) {
+        let token = self.comment_token(comment);
+        if self.pending_line.is_some() {
+            self.deferred_comments.push(token);
         } else {
-            svg_arc
-                .to_arc()
-                .flattened(self.tolerance)
-                .for_each(|point| self.line_to(point));
-        };
+            self.program.push(token);
+        }
     }
 
-    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<f64>) {
+    fn move_to(&mut self, to: Point<Float>) {
+        let to = self.snap(to.cast());
+        let continues = self.continues_from_position(to);
+        self.start_path(to, continues);
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        self.cut_line(to.cast());
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
         self.tool_on();
+        self.cut(|flattening, segments| flattening.arc(svg_arc, segments));
+    }
 
-        if self
-            .machine
-            .supported_functionality()
-            .circular_interpolation
-        {
-            FlattenWithArcs::<f64>::flattened(&cbs, self.tolerance)
-                .into_iter()
-                .for_each(|segment| match segment {
-                    ArcOrLineSegment::Arc(arc) => {
-                        self.program.append(&mut self.circular_interpolation(arc))
-                    }
-                    ArcOrLineSegment::Line(line) => self.line_to(line.to),
-                });
-        } else {
-            cbs.flattened(self.tolerance)
-                .for_each(|point| self.line_to(point));
-        };
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.tool_on();
+        self.cut(|flattening, segments| flattening.cubic_bezier(cbs, segments));
     }
 
-    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<f64>) {
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
         self.cubic_bezier(qbs.to_cubic());
     }
 }
+
+/// `value` on a `grid` this many millimeters apart, if there is one
+fn on_grid(value: f64, grid: Option<f64>) -> f64 {
+    match grid {
+        // Dividing by the number of steps in a millimeter keeps i.e. 3 × 0.1 from coming out as 0.30000000000000004
+        Some(grid) => {
+            let steps = 1. / grid;
+            (value * steps).round() / steps
+        }
+        None => value,
+    }
+}
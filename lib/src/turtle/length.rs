@@ -0,0 +1,84 @@
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::Turtle;
+use crate::{Float, widen};
+
+/// Turtle that measures how far the tool moves while cutting and while traveling between paths
+///
+/// Use it with [crate::svg2turtle] for a quick estimate of how long a job takes or how much ink it uses, without
+/// generating any g-code. The lengths in [crate::ConversionStatistics] are measured the same way.
+#[derive(Debug, Clone)]
+pub struct LengthTurtle {
+    /// Distance moved while cutting in millimeters
+    pub cut_length: f64,
+    /// Distance moved between paths in millimeters, in a straight line from the end of one to the start of the next
+    pub travel_length: f64,
+    /// Tolerance for measuring curves, except for circular arcs which are measured exactly
+    tolerance: Float,
+    position: Option<Point<Float>>,
+}
+
+impl LengthTurtle {
+    pub fn new(tolerance: Float) -> Self {
+        Self {
+            cut_length: 0.,
+            travel_length: 0.,
+            tolerance,
+            position: None,
+        }
+    }
+
+    fn cut(&mut self, length: Float, to: Point<Float>) {
+        self.cut_length += widen(length);
+        self.position = Some(to);
+    }
+}
+
+impl Turtle for LengthTurtle {
+    fn begin(&mut self) {}
+
+    fn end(&mut self) {}
+
+    fn comment(&mut self, _comment: String) {}
+
+    fn move_to(&mut self, to: Point<Float>) {
+        if let Some(from) = self.position {
+            self.travel_length += widen((to - from).length());
+        }
+        self.position = Some(to);
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        let from = self.position.unwrap_or(to);
+        self.cut((to - from).length(), to);
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        if svg_arc.is_straight_line() {
+            self.line_to(svg_arc.to);
+            return;
+        }
+        let arc = svg_arc.to_arc();
+        let length = if arc.radii.x.abs() == arc.radii.y.abs() {
+            arc.radii.x.abs() * arc.sweep_angle.radians.abs()
+        } else {
+            arc.approximate_length(self.tolerance)
+        };
+        self.cut(length, svg_arc.to);
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.move_to(at);
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.cut(cbs.approximate_length(self.tolerance), cbs.to);
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.cut(qbs.length(), qbs.to);
+    }
+}
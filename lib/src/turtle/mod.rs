@@ -1,4 +1,6 @@
-use std::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt::Debug;
 
 use lyon_geom::{
     ArcFlags, CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc, Vector,
@@ -6,38 +8,184 @@ use lyon_geom::{
     point, vector,
 };
 
-use crate::arc::Transformed;
+use crate::{Float, arc::Transformed};
+#[cfg(feature = "std")]
+use crate::{GroupOverride, PathSource};
 
+mod bounding_box;
+#[cfg(feature = "std")]
+mod debug;
+#[cfg(feature = "std")]
 mod dpi;
+#[cfg(feature = "std")]
+mod flatten;
+#[cfg(feature = "std")]
 mod g_code;
+mod length;
+#[cfg(feature = "std")]
+mod path_range;
+#[cfg(feature = "std")]
+mod polyline;
+#[cfg(feature = "std")]
 mod preprocess;
-pub use self::{dpi::DpiConvertingTurtle, g_code::GCodeTurtle, preprocess::PreprocessTurtle};
+#[cfg(feature = "std")]
+mod preview;
+#[cfg(feature = "std")]
+mod record;
+#[cfg(feature = "std")]
+mod reorder;
+pub use self::{bounding_box::BoundingBoxTurtle, length::LengthTurtle};
+#[cfg(feature = "std")]
+pub use self::{
+    debug::{DebugEntry, DebugTurtle, TurtleCall},
+    dpi::DpiConvertingTurtle,
+    flatten::Flattening,
+    g_code::GCodeTurtle,
+    path_range::PathRangeTurtle,
+    polyline::PolylineTurtle,
+    preprocess::PreprocessTurtle,
+    preview::PreviewTurtle,
+    record::{Command, RecordingTurtle},
+    reorder::ReorderingTurtle,
+};
 
 /// Abstraction for drawing paths based on [Turtle graphics](https://en.wikipedia.org/wiki/Turtle_graphics)
 pub trait Turtle: Debug {
     fn begin(&mut self);
     fn end(&mut self);
     fn comment(&mut self, comment: String);
-    fn move_to(&mut self, to: Point<f64>);
-    fn line_to(&mut self, to: Point<f64>);
-    fn arc(&mut self, svg_arc: SvgArc<f64>);
-    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<f64>);
-    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<f64>);
+    fn move_to(&mut self, to: Point<Float>);
+    fn line_to(&mut self, to: Point<Float>);
+    fn arc(&mut self, svg_arc: SvgArc<Float>);
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>);
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>);
+    /// Drill a hole, see [crate::ConversionConfig::drilling]
+    fn drill(&mut self, at: Point<Float>);
+    /// Marks the start of content that is repeated elsewhere in the drawing, i.e. by a `<use>` element
+    fn begin_instance(&mut self) {}
+    /// Marks the end of the content since [Self::begin_instance]
+    fn end_instance(&mut self) {}
+    /// Use the settings of a [GroupOverride] for the paths that follow, or the configured ones for [None]
+    #[cfg(feature = "std")]
+    fn override_settings(&mut self, _group_override: Option<&GroupOverride>) {}
+    /// Element that the paths that follow are drawn from
+    #[cfg(feature = "std")]
+    fn source(&mut self, _source: &PathSource) {}
+    /// Feedrate in millimeters / minute of the paths that follow, from [crate::ConversionConfig::feedrate] or a
+    /// [GroupOverride]
+    fn change_feedrate(&mut self, _feedrate: f64) {}
+    /// Laser power or spindle speed of the paths that follow, from [crate::ConversionConfig::power] or a
+    /// [GroupOverride]
+    fn change_power(&mut self, _power: Option<f64>) {}
+    /// Marks the start of an Inkscape layer, given its label
+    fn begin_layer(&mut self, _label: Option<&str>) {}
+    /// Marks the end of the layer since [Self::begin_layer]
+    fn end_layer(&mut self) {}
+    /// Marks the start of pass `pass` (counting from 0) of the `passes` over the shape that follows
+    fn begin_pass(&mut self, _pass: usize, _passes: usize) {}
+    /// Marks the end of the pass since [Self::begin_pass]
+    fn end_pass(&mut self) {}
+}
+
+/// Lets a turtle be drawn with through a reference, i.e. as a `&mut dyn Turtle`
+impl<T: Turtle + ?Sized> Turtle for &mut T {
+    fn begin(&mut self) {
+        (**self).begin()
+    }
+
+    fn end(&mut self) {
+        (**self).end()
+    }
+
+    fn comment(&mut self, comment: String) {
+        (**self).comment(comment)
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        (**self).move_to(to)
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        (**self).line_to(to)
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        (**self).arc(svg_arc)
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        (**self).cubic_bezier(cbs)
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        (**self).quadratic_bezier(qbs)
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        (**self).drill(at)
+    }
+
+    fn begin_instance(&mut self) {
+        (**self).begin_instance()
+    }
+
+    fn end_instance(&mut self) {
+        (**self).end_instance()
+    }
+
+    #[cfg(feature = "std")]
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        (**self).override_settings(group_override)
+    }
+
+    #[cfg(feature = "std")]
+    fn source(&mut self, source: &PathSource) {
+        (**self).source(source)
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        (**self).change_feedrate(feedrate)
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        (**self).change_power(power)
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        (**self).begin_layer(label)
+    }
+
+    fn end_layer(&mut self) {
+        (**self).end_layer()
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        (**self).begin_pass(pass, passes)
+    }
+
+    fn end_pass(&mut self) {
+        (**self).end_pass()
+    }
 }
 
 /// Wrapper for [Turtle] that handles transforms, position, offsets, etc.  See https://www.w3.org/TR/SVG/paths.html
 #[derive(Debug)]
-pub struct Terrarium<T: Turtle + std::fmt::Debug> {
+pub struct Terrarium<T: Turtle + Debug> {
     pub turtle: T,
-    current_position: Point<f64>,
-    initial_position: Point<f64>,
-    current_transform: Transform2D<f64>,
-    pub transform_stack: Vec<Transform2D<f64>>,
-    previous_quadratic_control: Option<Point<f64>>,
-    previous_cubic_control: Option<Point<f64>>,
+    current_position: Point<Float>,
+    initial_position: Point<Float>,
+    current_transform: Transform2D<Float>,
+    /// Inverse of [Self::current_transform], if it has one, kept alongside so that it is only computed once per transform
+    current_inverse: Option<Transform2D<Float>>,
+    /// Transforms and their inverses from before each [Self::push_transform]
+    transform_stack: Vec<(Transform2D<Float>, Option<Transform2D<Float>>)>,
+    previous_quadratic_control: Option<Point<Float>>,
+    previous_cubic_control: Option<Point<Float>>,
+    /// Whether the next segment is the last of its subpath, see [Self::next_ends_subpath]
+    ends_subpath: bool,
 }
 
-impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
+impl<T: Turtle + Debug> Terrarium<T> {
     /// Create a turtle at the origin with no transform
     pub fn new(turtle: T) -> Self {
         Self {
@@ -45,9 +193,11 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
             current_position: Point::zero(),
             initial_position: Point::zero(),
             current_transform: Transform2D::identity(),
+            current_inverse: Some(Transform2D::identity()),
             transform_stack: vec![],
             previous_quadratic_control: None,
             previous_cubic_control: None,
+            ends_subpath: false,
         }
     }
 
@@ -55,14 +205,13 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
     /// https://www.w3.org/TR/SVG/paths.html#PathDataMovetoCommands
     pub fn move_to<X, Y>(&mut self, abs: bool, x: X, y: Y)
     where
-        X: Into<Option<f64>>,
-        Y: Into<Option<f64>>,
+        X: Into<Option<Float>>,
+        Y: Into<Option<Float>>,
     {
-        let inverse_transform = self
-            .current_transform
-            .inverse()
-            .expect("transform is invertible");
-        let original_current_position = inverse_transform.transform_point(self.current_position);
+        if self.is_degenerate() {
+            return;
+        }
+        let original_current_position = self.untransformed_position();
         let x = x
             .into()
             .map(|x| {
@@ -85,6 +234,7 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
             .unwrap_or(original_current_position.y);
 
         let to = self.current_transform.transform_point(point(x, y));
+        self.ends_subpath = false;
         self.current_position = to;
         self.initial_position = to;
         self.previous_quadratic_control = None;
@@ -95,11 +245,15 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
     /// Close an SVG path, cutting back to its initial position
     /// https://www.w3.org/TR/SVG/paths.html#PathDataClosePathCommand
     pub fn close(&mut self) {
+        if self.is_degenerate() {
+            return;
+        }
+        self.ends_subpath = false;
         // See https://www.w3.org/TR/SVG/paths.html#Segment-CompletingClosePath
         // which could result in a G91 G1 X0 Y0
         if !(self.current_position - self.initial_position)
             .abs()
-            .lower_than(vector(f64::EPSILON, f64::EPSILON))
+            .lower_than(vector(Float::EPSILON, Float::EPSILON))
             .all()
         {
             self.turtle.line_to(self.initial_position);
@@ -113,14 +267,13 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
     /// https://www.w3.org/TR/SVG/paths.html#PathDataLinetoCommands
     pub fn line<X, Y>(&mut self, abs: bool, x: X, y: Y)
     where
-        X: Into<Option<f64>>,
-        Y: Into<Option<f64>>,
+        X: Into<Option<Float>>,
+        Y: Into<Option<Float>>,
     {
-        let inverse_transform = self
-            .current_transform
-            .inverse()
-            .expect("transform is invertible");
-        let original_current_position = inverse_transform.transform_point(self.current_position);
+        if self.is_degenerate() {
+            return;
+        }
+        let original_current_position = self.untransformed_position();
         let x = x
             .into()
             .map(|x| {
@@ -142,7 +295,7 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
             })
             .unwrap_or(original_current_position.y);
 
-        let to = self.current_transform.transform_point(point(x, y));
+        let to = self.snap_to_start(self.current_transform.transform_point(point(x, y)));
         self.current_position = to;
         self.previous_quadratic_control = None;
         self.previous_cubic_control = None;
@@ -155,21 +308,23 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
     pub fn cubic_bezier(
         &mut self,
         abs: bool,
-        mut ctrl1: Point<f64>,
-        mut ctrl2: Point<f64>,
-        mut to: Point<f64>,
+        mut ctrl1: Point<Float>,
+        mut ctrl2: Point<Float>,
+        mut to: Point<Float>,
     ) {
+        if self.is_degenerate() {
+            return;
+        }
         let from = self.current_position;
         if !abs {
-            let inverse_transform = self.current_transform.inverse().unwrap();
-            let original_current_position = inverse_transform.transform_point(from);
+            let original_current_position = self.untransformed_position();
             ctrl1 = original_current_position + ctrl1.to_vector();
             ctrl2 = original_current_position + ctrl2.to_vector();
             to = original_current_position + to.to_vector();
         }
         ctrl1 = self.current_transform.transform_point(ctrl1);
         ctrl2 = self.current_transform.transform_point(ctrl2);
-        to = self.current_transform.transform_point(to);
+        to = self.snap_to_start(self.current_transform.transform_point(to));
 
         let cbs = lyon_geom::CubicBezierSegment {
             from,
@@ -192,20 +347,24 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
 
     /// Draw a shorthand/smooth cubic bezier segment, where the first control point was already given
     /// https://www.w3.org/TR/SVG/paths.html#PathDataCubicBezierCommands
-    pub fn smooth_cubic_bezier(&mut self, abs: bool, mut ctrl2: Point<f64>, mut to: Point<f64>) {
+    pub fn smooth_cubic_bezier(
+        &mut self,
+        abs: bool,
+        mut ctrl2: Point<Float>,
+        mut to: Point<Float>,
+    ) {
+        if self.is_degenerate() {
+            return;
+        }
         let from = self.current_position;
         let ctrl1 = self.previous_cubic_control.unwrap_or(self.current_position);
         if !abs {
-            let inverse_transform = self
-                .current_transform
-                .inverse()
-                .expect("transform is invertible");
-            let original_current_position = inverse_transform.transform_point(from);
+            let original_current_position = self.untransformed_position();
             ctrl2 = original_current_position + ctrl2.to_vector();
             to = original_current_position + to.to_vector();
         }
         ctrl2 = self.current_transform.transform_point(ctrl2);
-        to = self.current_transform.transform_point(to);
+        to = self.snap_to_start(self.current_transform.transform_point(to));
 
         let cbs = lyon_geom::CubicBezierSegment {
             from,
@@ -228,20 +387,19 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
 
     /// Draw a shorthand/smooth cubic bezier segment, where the control point was already given
     /// https://www.w3.org/TR/SVG/paths.html#PathDataQuadraticBezierCommands
-    pub fn smooth_quadratic_bezier(&mut self, abs: bool, mut to: Point<f64>) {
+    pub fn smooth_quadratic_bezier(&mut self, abs: bool, mut to: Point<Float>) {
+        if self.is_degenerate() {
+            return;
+        }
         let from = self.current_position;
         let ctrl = self
             .previous_quadratic_control
             .unwrap_or(self.current_position);
         if !abs {
-            let inverse_transform = self
-                .current_transform
-                .inverse()
-                .expect("transform is invertible");
-            let original_current_position = inverse_transform.transform_point(from);
+            let original_current_position = self.untransformed_position();
             to = original_current_position + to.to_vector();
         }
-        to = self.current_transform.transform_point(to);
+        to = self.snap_to_start(self.current_transform.transform_point(to));
 
         let qbs = QuadraticBezierSegment { from, ctrl, to };
 
@@ -259,19 +417,18 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
 
     /// Draw a quadratic bezier segment
     /// https://www.w3.org/TR/SVG/paths.html#PathDataQuadraticBezierCommands
-    pub fn quadratic_bezier(&mut self, abs: bool, mut ctrl: Point<f64>, mut to: Point<f64>) {
+    pub fn quadratic_bezier(&mut self, abs: bool, mut ctrl: Point<Float>, mut to: Point<Float>) {
+        if self.is_degenerate() {
+            return;
+        }
         let from = self.current_position;
         if !abs {
-            let inverse_transform = self
-                .current_transform
-                .inverse()
-                .expect("transform is invertible");
-            let original_current_position = inverse_transform.transform_point(from);
+            let original_current_position = self.untransformed_position();
             to = original_current_position + to.to_vector();
             ctrl = original_current_position + ctrl.to_vector();
         }
         ctrl = self.current_transform.transform_point(ctrl);
-        to = self.current_transform.transform_point(to);
+        to = self.snap_to_start(self.current_transform.transform_point(to));
 
         let qbs = QuadraticBezierSegment { from, ctrl, to };
 
@@ -292,21 +449,20 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
     pub fn elliptical(
         &mut self,
         abs: bool,
-        radii: Vector<f64>,
-        x_rotation: Angle<f64>,
+        radii: Vector<Float>,
+        x_rotation: Angle<Float>,
         flags: ArcFlags,
-        mut to: Point<f64>,
+        mut to: Point<Float>,
     ) {
-        let from = self
-            .current_transform
-            .inverse()
-            .expect("transform is invertible")
-            .transform_point(self.current_position);
+        if self.is_degenerate() {
+            return;
+        }
+        let from = self.untransformed_position();
 
         if !abs {
             to = from + to.to_vector()
         }
-        let svg_arc = SvgArc {
+        let mut svg_arc = SvgArc {
             from,
             to,
             radii,
@@ -314,6 +470,7 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
             flags,
         }
         .transformed(&self.current_transform);
+        svg_arc.to = self.snap_to_start(svg_arc.to);
 
         self.current_position = svg_arc.to;
         self.previous_quadratic_control = None;
@@ -322,24 +479,127 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
         self.turtle.arc(svg_arc);
     }
 
+    /// Radius of a circle after the current transform, unless it is no longer a circle
+    pub fn transform_radius(&self, radius: Float) -> Option<Float> {
+        let x = self.current_transform.transform_vector(vector(radius, 0.));
+        let y = self.current_transform.transform_vector(vector(0., radius));
+        let tolerance = (1e-9 as Float).max(Float::EPSILON * 16.) * x.length().max(y.length());
+        ((x.length() - y.length()).abs() <= tolerance && x.dot(y).abs() <= tolerance * x.length())
+            .then(|| x.length())
+    }
+
+    /// Drill a hole at the given absolute coordinates in the current transform
+    pub fn drill(&mut self, at: Point<Float>) {
+        if self.is_degenerate() {
+            return;
+        }
+        let at = self.current_transform.transform_point(at);
+        self.current_position = at;
+        self.initial_position = at;
+        self.previous_quadratic_control = None;
+        self.previous_cubic_control = None;
+        self.turtle.drill(at);
+    }
+
     /// Push a generic transform onto the stack
     /// Could be any valid CSS transform https://drafts.csswg.org/css-transforms-1/#typedef-transform-function
     /// https://www.w3.org/TR/SVG/coords.html#InterfaceSVGTransform
-    pub fn push_transform(&mut self, trans: Transform2D<f64>) {
-        self.transform_stack.push(self.current_transform);
+    pub fn push_transform(&mut self, trans: Transform2D<Float>) {
+        self.transform_stack
+            .push((self.current_transform, self.current_inverse));
         // https://stackoverflow.com/questions/18582935/the-applying-order-of-svg-transforms
         self.current_transform = trans.then(&self.current_transform);
+        self.current_inverse = self
+            .current_transform
+            .inverse()
+            .filter(|inverse| is_finite(&self.current_transform) && is_finite(inverse));
+    }
+
+    /// Whether the current transform squashes everything under it flat, or blows it up to infinity, i.e. with a
+    /// `scale(0)`
+    ///
+    /// Nothing can be drawn in such a transform, so drawing commands do nothing until it is popped.
+    pub fn is_degenerate(&self) -> bool {
+        self.current_inverse.is_none()
+    }
+
+    /// Transform from the coordinates of the current element to those of the turtle
+    pub fn transform(&self) -> &Transform2D<Float> {
+        &self.current_transform
     }
 
     /// Pop a generic transform off the stack, returning to the previous transform state
     /// This means that most recent transform went out of scope
     pub fn pop_transform(&mut self) {
-        self.current_transform = self
+        (self.current_transform, self.current_inverse) = self
             .transform_stack
             .pop()
             .expect("pop only called when transforms remain");
     }
 
+    /// Mark the next segment as the last of its subpath, i.e. because a close path or a move comes after it
+    ///
+    /// If it only misses the start of the subpath by rounding, it ends exactly on it instead.
+    pub fn next_ends_subpath(&mut self) {
+        self.ends_subpath = true;
+    }
+
+    /// `to`, or exactly the start of the subpath if this segment ends the subpath and only misses it by rounding
+    ///
+    /// Relative coordinates go through the inverse of the transform and back, so a shape that returns to its start
+    /// lands a hair off from it. Closing that gap would take a sliver of a segment, where the machine slows down to
+    /// turn and a laser burns a pinhole.
+    fn snap_to_start(&mut self, to: Point<Float>) -> Point<Float> {
+        if !core::mem::take(&mut self.ends_subpath) {
+            return to;
+        }
+        let start = self.initial_position;
+        let scale = start
+            .x
+            .abs()
+            .max(start.y.abs())
+            .max(to.x.abs())
+            .max(to.y.abs())
+            .max(1.);
+        if (to - start).length() <= 16. * Float::EPSILON * scale {
+            start
+        } else {
+            to
+        }
+    }
+
+    /// The current position before the current transform was applied
+    fn untransformed_position(&self) -> Point<Float> {
+        self.current_inverse
+            .expect("nothing is drawn in a degenerate transform")
+            .transform_point(self.current_position)
+    }
+
+    /// Lend this to code that can't be generic over the turtle, i.e. a handler from [crate::Hooks::on_element]
+    pub fn with_dyn<R>(&mut self, f: impl FnOnce(&mut Terrarium<&mut dyn Turtle>) -> R) -> R {
+        let mut lent = Terrarium {
+            turtle: &mut self.turtle as &mut dyn Turtle,
+            current_position: self.current_position,
+            initial_position: self.initial_position,
+            current_transform: self.current_transform,
+            current_inverse: self.current_inverse,
+            transform_stack: core::mem::take(&mut self.transform_stack),
+            previous_quadratic_control: self.previous_quadratic_control,
+            previous_cubic_control: self.previous_cubic_control,
+            ends_subpath: self.ends_subpath,
+        };
+        let result = f(&mut lent);
+        self.current_position = lent.current_position;
+        self.initial_position = lent.initial_position;
+        self.current_transform = lent.current_transform;
+        self.current_inverse = lent.current_inverse;
+        self.transform_stack = lent.transform_stack;
+        self.previous_quadratic_control = lent.previous_quadratic_control;
+        self.previous_cubic_control = lent.previous_cubic_control;
+        self.ends_subpath = lent.ends_subpath;
+        result
+    }
+
     /// Reset the position of the turtle to the origin in the current transform stack
     /// Used for starting a new path
     pub fn reset(&mut self) {
@@ -347,5 +607,10 @@ impl<T: Turtle + std::fmt::Debug> Terrarium<T> {
         self.initial_position = self.current_position;
         self.previous_quadratic_control = None;
         self.previous_cubic_control = None;
+        self.ends_subpath = false;
     }
 }
+
+fn is_finite(transform: &Transform2D<Float>) -> bool {
+    transform.to_array().iter().all(|value| value.is_finite())
+}
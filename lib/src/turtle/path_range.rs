@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use crate::{Float, GroupOverride, PathSource, Turtle};
+
+/// Wrapper turtle that only draws the paths whose index is within a range, see [crate::ConversionOptions::path_range]
+///
+/// A path starts at each [Turtle::move_to] or [Turtle::drill]. Comments are held back until it is known which path they belong to.
+#[derive(Debug)]
+pub struct PathRangeTurtle<T: Turtle> {
+    pub inner: T,
+    range: Range<usize>,
+    /// Number of paths started so far
+    paths: usize,
+    pending_comments: Vec<String>,
+}
+
+impl<T: Turtle> PathRangeTurtle<T> {
+    pub fn new(inner: T, range: Option<Range<usize>>) -> Self {
+        Self {
+            inner,
+            range: range.unwrap_or(0..usize::MAX),
+            paths: 0,
+            pending_comments: vec![],
+        }
+    }
+
+    /// Whether the path currently being drawn is in range
+    fn drawing(&self) -> bool {
+        self.range.contains(&self.paths.saturating_sub(1))
+    }
+
+    /// Pass on or discard [Self::pending_comments] once it is known whether their path is in range
+    fn flush_comments(&mut self, in_range: bool) {
+        for comment in self.pending_comments.drain(..) {
+            if in_range {
+                self.inner.comment(comment);
+            }
+        }
+    }
+}
+
+impl<T: Turtle> Turtle for PathRangeTurtle<T> {
+    fn begin(&mut self) {
+        self.inner.begin()
+    }
+
+    fn end(&mut self) {
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        self.inner.end()
+    }
+
+    fn comment(&mut self, comment: String) {
+        self.pending_comments.push(comment);
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.paths += 1;
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.drill(at)
+        }
+    }
+
+    fn begin_instance(&mut self) {
+        self.inner.begin_instance()
+    }
+
+    fn end_instance(&mut self) {
+        self.inner.end_instance()
+    }
+
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        self.inner.override_settings(group_override)
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        self.inner.source(source)
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        self.inner.change_feedrate(feedrate)
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        self.inner.change_power(power)
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        self.inner.begin_layer(label)
+    }
+
+    fn end_layer(&mut self) {
+        self.inner.end_layer()
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        self.inner.begin_pass(pass, passes)
+    }
+
+    fn end_pass(&mut self) {
+        self.inner.end_pass()
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.paths += 1;
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.move_to(to)
+        }
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.line_to(to)
+        }
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.arc(svg_arc)
+        }
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.cubic_bezier(cbs)
+        }
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        let in_range = self.drawing();
+        self.flush_comments(in_range);
+        if in_range {
+            self.inner.quadratic_bezier(qbs)
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::Flattening;
+use crate::{Float, PathSource, Polyline, Turtle, arc::ArcOrLineSegment};
+
+/// Turtle that collects paths as polylines, see [crate::svg2polylines]
+///
+/// Curves are flattened with the given [Flattening], which should not fit arcs.
+#[derive(Debug)]
+pub struct PolylineTurtle {
+    pub polylines: Vec<Polyline>,
+    flattening: Flattening,
+    source: PathSource,
+    segments: Vec<ArcOrLineSegment<Float>>,
+}
+
+impl PolylineTurtle {
+    pub fn new(flattening: Flattening) -> Self {
+        Self {
+            polylines: vec![],
+            flattening,
+            source: PathSource::default(),
+            segments: vec![],
+        }
+    }
+
+    fn start(&mut self, at: Point<Float>, drill: bool) {
+        self.polylines.push(Polyline {
+            source: self.source.clone(),
+            points: vec![at],
+            drill,
+        });
+    }
+
+    /// Add the end points of [Self::segments] to the current polyline
+    fn extend(&mut self) {
+        let points = self.segments.drain(..).map(|segment| match segment {
+            ArcOrLineSegment::Arc(svg_arc) => svg_arc.to,
+            ArcOrLineSegment::Line(line) => line.to,
+        });
+        if let Some(polyline) = self.polylines.last_mut() {
+            polyline.points.extend(points);
+        }
+    }
+}
+
+impl Turtle for PolylineTurtle {
+    fn begin(&mut self) {}
+
+    fn end(&mut self) {}
+
+    fn comment(&mut self, _comment: String) {}
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.start(at, true);
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        self.source = source.clone();
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.start(to, false);
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        if let Some(polyline) = self.polylines.last_mut() {
+            polyline.points.push(to);
+        }
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        self.flattening.arc(svg_arc, &mut self.segments);
+        self.extend();
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.flattening.cubic_bezier(cbs, &mut self.segments);
+        self.extend();
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.cubic_bezier(qbs.to_cubic());
+    }
+}
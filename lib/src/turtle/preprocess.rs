@@ -1,11 +1,12 @@
 use lyon_geom::{Box2D, CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
 
 use super::Turtle;
+use crate::Float;
 
 /// Generates a bounding box for all draw operations, used to properly apply [crate::ConversionConfig::origin]
 #[derive(Debug, Default)]
 pub struct PreprocessTurtle {
-    pub bounding_box: Box2D<f64>,
+    pub bounding_box: Box2D<Float>,
 }
 
 impl Turtle for PreprocessTurtle {
@@ -15,15 +16,17 @@ impl Turtle for PreprocessTurtle {
 
     fn comment(&mut self, _comment: String) {}
 
-    fn move_to(&mut self, to: Point<f64>) {
-        self.bounding_box = Box2D::from_points([self.bounding_box.min, self.bounding_box.max, to]);
+    fn move_to(&mut self, to: Point<Float>) {
+        self.bounding_box =
+            Box2D::new(self.bounding_box.min.min(to), self.bounding_box.max.max(to));
     }
 
-    fn line_to(&mut self, to: Point<f64>) {
-        self.bounding_box = Box2D::from_points([self.bounding_box.min, self.bounding_box.max, to]);
+    fn line_to(&mut self, to: Point<Float>) {
+        self.bounding_box =
+            Box2D::new(self.bounding_box.min.min(to), self.bounding_box.max.max(to));
     }
 
-    fn arc(&mut self, svg_arc: SvgArc<f64>) {
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
         if svg_arc.is_straight_line() {
             self.line_to(svg_arc.to);
         } else {
@@ -31,11 +34,15 @@ impl Turtle for PreprocessTurtle {
         }
     }
 
-    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<f64>) {
+    fn drill(&mut self, at: Point<Float>) {
+        self.move_to(at);
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
         self.bounding_box = self.bounding_box.union(&cbs.bounding_box());
     }
 
-    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<f64>) {
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
         self.bounding_box = self.bounding_box.union(&qbs.bounding_box());
     }
 }
@@ -0,0 +1,192 @@
+use std::fmt;
+
+use lyon_geom::{Box2D, CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::{BoundingBoxTurtle, Turtle};
+use crate::Float;
+
+const CUT_COLOR: &str = "#d62728";
+const TRAVEL_COLOR: &str = "#7f7f7f";
+const START_COLOR: &str = "#2ca02c";
+const DRILL_COLOR: &str = "#1f77b4";
+
+/// Turtle that draws the toolpath back out as an SVG, to check the transforms and order of a job before running it
+///
+/// Cuts are solid with an arrow at their end, travel moves between them are dashed, and the start of each cut is
+/// marked with a dot. Drilled holes are circled. Coordinates are in millimeters with the Y axis pointing up, as the
+/// machine sees them.
+///
+/// Use it with [crate::svg2turtle], then format it with [fmt::Display] to get the SVG document.
+#[derive(Debug, Default, Clone)]
+pub struct PreviewTurtle {
+    /// Start of each cut and its path data after the initial move
+    cuts: Vec<(Point<Float>, String)>,
+    travels: Vec<(Point<Float>, Point<Float>)>,
+    drills: Vec<Point<Float>>,
+    /// Extents of the cuts and drills, so the document can be sized to fit them
+    extents: BoundingBoxTurtle,
+    position: Option<Point<Float>>,
+}
+
+impl PreviewTurtle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn travel_to(&mut self, to: Point<Float>) {
+        if let Some(from) = self.position
+            && from != to
+        {
+            self.travels.push((from, to));
+        }
+        self.position = Some(to);
+    }
+
+    /// Add a segment to the current cut, ending at `to`
+    fn cut(&mut self, segment: fmt::Arguments, to: Point<Float>) {
+        if let Some((_, data)) = self.cuts.last_mut() {
+            data.push_str(&segment.to_string());
+        }
+        self.position = Some(to);
+    }
+}
+
+impl Turtle for PreviewTurtle {
+    fn begin(&mut self) {}
+
+    fn end(&mut self) {}
+
+    fn comment(&mut self, _comment: String) {}
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.travel_to(to);
+        self.extents.move_to(to);
+        self.cuts.push((to, String::new()));
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        self.extents.line_to(to);
+        self.cut(format_args!(" L{},{}", to.x, to.y), to);
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        self.extents.arc(svg_arc);
+        self.cut(
+            format_args!(
+                " A{},{} {} {} {} {},{}",
+                svg_arc.radii.x,
+                svg_arc.radii.y,
+                svg_arc.x_rotation.to_degrees(),
+                svg_arc.flags.large_arc as u8,
+                svg_arc.flags.sweep as u8,
+                svg_arc.to.x,
+                svg_arc.to.y
+            ),
+            svg_arc.to,
+        );
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.travel_to(at);
+        self.extents.drill(at);
+        self.drills.push(at);
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.extents.cubic_bezier(cbs);
+        self.cut(
+            format_args!(
+                " C{},{} {},{} {},{}",
+                cbs.ctrl1.x, cbs.ctrl1.y, cbs.ctrl2.x, cbs.ctrl2.y, cbs.to.x, cbs.to.y
+            ),
+            cbs.to,
+        );
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.extents.quadratic_bezier(qbs);
+        self.cut(
+            format_args!(" Q{},{} {},{}", qbs.ctrl.x, qbs.ctrl.y, qbs.to.x, qbs.to.y),
+            qbs.to,
+        );
+    }
+}
+
+/// Formats as a standalone SVG document
+impl fmt::Display for PreviewTurtle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Travel moves start and end at cuts or drills, so these cover them too
+        let extents = self.extents.bounding_box.unwrap_or_default();
+        let size = extents.size();
+        let stroke_width = size.width.max(size.height).max(1.) / 400.;
+        let extents = extents.inflate(stroke_width * 4., stroke_width * 4.);
+        let Box2D { min, max } = extents;
+        let size = extents.size();
+
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}mm" height="{}mm" viewBox="{} {} {} {}">"#,
+            size.width, size.height, min.x, -max.y, size.width, size.height
+        )?;
+        writeln!(
+            f,
+            r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="4" markerHeight="4" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="{CUT_COLOR}"/></marker></defs>"#
+        )?;
+        // Flip so that Y points up
+        writeln!(
+            f,
+            r#"<g transform="scale(1 -1)" fill="none" stroke-width="{stroke_width}">"#
+        )?;
+
+        writeln!(
+            f,
+            r#"<g stroke="{TRAVEL_COLOR}" stroke-dasharray="{} {}">"#,
+            stroke_width * 4.,
+            stroke_width * 4.
+        )?;
+        for (from, to) in &self.travels {
+            writeln!(
+                f,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                from.x, from.y, to.x, to.y
+            )?;
+        }
+        writeln!(f, "</g>")?;
+
+        writeln!(
+            f,
+            r#"<g stroke="{CUT_COLOR}" stroke-linejoin="round" marker-end="url(#arrow)">"#
+        )?;
+        for (start, data) in self.cuts.iter().filter(|(_, data)| !data.is_empty()) {
+            writeln!(f, r#"<path d="M{},{}{data}"/>"#, start.x, start.y)?;
+        }
+        writeln!(f, "</g>")?;
+
+        writeln!(f, r#"<g fill="{START_COLOR}">"#)?;
+        for (start, _) in self.cuts.iter().filter(|(_, data)| !data.is_empty()) {
+            writeln!(
+                f,
+                r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+                start.x,
+                start.y,
+                stroke_width * 2.
+            )?;
+        }
+        writeln!(f, "</g>")?;
+
+        writeln!(f, r#"<g stroke="{DRILL_COLOR}">"#)?;
+        for at in &self.drills {
+            writeln!(
+                f,
+                r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+                at.x,
+                at.y,
+                stroke_width * 3.
+            )?;
+        }
+        writeln!(f, "</g>")?;
+
+        writeln!(f, "</g>")?;
+        writeln!(f, "</svg>")
+    }
+}
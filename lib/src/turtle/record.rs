@@ -0,0 +1,175 @@
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc};
+
+use super::Flattening;
+use crate::{Float, GroupOverride, PathSource, Turtle, arc::ArcOrLineSegment};
+
+/// Turtle that keeps what it is asked to draw, so that it can be drawn again later
+///
+/// With a [Flattening], curves are kept as the segments they are cut as. See [crate::Drawing] and [crate::Geometry].
+#[derive(Debug)]
+pub struct RecordingTurtle {
+    pub commands: Vec<Command>,
+    flattening: Option<Flattening>,
+    segments: Vec<ArcOrLineSegment<Float>>,
+}
+
+/// One call on a [Turtle], except for [Turtle::begin] and [Turtle::end]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Comment(String),
+    MoveTo(Point<Float>),
+    LineTo(Point<Float>),
+    Arc(SvgArc<Float>),
+    /// A curve that has not been flattened, or that flattens to nothing and is kept so the turtle drawing it still
+    /// sees it
+    ///
+    /// Quadratic curves are recorded as the equivalent cubic curve.
+    CubicBezier(CubicBezierSegment<Float>),
+    Drill(Point<Float>),
+    BeginInstance,
+    EndInstance,
+    OverrideSettings(Option<GroupOverride>),
+    Source(PathSource),
+    ChangeFeedrate(f64),
+    ChangePower(Option<f64>),
+    BeginLayer(Option<String>),
+    EndLayer,
+    BeginPass(usize, usize),
+    EndPass,
+}
+
+impl Command {
+    /// Make the call on a turtle that this was recorded from
+    pub fn replay(&self, turtle: &mut impl Turtle) {
+        match self {
+            Self::Comment(comment) => turtle.comment(comment.clone()),
+            Self::MoveTo(to) => turtle.move_to(*to),
+            Self::LineTo(to) => turtle.line_to(*to),
+            Self::Arc(svg_arc) => turtle.arc(*svg_arc),
+            Self::CubicBezier(cbs) => turtle.cubic_bezier(*cbs),
+            Self::Drill(at) => turtle.drill(*at),
+            Self::BeginInstance => turtle.begin_instance(),
+            Self::EndInstance => turtle.end_instance(),
+            Self::OverrideSettings(group_override) => {
+                turtle.override_settings(group_override.as_ref())
+            }
+            Self::Source(source) => turtle.source(source),
+            Self::ChangeFeedrate(feedrate) => turtle.change_feedrate(*feedrate),
+            Self::ChangePower(power) => turtle.change_power(*power),
+            Self::BeginLayer(label) => turtle.begin_layer(label.as_deref()),
+            Self::EndLayer => turtle.end_layer(),
+            Self::BeginPass(pass, passes) => turtle.begin_pass(*pass, *passes),
+            Self::EndPass => turtle.end_pass(),
+        }
+    }
+}
+
+impl RecordingTurtle {
+    pub fn new(flattening: Option<Flattening>) -> Self {
+        Self {
+            commands: vec![],
+            flattening,
+            segments: vec![],
+        }
+    }
+
+    /// Record the segments that `flatten` gives from the [Self::flattening], or `curve` if there are none
+    fn record_flattened(
+        &mut self,
+        curve: Command,
+        flatten: impl FnOnce(&Flattening, &mut Vec<ArcOrLineSegment<Float>>),
+    ) {
+        if let Some(flattening) = &self.flattening {
+            flatten(flattening, &mut self.segments);
+        }
+        if self.segments.is_empty() {
+            self.commands.push(curve);
+            return;
+        }
+        self.commands
+            .extend(self.segments.drain(..).map(|segment| match segment {
+                ArcOrLineSegment::Arc(svg_arc) => Command::Arc(svg_arc),
+                ArcOrLineSegment::Line(line) => Command::LineTo(line.to),
+            }));
+    }
+}
+
+impl Turtle for RecordingTurtle {
+    fn begin(&mut self) {}
+
+    fn end(&mut self) {}
+
+    fn comment(&mut self, comment: String) {
+        self.commands.push(Command::Comment(comment));
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        self.commands.push(Command::Drill(at));
+    }
+
+    fn begin_instance(&mut self) {
+        self.commands.push(Command::BeginInstance);
+    }
+
+    fn end_instance(&mut self) {
+        self.commands.push(Command::EndInstance);
+    }
+
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        self.commands
+            .push(Command::OverrideSettings(group_override.cloned()));
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        self.commands.push(Command::Source(source.clone()));
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        self.commands.push(Command::ChangeFeedrate(feedrate));
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        self.commands.push(Command::ChangePower(power));
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        self.commands
+            .push(Command::BeginLayer(label.map(str::to_string)));
+    }
+
+    fn end_layer(&mut self) {
+        self.commands.push(Command::EndLayer);
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        self.commands.push(Command::BeginPass(pass, passes));
+    }
+
+    fn end_pass(&mut self) {
+        self.commands.push(Command::EndPass);
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        self.commands.push(Command::MoveTo(to));
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        self.commands.push(Command::LineTo(to));
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        self.record_flattened(Command::Arc(svg_arc), |flattening, segments| {
+            flattening.arc(svg_arc, segments)
+        });
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        self.record_flattened(Command::CubicBezier(cbs), |flattening, segments| {
+            flattening.cubic_bezier(cbs, segments)
+        });
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        self.cubic_bezier(qbs.to_cubic());
+    }
+}
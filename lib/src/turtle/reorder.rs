@@ -0,0 +1,455 @@
+use lyon_geom::{CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc, point};
+
+#[cfg(feature = "rayon")]
+use super::Flattening;
+#[cfg(feature = "rayon")]
+use crate::arc::ArcOrLineSegment;
+use crate::{
+    Cancellation, Float, GroupOverride, PathOrder, PathSource, RefinementBudget, Turtle,
+    ordering::{nearest_neighbor, refine},
+};
+
+/// Wrapper turtle that draws paths in a different order than they are given, see [crate::ConversionConfig::path_order]
+///
+/// With [PathOrder::Document] everything is passed straight through. Otherwise, paths are held back until
+/// [Turtle::end] and then drawn in an order that shortens travel. Paths keep their direction, their comments, and the
+/// [GroupOverride], feedrate, and power they were drawn with. Instances, layers, and passes are only passed on in
+/// document order, since otherwise they may no longer be contiguous.
+///
+/// With the `rayon` feature and [Self::flatten_in_parallel], paths are held back in document order too so that their
+/// curves can be flattened in parallel before they are drawn.
+#[derive(Debug)]
+pub struct ReorderingTurtle<T: Turtle> {
+    pub inner: T,
+    order: PathOrder,
+    refinement: Option<RefinementBudget>,
+    cancellation: Option<Cancellation>,
+    #[cfg(feature = "rayon")]
+    flattening: Option<Flattening>,
+    paths: Vec<RecordedPath>,
+    /// Comments and boundaries since the last path or step, which belong to whichever comes next
+    pending_events: Vec<Event>,
+    group_override: Option<GroupOverride>,
+    source: PathSource,
+    /// Given by [Turtle::change_feedrate], [None] until it is first called
+    feedrate: Option<f64>,
+    /// Given by [Turtle::change_power], [None] until it is first called
+    power: Option<Option<f64>>,
+}
+
+#[derive(Debug)]
+struct RecordedPath {
+    events: Vec<Event>,
+    group_override: Option<GroupOverride>,
+    source: PathSource,
+    feedrate: Option<f64>,
+    power: Option<Option<f64>>,
+    start: Start,
+    steps: Vec<Step>,
+    end: Point<Float>,
+}
+
+#[derive(Debug)]
+enum Start {
+    MoveTo(Point<Float>),
+    Drill(Point<Float>),
+}
+
+#[derive(Debug)]
+enum Event {
+    Comment(String),
+    BeginInstance,
+    EndInstance,
+    BeginLayer(Option<String>),
+    EndLayer,
+    BeginPass(usize, usize),
+    EndPass,
+}
+
+#[derive(Debug)]
+enum Step {
+    Event(Event),
+    LineTo(Point<Float>),
+    Arc(SvgArc<Float>),
+    CubicBezier(CubicBezierSegment<Float>),
+    QuadraticBezier(QuadraticBezierSegment<Float>),
+}
+
+impl<T: Turtle> ReorderingTurtle<T> {
+    /// Ordering stops early once `cancellation` is cancelled, and then no paths are drawn
+    pub fn new(
+        inner: T,
+        order: PathOrder,
+        refinement: Option<RefinementBudget>,
+        cancellation: Option<Cancellation>,
+    ) -> Self {
+        Self {
+            inner,
+            order,
+            refinement,
+            cancellation,
+            #[cfg(feature = "rayon")]
+            flattening: None,
+            paths: vec![],
+            pending_events: vec![],
+            group_override: None,
+            source: PathSource::default(),
+            feedrate: None,
+            power: None,
+        }
+    }
+
+    /// Flatten curves in parallel once all paths are known, the same way the inner turtle would with `flattening`
+    #[cfg(feature = "rayon")]
+    pub fn flatten_in_parallel(self, flattening: Flattening) -> Self {
+        Self {
+            flattening: Some(flattening),
+            ..self
+        }
+    }
+
+    fn passthrough(&self) -> bool {
+        #[cfg(feature = "rayon")]
+        if self.flattening.is_some() {
+            return false;
+        }
+        self.order == PathOrder::Document
+    }
+
+    fn emit(&mut self, event: Event) {
+        match event {
+            Event::Comment(comment) => self.inner.comment(comment),
+            Event::BeginInstance if self.order == PathOrder::Document => {
+                self.inner.begin_instance()
+            }
+            Event::EndInstance if self.order == PathOrder::Document => self.inner.end_instance(),
+            Event::BeginLayer(label) if self.order == PathOrder::Document => {
+                self.inner.begin_layer(label.as_deref())
+            }
+            Event::EndLayer if self.order == PathOrder::Document => self.inner.end_layer(),
+            Event::BeginPass(pass, passes) if self.order == PathOrder::Document => {
+                self.inner.begin_pass(pass, passes)
+            }
+            Event::EndPass if self.order == PathOrder::Document => self.inner.end_pass(),
+            Event::BeginInstance
+            | Event::EndInstance
+            | Event::BeginLayer(_)
+            | Event::EndLayer
+            | Event::BeginPass(..)
+            | Event::EndPass => {}
+        }
+    }
+
+    fn start_path(&mut self, start: Start) {
+        let end = match start {
+            Start::MoveTo(at) | Start::Drill(at) => at,
+        };
+        self.paths.push(RecordedPath {
+            events: std::mem::take(&mut self.pending_events),
+            group_override: self.group_override.clone(),
+            source: self.source.clone(),
+            feedrate: self.feedrate,
+            power: self.power,
+            start,
+            steps: vec![],
+            end,
+        });
+    }
+
+    /// Add a step to the current path, along with any events since the last one
+    ///
+    /// The [crate::turtle::Terrarium] always begins a path with [Turtle::move_to], so there is one to add to.
+    fn record(&mut self, step: Step, to: Point<Float>) {
+        if let Some(path) = self.paths.last_mut() {
+            path.steps
+                .extend(self.pending_events.drain(..).map(Step::Event));
+            path.steps.push(step);
+            path.end = to;
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn flattened(&self, mut paths: Vec<RecordedPath>) -> Vec<RecordedPath> {
+        use rayon::prelude::*;
+
+        if let Some(flattening) = self.flattening {
+            phase!("flatten", paths = paths.len());
+            paths
+                .par_iter_mut()
+                .for_each_init(Vec::new, |segments, path| {
+                    path.flatten(&flattening, segments)
+                });
+        }
+        paths
+    }
+
+    /// Draw the recorded paths in order of [Self::order]
+    fn replay(&mut self) {
+        let paths = std::mem::take(&mut self.paths);
+        #[cfg(feature = "rayon")]
+        let paths = self.flattened(paths);
+        let endpoints = paths
+            .iter()
+            .map(|path| {
+                let (Start::MoveTo(start) | Start::Drill(start)) = path.start;
+                [start, path.end]
+            })
+            .collect::<Vec<_>>();
+        let order = {
+            phase!("optimize", paths = paths.len());
+            let from = point(0., 0.);
+            let mut order = match self.order {
+                PathOrder::Document => (0..paths.len()).collect(),
+                PathOrder::NearestNeighbor => {
+                    nearest_neighbor(&endpoints, from, self.cancellation.as_ref())
+                }
+            };
+            if let (PathOrder::NearestNeighbor, Some(budget)) = (self.order, self.refinement) {
+                refine(
+                    &endpoints,
+                    from,
+                    &mut order,
+                    budget,
+                    self.cancellation.as_ref(),
+                );
+            }
+            order
+        };
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(Cancellation::is_cancelled)
+        {
+            return;
+        }
+
+        phase!("emit", paths = paths.len());
+        let mut paths = paths.into_iter().map(Some).collect::<Vec<_>>();
+        let mut group_override = None;
+        let mut source = None;
+        let mut feedrate = None;
+        let mut power = None;
+        for index in order {
+            let Some(path) = paths[index].take() else {
+                continue;
+            };
+            if path.group_override != group_override {
+                self.inner.override_settings(path.group_override.as_ref());
+                group_override = path.group_override;
+            }
+            if source.as_ref() != Some(&path.source) {
+                self.inner.source(&path.source);
+                source = Some(path.source);
+            }
+            if let Some(path_feedrate) = path.feedrate
+                && feedrate != path.feedrate
+            {
+                self.inner.change_feedrate(path_feedrate);
+                feedrate = path.feedrate;
+            }
+            if let Some(path_power) = path.power
+                && power != path.power
+            {
+                self.inner.change_power(path_power);
+                power = path.power;
+            }
+            for event in path.events {
+                self.emit(event);
+            }
+            match path.start {
+                Start::MoveTo(to) => self.inner.move_to(to),
+                Start::Drill(at) => self.inner.drill(at),
+            }
+            for step in path.steps {
+                match step {
+                    Step::Event(event) => self.emit(event),
+                    Step::LineTo(to) => self.inner.line_to(to),
+                    Step::Arc(svg_arc) => self.inner.arc(svg_arc),
+                    Step::CubicBezier(cbs) => self.inner.cubic_bezier(cbs),
+                    Step::QuadraticBezier(qbs) => self.inner.quadratic_bezier(qbs),
+                }
+            }
+        }
+        for event in std::mem::take(&mut self.pending_events) {
+            self.emit(event);
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl RecordedPath {
+    /// Replace curves with the lines and arcs they are cut as
+    ///
+    /// A curve that flattens to nothing is kept so the inner turtle still sees it. `segments` is a buffer that can be
+    /// reused between paths.
+    fn flatten(&mut self, flattening: &Flattening, segments: &mut Vec<ArcOrLineSegment<Float>>) {
+        let steps = std::mem::take(&mut self.steps);
+        for step in steps {
+            match &step {
+                Step::Arc(svg_arc) => flattening.arc(*svg_arc, segments),
+                Step::CubicBezier(cbs) => flattening.cubic_bezier(*cbs, segments),
+                Step::QuadraticBezier(qbs) => flattening.cubic_bezier(qbs.to_cubic(), segments),
+                Step::Event(_) | Step::LineTo(_) => {}
+            };
+            if segments.is_empty() {
+                self.steps.push(step);
+                continue;
+            }
+            self.steps
+                .extend(segments.drain(..).map(|segment| match segment {
+                    ArcOrLineSegment::Arc(svg_arc) => Step::Arc(svg_arc),
+                    ArcOrLineSegment::Line(line) => Step::LineTo(line.to),
+                }));
+        }
+    }
+}
+
+impl<T: Turtle> Turtle for ReorderingTurtle<T> {
+    fn begin(&mut self) {
+        self.inner.begin()
+    }
+
+    fn end(&mut self) {
+        if !self.passthrough() {
+            self.replay();
+        }
+        self.inner.end()
+    }
+
+    fn comment(&mut self, comment: String) {
+        if self.passthrough() {
+            self.inner.comment(comment)
+        } else {
+            self.pending_events.push(Event::Comment(comment));
+        }
+    }
+
+    fn drill(&mut self, at: Point<Float>) {
+        if self.passthrough() {
+            self.inner.drill(at)
+        } else {
+            self.start_path(Start::Drill(at));
+        }
+    }
+
+    fn begin_instance(&mut self) {
+        if self.passthrough() {
+            self.inner.begin_instance()
+        } else {
+            self.pending_events.push(Event::BeginInstance);
+        }
+    }
+
+    fn end_instance(&mut self) {
+        if self.passthrough() {
+            self.inner.end_instance()
+        } else {
+            self.pending_events.push(Event::EndInstance);
+        }
+    }
+
+    fn override_settings(&mut self, group_override: Option<&GroupOverride>) {
+        if self.passthrough() {
+            self.inner.override_settings(group_override)
+        } else {
+            self.group_override = group_override.cloned();
+        }
+    }
+
+    fn source(&mut self, source: &PathSource) {
+        if self.passthrough() {
+            self.inner.source(source)
+        } else {
+            self.source = source.clone();
+        }
+    }
+
+    fn change_feedrate(&mut self, feedrate: f64) {
+        if self.passthrough() {
+            self.inner.change_feedrate(feedrate)
+        } else {
+            self.feedrate = Some(feedrate);
+        }
+    }
+
+    fn change_power(&mut self, power: Option<f64>) {
+        if self.passthrough() {
+            self.inner.change_power(power)
+        } else {
+            self.power = Some(power);
+        }
+    }
+
+    fn begin_layer(&mut self, label: Option<&str>) {
+        if self.passthrough() {
+            self.inner.begin_layer(label)
+        } else {
+            self.pending_events
+                .push(Event::BeginLayer(label.map(str::to_string)));
+        }
+    }
+
+    fn end_layer(&mut self) {
+        if self.passthrough() {
+            self.inner.end_layer()
+        } else {
+            self.pending_events.push(Event::EndLayer);
+        }
+    }
+
+    fn begin_pass(&mut self, pass: usize, passes: usize) {
+        if self.passthrough() {
+            self.inner.begin_pass(pass, passes)
+        } else {
+            self.pending_events.push(Event::BeginPass(pass, passes));
+        }
+    }
+
+    fn end_pass(&mut self) {
+        if self.passthrough() {
+            self.inner.end_pass()
+        } else {
+            self.pending_events.push(Event::EndPass);
+        }
+    }
+
+    fn move_to(&mut self, to: Point<Float>) {
+        if self.passthrough() {
+            self.inner.move_to(to)
+        } else {
+            self.start_path(Start::MoveTo(to));
+        }
+    }
+
+    fn line_to(&mut self, to: Point<Float>) {
+        if self.passthrough() {
+            self.inner.line_to(to)
+        } else {
+            self.record(Step::LineTo(to), to);
+        }
+    }
+
+    fn arc(&mut self, svg_arc: SvgArc<Float>) {
+        if self.passthrough() {
+            self.inner.arc(svg_arc)
+        } else {
+            self.record(Step::Arc(svg_arc), svg_arc.to);
+        }
+    }
+
+    fn cubic_bezier(&mut self, cbs: CubicBezierSegment<Float>) {
+        if self.passthrough() {
+            self.inner.cubic_bezier(cbs)
+        } else {
+            self.record(Step::CubicBezier(cbs), cbs.to);
+        }
+    }
+
+    fn quadratic_bezier(&mut self, qbs: QuadraticBezierSegment<Float>) {
+        if self.passthrough() {
+            self.inner.quadratic_bezier(qbs)
+        } else {
+            self.record(Step::QuadraticBezier(qbs), qbs.to);
+        }
+    }
+}
@@ -78,6 +78,11 @@ pub fn settings_form() -> Html {
         form.line_numbers = event.target_unchecked_into::<HtmlInputElement>().checked();
     });
 
+    let on_marlin_checksums_change =
+        form_dispatch.reduce_mut_callback_with(|form, event: Event| {
+            form.marlin_checksums = event.target_unchecked_into::<HtmlInputElement>().checked();
+        });
+
     let on_newline_before_comment_change =
         form_dispatch.reduce_mut_callback_with(|form, event: Event| {
             form.newline_before_comment =
@@ -177,6 +182,16 @@ pub fn settings_form() -> Html {
                             />
                         </FormGroup>
                     </div>
+                    <div class="column col-6 col-sm-12">
+                        <FormGroup>
+                            <Checkbox
+                                label="Marlin line checksums"
+                                desc="Numbers and checksums every line and strips comments for streaming to Marlin"
+                                checked={form_state.marlin_checksums}
+                                onchange={on_marlin_checksums_change}
+                            />
+                        </FormGroup>
+                    </div>
                     <div class="column col-6 col-sm-12">
                         <FormGroup>
                             <Checkbox
@@ -4,14 +4,13 @@ use std::{
 };
 
 use base64::Engine;
-use g_code::{
-    emit::{FormatOptions, format_gcode_fmt, format_gcode_io},
-    parse::snippet_parser,
-};
+use g_code::parse::snippet_parser;
 use js_sys::Date;
 use log::Level;
 use roxmltree::{Document, ParsingOptions};
-use svg2gcode::{ConversionOptions, Machine, svg2program};
+use svg2gcode::{
+    ConversionOptions, Hooks, Machine, format_program_fmt, format_program_io, svg2program,
+};
 use yew::prelude::*;
 
 mod forms;
@@ -65,10 +64,16 @@ fn app() -> Html {
             for svg in app_store.svgs.iter() {
                 let options = ConversionOptions {
                     dimensions: svg.dimensions,
+                    source_name: Some(svg.filename.clone()),
+                    group: None,
+                    path_range: None,
+                    cancellation: None,
+                    hooks: Hooks::default(),
                 };
 
                 let machine = Machine::new(
                     app_store.settings.machine.supported_functionality.clone(),
+                    app_store.settings.machine.kinematics,
                     app_store
                         .settings
                         .machine
@@ -101,6 +106,17 @@ fn app() -> Html {
                         .map(snippet_parser)
                         .transpose()
                         .unwrap(),
+                )
+                .with_pen_servo(app_store.settings.machine.pen_servo)
+                .with_program_end(
+                    app_store
+                        .settings
+                        .machine
+                        .program_end
+                        .as_ref()
+                        .map(|program_end| snippet_parser(program_end.gcode()))
+                        .transpose()
+                        .unwrap(),
                 );
                 let document = Document::parse_with_options(
                     svg.content.as_str(),
@@ -111,8 +127,18 @@ fn app() -> Html {
                 )
                 .unwrap();
 
-                let program =
-                    svg2program(&document, &app_store.settings.conversion, options, machine);
+                let program = match svg2program(
+                    &document,
+                    &app_store.settings.conversion,
+                    options,
+                    machine,
+                ) {
+                    Ok(program) => program.tokens,
+                    Err(err) => {
+                        log::error!("Could not convert {}: {err}", svg.filename);
+                        continue;
+                    }
+                };
 
                 let filepath = if app_store.svgs.len() > 1 {
                     PathBuf::from("svg2gcode_output")
@@ -126,20 +152,8 @@ fn app() -> Html {
                     1 => {
                         let gcode = {
                             let mut acc = String::new();
-                            format_gcode_fmt(
-                                &program,
-                                FormatOptions {
-                                    checksums: app_store.settings.postprocess.checksums,
-                                    line_numbers: app_store.settings.postprocess.line_numbers,
-                                    newline_before_comment: app_store
-                                        .settings
-                                        .postprocess
-                                        .newline_before_comment,
-                                    ..Default::default()
-                                },
-                                &mut acc,
-                            )
-                            .unwrap();
+                            format_program_fmt(&program, &app_store.settings.postprocess, &mut acc)
+                                .unwrap();
                             acc
                         };
                         prompt_download(filepath, gcode.as_bytes());
@@ -147,20 +161,8 @@ fn app() -> Html {
                     _multiple => {
                         zip.start_file(filepath.to_string_lossy(), opts).unwrap();
 
-                        format_gcode_io(
-                            &program,
-                            FormatOptions {
-                                checksums: app_store.settings.postprocess.checksums,
-                                line_numbers: app_store.settings.postprocess.line_numbers,
-                                newline_before_comment: app_store
-                                    .settings
-                                    .postprocess
-                                    .newline_before_comment,
-                                ..Default::default()
-                            },
-                            &mut zip,
-                        )
-                        .unwrap();
+                        format_program_io(&program, &app_store.settings.postprocess, &mut zip)
+                            .unwrap();
                     }
                 }
             }
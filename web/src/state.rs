@@ -22,7 +22,10 @@ pub struct FormState {
     pub end_sequence: Option<Result<String, String>>,
     pub checksums: bool,
     pub line_numbers: bool,
+    pub marlin_checksums: bool,
     pub newline_before_comment: bool,
+    /// Settings the form was filled from, so that saving keeps the ones it can't edit
+    pub settings: Settings,
 }
 
 impl Default for FormState {
@@ -44,6 +47,12 @@ impl TryInto<Settings> for &FormState {
     type Error = FormStateConversionError;
 
     fn try_into(self) -> Result<Settings, Self::Error> {
+        let Settings {
+            conversion,
+            machine,
+            postprocess,
+            ..
+        } = self.settings.clone();
         Ok(Settings {
             conversion: ConversionConfig {
                 tolerance: self.tolerance.clone()?,
@@ -54,10 +63,12 @@ impl TryInto<Settings> for &FormState {
                     self.origin[1].clone().transpose()?,
                 ],
                 extra_attribute_name: None,
+                ..conversion
             },
             machine: MachineConfig {
                 supported_functionality: SupportedFunctionality {
                     circular_interpolation: self.circular_interpolation,
+                    ..machine.supported_functionality
                 },
                 tool_on_sequence: self
                     .tool_on_sequence
@@ -79,11 +90,14 @@ impl TryInto<Settings> for &FormState {
                     .clone()
                     .transpose()
                     .map_err(FormStateConversionError::GCode)?,
+                ..machine
             },
             postprocess: PostprocessConfig {
                 checksums: self.checksums,
                 line_numbers: self.line_numbers,
+                marlin_checksums: self.marlin_checksums,
                 newline_before_comment: self.newline_before_comment,
+                ..postprocess
             },
             version: Version::latest(),
         })
@@ -110,7 +124,9 @@ impl From<&Settings> for FormState {
             end_sequence: settings.machine.end_sequence.clone().map(Ok),
             checksums: settings.postprocess.checksums,
             line_numbers: settings.postprocess.line_numbers,
+            marlin_checksums: settings.postprocess.marlin_checksums,
             newline_before_comment: settings.postprocess.newline_before_comment,
+            settings: settings.clone(),
         }
     }
 }